@@ -0,0 +1,7 @@
+-- 创建 rate_limit_buckets 表，用于在多个应用实例之间共享限流的令牌桶
+CREATE TABLE rate_limit_buckets(
+    key TEXT NOT NULL,
+    PRIMARY KEY (key),
+    tokens DOUBLE PRECISION NOT NULL,
+    updated_at timestamptz NOT NULL
+);
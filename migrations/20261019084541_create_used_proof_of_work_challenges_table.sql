@@ -0,0 +1,6 @@
+-- 创建 used_proof_of_work_challenges 表，记录已经使用过的工作量证明挑战，防止重放
+CREATE TABLE used_proof_of_work_challenges(
+    nonce TEXT NOT NULL,
+    PRIMARY KEY (nonce),
+    expires_at timestamptz NOT NULL
+);
@@ -0,0 +1,5 @@
+-- 为 subscriptions 表添加 status 列
+-- 在此之前订阅的用户没有经过邮件确认，视为已确认，以免他们收不到邮件
+ALTER TABLE subscriptions ADD COLUMN status TEXT NULL;
+UPDATE subscriptions SET status = 'confirmed' WHERE status IS NULL;
+ALTER TABLE subscriptions ALTER COLUMN status SET NOT NULL;
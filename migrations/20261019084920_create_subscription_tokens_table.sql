@@ -0,0 +1,7 @@
+-- 创建 subscription_tokens 表，用于确认订阅的邮件链接
+CREATE TABLE subscription_tokens(
+    subscription_token TEXT NOT NULL,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    PRIMARY KEY (subscription_token)
+);
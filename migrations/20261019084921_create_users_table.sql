@@ -0,0 +1,7 @@
+-- 创建 users 表，保存可以调用受保护API（如发布newsletter）的用户
+-- 注：只保存密码的argon2哈希值（PHC字符串格式，包含了算法、参数和盐值），而不是密码本身
+CREATE TABLE users(
+    user_id uuid PRIMARY KEY,
+    username TEXT NOT NULL UNIQUE,
+    password_hash TEXT NOT NULL
+);
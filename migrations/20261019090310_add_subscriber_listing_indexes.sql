@@ -0,0 +1,11 @@
+-- 管理后台订阅者列表使用的索引
+-- 键集分页按(排序列, id)定位下一页，每个可排序的列都需要一个对应的复合索引
+CREATE INDEX subscriptions_subscribed_at_id_idx ON subscriptions (subscribed_at, id);
+CREATE INDEX subscriptions_email_id_idx ON subscriptions (email, id);
+CREATE INDEX subscriptions_name_id_idx ON subscriptions (name, id);
+-- 最常见的查询是按状态过滤后再按订阅时间排序
+CREATE INDEX subscriptions_status_subscribed_at_id_idx ON subscriptions (status, subscribed_at, id);
+-- 不区分大小写的子串搜索（ILIKE '%...%'）无法使用B-tree索引，需要三元组索引
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+CREATE INDEX subscriptions_email_trgm_idx ON subscriptions USING gin (email gin_trgm_ops);
+CREATE INDEX subscriptions_name_trgm_idx ON subscriptions USING gin (name gin_trgm_ops);
@@ -0,0 +1,14 @@
+-- 创建 subscriber_audit_log 表，记录管理员对订阅者所做的修改：谁在什么时候改了什么
+-- 注：subscriber_id不设外键，订阅者被删除后仍需保留其审计记录
+CREATE TABLE subscriber_audit_log(
+    id BIGSERIAL NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL,
+    changed_by uuid NOT NULL
+        REFERENCES users (user_id),
+    action TEXT NOT NULL,
+    -- 每个被修改的字段的旧值和新值，如{"name": {"from": "...", "to": "..."}}
+    changes JSONB NOT NULL,
+    changed_at timestamptz NOT NULL
+);
+CREATE INDEX subscriber_audit_log_subscriber_id_idx ON subscriber_audit_log (subscriber_id, changed_at);
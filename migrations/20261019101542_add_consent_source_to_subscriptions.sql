@@ -0,0 +1,3 @@
+-- 为 subscriptions 表添加 consent_source 列，记录订阅者是在哪里、以何种方式同意接收邮件的
+-- 通过订阅表单注册的订阅者为NULL（同意记录就是确认邮件），从其他工具导入的订阅者需要注明来源
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
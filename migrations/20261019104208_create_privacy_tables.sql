@@ -0,0 +1,20 @@
+-- 创建 privacy_request_tokens 表，用于验证查询或删除个人数据的请求确实来自邮箱的主人
+CREATE TABLE privacy_request_tokens(
+    privacy_token TEXT NOT NULL,
+    PRIMARY KEY (privacy_token),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    -- access：导出数据；erasure：删除数据
+    kind TEXT NOT NULL,
+    requested_at timestamptz NOT NULL,
+    expires_at timestamptz NOT NULL
+);
+
+-- 创建 suppressions 表，其中的邮箱地址不会再被导入
+-- 只保存邮箱地址的哈希值：只能用来判断某个已知的地址是否在表中，无法还原出地址本身
+CREATE TABLE suppressions(
+    email_hash TEXT NOT NULL,
+    PRIMARY KEY (email_hash),
+    reason TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
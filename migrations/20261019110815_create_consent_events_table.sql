@@ -0,0 +1,24 @@
+-- 创建 consent_events 表，记录订阅者每一次状态变化的时间、方式和来源，用于证明订阅者何时、如何同意接收邮件
+-- 注：subscriber_id不设外键，订阅者被删除后仍需保留这些记录
+CREATE TABLE consent_events(
+    id BIGSERIAL NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL,
+    -- signup、confirm、unsubscribe、block、admin_edit、import、delete、erasure
+    event TEXT NOT NULL,
+    -- 事件的来源，如subscription_form、confirmation_link、admin:<username>
+    source TEXT NOT NULL,
+    -- 只有订阅者本人发起的请求才会记录
+    ip_address TEXT NULL,
+    user_agent TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX consent_events_subscriber_id_idx ON consent_events (subscriber_id, occurred_at);
+
+-- 为已有的订阅者补一条订阅事件，时间为订阅时间。当时没有记录IP和User-Agent
+INSERT INTO consent_events (subscriber_id, event, source, occurred_at)
+SELECT id,
+    CASE WHEN consent_source IS NULL THEN 'signup' ELSE 'import' END,
+    COALESCE(consent_source, 'recorded before consent tracking'),
+    subscribed_at
+FROM subscriptions;
@@ -0,0 +1,63 @@
+-- 创建 lists 表：每个邮件列表（如每周精选、产品公告、开发者更新日志）各占一行
+CREATE TABLE lists(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    -- 在订阅表单和API中引用列表时使用的标识，如weekly-digest
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL
+);
+-- 原来唯一的newsletter成为默认列表：没有指定列表的订阅和发布都使用它
+INSERT INTO lists (id, slug, name, created_at)
+VALUES (gen_random_uuid(), 'newsletter', 'Newsletter', now());
+
+-- 订阅者与列表的多对多关系。订阅状态从subscriptions移到这里，每个列表分别确认、分别退订
+CREATE TABLE list_memberships(
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id),
+    list_id uuid NOT NULL
+        REFERENCES lists (id),
+    PRIMARY KEY (subscriber_id, list_id),
+    status TEXT NOT NULL,
+    -- 加入该列表的时间
+    subscribed_at timestamptz NOT NULL,
+    -- 该列表的邮件中退订链接使用的令牌
+    unsubscribe_token TEXT NOT NULL UNIQUE
+);
+-- 发布时按列表和状态查找收件人
+CREATE INDEX list_memberships_list_id_status_idx ON list_memberships (list_id, status);
+
+-- 已有的订阅者都属于默认列表，保留原来的状态
+INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+SELECT s.id, l.id, s.status, s.subscribed_at, replace(gen_random_uuid()::text, '-', '')
+FROM subscriptions s
+CROSS JOIN lists l
+WHERE l.slug = 'newsletter';
+-- 注：subscriptions_status_subscribed_at_id_idx随列一起被删除
+ALTER TABLE subscriptions DROP COLUMN status;
+
+-- 确认令牌只确认订阅者在某一个列表中的成员资格
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (id);
+UPDATE subscription_tokens SET list_id = (SELECT id FROM lists WHERE slug = 'newsletter');
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
+
+-- 与成员资格相关的同意事件记录是哪个列表；修改资料、删除等针对订阅者本身的事件为NULL
+ALTER TABLE consent_events ADD COLUMN list_id uuid NULL REFERENCES lists (id);
+UPDATE consent_events SET list_id = (SELECT id FROM lists WHERE slug = 'newsletter')
+WHERE event IN ('signup', 'confirm', 'unsubscribe', 'block', 'import');
+
+-- 订阅者所在的列表及其状态，按列表标识排序，如[{"list": "newsletter", "status": "confirmed", ...}]
+-- 订阅者的每个查询都需要它，定义为函数以免在每条SQL中重复这段子查询
+CREATE FUNCTION subscriber_lists(subscriber uuid) RETURNS jsonb
+LANGUAGE sql STABLE AS $$
+    SELECT COALESCE(
+        jsonb_agg(
+            jsonb_build_object('list', l.slug, 'status', m.status, 'subscribed_at', m.subscribed_at)
+            ORDER BY l.slug
+        ),
+        '[]'::jsonb
+    )
+    FROM list_memberships m
+    JOIN lists l ON l.id = m.list_id
+    WHERE m.subscriber_id = subscriber
+$$;
@@ -0,0 +1,24 @@
+-- 订阅者偏好设置：接收频率、暂停接收，以及访问偏好设置页面所用的令牌
+-- 注：令牌由数据库生成（gen_random_uuid使用安全的随机数），这样所有写入subscriptions的地方都无需关心它
+ALTER TABLE subscriptions
+    ADD COLUMN preferences_token TEXT NOT NULL UNIQUE
+        DEFAULT replace(gen_random_uuid()::text, '-', ''),
+    ADD COLUMN delivery_frequency TEXT NOT NULL DEFAULT 'immediate',
+    -- 在此时间之前不发送任何邮件，NULL表示未暂停
+    ADD COLUMN paused_until timestamptz NULL;
+
+-- 选择每周摘要的订阅者，在发布时把新闻邮件放入队列，由send_digests每周汇总发送
+CREATE TABLE digest_items(
+    id BIGSERIAL NOT NULL,
+    PRIMARY KEY (id),
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    title TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    queued_at timestamptz NOT NULL
+);
+CREATE INDEX digest_items_subscriber_id_idx ON digest_items (subscriber_id, id);
+
+-- 订阅者在偏好设置页面所做的修改也记入审计日志，此时没有管理员，changed_by为NULL
+ALTER TABLE subscriber_audit_log ALTER COLUMN changed_by DROP NOT NULL;
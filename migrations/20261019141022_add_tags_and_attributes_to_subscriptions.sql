@@ -0,0 +1,8 @@
+-- 为 subscriptions 表添加标签和属性，用于向部分订阅者发送邮件
+-- tags：管理员打的标签；attributes：订阅表单上提交的额外字段，如{"source": "conference-page", "locale": "de"}
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+-- 按标签（tags @> ARRAY[...]）和属性（attributes @> '{...}'）过滤时使用
+CREATE INDEX subscriptions_tags_idx ON subscriptions USING GIN (tags);
+CREATE INDEX subscriptions_attributes_idx ON subscriptions USING GIN (attributes jsonb_path_ops);
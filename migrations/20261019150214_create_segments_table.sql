@@ -0,0 +1,11 @@
+-- 创建 segments 表：保存的订阅者分组，发布时可以只发送给满足表达式的订阅者
+CREATE TABLE segments(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    name TEXT NOT NULL UNIQUE,
+    -- 分组表达式的原文，如tag:beta AND attr.locale = "de"。保存前已经校验过
+    expression TEXT NOT NULL,
+    created_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL
+);
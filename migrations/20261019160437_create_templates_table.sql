@@ -0,0 +1,25 @@
+-- 创建 templates 表：管理员维护的邮件模板（Handlebars语法），每个模板都有HTML和纯文本两个版本
+-- 模板可以通过{{> 名称}}引用其他模板作为片段，也可以指定一个布局，布局通过{{{body}}}插入模板的内容
+CREATE TABLE templates(
+    -- 在API、片段引用和布局中使用的标识，如confirmation
+    name TEXT NOT NULL,
+    PRIMARY KEY (name),
+    -- 邮件标题，只有直接发送的模板才需要，布局和片段为NULL
+    subject TEXT NULL,
+    html TEXT NOT NULL,
+    text TEXT NOT NULL,
+    layout TEXT NULL
+        REFERENCES templates (name),
+    updated_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    updated_at timestamptz NOT NULL
+);
+-- 确认邮件原来是硬编码的，改为可以编辑的模板，内容保持不变
+INSERT INTO templates (name, subject, html, text, updated_at)
+VALUES (
+    'confirmation',
+    'Welcome!',
+    'Welcome to {{list}}!<br />Click <a href="{{confirmation_url}}">here</a> to confirm your subscription.',
+    E'Welcome to {{list}}!\nVisit {{confirmation_url}} to confirm your subscription.',
+    now()
+);
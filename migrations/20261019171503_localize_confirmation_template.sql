@@ -0,0 +1,11 @@
+-- 确认邮件改为按订阅者的语言翻译：文字来自locales/下的目录，通过{{t}}插入
+-- 只更新由迁移创建、未被管理员修改过的模板
+UPDATE templates
+SET subject = '{{t "confirmation.subject"}}',
+    html = '{{t "confirmation.welcome" list=list}}<br /><a href="{{confirmation_url}}">{{t "confirmation.link"}}</a>',
+    text = E'{{t "confirmation.welcome" list=list}}\n{{t "confirmation.visit" url=confirmation_url}}',
+    updated_at = now()
+WHERE name = 'confirmation'
+    AND subject = 'Welcome!'
+    AND html = 'Welcome to {{list}}!<br />Click <a href="{{confirmation_url}}">here</a> to confirm your subscription.'
+    AND text = E'Welcome to {{list}}!\nVisit {{confirmation_url}} to confirm your subscription.';
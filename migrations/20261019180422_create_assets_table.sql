@@ -0,0 +1,13 @@
+-- 创建 assets 表：上传的附件和内嵌图片。内容保存在blob存储中，blob_key是内容的SHA-256
+-- 相同的内容可以被上传多次（不同的文件名），它们共享同一个blob
+CREATE TABLE assets(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    blob_key TEXT NOT NULL,
+    filename TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size_bytes BIGINT NOT NULL,
+    uploaded_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    uploaded_at timestamptz NOT NULL
+);
@@ -0,0 +1,37 @@
+-- 创建 newsletter_issues 表：保存下来、稍后发送的一期新闻邮件
+-- 状态：draft（未安排发送）-> scheduled（等待scheduled_at）-> sending（已放入投递队列）-> sent
+-- 开始发送之前可以被取消（cancelled）；到期时内容已经无法发送（如引用的模板被删除）也会被取消，原因记录在failure_reason
+CREATE TABLE newsletter_issues(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    title TEXT NOT NULL,
+    -- 发布请求（与POST /api/v1/newsletters的JSON请求体相同），发送时重新解析
+    request JSONB NOT NULL,
+    status TEXT NOT NULL
+        CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled')),
+    scheduled_at timestamptz NULL,
+    -- 安排发送时使用的时区（IANA名称），只用于展示，scheduled_at已经是绝对时间
+    timezone TEXT NULL,
+    failure_reason TEXT NULL,
+    created_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL,
+    updated_at timestamptz NOT NULL,
+    sent_at timestamptz NULL
+);
+
+-- 调度循环只查找到期的scheduled
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (scheduled_at)
+    WHERE status = 'scheduled';
+
+-- 创建 issue_delivery_queue 表：每一行是一封待发送的邮件，发送成功后删除
+CREATE TABLE issue_delivery_queue(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    PRIMARY KEY (newsletter_issue_id, subscriber_id),
+    -- 已经失败的次数，以及下一次尝试的时间
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL
+);
@@ -0,0 +1,30 @@
+-- 创建 newsletter_issue_revisions 表：一期邮件每次保存都生成一个不可修改的版本
+-- 安排发送（或立即发布）时选定一个版本，之后的编辑不会影响这次发送
+CREATE TABLE newsletter_issue_revisions(
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    -- 从1开始递增
+    revision INT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, revision),
+    -- 发布请求（与POST /api/v1/newsletters的JSON请求体相同）
+    request JSONB NOT NULL,
+    created_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    created_at timestamptz NOT NULL
+);
+
+-- 已有的每一期作为它的第一个版本
+INSERT INTO newsletter_issue_revisions (newsletter_issue_id, revision, request, created_by, created_at)
+SELECT id, 1, request, created_by, created_at FROM newsletter_issues;
+
+ALTER TABLE newsletter_issues ADD COLUMN latest_revision INT NOT NULL DEFAULT 1;
+-- 被安排发送的版本，草稿没有
+ALTER TABLE newsletter_issues ADD COLUMN published_revision INT NULL;
+UPDATE newsletter_issues SET published_revision = 1 WHERE status <> 'draft';
+ALTER TABLE newsletter_issues ALTER COLUMN latest_revision DROP DEFAULT;
+ALTER TABLE newsletter_issues DROP COLUMN request;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_published_revision_check
+    CHECK (status IN ('draft', 'cancelled') OR published_revision IS NOT NULL);
+
+-- 管理员自己的邮箱地址，"发送测试邮件"只发给它
+ALTER TABLE users ADD COLUMN email TEXT NULL;
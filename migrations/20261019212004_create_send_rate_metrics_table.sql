@@ -0,0 +1,14 @@
+-- 创建 send_rate_metrics 表：投递任务发送速率限制的统计，由所有实例共同累加
+-- 令牌桶本身保存在rate_limit_buckets表中，键为send:global或send:transport:{主机名}
+CREATE TABLE send_rate_metrics(
+    key TEXT NOT NULL,
+    PRIMARY KEY (key),
+    -- 最近一次使用的配置
+    messages_per_second DOUBLE PRECISION NOT NULL,
+    -- 通过该桶发出的邮件数
+    sent BIGINT NOT NULL,
+    -- 需要等待令牌才能发出的邮件数，以及累计等待的时间
+    throttled BIGINT NOT NULL,
+    waited_seconds DOUBLE PRECISION NOT NULL,
+    updated_at timestamptz NOT NULL
+);
@@ -0,0 +1,27 @@
+-- 创建 email_events 表：邮件服务商通过webhook报告的投递、退信、投诉、打开和点击事件
+-- 与suppressions表相同，只保存邮箱地址的哈希值
+CREATE TABLE email_events(
+    id uuid NOT NULL,
+    PRIMARY KEY (id),
+    kind TEXT NOT NULL
+        CHECK (kind IN ('delivered', 'bounced-hard', 'bounced-soft', 'complaint', 'opened', 'clicked')),
+    email_hash TEXT NOT NULL,
+    subscriber_id uuid NULL
+        REFERENCES subscriptions (id) ON DELETE SET NULL,
+    -- 服务商给事件的id，服务商重试webhook时用来去重
+    provider_event_id TEXT NULL UNIQUE,
+    message_id TEXT NULL,
+    -- 退信原因、点击的链接等
+    detail TEXT NULL,
+    occurred_at timestamptz NOT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_events_email_hash_idx ON email_events (email_hash, occurred_at);
+
+-- 每个地址连续软退信的次数，成功投递后清零，达到上限时屏蔽该地址
+CREATE TABLE soft_bounce_counts(
+    email_hash TEXT NOT NULL,
+    PRIMARY KEY (email_hash),
+    count INT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
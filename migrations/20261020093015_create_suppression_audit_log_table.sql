@@ -0,0 +1,18 @@
+-- suppressions表中的地址在订阅、导入和发送之前都会被检查，再也不会收到邮件
+-- 创建 suppression_audit_log 表，记录地址何时、因为什么被屏蔽或解除屏蔽
+-- 注：退信、投诉和删除数据自动屏蔽的地址performed_by为NULL
+CREATE TABLE suppression_audit_log(
+    id BIGSERIAL NOT NULL,
+    PRIMARY KEY (id),
+    email_hash TEXT NOT NULL,
+    action TEXT NOT NULL
+        CHECK (action IN ('added', 'lifted')),
+    -- 屏蔽的原因，如hard-bounce、complaint、erasure、manual
+    reason TEXT NOT NULL,
+    -- 管理员填写的说明
+    note TEXT NULL,
+    performed_by uuid NULL
+        REFERENCES users (user_id) ON DELETE SET NULL,
+    performed_at timestamptz NOT NULL
+);
+CREATE INDEX suppression_audit_log_email_hash_idx ON suppression_audit_log (email_hash, performed_at);
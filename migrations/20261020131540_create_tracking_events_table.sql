@@ -0,0 +1,20 @@
+-- 订阅者可以在偏好设置页面选择不被跟踪，之后发给他的邮件中不再加入跟踪像素和跟踪链接
+ALTER TABLE subscriptions ADD COLUMN tracking_opt_out BOOLEAN NOT NULL DEFAULT false;
+
+-- 创建 tracking_events 表，记录订阅者何时打开了哪一期、点击了其中的哪个链接
+-- 注：订阅者被删除时一并删除其跟踪记录
+CREATE TABLE tracking_events(
+    id BIGSERIAL NOT NULL,
+    PRIMARY KEY (id),
+    newsletter_issue_id uuid NOT NULL
+        REFERENCES newsletter_issues (id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL
+        REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL
+        CHECK (kind IN ('open', 'click')),
+    -- 点击的链接，打开时为NULL
+    url TEXT NULL,
+    occurred_at timestamptz NOT NULL
+);
+CREATE INDEX tracking_events_newsletter_issue_id_idx ON tracking_events (newsletter_issue_id, kind);
+CREATE INDEX tracking_events_subscriber_id_idx ON tracking_events (subscriber_id);
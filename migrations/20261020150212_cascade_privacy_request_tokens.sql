@@ -0,0 +1,5 @@
+-- 订阅者被管理员删除时，其未使用的查询/删除链接也一并删除，否则外键会阻止删除订阅者
+ALTER TABLE privacy_request_tokens
+    DROP CONSTRAINT privacy_request_tokens_subscriber_id_fkey,
+    ADD CONSTRAINT privacy_request_tokens_subscriber_id_fkey
+        FOREIGN KEY (subscriber_id) REFERENCES subscriptions (id) ON DELETE CASCADE;
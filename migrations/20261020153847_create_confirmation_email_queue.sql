@@ -0,0 +1,10 @@
+-- 创建 confirmation_email_queue 表：导入的待确认订阅者的确认邮件，由后台任务按发送速率逐封发送
+-- 令牌被删除（如订阅者被删除）时，对应的邮件也不再发送
+CREATE TABLE confirmation_email_queue(
+    subscription_token TEXT NOT NULL
+        REFERENCES subscription_tokens (subscription_token) ON DELETE CASCADE,
+    PRIMARY KEY (subscription_token),
+    -- 已经失败的次数，以及下一次尝试的时间
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL
+);
@@ -0,0 +1,44 @@
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use super::BlobStoreError;
+
+// 把blob保存为本地目录中的文件，按键的前两个字符分成子目录，避免一个目录中的文件过多
+pub struct FilesystemStore {
+    directory: PathBuf,
+}
+
+impl FilesystemStore {
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            directory: directory.into(),
+        }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        self.directory.join(&key[..2]).join(key)
+    }
+
+    // 内容相同的blob只写一次。先写入临时文件再重命名，读取者不会看到写了一半的文件
+    pub async fn put(&self, key: &str, content: &[u8]) -> Result<(), BlobStoreError> {
+        let path = self.path(key);
+        if tokio::fs::try_exists(&path).await? {
+            return Ok(());
+        }
+        let parent = path.parent().expect("Blob paths always have a parent");
+        tokio::fs::create_dir_all(parent).await?;
+        let temporary = parent.join(format!(".{}.{}", key, Uuid::new_v4()));
+        tokio::fs::write(&temporary, content).await?;
+        tokio::fs::rename(&temporary, &path).await?;
+        Ok(())
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, BlobStoreError> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(content) => Ok(content),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(BlobStoreError::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
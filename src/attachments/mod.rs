@@ -0,0 +1,293 @@
+// 新闻邮件的附件和内嵌图片
+// 上传的文件保存在按内容寻址的blob存储中（键是内容的SHA-256），相同的文件只保存一份；
+// assets表记录每次上传的文件名和类型，发布时通过asset的id引用
+mod filesystem;
+mod multipart;
+mod s3;
+
+use std::sync::Arc;
+
+use actix_web::{error::PayloadError, http::StatusCode, web, HttpResponse, ResponseError};
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::configuration::{AttachmentSettings, BlobStoreKind, BlobStoreSettings};
+use crate::email_message::Attachment;
+use crate::repository::assets::{self, Asset};
+use filesystem::FilesystemStore;
+
+pub use multipart::{parse_form_data, FormPart};
+pub use s3::{canonical_uri, S3Store};
+
+#[derive(Debug)]
+pub enum BlobStoreError {
+    NotFound,
+    Io(std::io::Error),
+    Http(reqwest::Error),
+    Backend(String),
+}
+
+impl std::fmt::Display for BlobStoreError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("The blob does not exist."),
+            Self::Io(e) => write!(f, "Failed to access the blob: {}", e),
+            Self::Http(e) => write!(f, "Failed to reach the object store: {}", e),
+            Self::Backend(e) => f.write_str(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for BlobStoreError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<reqwest::Error> for BlobStoreError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+// blob存储的后端：单实例部署时使用本地目录即可，多实例部署时需要共享的对象存储
+enum BlobBackend {
+    Filesystem(FilesystemStore),
+    S3(S3Store),
+}
+
+// 按内容寻址的blob存储
+pub struct BlobStore {
+    backend: BlobBackend,
+}
+
+impl BlobStore {
+    pub fn new(settings: &BlobStoreSettings) -> Result<Self, String> {
+        let backend = match settings.backend {
+            BlobStoreKind::Filesystem => {
+                BlobBackend::Filesystem(FilesystemStore::new(&settings.directory))
+            }
+            BlobStoreKind::S3 => {
+                BlobBackend::S3(S3Store::new(settings.s3.clone().ok_or(
+                    "The s3 blob store requires the attachments.store.s3 settings.",
+                )?))
+            }
+        };
+        Ok(Self { backend })
+    }
+
+    // 内容的键：SHA-256的十六进制表示
+    pub fn key(content: &[u8]) -> String {
+        hex::encode(Sha256::digest(content))
+    }
+
+    // 保存内容并返回它的键
+    #[tracing::instrument(name = "Store blob", skip(self, content), fields(size = content.len()))]
+    pub async fn put(&self, content: &[u8]) -> Result<String, BlobStoreError> {
+        let key = Self::key(content);
+        match &self.backend {
+            BlobBackend::Filesystem(store) => store.put(&key, content).await?,
+            BlobBackend::S3(store) => store.put(&key, content).await?,
+        }
+        Ok(key)
+    }
+
+    #[tracing::instrument(name = "Fetch blob", skip(self))]
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, BlobStoreError> {
+        // 键来自数据库，但仍然检查格式，避免拼接出意外的路径
+        if key.len() != 64 || !key.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(BlobStoreError::NotFound);
+        }
+        match &self.backend {
+            BlobBackend::Filesystem(store) => store.get(key).await,
+            BlobBackend::S3(store) => store.get(key).await,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum AttachmentError {
+    NotFound,
+    // 超过的上限（字节）
+    TooLarge(u64),
+    UnsupportedType(String),
+    Invalid(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AttachmentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => f.write_str("The asset does not exist."),
+            Self::TooLarge(limit) => write!(f, "Attachments are limited to {} bytes.", limit),
+            Self::UnsupportedType(content_type) => {
+                write!(f, "Attachments of type {} are not allowed.", content_type)
+            }
+            Self::Invalid(e) => f.write_str(e),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AttachmentError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::TooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnsupportedType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for AttachmentError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+impl From<BlobStoreError> for AttachmentError {
+    fn from(e: BlobStoreError) -> Self {
+        tracing::error!("Blob store failure: {}", e);
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 读取整个请求体，超过limit字节时立即停止，而不是先把整个请求体读入内存
+pub async fn read_payload<S>(mut payload: S, limit: u64) -> Result<Vec<u8>, AttachmentError>
+where
+    S: Stream<Item = Result<web::Bytes, PayloadError>> + Unpin,
+{
+    let mut content = Vec::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk.map_err(|e| AttachmentError::Invalid(e.to_string()))?;
+        if (content.len() + chunk.len()) as u64 > limit {
+            return Err(AttachmentError::TooLarge(limit));
+        }
+        content.extend_from_slice(&chunk);
+    }
+    Ok(content)
+}
+
+// blob存储以及配置中的大小和类型限制
+pub struct Attachments {
+    store: BlobStore,
+    max_size_bytes: u64,
+    max_total_bytes: u64,
+    allowed_content_types: Vec<String>,
+}
+
+impl Attachments {
+    pub fn new(settings: &AttachmentSettings) -> Result<Self, String> {
+        Ok(Self {
+            store: BlobStore::new(&settings.store)?,
+            max_size_bytes: settings.max_size_bytes,
+            max_total_bytes: settings.max_total_bytes,
+            allowed_content_types: settings
+                .allowed_content_types
+                .iter()
+                .map(|t| t.to_ascii_lowercase())
+                .collect(),
+        })
+    }
+
+    pub fn max_size_bytes(&self) -> u64 {
+        self.max_size_bytes
+    }
+
+    pub fn max_total_bytes(&self) -> u64 {
+        self.max_total_bytes
+    }
+
+    // 只比较类型本身，忽略charset等参数
+    pub fn check_content_type(&self, content_type: &str) -> Result<(), AttachmentError> {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let allowed =
+            self.allowed_content_types
+                .iter()
+                .any(|allowed| match allowed.strip_suffix("/*") {
+                    Some(family) => essence.split('/').next() == Some(family),
+                    None => *allowed == essence,
+                });
+        if allowed {
+            Ok(())
+        } else {
+            Err(AttachmentError::UnsupportedType(essence))
+        }
+    }
+
+    pub fn check_total_size(&self, size: u64) -> Result<(), AttachmentError> {
+        if size > self.max_total_bytes {
+            return Err(AttachmentError::TooLarge(self.max_total_bytes));
+        }
+        Ok(())
+    }
+
+    // 检查限制，保存内容并记录一个新的asset
+    #[tracing::instrument(name = "Upload asset", skip(self, pool, content))]
+    pub async fn upload(
+        &self,
+        pool: &PgPool,
+        filename: &str,
+        content_type: &str,
+        content: &[u8],
+        uploaded_by: Uuid,
+    ) -> Result<Asset, AttachmentError> {
+        let filename = filename.trim();
+        if filename.is_empty()
+            || filename.len() > 255
+            || filename.contains(['/', '\\', '"', '\r', '\n'])
+        {
+            return Err(AttachmentError::Invalid(format!(
+                "{:?} is not a valid file name.",
+                filename
+            )));
+        }
+        self.check_content_type(content_type)?;
+        if content.len() as u64 > self.max_size_bytes {
+            return Err(AttachmentError::TooLarge(self.max_size_bytes));
+        }
+        let essence = content_type.split(';').next().unwrap_or_default().trim();
+        let blob_key = self.store.put(content).await?;
+        let asset = assets::insert(
+            pool,
+            &blob_key,
+            filename,
+            &essence.to_ascii_lowercase(),
+            content.len() as i64,
+            uploaded_by,
+        )
+        .await?;
+        Ok(asset)
+    }
+
+    pub async fn content(&self, asset: &Asset) -> Result<Vec<u8>, AttachmentError> {
+        Ok(self.store.get(&asset.blob_key).await?)
+    }
+
+    // 读取asset的内容，作为邮件的附件。有content_id时是内嵌图片，HTML中通过cid:引用
+    pub async fn load(
+        &self,
+        asset: &Asset,
+        content_id: Option<String>,
+    ) -> Result<Attachment, AttachmentError> {
+        Ok(Attachment {
+            filename: asset.filename.clone(),
+            content_type: asset.content_type.clone(),
+            content: Arc::from(self.content(asset).await?),
+            content_id,
+        })
+    }
+}
@@ -0,0 +1,72 @@
+// multipart/form-data请求体（RFC 7578）的解析。请求体已经整个读入内存，大小由调用者限制
+#[derive(Debug)]
+pub struct FormPart {
+    pub name: String,
+    // 只有文件才有文件名
+    pub filename: Option<String>,
+    pub content_type: Option<String>,
+    pub content: Vec<u8>,
+}
+
+pub fn parse_form_data(boundary: &str, body: &[u8]) -> Result<Vec<FormPart>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let mut rest = match find(body, &delimiter) {
+        Some(start) => &body[start + delimiter.len()..],
+        None => return Err("The multipart body has no parts.".into()),
+    };
+    let closing = [b"\r\n".as_slice(), &delimiter].concat();
+    let mut parts = Vec::new();
+    loop {
+        if rest.starts_with(b"--") {
+            return Ok(parts);
+        }
+        rest = rest
+            .strip_prefix(b"\r\n")
+            .ok_or("Malformed multipart delimiter.")?;
+        let headers_end = find(rest, b"\r\n\r\n").ok_or("Malformed multipart headers.")?;
+        let headers = std::str::from_utf8(&rest[..headers_end])
+            .map_err(|_| "Multipart headers must be UTF-8.")?;
+        rest = &rest[headers_end + 4..];
+        let end = find(rest, &closing).ok_or("The multipart body is truncated.")?;
+        parts.push(part(headers, &rest[..end])?);
+        rest = &rest[end + closing.len()..];
+    }
+}
+
+fn part(headers: &str, content: &[u8]) -> Result<FormPart, String> {
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    for header in headers.split("\r\n") {
+        let Some((header_name, value)) = header.split_once(':') else {
+            continue;
+        };
+        if header_name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.trim().to_string());
+        } else if header_name.eq_ignore_ascii_case("content-disposition") {
+            for parameter in value.split(';').skip(1) {
+                let Some((key, value)) = parameter.split_once('=') else {
+                    continue;
+                };
+                let value = value.trim().trim_matches('"').to_string();
+                match key.trim().to_ascii_lowercase().as_str() {
+                    "name" => name = Some(value),
+                    "filename" => filename = Some(value),
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(FormPart {
+        name: name.ok_or("Every multipart part needs a name.")?,
+        filename,
+        content_type,
+        content: content.to_vec(),
+    })
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,198 @@
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method, StatusCode};
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+
+use super::BlobStoreError;
+use crate::configuration::S3Settings;
+
+// 兼容S3 API的对象存储。请求使用AWS Signature Version 4签名，对象的键就是blob的键
+pub struct S3Store {
+    http_client: Client,
+    settings: S3Settings,
+}
+
+impl S3Store {
+    pub fn new(settings: S3Settings) -> Self {
+        Self {
+            http_client: Client::new(),
+            settings,
+        }
+    }
+
+    // PUT是幂等的，内容相同的blob重复上传只会覆盖为相同的内容
+    pub async fn put(&self, key: &str, content: &[u8]) -> Result<(), BlobStoreError> {
+        let response = self.request(Method::PUT, key, content).await?;
+        if !response.status().is_success() {
+            return Err(BlobStoreError::Backend(format!(
+                "S3 returned {} when storing {}",
+                response.status(),
+                key
+            )));
+        }
+        Ok(())
+    }
+
+    pub async fn get(&self, key: &str) -> Result<Vec<u8>, BlobStoreError> {
+        let response = self.request(Method::GET, key, &[]).await?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Err(BlobStoreError::NotFound),
+            status if status.is_success() => Ok(response.bytes().await?.to_vec()),
+            status => Err(BlobStoreError::Backend(format!(
+                "S3 returned {} when fetching {}",
+                status, key
+            ))),
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        key: &str,
+        content: &[u8],
+    ) -> Result<reqwest::Response, BlobStoreError> {
+        let mut url = reqwest::Url::parse(&format!(
+            "{}/{}/{}",
+            self.settings.endpoint.trim_end_matches('/'),
+            self.settings.bucket,
+            key
+        ))
+        .map_err(|e| BlobStoreError::Backend(e.to_string()))?;
+        // 发出的路径必须与签名中的完全一致
+        url.set_path(&canonical_uri(url.path()));
+        let now = Utc::now();
+        let payload_hash = hex::encode(Sha256::digest(content));
+        let authorization = self.authorization(&method, &url, &payload_hash, now);
+        let response = self
+            .http_client
+            .request(method, url)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("x-amz-date", now.format("%Y%m%dT%H%M%SZ").to_string())
+            .header("Authorization", authorization)
+            .body(content.to_vec())
+            .send()
+            .await?;
+        Ok(response)
+    }
+
+    // 签名host、x-amz-content-sha256和x-amz-date三个头，返回Authorization头的值
+    pub fn authorization(
+        &self,
+        method: &Method,
+        url: &reqwest::Url,
+        payload_hash: &str,
+        now: DateTime<Utc>,
+    ) -> String {
+        let date = now.format("%Y%m%d").to_string();
+        let timestamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            canonical_uri(url.path()),
+            canonical_query(url),
+            host,
+            payload_hash,
+            timestamp,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.settings.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let secret = format!("AWS4{}", self.settings.secret_access_key.expose_secret());
+        let signing_key = [
+            date.as_str(),
+            self.settings.region.as_str(),
+            "s3",
+            "aws4_request",
+        ]
+        .iter()
+        .fold(secret.into_bytes(), |key, part| hmac(&key, part));
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.settings.access_key_id,
+            scope,
+            signed_headers,
+            hex::encode(hmac(&signing_key, &string_to_sign))
+        )
+    }
+}
+
+// 按AWS的规则对路径的每一段重新编码：先还原已有的%XX，再把字母、数字和-._~以外的字节编码为%XX，/保持不变
+// 否则含有空格、+或非ASCII字符的路径会得到SignatureDoesNotMatch
+// 注：S3的路径只编码一次，其他AWS服务需要编码两次
+pub fn canonical_uri(path: &str) -> String {
+    path.split('/')
+        .map(|segment| uri_encode(&percent_decode(segment)))
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+// 查询参数按名称排序，名称和值都经过编码。没有值的参数写成name=
+fn canonical_query(url: &reqwest::Url) -> String {
+    let mut pairs: Vec<(String, String)> = url
+        .query_pairs()
+        .map(|(name, value)| (uri_encode(name.as_bytes()), uri_encode(value.as_bytes())))
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn uri_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len() * 3);
+    for &byte in bytes {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+// 不是合法%XX的%保持原样
+fn percent_decode(segment: &str) -> Vec<u8> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = match bytes[i] {
+            b'%' => bytes
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+            _ => None,
+        };
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    decoded
+}
+
+fn hmac(key: &[u8], message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,47 @@
+// 创建管理员用户。迁移中没有预置账号，部署后用这个命令创建第一个管理员
+// 用法：echo <密码> | create_admin <用户名>
+// 密码从标准输入读取，不会出现在命令行参数和shell历史中
+use std::io::BufRead;
+
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+use zero2prod_lib::{
+    authentication::create_user,
+    configuration::get_configuration,
+    telemetry::{get_subscriber, init_subscriber},
+};
+
+const USAGE: &str = "Usage: create_admin <USERNAME> (the password is read from stdin)";
+
+// 太短的密码容易被暴力破解
+const MIN_PASSWORD_LENGTH: usize = 12;
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 日志输出到stderr，stdout只输出新用户的id
+    let subscriber = get_subscriber("create_admin".into(), "info".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let mut args = std::env::args().skip(1);
+    let username = args.next().ok_or(USAGE)?;
+    if args.next().is_some() || username.trim().is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let mut password = String::new();
+    std::io::stdin().lock().read_line(&mut password)?;
+    let password = Secret::new(password.trim_end_matches(['\r', '\n']).to_string());
+    if password.expose_secret().chars().count() < MIN_PASSWORD_LENGTH {
+        return Err(format!(
+            "The password must be at least {} characters long.",
+            MIN_PASSWORD_LENGTH
+        )
+        .into());
+    }
+
+    let configuration = get_configuration()?;
+    let pool = PgPool::connect(configuration.database.connection_string().expose_secret()).await?;
+    let user_id = create_user(&username, password, &pool).await?;
+    println!("{}", user_id);
+    Ok(())
+}
@@ -0,0 +1,56 @@
+// 与POST /admin/subscribers/import相同的导入逻辑，用于直接在服务器上导入大文件
+// 用法：import_subscribers <CSV文件> [--confirmed --consent-source <来源>]
+use std::io::Read;
+
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use zero2prod_lib::{
+    configuration::get_configuration,
+    subscriber_import::{ImportOptions, SubscriberImporter},
+    telemetry::{get_subscriber, init_subscriber},
+};
+
+const USAGE: &str =
+    "Usage: import_subscribers <FILE> [--list <LIST>] [--confirmed --consent-source <SOURCE>]";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 日志输出到stderr，stdout只输出导入结果
+    let subscriber = get_subscriber("import_subscribers".into(), "info".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let mut path = None;
+    let mut options = ImportOptions {
+        confirmed: false,
+        consent_source: None,
+        list: None,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--confirmed" => options.confirmed = true,
+            "--consent-source" => options.consent_source = Some(args.next().ok_or(USAGE)?),
+            "--list" => options.list = Some(args.next().ok_or(USAGE)?),
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+
+    let configuration = get_configuration()?;
+    let pool = PgPool::connect(configuration.database.connection_string().expose_secret()).await?;
+    let mut importer = SubscriberImporter::new(&pool, options).await?;
+
+    let mut file = std::fs::File::open(&path)?;
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let n = file.read(&mut buffer)?;
+        if n == 0 {
+            break;
+        }
+        importer.feed(&buffer[..n]).await?;
+    }
+    let report = importer.finish().await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
@@ -0,0 +1,32 @@
+// 发送每周摘要，应由定时任务每周执行一次
+// 用法：send_digests
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use zero2prod_lib::{
+    configuration::get_configuration,
+    digest::send_digests,
+    email_client::EmailClient,
+    i18n::I18n,
+    telemetry::{get_subscriber, init_subscriber},
+};
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // 日志输出到stderr，stdout只输出发送结果
+    let subscriber = get_subscriber("send_digests".into(), "info".into(), std::io::stderr);
+    init_subscriber(subscriber);
+
+    let configuration = get_configuration()?;
+    let pool = PgPool::connect(configuration.database.connection_string().expose_secret()).await?;
+    let email_client = EmailClient::from_settings(&configuration.email_client)?;
+    let i18n = I18n::new(&configuration.application.default_locale)?;
+    let report = send_digests(
+        &pool,
+        &email_client,
+        &configuration.application.base_url,
+        &i18n,
+    )
+    .await?;
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
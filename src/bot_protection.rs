@@ -0,0 +1,217 @@
+use actix_web::{http::StatusCode, ResponseError};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::configuration::{BotProtectionSettings, ProofOfWorkSettings};
+
+// 订阅表单的机器人防护，两种手段都可以在配置中单独开启
+pub struct BotProtection {
+    // 是否启用蜜罐字段
+    pub honeypot: bool,
+    pub proof_of_work: Option<ProofOfWork>,
+}
+
+impl BotProtection {
+    pub fn new(settings: &BotProtectionSettings) -> Self {
+        Self {
+            honeypot: settings.honeypot,
+            proof_of_work: settings
+                .proof_of_work
+                .enabled
+                .then(|| ProofOfWork::new(&settings.proof_of_work)),
+        }
+    }
+
+    // 蜜罐字段对真实用户不可见，只有自动填写所有字段的机器人才会给它赋值
+    pub fn is_caught_by_honeypot(&self, honeypot_value: Option<&str>) -> bool {
+        self.honeypot && honeypot_value.is_some_and(|v| !v.is_empty())
+    }
+}
+
+// 轻量级的工作量证明，用于代替第三方CAPTCHA服务：
+// 1. 服务端签发一个带HMAC签名的挑战：`{随机数}.{过期时间戳}.{难度}.{签名}`
+// 2. 客户端寻找一个solution，使得sha256(challenge + solution)的前`难度`个比特都为0
+// 3. 服务端校验签名、过期时间和哈希值，并记录已使用的随机数，防止同一个解被重复使用
+pub struct ProofOfWork {
+    secret: Secret<String>,
+    difficulty: u8,
+    ttl: chrono::Duration,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct Challenge {
+    pub challenge: String,
+    pub difficulty: u8,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl ProofOfWork {
+    pub fn new(settings: &ProofOfWorkSettings) -> Self {
+        Self {
+            secret: settings.secret.clone(),
+            difficulty: settings.difficulty,
+            ttl: chrono::Duration::seconds(settings.ttl_seconds as i64),
+        }
+    }
+
+    pub fn issue(&self) -> Challenge {
+        let mut nonce = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let expires_at = Utc::now() + self.ttl;
+        let payload = format!(
+            "{}.{}.{}",
+            hex::encode(nonce),
+            expires_at.timestamp(),
+            self.difficulty
+        );
+        let signature = hex::encode(self.sign(&payload));
+        Challenge {
+            challenge: format!("{}.{}", payload, signature),
+            difficulty: self.difficulty,
+            expires_at,
+        }
+    }
+
+    #[tracing::instrument(name = "Verifying proof of work", skip(self, pool))]
+    pub async fn verify(
+        &self,
+        pool: &PgPool,
+        challenge: &str,
+        solution: &str,
+    ) -> Result<(), ProofOfWorkError> {
+        // 从右侧拆出签名，剩下的部分就是被签名的内容
+        let (payload, signature) = challenge
+            .rsplit_once('.')
+            .ok_or(ProofOfWorkError::Malformed)?;
+        let signature = hex::decode(signature).map_err(|_| ProofOfWorkError::Malformed)?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        // verify_slice以常量时间比较签名，避免时序攻击
+        mac.verify_slice(&signature)
+            .map_err(|_| ProofOfWorkError::InvalidSignature)?;
+
+        let parts: Vec<&str> = payload.split('.').collect();
+        let [nonce, expires_at, difficulty] = parts[..] else {
+            return Err(ProofOfWorkError::Malformed);
+        };
+        let expires_at = expires_at
+            .parse::<i64>()
+            .ok()
+            .and_then(|t| DateTime::from_timestamp(t, 0))
+            .ok_or(ProofOfWorkError::Malformed)?;
+        let difficulty: u32 = difficulty
+            .parse()
+            .map_err(|_| ProofOfWorkError::Malformed)?;
+        if expires_at < Utc::now() {
+            return Err(ProofOfWorkError::Expired);
+        }
+
+        let hash = Sha256::digest(format!("{}{}", challenge, solution).as_bytes());
+        if leading_zero_bits(&hash) < difficulty {
+            return Err(ProofOfWorkError::WrongSolution);
+        }
+
+        mark_challenge_as_used(pool, nonce, expires_at).await
+    }
+
+    fn sign(&self, payload: &str) -> Vec<u8> {
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+}
+
+pub fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut bits = 0;
+    for byte in hash {
+        bits += byte.leading_zeros();
+        if *byte != 0 {
+            break;
+        }
+    }
+    bits
+}
+
+// 每个挑战只能使用一次。利用主键冲突判断是否已被使用，这在多个应用实例之间同样有效
+async fn mark_challenge_as_used(
+    pool: &PgPool,
+    nonce: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), ProofOfWorkError> {
+    // 顺便清理已过期的记录：过期的挑战无论如何都会被拒绝，不需要再保留
+    sqlx::query!("DELETE FROM used_proof_of_work_challenges WHERE expires_at < now()")
+        .execute(pool)
+        .await
+        .map_err(ProofOfWorkError::Database)?;
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO used_proof_of_work_challenges (nonce, expires_at)
+        VALUES ($1, $2)
+        ON CONFLICT (nonce) DO NOTHING
+        "#,
+        nonce,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map_err(ProofOfWorkError::Database)?;
+    if inserted.rows_affected() == 0 {
+        return Err(ProofOfWorkError::AlreadyUsed);
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum ProofOfWorkError {
+    Missing,
+    Malformed,
+    InvalidSignature,
+    Expired,
+    WrongSolution,
+    AlreadyUsed,
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ProofOfWorkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            Self::Missing => "A proof of work is required.",
+            Self::Malformed => "The proof of work challenge is malformed.",
+            Self::InvalidSignature => "The proof of work challenge was not issued by us.",
+            Self::Expired => "The proof of work challenge has expired.",
+            Self::WrongSolution => "The proof of work solution is wrong.",
+            Self::AlreadyUsed => "The proof of work challenge has already been used.",
+            Self::Database(_) => "Failed to verify the proof of work.",
+        };
+        f.write_str(message)
+    }
+}
+
+impl std::error::Error for ProofOfWorkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseError for ProofOfWorkError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
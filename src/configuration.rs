@@ -1,3 +1,5 @@
+use std::collections::HashMap;
+
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 
@@ -6,12 +8,23 @@ use serde::Deserialize;
 pub struct Settings {
     pub database: DatabaseSettings,
     pub application: ApplicationSettings,
+    pub email_client: EmailClientSettings,
+    pub rate_limit: RateLimitSettings,
+    pub bot_protection: BotProtectionSettings,
+    pub attachments: AttachmentSettings,
+    pub delivery: DeliverySettings,
+    pub email_events: EmailEventSettings,
+    pub tracking: TrackingSettings,
 }
 
 #[derive(Deserialize)]
 pub struct ApplicationSettings {
     pub port: u16,
     pub host: String,
+    // 应用对外可访问的地址，用于生成邮件中的链接
+    pub base_url: String,
+    // 订阅者没有保存语言、或者没有对应的翻译时使用的语言，如en、zh-CN
+    pub default_locale: String,
 }
 
 #[derive(Deserialize)]
@@ -51,6 +64,196 @@ impl DatabaseSettings {
     }
 }
 
+#[derive(Deserialize)]
+pub struct EmailClientSettings {
+    // 没有设置时使用邮件服务商的REST API
+    #[serde(default)]
+    pub transport: EmailTransportKind,
+    pub base_url: String,
+    pub sender_email: String,
+    // 邮件服务商的API令牌设置为隐私数据
+    pub authorization_token: Secret<String>,
+    pub timeout_milliseconds: u64,
+    // smtp传输的连接信息
+    pub smtp: Option<SmtpSettings>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum EmailTransportKind {
+    // Postmark风格的REST API，附件以JSON的形式上传
+    #[default]
+    Http,
+    // 通过SMTP服务器发送完整的MIME邮件，附件和内嵌图片在邮件中
+    Smtp,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    pub port: u16,
+    pub username: String,
+    pub password: Secret<String>,
+    pub tls: SmtpTls,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    // 连接后通过STARTTLS升级（通常是587端口）
+    Starttls,
+    // 一开始就使用TLS连接（通常是465端口）
+    Tls,
+    // 不加密，只用于本地的测试服务器
+    None,
+}
+
+impl EmailClientSettings {
+    pub fn timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.timeout_milliseconds)
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RateLimitSettings {
+    pub store: RateLimitStoreKind,
+    // 受信任的反向代理（CIDR格式），只有来自这些地址的X-Forwarded-For头才会被采信
+    #[serde(default)]
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+    pub per_ip: TokenBucketSettings,
+    pub per_email: TokenBucketSettings,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct TokenBucketSettings {
+    // 桶的容量，即允许的突发请求数
+    pub capacity: u32,
+    // 每隔多少秒补充一个令牌
+    pub refill_interval_seconds: u64,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum RateLimitStoreKind {
+    // 单实例部署使用
+    Memory,
+    // 多实例部署时，所有实例共享Postgres中的令牌桶
+    Postgres,
+}
+
+#[derive(Deserialize)]
+pub struct BotProtectionSettings {
+    // 是否启用订阅表单中的蜜罐字段
+    pub honeypot: bool,
+    pub proof_of_work: ProofOfWorkSettings,
+}
+
+#[derive(Deserialize)]
+pub struct ProofOfWorkSettings {
+    pub enabled: bool,
+    // 要求哈希值前导0的比特数，每增加1，客户端的平均计算量翻倍
+    pub difficulty: u8,
+    // 挑战的有效期
+    pub ttl_seconds: u64,
+    // 用于签名挑战的密钥
+    pub secret: Secret<String>,
+}
+
+#[derive(Deserialize)]
+pub struct AttachmentSettings {
+    // 单个附件的大小上限
+    pub max_size_bytes: u64,
+    // 一期邮件所有附件的大小上限
+    pub max_total_bytes: u64,
+    // 允许的MIME类型，如application/pdf。以/*结尾时匹配该大类下的所有类型，如image/*
+    pub allowed_content_types: Vec<String>,
+    pub store: BlobStoreSettings,
+}
+
+#[derive(Deserialize)]
+pub struct BlobStoreSettings {
+    pub backend: BlobStoreKind,
+    // filesystem后端保存文件的目录
+    pub directory: String,
+    // s3后端的连接信息
+    pub s3: Option<S3Settings>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "lowercase")]
+pub enum BlobStoreKind {
+    // 单实例部署使用本地目录
+    Filesystem,
+    // 任何兼容S3 API的对象存储（AWS S3、MinIO等）
+    S3,
+}
+
+#[derive(Deserialize)]
+pub struct DeliverySettings {
+    // 没有到期的一期或待发送的邮件时，两次检查之间的间隔
+    pub poll_interval_milliseconds: u64,
+    // 一封邮件发送失败后最多重试的次数，超过后放弃
+    pub max_retries: u16,
+    // 第一次重试前等待的时间，之后每次翻倍
+    pub retry_base_seconds: u64,
+    pub send_rate: SendRateSettings,
+}
+
+impl DeliverySettings {
+    pub fn poll_interval(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.poll_interval_milliseconds)
+    }
+
+    pub fn retry_base(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.retry_base_seconds)
+    }
+}
+
+// 投递任务的发送速率限制，所有实例共享
+#[derive(Deserialize, Clone, Debug)]
+pub struct SendRateSettings {
+    // 所有邮件共享的速率，省略时不限制
+    pub global: Option<SendRate>,
+    // 按传输（邮件服务商API的主机名，如api.postmarkapp.com）限制的速率，与全局速率同时生效
+    #[serde(default)]
+    pub per_transport: HashMap<String, SendRate>,
+}
+
+#[derive(Deserialize, Clone, Copy, Debug)]
+pub struct SendRate {
+    pub messages_per_second: f64,
+    // 空闲之后允许连续发出的邮件数，即令牌桶的容量
+    pub burst: u32,
+}
+
+#[derive(Deserialize)]
+pub struct EmailEventSettings {
+    // 邮件服务商签名webhook请求使用的共享密钥
+    pub webhook_secret: Secret<String>,
+    // 签名中的时间戳与当前时间的最大偏差，超过时视为重放的请求
+    pub tolerance_seconds: u64,
+    // 连续软退信达到该次数后屏蔽该地址
+    pub soft_bounce_limit: u32,
+}
+
+#[derive(Deserialize)]
+pub struct TrackingSettings {
+    // 总开关：关闭时不再给邮件加入跟踪像素和跟踪链接，已经发出的链接仍然可以跳转，但不再记录
+    pub enabled: bool,
+    // 签名跟踪链接的密钥，只有我们签名过的地址才会被跳转
+    pub signing_key: Secret<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct S3Settings {
+    // 如https://s3.eu-west-1.amazonaws.com，对象的地址为{endpoint}/{bucket}/{key}
+    pub endpoint: String,
+    pub bucket: String,
+    pub region: String,
+    pub access_key_id: String,
+    pub secret_access_key: Secret<String>,
+}
+
 pub enum Environment {
     Local,
     Production,
@@ -100,6 +303,15 @@ pub fn get_configuration() -> Result<Settings, config::ConfigError> {
         .add_source(config::File::from(
             configuration_directory.join(&environment_filename),
         ))
+        // 环境变量优先级最高，如APP_DATABASE__DATABASE_NAME=other会覆盖database.database_name
+        // 这样命令行工具（如import_subscribers）不需要修改配置文件就可以指向另一个数据库
+        .add_source(
+            config::Environment::with_prefix("APP")
+                .prefix_separator("_")
+                .separator("__")
+                // 将"8000"、"true"等解析为数字和布尔值，否则无法反序列化为u16等类型
+                .try_parsing(true),
+        )
         .build()?;
     // 尝试将读到的配置转化为Settings类型
     setting.try_deserialize::<Settings>()
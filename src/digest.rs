@@ -0,0 +1,109 @@
+// 每周摘要：把队列中的新闻邮件合并成一封发给选择每周摘要的订阅者
+// 由send_digests命令执行，例如每周一次的cron任务
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::i18n::{Catalogue, I18n};
+use crate::repository::digests::{self, DigestItem};
+use crate::repository::suppressions;
+use crate::routes::{escape_html, preferences_link};
+
+#[derive(Serialize, Debug, Default)]
+pub struct DigestReport {
+    // 发出的摘要数量
+    pub sent: usize,
+    // 发送失败的订阅者，其队列中的内容保留到下一次
+    pub failed: usize,
+    // 地址被屏蔽的订阅者，其队列中的内容被丢弃
+    pub suppressed: usize,
+}
+
+#[tracing::instrument(name = "Send weekly digests", skip(pool, email_client, i18n))]
+pub async fn send_digests(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &str,
+    i18n: &I18n,
+) -> Result<DigestReport, sqlx::Error> {
+    let mut report = DigestReport::default();
+    let recipients = digests::recipients(pool, Utc::now()).await?;
+    // 内容放入队列之后才被屏蔽的地址也不再发送
+    let emails: Vec<&str> = recipients.iter().map(|r| r.email.as_str()).collect();
+    let suppressed = suppressions::find_suppressed(pool, &emails).await?;
+    for recipient in recipients {
+        if suppressed.contains(&suppressions::email_hash(&recipient.email)) {
+            let mut transaction = pool.begin().await?;
+            digests::take(&mut transaction, recipient.subscriber_id).await?;
+            transaction.commit().await?;
+            report.suppressed += 1;
+            continue;
+        }
+        let email = match SubscriberEmail::parse(recipient.email) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!("Skipping a digest. The stored email is invalid: {}", e);
+                report.failed += 1;
+                continue;
+            }
+        };
+        // 发送成功后才提交，删除队列中的内容
+        let mut transaction = pool.begin().await?;
+        let items = digests::take(&mut transaction, recipient.subscriber_id).await?;
+        if items.is_empty() {
+            continue;
+        }
+        let link = preferences_link(base_url, &recipient.preferences_token);
+        let catalogue = i18n.for_locale(recipient.locale.as_deref());
+        let (subject, html, text) = compose(&items, &link, catalogue);
+        match email_client
+            .send_email(email.as_ref(), &subject, &html, &text)
+            .await
+        {
+            Ok(()) => {
+                transaction.commit().await?;
+                report.sent += 1;
+            }
+            Err(e) => {
+                tracing::error!("Failed to send digest to {}: {:?}", email, e);
+                report.failed += 1;
+            }
+        }
+    }
+    Ok(report)
+}
+
+// 摘要邮件的标题、HTML和纯文本内容
+fn compose(
+    items: &[DigestItem],
+    preferences_link: &str,
+    catalogue: &Catalogue,
+) -> (String, String, String) {
+    let subject = match items.len() {
+        1 => catalogue.t("digest.subject_one").to_string(),
+        n => catalogue.format("digest.subject", &[("count", &n.to_string())]),
+    };
+    let manage = catalogue.t("email.manage_preferences");
+    let mut html = String::new();
+    let mut text = String::new();
+    for item in items {
+        html.push_str(&format!(
+            "<h2>{}</h2>\n{}\n<hr>\n",
+            escape_html(&item.title),
+            item.html_content
+        ));
+        text.push_str(&format!(
+            "{}\n\n{}\n\n---\n\n",
+            item.title, item.text_content
+        ));
+    }
+    html.push_str(&format!(
+        "<p><a href=\"{}\">{}</a></p>",
+        preferences_link,
+        escape_html(manage)
+    ));
+    text.push_str(&format!("{}: {}", manage, preferences_link));
+    (subject, html, text)
+}
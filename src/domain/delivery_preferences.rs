@@ -0,0 +1,64 @@
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+
+// 最多可以暂停多久
+const MAX_PAUSE_DAYS: u64 = 366;
+
+// 订阅者希望多久收到一次邮件，对应subscriptions表的delivery_frequency列
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryFrequency {
+    // 发布后立即发送
+    Immediate,
+    // 放入队列，每周汇总成一封邮件
+    WeeklyDigest,
+}
+
+impl DeliveryFrequency {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "immediate" => Ok(Self::Immediate),
+            "weekly_digest" => Ok(Self::WeeklyDigest),
+            other => Err(format!("{} is not a valid delivery frequency.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Immediate => "immediate",
+            Self::WeeklyDigest => "weekly_digest",
+        }
+    }
+}
+
+impl std::fmt::Display for DeliveryFrequency {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// 暂停接收邮件直到某一天（UTC零点）
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PausedUntil(DateTime<Utc>);
+
+impl PausedUntil {
+    // 接受YYYY-MM-DD格式的日期，必须晚于today且不超过一年
+    pub fn parse(s: &str, today: NaiveDate) -> Result<Self, String> {
+        let date = NaiveDate::parse_from_str(s.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("{} is not a valid date (YYYY-MM-DD).", s))?;
+        if date <= today {
+            return Err("The pause must end after today.".into());
+        }
+        if date > today + Days::new(MAX_PAUSE_DAYS) {
+            return Err(format!(
+                "Delivery cannot be paused for more than {} days.",
+                MAX_PAUSE_DAYS
+            ));
+        }
+        Ok(Self(date.and_time(Default::default()).and_utc()))
+    }
+
+    pub fn into_inner(self) -> DateTime<Utc> {
+        self.0
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+// 邮件服务商通过webhook报告的事件，不同服务商的格式统一转换成这几种，对应email_events表的kind列
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmailEventKind {
+    Delivered,
+    // 地址不存在等永久性的退信，立即屏蔽该地址
+    BouncedHard,
+    // 邮箱已满等暂时性的退信，连续多次之后才屏蔽
+    BouncedSoft,
+    // 收件人把邮件标记为垃圾邮件，立即屏蔽该地址
+    Complaint,
+    Opened,
+    Clicked,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Delivered => "delivered",
+            Self::BouncedHard => "bounced-hard",
+            Self::BouncedSoft => "bounced-soft",
+            Self::Complaint => "complaint",
+            Self::Opened => "opened",
+            Self::Clicked => "clicked",
+        }
+    }
+}
+
+impl std::fmt::Display for EmailEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,29 @@
+mod delivery_preferences;
+mod email_event_kind;
+mod issue_status;
+mod list_slug;
+mod new_subscriber;
+mod segment;
+mod subscriber_attributes;
+mod subscriber_email;
+mod subscriber_name;
+mod subscriber_tag;
+mod subscriber_update;
+mod subscription_status;
+mod subscription_token;
+mod template_name;
+
+pub use delivery_preferences::*;
+pub use email_event_kind::*;
+pub use issue_status::*;
+pub use list_slug::*;
+pub use new_subscriber::*;
+pub use segment::*;
+pub use subscriber_attributes::*;
+pub use subscriber_email::*;
+pub use subscriber_name::*;
+pub use subscriber_tag::*;
+pub use subscriber_update::*;
+pub use subscription_status::*;
+pub use subscription_token::*;
+pub use template_name::*;
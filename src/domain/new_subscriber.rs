@@ -0,0 +1,40 @@
+use std::collections::BTreeMap;
+
+use crate::domain::{SubscriberEmail, SubscriberName};
+
+pub struct NewSubscriber {
+    pub email: SubscriberEmail,
+    pub name: SubscriberName,
+}
+
+// 按字段归类的校验错误，键为字段名，值为该字段的所有错误信息
+// 使用BTreeMap让字段按固定的顺序输出
+pub type FieldErrors = BTreeMap<&'static str, Vec<String>>;
+
+impl NewSubscriber {
+    // 校验所有字段，并收集每个字段的错误（而不是遇到第一个错误就返回），这样调用方可以一次性修正所有问题
+    pub fn parse(email: Option<String>, name: Option<String>) -> Result<Self, FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let email = parse_field(&mut errors, "email", email, SubscriberEmail::parse);
+        let name = parse_field(&mut errors, "name", name, SubscriberName::parse);
+        match (email, name) {
+            (Some(email), Some(name)) => Ok(Self { email, name }),
+            _ => Err(errors),
+        }
+    }
+}
+
+fn parse_field<T>(
+    errors: &mut FieldErrors,
+    field: &'static str,
+    value: Option<String>,
+    parse: impl FnOnce(String) -> Result<T, String>,
+) -> Option<T> {
+    let outcome = match value {
+        Some(value) => parse(value),
+        None => Err(format!("The {} field is required.", field)),
+    };
+    outcome
+        .map_err(|e| errors.entry(field).or_default().push(e))
+        .ok()
+}
@@ -0,0 +1,341 @@
+use chrono::NaiveDate;
+
+use crate::domain::{SubscriberTag, SUBSCRIBER_ATTRIBUTES};
+
+// 表达式的最大长度和最大嵌套层数（括号和NOT），以免过深的递归耗尽栈空间
+const MAX_LENGTH: usize = 2000;
+const MAX_DEPTH: usize = 32;
+
+// 选择部分订阅者的布尔表达式，如：
+//   tag:beta AND attr.locale = "de" AND subscribed_at > 2026-01-01
+// 条件：
+//   tag:<标签>              带有该标签，标签含空格时写作tag:"early access"
+//   attr.<属性> = "<值>"    某个属性等于（或用!=表示不等于）某个值，属性只能是订阅表单接受的那些
+//   subscribed_at <op> <日期> 按订阅日期（UTC）比较，op为< <= > >= = !=之一
+// 条件之间用AND、OR、NOT和括号组合（不区分大小写），NOT优先于AND，AND优先于OR
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Segment {
+    And(Vec<Segment>),
+    Or(Vec<Segment>),
+    Not(Box<Segment>),
+    Tag(SubscriberTag),
+    Attribute {
+        key: String,
+        op: Comparison,
+        value: String,
+    },
+    SubscribedAt {
+        op: Comparison,
+        date: NaiveDate,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+        }
+    }
+}
+
+impl Segment {
+    // 错误信息中的位置从1开始，按字符计算
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        if s.chars().count() > MAX_LENGTH {
+            return Err(format!(
+                "The segment must not be longer than {} characters.",
+                MAX_LENGTH
+            ));
+        }
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Err("The segment must not be empty.".into());
+        }
+        let mut parser = Parser {
+            source: s,
+            tokens,
+            position: 0,
+            depth: 0,
+        };
+        let segment = parser.parse_or()?;
+        match parser.peek() {
+            Some(token) => Err(parser.unexpected(token)),
+            None => Ok(segment),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    // 标识符、关键字、日期等不带引号的词
+    Word(String),
+    // 带双引号的字符串，支持\"和\\转义
+    Quoted(String),
+    Op(Comparison),
+    LeftParen,
+    RightParen,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    // 在表达式中的字节偏移
+    offset: usize,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | ':')
+}
+
+fn position(source: &str, offset: usize) -> usize {
+    source[..offset].chars().count() + 1
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::LeftParen,
+            ')' => TokenKind::RightParen,
+            '=' => TokenKind::Op(Comparison::Eq),
+            '!' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Comparison::Ne),
+            '<' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Comparison::Le),
+            '<' => TokenKind::Op(Comparison::Lt),
+            '>' if chars.next_if(|(_, c)| *c == '=').is_some() => TokenKind::Op(Comparison::Ge),
+            '>' => TokenKind::Op(Comparison::Gt),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, c @ ('"' | '\\'))) => value.push(c),
+                            _ => {
+                                return Err(format!(
+                                    "Invalid escape sequence in the string at position {}.",
+                                    position(s, offset)
+                                ))
+                            }
+                        },
+                        Some((_, c)) => value.push(c),
+                        None => {
+                            return Err(format!(
+                                "The string at position {} is not terminated.",
+                                position(s, offset)
+                            ))
+                        }
+                    }
+                }
+                TokenKind::Quoted(value)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some((_, c)) = chars.next_if(|(_, c)| is_word_char(*c)) {
+                    word.push(c);
+                }
+                TokenKind::Word(word)
+            }
+            c => {
+                return Err(format!(
+                    "Unexpected character {} at position {}.",
+                    c,
+                    position(s, offset)
+                ))
+            }
+        };
+        tokens.push(Token { kind, offset });
+    }
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    tokens: Vec<Token>,
+    position: usize,
+    depth: usize,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| "The segment ends unexpectedly.".to_string())?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn unexpected(&self, token: &Token) -> String {
+        let text = match &token.kind {
+            TokenKind::Word(word) => word.clone(),
+            TokenKind::Quoted(value) => format!("\"{}\"", value),
+            TokenKind::Op(op) => op.as_str().into(),
+            TokenKind::LeftParen => "(".into(),
+            TokenKind::RightParen => ")".into(),
+        };
+        format!(
+            "Unexpected {} at position {}.",
+            text,
+            position(self.source, token.offset)
+        )
+    }
+
+    // 下一个词是否为某个关键字，是则跳过它
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token {
+                kind: TokenKind::Word(word),
+                ..
+            }) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Segment, String> {
+        let mut operands = vec![self.parse_and()?];
+        while self.keyword("or") {
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            Segment::Or(operands)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Segment, String> {
+        let mut operands = vec![self.parse_unary()?];
+        while self.keyword("and") {
+            operands.push(self.parse_unary()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.pop().unwrap()
+        } else {
+            Segment::And(operands)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Segment, String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(format!(
+                "The segment must not be nested more than {} levels deep.",
+                MAX_DEPTH
+            ));
+        }
+        let segment = if self.keyword("not") {
+            Segment::Not(Box::new(self.parse_unary()?))
+        } else if matches!(self.peek(), Some(t) if t.kind == TokenKind::LeftParen) {
+            self.position += 1;
+            let segment = self.parse_or()?;
+            let token = self.next()?;
+            if token.kind != TokenKind::RightParen {
+                return Err(self.unexpected(&token));
+            }
+            segment
+        } else {
+            self.parse_condition()?
+        };
+        self.depth -= 1;
+        Ok(segment)
+    }
+
+    fn parse_condition(&mut self) -> Result<Segment, String> {
+        let token = self.next()?;
+        let TokenKind::Word(word) = &token.kind else {
+            return Err(self.unexpected(&token));
+        };
+        if let Some(tag) = word.strip_prefix("tag:") {
+            // tag:"early access"
+            let tag = if tag.is_empty() {
+                match self.next()?.kind {
+                    TokenKind::Quoted(tag) => tag,
+                    _ => return Err(self.unexpected(&token)),
+                }
+            } else {
+                tag.to_string()
+            };
+            return SubscriberTag::parse(tag).map(Segment::Tag);
+        }
+        if let Some(key) = word.strip_prefix("attr.") {
+            if !SUBSCRIBER_ATTRIBUTES.contains(&key) {
+                return Err(format!(
+                    "{} is not a known attribute. Use one of: {}.",
+                    key,
+                    SUBSCRIBER_ATTRIBUTES.join(", ")
+                ));
+            }
+            let op = self.operator()?;
+            if !matches!(op, Comparison::Eq | Comparison::Ne) {
+                return Err(format!(
+                    "Attributes can only be compared with = or !=, not {}.",
+                    op.as_str()
+                ));
+            }
+            let value = match self.next()? {
+                Token {
+                    kind: TokenKind::Quoted(value) | TokenKind::Word(value),
+                    ..
+                } => value,
+                token => return Err(self.unexpected(&token)),
+            };
+            return Ok(Segment::Attribute {
+                key: key.into(),
+                op,
+                value,
+            });
+        }
+        if word == "subscribed_at" {
+            let op = self.operator()?;
+            let date = match self.next()? {
+                Token {
+                    kind: TokenKind::Word(date) | TokenKind::Quoted(date),
+                    ..
+                } => NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| format!("{} is not a valid date (YYYY-MM-DD).", date))?,
+                token => return Err(self.unexpected(&token)),
+            };
+            return Ok(Segment::SubscribedAt { op, date });
+        }
+        Err(format!(
+            "Unknown condition {} at position {}. Use tag:, attr. or subscribed_at.",
+            word,
+            position(self.source, token.offset)
+        ))
+    }
+
+    fn operator(&mut self) -> Result<Comparison, String> {
+        match self.next()? {
+            Token {
+                kind: TokenKind::Op(op),
+                ..
+            } => Ok(op),
+            token => Err(self.unexpected(&token)),
+        }
+    }
+}
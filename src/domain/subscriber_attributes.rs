@@ -0,0 +1,88 @@
+use serde_json::{Map, Value};
+
+use crate::domain::FieldErrors;
+
+// 订阅表单上除email和name之外可以提交的字段，保存在subscriptions表的attributes列中
+// 注：只接受这里列出的字段，以免任意数据被写入数据库
+pub const SUBSCRIBER_ATTRIBUTES: [&str; 2] = ["source", "locale"];
+
+const MAX_LENGTH: usize = 100;
+
+// 经过校验的订阅者属性，如{"source": "conference-page", "locale": "de"}
+#[derive(Debug, Clone, Default)]
+pub struct SubscriberAttributes(Map<String, Value>);
+
+impl SubscriberAttributes {
+    // 按字段收集错误，与NewSubscriber::parse一样。未填写或为空的字段被忽略
+    pub fn parse(source: Option<String>, locale: Option<String>) -> Result<Self, FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let mut attributes = Map::new();
+        for (field, value, parse) in [
+            (
+                "source",
+                source,
+                parse_source as fn(&str) -> Result<String, String>,
+            ),
+            ("locale", locale, parse_locale),
+        ] {
+            let Some(value) = value.as_deref().map(str::trim).filter(|v| !v.is_empty()) else {
+                continue;
+            };
+            match parse(value) {
+                Ok(value) => {
+                    attributes.insert(field.into(), value.into());
+                }
+                Err(e) => errors.entry(field).or_default().push(e),
+            }
+        }
+        if errors.is_empty() {
+            Ok(Self(attributes))
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn as_json(&self) -> Value {
+        Value::Object(self.0.clone())
+    }
+
+    pub fn locale(&self) -> Option<&str> {
+        self.0.get("locale").and_then(Value::as_str)
+    }
+}
+
+fn parse_source(s: &str) -> Result<String, String> {
+    if s.chars().count() > MAX_LENGTH {
+        Err(format!(
+            "The source must not be longer than {} characters.",
+            MAX_LENGTH
+        ))
+    } else if s.chars().any(char::is_control) {
+        Err("The source must not contain control characters.".into())
+    } else {
+        Ok(s.to_string())
+    }
+}
+
+// 语言标签，如en、zh-CN、de-AT。语言部分转为小写，地区部分转为大写
+fn parse_locale(s: &str) -> Result<String, String> {
+    let invalid = || format!("{} is not a valid locale, such as en or zh-CN.", s);
+    let mut parts = s.split(['-', '_']);
+    let language = parts.next().unwrap_or_default();
+    if !(2..=3).contains(&language.len()) || !language.chars().all(|c| c.is_ascii_alphabetic()) {
+        return Err(invalid());
+    }
+    let mut locale = language.to_ascii_lowercase();
+    for part in parts {
+        if !(2..=8).contains(&part.len()) || !part.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(invalid());
+        }
+        locale.push('-');
+        if part.len() == 2 {
+            locale.push_str(&part.to_ascii_uppercase());
+        } else {
+            locale.push_str(part);
+        }
+    }
+    Ok(locale)
+}
@@ -0,0 +1,41 @@
+use unicode_segmentation::UnicodeSegmentation;
+
+// 经过校验的订阅者名字。字段私有，只能通过parse构造，因此拿到SubscriberName就意味着它一定是合法的
+#[derive(Debug, Clone)]
+pub struct SubscriberName(String);
+
+// 名字中禁止出现的字符（可能被用于注入攻击）
+const FORBIDDEN_CHARACTERS: [char; 9] = ['/', '(', ')', '"', '<', '>', '\\', '{', '}'];
+// 名字的最大长度（按字素簇计算，即用户感知到的字符数）
+const MAX_LENGTH: usize = 256;
+
+impl SubscriberName {
+    pub fn parse(s: String) -> Result<SubscriberName, String> {
+        let is_empty_or_whitespace = s.trim().is_empty();
+        // 一个字素簇可能由多个Unicode码点组成（如带音调的字母），按字素簇计数才符合用户的直觉
+        let is_too_long = s.graphemes(true).count() > MAX_LENGTH;
+        let contains_forbidden_characters = s.chars().any(|c| FORBIDDEN_CHARACTERS.contains(&c));
+
+        if is_empty_or_whitespace {
+            Err("The name must not be empty.".into())
+        } else if is_too_long {
+            Err(format!(
+                "The name must not be longer than {} characters.",
+                MAX_LENGTH
+            ))
+        } else if contains_forbidden_characters {
+            Err(format!(
+                "The name must not contain any of the characters {:?}.",
+                FORBIDDEN_CHARACTERS
+            ))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for SubscriberName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
@@ -0,0 +1,46 @@
+use serde::Deserialize;
+
+// 管理员给订阅者打的标签，如conference-2026。统一转为小写，避免同一个标签出现多种写法
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct SubscriberTag(String);
+
+const MAX_LENGTH: usize = 64;
+
+impl SubscriberTag {
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let s = s.trim().to_lowercase();
+        if s.is_empty() {
+            Err("A tag must not be empty.".into())
+        } else if s.chars().count() > MAX_LENGTH {
+            Err(format!(
+                "A tag must not be longer than {} characters.",
+                MAX_LENGTH
+            ))
+        } else if s.chars().any(|c| c.is_control() || c == ',') {
+            Err(format!("{} is not a valid tag.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriberTag {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriberTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
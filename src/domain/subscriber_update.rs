@@ -0,0 +1,28 @@
+use crate::domain::{FieldErrors, SubscriberEmail, SubscriberName};
+
+// 对已有订阅者的部分修改，未提供的字段保持不变
+// 与NewSubscriber使用相同的校验规则
+pub struct SubscriberUpdate {
+    pub email: Option<SubscriberEmail>,
+    pub name: Option<SubscriberName>,
+}
+
+impl SubscriberUpdate {
+    pub fn parse(email: Option<String>, name: Option<String>) -> Result<Self, FieldErrors> {
+        let mut errors = FieldErrors::new();
+        let email = email
+            .map(SubscriberEmail::parse)
+            .transpose()
+            .map_err(|e| errors.entry("email").or_default().push(e))
+            .ok();
+        let name = name
+            .map(SubscriberName::parse)
+            .transpose()
+            .map_err(|e| errors.entry("name").or_default().push(e))
+            .ok();
+        match (email, name) {
+            (Some(email), Some(name)) => Ok(Self { email, name }),
+            _ => Err(errors),
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+// 订阅者的状态，对应subscriptions表的status列
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SubscriptionStatus {
+    PendingConfirmation,
+    Confirmed,
+    // 订阅者主动退订，或由管理员代为退订
+    Unsubscribed,
+    // 由管理员封禁（如滥用、投诉），不能再被确认
+    Blocked,
+}
+
+// 管理员可以手动执行的状态变更
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusTransition {
+    Confirm,
+    Unsubscribe,
+    Block,
+}
+
+impl SubscriptionStatus {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "pending_confirmation" => Ok(Self::PendingConfirmation),
+            "confirmed" => Ok(Self::Confirmed),
+            "unsubscribed" => Ok(Self::Unsubscribed),
+            "blocked" => Ok(Self::Blocked),
+            other => Err(format!("{} is not a valid subscription status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::PendingConfirmation => "pending_confirmation",
+            Self::Confirmed => "confirmed",
+            Self::Unsubscribed => "unsubscribed",
+            Self::Blocked => "blocked",
+        }
+    }
+
+    // 计算执行状态变更后的新状态。不允许的变更（如确认一个已被封禁的订阅者）返回错误信息
+    pub fn apply(self, transition: StatusTransition) -> Result<Self, String> {
+        use StatusTransition::*;
+        match (self, transition) {
+            (Self::PendingConfirmation, Confirm) => Ok(Self::Confirmed),
+            (Self::PendingConfirmation | Self::Confirmed, Unsubscribe) => Ok(Self::Unsubscribed),
+            (Self::PendingConfirmation | Self::Confirmed | Self::Unsubscribed, Block) => {
+                Ok(Self::Blocked)
+            }
+            (status, transition) => Err(format!(
+                "A {} subscriber cannot be changed with {:?}.",
+                status, transition
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for SubscriptionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
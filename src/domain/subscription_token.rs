@@ -0,0 +1,11 @@
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+// 生成一个25个字符的随机令牌（大小写字母和数字），可能的组合约为10^45个，足以防止被猜中
+// 用于确认、退订、偏好设置和个人数据请求等邮件链接
+pub fn generate_subscription_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(25)
+        .collect()
+}
@@ -0,0 +1,38 @@
+// 经过校验的模板名称，会出现在URL和{{> 名称}}片段引用中，因此只允许小写字母、数字和连字符
+#[derive(Debug, Clone)]
+pub struct TemplateName(String);
+
+const MAX_LENGTH: usize = 64;
+
+impl TemplateName {
+    pub fn parse(s: String) -> Result<TemplateName, String> {
+        let is_valid_character = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-';
+        if s.is_empty() {
+            Err("The template name must not be empty.".into())
+        } else if s.len() > MAX_LENGTH {
+            Err(format!(
+                "The template name must not be longer than {} characters.",
+                MAX_LENGTH
+            ))
+        } else if !s.chars().all(is_valid_character) || s.starts_with('-') || s.ends_with('-') {
+            Err(format!(
+                "{} is not a valid template name. Use lowercase letters, digits and hyphens.",
+                s
+            ))
+        } else {
+            Ok(Self(s))
+        }
+    }
+}
+
+impl AsRef<str> for TemplateName {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for TemplateName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -0,0 +1,273 @@
+use base64::Engine;
+use lettre::address::Envelope;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use serde::Serialize;
+
+use crate::configuration::{EmailClientSettings, EmailTransportKind, SmtpSettings, SmtpTls};
+use crate::email_message::{Attachment, EmailMessage, Mailbox};
+use crate::request_id::with_current_request_id;
+
+// 发送邮件的客户端：邮件服务商的REST API（Postmark风格）或者SMTP服务器
+pub struct EmailClient {
+    sender: String,
+    transport: Transport,
+}
+
+enum Transport {
+    Http {
+        // 复用同一个reqwest::Client，使其内部的连接池在多次请求之间共享
+        http_client: Client,
+        base_url: String,
+        // API令牌属于隐私数据，不能出现在日志中
+        authorization_token: Secret<String>,
+    },
+    // 发送to_mime()生成的完整邮件，附件和内嵌图片（cid:）都在邮件中
+    Smtp {
+        // 连接池在多次发送之间共享
+        mailer: AsyncSmtpTransport<Tokio1Executor>,
+        host: String,
+    },
+}
+
+#[derive(Debug)]
+pub enum EmailClientError {
+    Http(reqwest::Error),
+    Smtp(lettre::transport::smtp::Error),
+    // 发件人或收件人不是合法的地址
+    InvalidAddress(String),
+}
+
+impl std::fmt::Display for EmailClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) => write!(f, "Failed to call the email API: {}", e),
+            Self::Smtp(e) => write!(f, "Failed to send the email over SMTP: {}", e),
+            Self::InvalidAddress(e) => write!(f, "Invalid email address: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EmailClientError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Http(e) => Some(e),
+            Self::Smtp(e) => Some(e),
+            Self::InvalidAddress(_) => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    fn from(e: reqwest::Error) -> Self {
+        Self::Http(e)
+    }
+}
+
+impl From<lettre::transport::smtp::Error> for EmailClientError {
+    fn from(e: lettre::transport::smtp::Error) -> Self {
+        Self::Smtp(e)
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    // 完整的邮箱（"显示名" <地址>），非ASCII的显示名使用RFC 2047编码
+    from: String,
+    to: String,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<SendEmailAttachment<'a>>,
+}
+
+// 附件的内容使用base64编码。内嵌图片的ContentID形如cid:logo.png
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailAttachment<'a> {
+    name: &'a str,
+    content: String,
+    content_type: &'a str,
+    #[serde(rename = "ContentID", skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+impl<'a> From<&'a Attachment> for SendEmailAttachment<'a> {
+    fn from(attachment: &'a Attachment) -> Self {
+        Self {
+            name: &attachment.filename,
+            content: base64::engine::general_purpose::STANDARD.encode(&attachment.content),
+            content_type: &attachment.content_type,
+            content_id: attachment
+                .content_id
+                .as_ref()
+                .map(|content_id| format!("cid:{}", content_id)),
+        }
+    }
+}
+
+impl EmailClient {
+    pub fn new(
+        base_url: String,
+        sender: String,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            sender,
+            transport: Transport::Http {
+                http_client,
+                base_url,
+                authorization_token,
+            },
+        }
+    }
+
+    pub fn smtp(settings: &SmtpSettings, sender: String, timeout: std::time::Duration) -> Self {
+        let builder = match settings.tls {
+            SmtpTls::Starttls => {
+                AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&settings.host)
+            }
+            SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&settings.host),
+            SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+                &settings.host,
+            )),
+        }
+        .expect("Failed to set up TLS for the SMTP transport");
+        let mut builder = builder.port(settings.port).timeout(Some(timeout));
+        // 本地的测试服务器通常不需要登录
+        if !settings.username.is_empty() {
+            builder = builder.credentials(Credentials::new(
+                settings.username.clone(),
+                settings.password.expose_secret().clone(),
+            ));
+        }
+        Self {
+            sender,
+            transport: Transport::Smtp {
+                mailer: builder.build(),
+                host: settings.host.clone(),
+            },
+        }
+    }
+
+    // 按email_client.transport选择传输
+    pub fn from_settings(settings: &EmailClientSettings) -> Result<Self, String> {
+        match settings.transport {
+            EmailTransportKind::Http => Ok(Self::new(
+                settings.base_url.clone(),
+                settings.sender_email.clone(),
+                settings.authorization_token.clone(),
+                settings.timeout(),
+            )),
+            EmailTransportKind::Smtp => Ok(Self::smtp(
+                settings
+                    .smtp
+                    .as_ref()
+                    .ok_or("The smtp transport requires the email_client.smtp settings.")?,
+                settings.sender_email.clone(),
+                settings.timeout(),
+            )),
+        }
+    }
+
+    // 发件人地址
+    pub fn sender(&self) -> &str {
+        &self.sender
+    }
+
+    // 传输的名称，即邮件服务商API或SMTP服务器的主机名，用于按传输限制发送速率
+    pub fn transport(&self) -> String {
+        match &self.transport {
+            Transport::Http { base_url, .. } => reqwest::Url::parse(base_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_else(|| base_url.clone()),
+            Transport::Smtp { host, .. } => host.clone(),
+        }
+    }
+
+    // 发送一封邮件，HTML在发送前被净化并内联CSS，text_content为空时从HTML生成纯文本版本
+    pub async fn send_email(
+        &self,
+        recipient: &str,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailClientError> {
+        let message = EmailMessage::builder(Mailbox::new(&self.sender), Mailbox::new(recipient))
+            .subject(subject)
+            .html(html_content)
+            .text(text_content)
+            .build();
+        self.send(&message).await
+    }
+
+    // 发送一封构建好的邮件（可以带有附件）
+    pub async fn send(&self, message: &EmailMessage) -> Result<(), EmailClientError> {
+        match &self.transport {
+            Transport::Http {
+                http_client,
+                base_url,
+                authorization_token,
+            } => send_http(http_client, base_url, authorization_token, message).await,
+            Transport::Smtp { mailer, .. } => send_smtp(mailer, message).await,
+        }
+    }
+}
+
+// 如果是在处理某个请求的过程中调用，会通过X-Request-Id头把请求id转发给邮件服务商，
+// 这样就可以将服务商那边的日志与我们的日志关联起来
+async fn send_http(
+    http_client: &Client,
+    base_url: &str,
+    authorization_token: &Secret<String>,
+    message: &EmailMessage,
+) -> Result<(), EmailClientError> {
+    let url = format!("{}/email", base_url);
+    let request_body = SendEmailRequest {
+        from: message.from().to_string(),
+        to: message.to().to_string(),
+        subject: message.subject(),
+        html_body: message.html(),
+        text_body: message.text(),
+        attachments: message.attachments().iter().map(Into::into).collect(),
+    };
+    let builder = http_client
+        .post(&url)
+        .header(
+            "X-Postmark-Server-Token",
+            authorization_token.expose_secret(),
+        )
+        .json(&request_body);
+    with_current_request_id(builder)
+        .send()
+        .await?
+        // 服务商返回4xx/5xx时也视为失败
+        .error_for_status()?;
+    Ok(())
+}
+
+// 信封中的地址与邮件头中的From和To一致
+async fn send_smtp(
+    mailer: &AsyncSmtpTransport<Tokio1Executor>,
+    message: &EmailMessage,
+) -> Result<(), EmailClientError> {
+    let address = |mailbox: &Mailbox| {
+        mailbox
+            .address
+            .parse()
+            .map_err(|e| EmailClientError::InvalidAddress(format!("{}: {}", mailbox.address, e)))
+    };
+    let envelope = Envelope::new(Some(address(message.from())?), vec![address(message.to())?])
+        .map_err(|e| EmailClientError::InvalidAddress(e.to_string()))?;
+    mailer
+        .send_raw(&envelope, message.to_mime().as_bytes())
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,211 @@
+// 处理邮件服务商通过webhook报告的事件：
+// 1. 校验签名：服务商用共享密钥对`{时间戳}.{请求体}`计算HMAC-SHA256，放在X-Webhook-Signature头中
+// 2. 把服务商的格式（Postmark的RecordType等）转换成统一的EmailEvent
+// 3. 硬退信和投诉立即屏蔽该地址，软退信连续达到上限后屏蔽，成功投递后重新计数
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+
+use crate::configuration::EmailEventSettings;
+use crate::domain::{EmailEventKind, SubscriberEmail};
+use crate::repository::email_events::{self, EmailEvent};
+use crate::repository::suppressions;
+
+pub struct EmailEvents {
+    secret: Secret<String>,
+    tolerance_seconds: i64,
+    soft_bounce_limit: i32,
+}
+
+// 一次webhook请求的处理结果
+#[derive(Serialize, Debug, Default)]
+pub struct EventReport {
+    pub accepted: usize,
+    // 服务商重试时重复发送的事件
+    pub duplicates: usize,
+    // 不关心的事件类型，或者缺少收件人地址
+    pub ignored: usize,
+    // 因为这次的事件被屏蔽的地址数
+    pub suppressed: usize,
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    Missing,
+    Malformed,
+    Expired,
+    Invalid,
+}
+
+impl std::fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Missing => f.write_str("The request is not signed."),
+            Self::Malformed => f.write_str("The signature is malformed."),
+            Self::Expired => f.write_str("The signature timestamp is too old or in the future."),
+            Self::Invalid => f.write_str("The signature does not match."),
+        }
+    }
+}
+
+impl EmailEvents {
+    pub fn new(settings: &EmailEventSettings) -> Self {
+        Self {
+            secret: settings.webhook_secret.clone(),
+            tolerance_seconds: settings.tolerance_seconds as i64,
+            soft_bounce_limit: settings.soft_bounce_limit as i32,
+        }
+    }
+
+    // timestamp为Unix时间戳（秒），signature形如sha256={十六进制}
+    pub fn verify(
+        &self,
+        timestamp: Option<&str>,
+        signature: Option<&str>,
+        body: &[u8],
+    ) -> Result<(), SignatureError> {
+        let (Some(timestamp), Some(signature)) = (timestamp, signature) else {
+            return Err(SignatureError::Missing);
+        };
+        let signature = signature
+            .strip_prefix("sha256=")
+            .and_then(|hex| hex::decode(hex).ok())
+            .ok_or(SignatureError::Malformed)?;
+        let signed_at = timestamp
+            .parse::<i64>()
+            .map_err(|_| SignatureError::Malformed)?;
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        // verify_slice以常量时间比较签名，避免时序攻击
+        mac.verify_slice(&signature)
+            .map_err(|_| SignatureError::Invalid)?;
+        // 签名正确之后再检查时间戳，限制截获的请求可以被重放的时间
+        if (Utc::now().timestamp() - signed_at).abs() > self.tolerance_seconds {
+            return Err(SignatureError::Expired);
+        }
+        Ok(())
+    }
+
+    // 每个事件在自己的事务中处理，服务商重试整个请求时已经处理过的事件会被当作重复事件跳过
+    #[tracing::instrument(name = "Record email events", skip(self, pool, events))]
+    pub async fn record(
+        &self,
+        pool: &PgPool,
+        events: Vec<Option<EmailEvent>>,
+    ) -> Result<EventReport, sqlx::Error> {
+        let mut report = EventReport::default();
+        for event in events {
+            let Some(event) = event else {
+                report.ignored += 1;
+                continue;
+            };
+            let mut transaction = pool.begin().await?;
+            if !email_events::insert(&mut transaction, &event).await? {
+                report.duplicates += 1;
+                continue;
+            }
+            report.accepted += 1;
+            let suppress = match event.kind {
+                EmailEventKind::BouncedHard => Some("hard-bounce"),
+                EmailEventKind::Complaint => Some("complaint"),
+                EmailEventKind::BouncedSoft => {
+                    let count =
+                        email_events::count_soft_bounce(&mut transaction, &event.email).await?;
+                    (count >= self.soft_bounce_limit).then_some("soft-bounce")
+                }
+                EmailEventKind::Delivered => {
+                    email_events::reset_soft_bounces(&mut transaction, &event.email).await?;
+                    None
+                }
+                EmailEventKind::Opened | EmailEventKind::Clicked => None,
+            };
+            if let Some(reason) = suppress {
+                tracing::info!("Suppressing an address after a {} event", event.kind);
+                suppressions::insert(&mut transaction, &event.email, reason, None, None).await?;
+                report.suppressed += 1;
+            }
+            transaction.commit().await?;
+        }
+        Ok(report)
+    }
+}
+
+// Postmark格式的事件：https://postmarkapp.com/developer/webhooks/webhooks-overview
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ProviderEvent {
+    record_type: String,
+    // 退信的类型，如HardBounce、SoftBounce
+    #[serde(rename = "Type")]
+    bounce_type: Option<String>,
+    #[serde(rename = "ID")]
+    id: Option<i64>,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    // 退信和投诉中的收件人
+    email: Option<String>,
+    // 投递、打开和点击中的收件人
+    recipient: Option<String>,
+    description: Option<String>,
+    original_link: Option<String>,
+    bounced_at: Option<DateTime<Utc>>,
+    delivered_at: Option<DateTime<Utc>>,
+    received_at: Option<DateTime<Utc>>,
+}
+
+// 服务商可以一次发送一个事件或者一批事件
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Payload {
+    One(ProviderEvent),
+    Many(Vec<ProviderEvent>),
+}
+
+// 把请求体转换成统一格式的事件，不关心的事件为None
+pub fn parse_events(body: &[u8]) -> Result<Vec<Option<EmailEvent>>, serde_json::Error> {
+    let events = match serde_json::from_slice(body)? {
+        Payload::One(event) => vec![event],
+        Payload::Many(events) => events,
+    };
+    Ok(events.into_iter().map(normalise).collect())
+}
+
+fn normalise(event: ProviderEvent) -> Option<EmailEvent> {
+    let kind = match (event.record_type.as_str(), event.bounce_type.as_deref()) {
+        ("Delivery", _) => EmailEventKind::Delivered,
+        ("SpamComplaint", _) | ("Bounce", Some("SpamComplaint")) => EmailEventKind::Complaint,
+        ("Bounce", Some("HardBounce" | "BadEmailAddress" | "ManuallyDeactivated")) => {
+            EmailEventKind::BouncedHard
+        }
+        ("Bounce", Some("SoftBounce" | "Transient" | "DnsError")) => EmailEventKind::BouncedSoft,
+        ("Open", _) => EmailEventKind::Opened,
+        ("Click", _) => EmailEventKind::Clicked,
+        // 自动回复、订阅变更等
+        _ => return None,
+    };
+    let email = SubscriberEmail::parse(event.email.or(event.recipient)?).ok()?;
+    // 不同类型的事件可能使用相同的id
+    let provider_event_id = event.id.map(|id| format!("{}:{}", event.record_type, id));
+    let detail = match kind {
+        EmailEventKind::Clicked => event.original_link,
+        _ => event.description,
+    };
+    Some(EmailEvent {
+        kind,
+        email: email.as_ref().to_string(),
+        provider_event_id,
+        message_id: event.message_id,
+        detail,
+        occurred_at: event
+            .bounced_at
+            .or(event.delivered_at)
+            .or(event.received_at)
+            .unwrap_or_else(Utc::now),
+    })
+}
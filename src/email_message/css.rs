@@ -0,0 +1,200 @@
+// 把<style>中的规则写进元素的style属性。很多邮件客户端会丢掉<style>，只认内联样式
+// 只支持简单选择器：标签、.类、#id以及它们的组合（如p.note），带有组合符或伪类的规则被忽略
+use super::html::{tokenize, write_token, Attribute, Token};
+
+struct Rule {
+    selector: Selector,
+    declarations: String,
+    // 出现的顺序，特异性相同时后出现的规则优先
+    order: usize,
+}
+
+#[derive(Default)]
+struct Selector {
+    tag: Option<String>,
+    id: Option<String>,
+    classes: Vec<String>,
+}
+
+impl Selector {
+    fn parse(selector: &str) -> Option<Self> {
+        let selector = selector.trim();
+        if selector.is_empty()
+            || selector.contains(|c: char| c.is_whitespace() || ">+~:[*".contains(c))
+        {
+            return None;
+        }
+        let mut result = Selector::default();
+        let mut rest = selector;
+        // 开头的标签名
+        let tag_end = rest.find(['.', '#']).unwrap_or(rest.len());
+        if tag_end > 0 {
+            result.tag = Some(rest[..tag_end].to_ascii_lowercase());
+        }
+        rest = &rest[tag_end..];
+        while !rest.is_empty() {
+            let end = rest[1..].find(['.', '#']).map_or(rest.len(), |end| end + 1);
+            let name = &rest[1..end];
+            if name.is_empty() {
+                return None;
+            }
+            if rest.starts_with('#') {
+                result.id = Some(name.to_string());
+            } else {
+                result.classes.push(name.to_string());
+            }
+            rest = &rest[end..];
+        }
+        Some(result)
+    }
+
+    // (id数, 类数, 标签数)
+    fn specificity(&self) -> (usize, usize, usize) {
+        (
+            usize::from(self.id.is_some()),
+            self.classes.len(),
+            usize::from(self.tag.is_some()),
+        )
+    }
+
+    fn matches(&self, name: &str, attributes: &[Attribute]) -> bool {
+        let attribute = |wanted: &str| {
+            attributes
+                .iter()
+                .find(|a| a.name == wanted)
+                .and_then(|a| a.value.as_deref())
+        };
+        self.tag.as_deref().is_none_or(|tag| tag == name)
+            && self
+                .id
+                .as_deref()
+                .is_none_or(|id| attribute("id") == Some(id))
+            && self.classes.iter().all(|class| {
+                attribute("class")
+                    .is_some_and(|classes| classes.split_whitespace().any(|c| c == class))
+            })
+    }
+}
+
+// 解析样式表，跳过@media等@规则
+fn parse_rules(stylesheet: &str, rules: &mut Vec<Rule>) {
+    let stylesheet = strip_comments(stylesheet);
+    let mut rest = stylesheet.as_str();
+    while let Some(open) = rest.find('{') {
+        let prelude = rest[..open].trim();
+        if prelude.starts_with('@') {
+            // 跳过整个@规则，包括其中嵌套的块
+            let mut depth = 0;
+            let mut end = rest.len();
+            for (i, c) in rest[open..].char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            end = open + i + 1;
+                            break;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            rest = &rest[end..];
+            continue;
+        }
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        let declarations = rest[open + 1..close]
+            .split(';')
+            .map(str::trim)
+            .filter(|d| d.contains(':'))
+            .collect::<Vec<_>>()
+            .join("; ");
+        if !declarations.is_empty() {
+            for selector in prelude.split(',').filter_map(Selector::parse) {
+                rules.push(Rule {
+                    selector,
+                    declarations: declarations.clone(),
+                    order: rules.len(),
+                });
+            }
+        }
+        rest = &rest[close + 1..];
+    }
+}
+
+fn strip_comments(stylesheet: &str) -> String {
+    let mut out = String::with_capacity(stylesheet.len());
+    let mut rest = stylesheet;
+    while let Some(start) = rest.find("/*") {
+        out.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    out.push_str(rest);
+    out
+}
+
+// 内联<style>中的规则。元素原有的style属性放在最后，因此优先于样式表
+// 没有可以内联的规则时原样返回
+pub fn inline_css(html: &str) -> String {
+    let tokens = tokenize(html);
+    let mut rules = Vec::new();
+    let mut in_style = false;
+    for token in &tokens {
+        match token {
+            Token::StartTag { name, .. } if name == "style" => in_style = true,
+            Token::EndTag(name) if name == "style" => in_style = false,
+            Token::Text(text) if in_style => parse_rules(text, &mut rules),
+            _ => {}
+        }
+    }
+    if rules.is_empty() {
+        return html.to_string();
+    }
+    rules.sort_by_key(|rule| (rule.selector.specificity(), rule.order));
+
+    let mut out = String::with_capacity(html.len());
+    for token in tokens {
+        match token {
+            Token::StartTag {
+                name,
+                mut attributes,
+                self_closing,
+            } => {
+                let mut declarations: Vec<&str> = rules
+                    .iter()
+                    .filter(|rule| rule.selector.matches(&name, &attributes))
+                    .map(|rule| rule.declarations.as_str())
+                    .collect();
+                if !declarations.is_empty() {
+                    let existing = attributes.iter().position(|a| a.name == "style");
+                    let inline = existing
+                        .map(|i| attributes.remove(i))
+                        .and_then(|a| a.value)
+                        .unwrap_or_default();
+                    let inline = inline.trim().trim_end_matches(';');
+                    if !inline.is_empty() {
+                        declarations.push(inline);
+                    }
+                    attributes.push(Attribute {
+                        name: "style".into(),
+                        value: Some(declarations.join("; ")),
+                    });
+                }
+                write_token(
+                    &mut out,
+                    &Token::StartTag {
+                        name,
+                        attributes,
+                        self_closing,
+                    },
+                );
+            }
+            token => write_token(&mut out, &token),
+        }
+    }
+    out
+}
@@ -0,0 +1,750 @@
+// 一个只够处理邮件内容的HTML分词器，以及基于它的净化和纯文本转换
+// 注：不构建DOM，也不修正不配对的标签，邮件客户端对此足够宽容
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+
+// 允许保留的标签，其他标签被去掉但保留其中的文字
+const ALLOWED_TAGS: [&str; 50] = [
+    "a",
+    "abbr",
+    "b",
+    "big",
+    "blockquote",
+    "body",
+    "br",
+    "caption",
+    "center",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "font",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "hr",
+    "html",
+    "i",
+    "img",
+    "ins",
+    "li",
+    "ol",
+    "p",
+    "pre",
+    "s",
+    "small",
+    "span",
+    "strike",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+// 连同内容一起去掉的标签
+const DROPPED_WITH_CONTENT: [&str; 15] = [
+    "applet", "button", "embed", "frame", "frameset", "iframe", "math", "noscript", "object",
+    "script", "select", "style", "svg", "template", "textarea",
+];
+
+// 允许保留的属性，on*事件属性等其他属性都会被去掉
+const ALLOWED_ATTRIBUTES: [&str; 22] = [
+    "align",
+    "alt",
+    "bgcolor",
+    "border",
+    "cellpadding",
+    "cellspacing",
+    "class",
+    "color",
+    "colspan",
+    "dir",
+    "face",
+    "height",
+    "href",
+    "id",
+    "lang",
+    "rowspan",
+    "size",
+    "src",
+    "style",
+    "target",
+    "title",
+    "width",
+];
+
+// 没有结束标签的元素
+const VOID_ELEMENTS: [&str; 8] = ["br", "col", "hr", "img", "input", "link", "meta", "wbr"];
+
+// 内容是原始文本的元素，其中的<不是标签的开始
+const RAW_TEXT_ELEMENTS: [&str; 5] = ["script", "style", "textarea", "title", "xmp"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token<'a> {
+    // 原样保存，实体没有解码
+    Text(&'a str),
+    StartTag {
+        // 小写的标签名
+        name: String,
+        attributes: Vec<Attribute>,
+        self_closing: bool,
+    },
+    EndTag(String),
+    Comment(&'a str),
+    Doctype(&'a str),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Attribute {
+    // 小写的属性名
+    pub name: String,
+    // 原样保存，实体没有解码
+    pub value: Option<String>,
+}
+
+pub(crate) fn tokenize(html: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    while position < html.len() {
+        let rest = &html[position..];
+        let Some(start) = rest.find('<') else {
+            tokens.push(Token::Text(rest));
+            break;
+        };
+        if start > 0 {
+            tokens.push(Token::Text(&rest[..start]));
+        }
+        position += start;
+        let rest = &html[position..];
+        let next = rest[1..].chars().next();
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            let end = comment.find("-->").map_or(rest.len(), |end| end + 7);
+            tokens.push(Token::Comment(&rest[..end]));
+            position += end;
+        } else if matches!(next, Some('!') | Some('?')) {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let declaration = &rest[..end];
+            if declaration.to_ascii_lowercase().starts_with("<!doctype") {
+                tokens.push(Token::Doctype(declaration));
+            } else {
+                tokens.push(Token::Comment(declaration));
+            }
+            position += end;
+        } else if next == Some('/') && rest[2..].starts_with(|c: char| c.is_ascii_alphabetic()) {
+            let end = rest.find('>').map_or(rest.len(), |end| end + 1);
+            let name: String = rest[2..]
+                .chars()
+                .take_while(|c| c.is_ascii_alphanumeric())
+                .collect();
+            tokens.push(Token::EndTag(name.to_ascii_lowercase()));
+            position += end;
+        } else if next.is_some_and(|c| c.is_ascii_alphabetic()) {
+            let (token, length) = start_tag(rest);
+            position += length;
+            if let Token::StartTag {
+                name,
+                self_closing: false,
+                ..
+            } = &token
+            {
+                if RAW_TEXT_ELEMENTS.contains(&name.as_str()) {
+                    let name = name.clone();
+                    tokens.push(token);
+                    let rest = &html[position..];
+                    let end = rest
+                        .to_ascii_lowercase()
+                        .find(&format!("</{}", name))
+                        .unwrap_or(rest.len());
+                    if end > 0 {
+                        tokens.push(Token::Text(&rest[..end]));
+                    }
+                    position += end;
+                    continue;
+                }
+            }
+            tokens.push(token);
+        } else {
+            // 不是标签的<
+            tokens.push(Token::Text(&rest[..1]));
+            position += 1;
+        }
+    }
+    tokens
+}
+
+// 解析以<开头的开始标签，返回标签和它的长度
+fn start_tag(source: &str) -> (Token<'_>, usize) {
+    let bytes = source.as_bytes();
+    let mut i = 1;
+    while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'-') {
+        i += 1;
+    }
+    let name = source[1..i].to_ascii_lowercase();
+    let mut attributes = Vec::new();
+    let mut self_closing = false;
+    loop {
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i >= bytes.len() {
+            break;
+        }
+        match bytes[i] {
+            b'>' => {
+                i += 1;
+                break;
+            }
+            b'/' => {
+                i += 1;
+                if bytes.get(i) == Some(&b'>') {
+                    self_closing = true;
+                    i += 1;
+                    break;
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let name_start = i;
+        while i < bytes.len()
+            && !bytes[i].is_ascii_whitespace()
+            && !matches!(bytes[i], b'=' | b'>' | b'/')
+        {
+            i += 1;
+        }
+        let attribute_name = source[name_start..i].to_ascii_lowercase();
+        while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        let mut value = None;
+        if bytes.get(i) == Some(&b'=') {
+            i += 1;
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(&quote) if quote == b'"' || quote == b'\'' => {
+                    let end = source[i + 1..]
+                        .find(quote as char)
+                        .map_or(source.len(), |end| i + 1 + end);
+                    value = Some(source[i + 1..end].to_string());
+                    i = (end + 1).min(source.len());
+                }
+                _ => {
+                    let value_start = i;
+                    while i < bytes.len() && !bytes[i].is_ascii_whitespace() && bytes[i] != b'>' {
+                        i += 1;
+                    }
+                    value = Some(source[value_start..i].to_string());
+                }
+            }
+        }
+        if !attribute_name.is_empty() {
+            attributes.push(Attribute {
+                name: attribute_name,
+                value,
+            });
+        }
+    }
+    (
+        Token::StartTag {
+            name,
+            attributes,
+            self_closing,
+        },
+        i,
+    )
+}
+
+// 把标签重新写成HTML。文字中不属于标签的<被转义
+pub(crate) fn write_token(out: &mut String, token: &Token) {
+    match token {
+        Token::Text(text) => out.push_str(&text.replace('<', "&lt;")),
+        Token::Comment(comment) => out.push_str(comment),
+        Token::Doctype(doctype) => out.push_str(doctype),
+        Token::EndTag(name) => {
+            out.push_str("</");
+            out.push_str(name);
+            out.push('>');
+        }
+        Token::StartTag {
+            name,
+            attributes,
+            self_closing,
+        } => {
+            out.push('<');
+            out.push_str(name);
+            for attribute in attributes {
+                out.push(' ');
+                out.push_str(&attribute.name);
+                if let Some(value) = &attribute.value {
+                    out.push_str("=\"");
+                    out.push_str(&value.replace('"', "&quot;"));
+                    out.push('"');
+                }
+            }
+            out.push_str(if *self_closing { " />" } else { ">" });
+        }
+    }
+}
+
+// 去掉不允许的标签和属性：脚本、表单、嵌入的内容、事件属性以及javascript:等链接
+pub fn sanitize_html(html: &str) -> String {
+    let mut out = String::with_capacity(html.len());
+    // 正在被整个去掉的元素，以及同名元素的嵌套层数
+    let mut dropping: Option<(String, usize)> = None;
+    for token in tokenize(html) {
+        if let Some((dropped, depth)) = &mut dropping {
+            match &token {
+                Token::StartTag {
+                    name,
+                    self_closing: false,
+                    ..
+                } if name == dropped => *depth += 1,
+                Token::EndTag(name) if name == dropped => {
+                    *depth -= 1;
+                    if *depth == 0 {
+                        dropping = None;
+                    }
+                }
+                _ => {}
+            }
+            continue;
+        }
+        match token {
+            Token::Comment(_) => {}
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+            } => {
+                if DROPPED_WITH_CONTENT.contains(&name.as_str()) {
+                    if !self_closing && !VOID_ELEMENTS.contains(&name.as_str()) {
+                        dropping = Some((name, 1));
+                    }
+                } else if ALLOWED_TAGS.contains(&name.as_str()) {
+                    let attributes = attributes
+                        .into_iter()
+                        .filter(is_allowed_attribute)
+                        .collect();
+                    write_token(
+                        &mut out,
+                        &Token::StartTag {
+                            name,
+                            attributes,
+                            self_closing,
+                        },
+                    );
+                }
+            }
+            Token::EndTag(name) => {
+                if ALLOWED_TAGS.contains(&name.as_str()) {
+                    write_token(&mut out, &Token::EndTag(name));
+                }
+            }
+            token => write_token(&mut out, &token),
+        }
+    }
+    out
+}
+
+fn is_allowed_attribute(attribute: &Attribute) -> bool {
+    if !ALLOWED_ATTRIBUTES.contains(&attribute.name.as_str()) {
+        return false;
+    }
+    // 先解码实体、去掉空白和控制字符，避免java&#x09;script:之类的写法绕过检查
+    let value: String = decode_attribute(attribute.value.as_deref().unwrap_or_default())
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    match attribute.name.as_str() {
+        "href" | "src" => is_safe_url(&value),
+        "style" => ![
+            "expression(",
+            "javascript:",
+            "behavior:",
+            "-moz-binding",
+            "\\",
+        ]
+        .iter()
+        .any(|pattern| value.contains(pattern)),
+        _ => true,
+    }
+}
+
+// 只允许http、https、mailto、tel、cid协议，以及相对地址和页内锚点
+fn is_safe_url(url: &str) -> bool {
+    match url.split_once(':') {
+        Some((scheme, _)) if !scheme.contains(['/', '?', '#']) => {
+            ["http", "https", "mailto", "tel", "cid"].contains(&scheme)
+        }
+        _ => true,
+    }
+}
+
+// 把每个<a>和<area>的href属性的值（解码实体之后）交给rewrite，返回Some时替换为新的地址
+// 与净化使用同一个分词器，data-href之类的其他属性以及注释和文本中的href=都不会被改动
+pub fn rewrite_links(html: &str, rewrite: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(html.len());
+    for mut token in tokenize(html) {
+        if let Token::StartTag {
+            name, attributes, ..
+        } = &mut token
+        {
+            if name == "a" || name == "area" {
+                for attribute in attributes.iter_mut().filter(|a| a.name == "href") {
+                    let url = decode_attribute(attribute.value.as_deref().unwrap_or_default())
+                        .trim()
+                        .to_string();
+                    if let Some(url) = rewrite(&url) {
+                        attribute.value = Some(url.replace('&', "&amp;"));
+                    }
+                }
+            }
+        }
+        write_token(&mut out, &token);
+    }
+    out
+}
+
+// 生成纯文本版本：块级元素之间换行，链接写成“文字 (地址)”，图片使用alt文字
+pub fn html_to_text(html: &str) -> String {
+    let mut text = TextWriter::default();
+    // 正在跳过的元素（head、脚本、样式等）的嵌套层数
+    let mut skipping = 0usize;
+    let mut preformatted = 0usize;
+    // 链接的地址及其文字在输出中的起始位置
+    let mut links: Vec<(Option<String>, usize)> = Vec::new();
+    // 有序列表的序号，无序列表为None
+    let mut lists: Vec<Option<usize>> = Vec::new();
+    for token in tokenize(html) {
+        let (name, attributes, is_start) = match &token {
+            Token::Text(raw) => {
+                if skipping == 0 {
+                    text.push_text(&decode_entities(raw), preformatted > 0);
+                }
+                continue;
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                self_closing,
+            } => {
+                if skipping > 0 || is_skipped(name) {
+                    if !self_closing && !VOID_ELEMENTS.contains(&name.as_str()) {
+                        skipping += usize::from(is_skipped(name));
+                    }
+                    continue;
+                }
+                (name.as_str(), attributes.as_slice(), true)
+            }
+            Token::EndTag(name) => {
+                if is_skipped(name) {
+                    skipping = skipping.saturating_sub(1);
+                    continue;
+                }
+                if skipping > 0 {
+                    continue;
+                }
+                (name.as_str(), &[][..], false)
+            }
+            Token::Comment(_) | Token::Doctype(_) => continue,
+        };
+        let attribute = |wanted: &str| {
+            attributes
+                .iter()
+                .find(|a| a.name == wanted)
+                .and_then(|a| a.value.as_deref())
+                .map(|v| decode_attribute(v).into_owned())
+        };
+        match (name, is_start) {
+            ("br", true) => text.newline(),
+            ("p" | "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "blockquote" | "table", _) => {
+                text.blank_line()
+            }
+            ("ul", true) => {
+                text.blank_line();
+                lists.push(None);
+            }
+            ("ol", true) => {
+                text.blank_line();
+                lists.push(Some(0));
+            }
+            ("ul" | "ol", false) => {
+                lists.pop();
+                text.blank_line();
+            }
+            ("li", true) => {
+                text.line_break();
+                let indent = "  ".repeat(lists.len().saturating_sub(1));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        text.push_raw(&format!("{}{}. ", indent, number));
+                    }
+                    _ => text.push_raw(&format!("{}- ", indent)),
+                }
+            }
+            ("pre", true) => {
+                text.blank_line();
+                preformatted += 1;
+            }
+            ("pre", false) => {
+                preformatted = preformatted.saturating_sub(1);
+                text.blank_line();
+            }
+            ("hr", true) => {
+                text.line_break();
+                text.push_raw("----------");
+                text.line_break();
+            }
+            ("div" | "tr" | "li" | "dt" | "dd" | "center" | "caption", _) => text.line_break(),
+            ("td" | "th", true) => text.push_text(" ", false),
+            ("img", true) => {
+                if let Some(alt) = attribute("alt") {
+                    text.push_text(&alt, false);
+                }
+            }
+            ("a", true) => links.push((attribute("href"), text.len())),
+            ("a", false) => {
+                if let Some((Some(href), start)) = links.pop() {
+                    text.append_link(&href, start);
+                }
+            }
+            _ => {}
+        }
+    }
+    text.finish()
+}
+
+// 其中的文字不出现在纯文本版本中
+fn is_skipped(name: &str) -> bool {
+    name == "head" || name == "title" || DROPPED_WITH_CONTENT.contains(&name)
+}
+
+#[derive(Default)]
+struct TextWriter {
+    out: String,
+}
+
+impl TextWriter {
+    fn len(&self) -> usize {
+        self.out.len()
+    }
+
+    // 普通文字中的连续空白合并为一个空格，行首不留空格
+    fn push_text(&mut self, text: &str, preformatted: bool) {
+        let text = text.replace('\u{a0}', " ");
+        if preformatted {
+            self.out.push_str(&text);
+            return;
+        }
+        for (i, word) in text.split_whitespace().enumerate() {
+            let needs_space = i > 0 || text.starts_with(char::is_whitespace);
+            if needs_space && !self.out.is_empty() && !self.out.ends_with([' ', '\n']) {
+                self.out.push(' ');
+            }
+            self.out.push_str(word);
+        }
+        if text.ends_with(char::is_whitespace)
+            && !text.trim().is_empty()
+            && !self.out.ends_with([' ', '\n'])
+        {
+            self.out.push(' ');
+        }
+    }
+
+    fn push_raw(&mut self, text: &str) {
+        self.out.push_str(text);
+    }
+
+    fn trim_trailing_spaces(&mut self) {
+        let trimmed = self.out.trim_end_matches([' ', '\t']).len();
+        self.out.truncate(trimmed);
+    }
+
+    fn newline(&mut self) {
+        self.trim_trailing_spaces();
+        self.out.push('\n');
+    }
+
+    // 另起一行（已经在行首时不再换行）
+    fn line_break(&mut self) {
+        self.trim_trailing_spaces();
+        if !self.out.is_empty() && !self.out.ends_with('\n') {
+            self.out.push('\n');
+        }
+    }
+
+    // 空一行，作为段落之间的间隔
+    fn blank_line(&mut self) {
+        self.line_break();
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+    }
+
+    // 链接的文字后面附上地址，文字本身就是地址时不再重复
+    fn append_link(&mut self, href: &str, start: usize) {
+        if href.starts_with('#') || href.is_empty() {
+            return;
+        }
+        let label = self.out[start..].trim();
+        let shown = href.strip_prefix("mailto:").unwrap_or(href);
+        if label == href || label == shown {
+            return;
+        }
+        if label.is_empty() {
+            self.push_text(shown, false);
+        } else {
+            self.push_text(&format!(" ({})", shown), false);
+        }
+    }
+
+    // 去掉每行末尾的空白，最多保留一个空行
+    fn finish(self) -> String {
+        let mut result = String::with_capacity(self.out.len());
+        let mut blank_lines = 0;
+        for line in self.out.trim().lines() {
+            let line = line.trim_end();
+            if line.is_empty() {
+                blank_lines += 1;
+                if blank_lines > 1 {
+                    continue;
+                }
+            } else {
+                blank_lines = 0;
+            }
+            result.push_str(line);
+            result.push('\n');
+        }
+        result.trim_end().to_string()
+    }
+}
+
+// 按HTML5规范解码字符引用：任意长度的数字引用（可以没有分号）以及全部命名引用
+// 检查链接协议前必须像浏览器一样解码，否则javascript&#58;、&colon;之类的写法可以绕过检查
+pub(crate) fn decode_entities(s: &str) -> Cow<'_, str> {
+    decode(s, false)
+}
+
+// 属性值中没有分号的命名引用后面紧跟字母、数字或=时不解码（例如链接中的?a=1&copy=2）
+pub(crate) fn decode_attribute(s: &str) -> Cow<'_, str> {
+    decode(s, true)
+}
+
+fn decode(s: &str, in_attribute: bool) -> Cow<'_, str> {
+    if !s.contains('&') {
+        return Cow::Borrowed(s);
+    }
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        let decoded = match rest.strip_prefix('#') {
+            Some(number) => decode_numeric(number).map(|(c, length)| {
+                out.push(c);
+                length + 1
+            }),
+            None => decode_named(rest, in_attribute).map(|(characters, length)| {
+                out.push_str(characters);
+                length
+            }),
+        };
+        match decoded {
+            Some(length) => rest = &rest[length..],
+            None => out.push('&'),
+        }
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+// 返回解码后的字符以及引用的长度（不包括&#）
+fn decode_numeric(s: &str) -> Option<(char, usize)> {
+    let (radix, prefix) = match s.strip_prefix(['x', 'X']) {
+        Some(_) => (16, 1),
+        None => (10, 0),
+    };
+    let digits = s[prefix..]
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(s.len() - prefix);
+    if digits == 0 {
+        return None;
+    }
+    // 很长的数字只需要知道超出了范围
+    let code = s[prefix..prefix + digits]
+        .chars()
+        .filter_map(|c| c.to_digit(radix))
+        .fold(0u32, |code, digit| {
+            code.saturating_mul(radix).saturating_add(digit)
+        });
+    let length = prefix + digits + usize::from(s[prefix + digits..].starts_with(';'));
+    Some((numeric_character(code), length))
+}
+
+// 规范规定的替换：0、代理项和超出范围的值替换为U+FFFD，0x80-0x9F按windows-1252解释
+fn numeric_character(code: u32) -> char {
+    const WINDOWS_1252: [u32; 32] = [
+        0x20AC, 0x81, 0x201A, 0x0192, 0x201E, 0x2026, 0x2020, 0x2021, 0x02C6, 0x2030, 0x0160,
+        0x2039, 0x0152, 0x8D, 0x017D, 0x8F, 0x90, 0x2018, 0x2019, 0x201C, 0x201D, 0x2022, 0x2013,
+        0x2014, 0x02DC, 0x2122, 0x0161, 0x203A, 0x0153, 0x9D, 0x017E, 0x0178,
+    ];
+    let code = match code {
+        0x80..=0x9F => WINDOWS_1252[(code - 0x80) as usize],
+        code => code,
+    };
+    match code {
+        0 => char::REPLACEMENT_CHARACTER,
+        code => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+    }
+}
+
+// 最长的命名引用（不包括&）
+const MAX_ENTITY_LENGTH: usize = 32;
+
+// 所有命名引用，包括历史上可以不写分号的那些（例如&amp和&copy）
+static NAMED_ENTITIES: Lazy<HashMap<&'static str, &'static str>> = Lazy::new(|| {
+    entities::ENTITIES
+        .iter()
+        .map(|entity| (&entity.entity[1..], entity.characters))
+        .collect()
+});
+
+// 取最长的匹配，返回解码后的字符以及引用的长度（不包括&）
+fn decode_named(s: &str, in_attribute: bool) -> Option<(&'static str, usize)> {
+    let (characters, length) = (1..=s.len().min(MAX_ENTITY_LENGTH))
+        .rev()
+        .filter(|length| s.is_char_boundary(*length))
+        .find_map(|length| {
+            NAMED_ENTITIES
+                .get(&s[..length])
+                .map(|characters| (*characters, length))
+        })?;
+    let terminated = s[..length].ends_with(';');
+    let next = s[length..].chars().next();
+    if !terminated && in_attribute && next.is_some_and(|c| c.is_ascii_alphanumeric() || c == '=') {
+        return None;
+    }
+    Some((characters, length))
+}
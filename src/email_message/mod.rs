@@ -0,0 +1,368 @@
+// 发出的每封邮件都经过这里：HTML被净化并内联CSS，没有纯文本版本时从HTML生成，
+// 需要完整的邮件时（如通过SMTP发送、导出.eml）序列化为MIME消息
+mod css;
+mod html;
+
+use std::sync::Arc;
+
+use base64::Engine;
+use chrono::Utc;
+use uuid::Uuid;
+
+pub use css::inline_css;
+pub use html::{html_to_text, rewrite_links, sanitize_html};
+
+// 邮件头中的一个地址，可以带有显示名
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mailbox {
+    pub name: Option<String>,
+    pub address: String,
+}
+
+impl Mailbox {
+    pub fn new(address: impl Into<String>) -> Self {
+        Self {
+            name: None,
+            address: address.into(),
+        }
+    }
+
+    pub fn with_name(name: impl Into<String>, address: impl Into<String>) -> Self {
+        Self {
+            name: Some(name.into()),
+            address: address.into(),
+        }
+    }
+
+    // 邮件头中的形式：非ASCII的显示名使用RFC 2047编码，含有特殊字符的显示名加引号
+    fn to_header(&self) -> String {
+        let name = self.name.as_deref().map(strip_line_breaks);
+        match name.as_deref().map(str::trim) {
+            None | Some("") => self.address.clone(),
+            Some(name) if !name.is_ascii() => {
+                format!("{} <{}>", encode_word(name), self.address)
+            }
+            Some(name) if name.contains(|c: char| "()<>[]:;@\\,.\"".contains(c)) => format!(
+                "\"{}\" <{}>",
+                name.replace('\\', "\\\\").replace('"', "\\\""),
+                self.address
+            ),
+            Some(name) => format!("{} <{}>", name, self.address),
+        }
+    }
+
+    fn domain(&self) -> &str {
+        self.address
+            .rsplit_once('@')
+            .map_or("localhost", |(_, domain)| domain)
+    }
+}
+
+// 不折行的邮件头形式，用于邮件服务商API的From和To字段
+// 相邻的encoded-word之间的空白在解码时被忽略，所以把折行换成空格不改变显示名
+impl std::fmt::Display for Mailbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.to_header().replace("\r\n ", " "))
+    }
+}
+
+// 邮件的附件。有content_id时是内嵌图片，HTML中通过<img src="cid:{content_id}">引用
+#[derive(Debug, Clone)]
+pub struct Attachment {
+    pub filename: String,
+    pub content_type: String,
+    // 同一期邮件的所有收件人共享附件的内容
+    pub content: Arc<[u8]>,
+    pub content_id: Option<String>,
+}
+
+impl Attachment {
+    fn is_inline(&self) -> bool {
+        self.content_id.is_some()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EmailMessage {
+    from: Mailbox,
+    to: Mailbox,
+    subject: String,
+    html: String,
+    text: String,
+    attachments: Vec<Attachment>,
+}
+
+pub struct EmailMessageBuilder {
+    from: Mailbox,
+    to: Mailbox,
+    subject: String,
+    html: String,
+    text: Option<String>,
+    attachments: Vec<Attachment>,
+}
+
+impl EmailMessage {
+    pub fn builder(from: Mailbox, to: Mailbox) -> EmailMessageBuilder {
+        EmailMessageBuilder {
+            from,
+            to,
+            subject: String::new(),
+            html: String::new(),
+            text: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    pub fn from(&self) -> &Mailbox {
+        &self.from
+    }
+
+    pub fn to(&self) -> &Mailbox {
+        &self.to
+    }
+
+    pub fn subject(&self) -> &str {
+        &self.subject
+    }
+
+    // 净化并内联CSS之后的HTML
+    pub fn html(&self) -> &str {
+        &self.html
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn attachments(&self) -> &[Attachment] {
+        &self.attachments
+    }
+
+    // 完整的MIME消息，所有的行以CRLF结尾。结构为：
+    // multipart/mixed（有普通附件时）
+    //   multipart/related（有内嵌图片时）
+    //     multipart/alternative：纯文本部分在前、HTML部分在后（客户端优先显示最后一个能显示的部分）
+    //     内嵌图片
+    //   普通附件
+    // 正文使用quoted-printable编码，附件使用base64编码
+    pub fn to_mime(&self) -> String {
+        let mut mime = String::new();
+        for (name, value) in [
+            ("From", self.from.to_header()),
+            ("To", self.to.to_header()),
+            ("Subject", encode_header(&self.subject)),
+            ("Date", Utc::now().to_rfc2822()),
+            (
+                "Message-ID",
+                format!("<{}@{}>", Uuid::new_v4(), self.from.domain()),
+            ),
+            ("MIME-Version", "1.0".into()),
+        ] {
+            mime.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        let (inline, attached): (Vec<_>, Vec<_>) =
+            self.attachments.iter().partition(|a| a.is_inline());
+        let mut body = multipart(
+            "alternative",
+            [("text/plain", &self.text), ("text/html", &self.html)]
+                .iter()
+                .map(|(content_type, body)| {
+                    format!(
+                        "Content-Type: {}; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n{}",
+                        content_type,
+                        quoted_printable(body)
+                    )
+                })
+                .collect(),
+        );
+        if !inline.is_empty() {
+            let mut parts = vec![body];
+            parts.extend(inline.into_iter().map(attachment_part));
+            body = multipart("related", parts);
+        }
+        if !attached.is_empty() {
+            let mut parts = vec![body];
+            parts.extend(attached.into_iter().map(attachment_part));
+            body = multipart("mixed", parts);
+        }
+        mime.push_str(&body);
+        mime
+    }
+}
+
+// 由多个部分（各自带有头）组成的multipart实体，包括它自己的Content-Type头
+fn multipart(subtype: &str, parts: Vec<String>) -> String {
+    let boundary = format!("=_{}", Uuid::new_v4().simple());
+    let mut entity = format!(
+        "Content-Type: multipart/{};\r\n boundary=\"{}\"\r\n",
+        subtype, boundary
+    );
+    for part in parts {
+        entity.push_str(&format!("\r\n--{}\r\n{}", boundary, part));
+    }
+    entity.push_str(&format!("\r\n--{}--\r\n", boundary));
+    entity
+}
+
+fn attachment_part(attachment: &Attachment) -> String {
+    let mut part = format!(
+        "Content-Type: {};\r\n {}\r\nContent-Transfer-Encoding: base64\r\n",
+        attachment.content_type,
+        encode_parameter("name", &attachment.filename)
+    );
+    let disposition = if attachment.is_inline() {
+        "inline"
+    } else {
+        "attachment"
+    };
+    if let Some(content_id) = &attachment.content_id {
+        part.push_str(&format!("Content-ID: <{}>\r\n", content_id));
+    }
+    part.push_str(&format!(
+        "Content-Disposition: {};\r\n {}\r\n",
+        disposition,
+        encode_parameter("filename", &attachment.filename)
+    ));
+    part.push_str("\r\n");
+    let encoded = base64::engine::general_purpose::STANDARD.encode(&attachment.content);
+    // base64的每行不超过76个字符
+    for (i, line) in encoded.as_bytes().chunks(76).enumerate() {
+        if i > 0 {
+            part.push_str("\r\n");
+        }
+        part.push_str(std::str::from_utf8(line).expect("base64 is ASCII"));
+    }
+    part
+}
+
+// 文件名参数：ASCII的文件名加引号，非ASCII的文件名使用RFC 2231的name*=UTF-8''<百分号编码>
+// 注：RFC 2047禁止在引号中使用encoded-word，遵守规范的客户端会把它原样显示出来
+fn encode_parameter(name: &str, value: &str) -> String {
+    let value = strip_line_breaks(value);
+    if value.is_ascii() {
+        return format!(
+            "{}=\"{}\"",
+            name,
+            value.replace('\\', "\\\\").replace('"', "\\\"")
+        );
+    }
+    let mut encoded = String::with_capacity(value.len() * 3);
+    for byte in value.bytes() {
+        // RFC 2231中的attribute-char可以不编码
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    format!("{}*=UTF-8''{}", name, encoded)
+}
+
+impl EmailMessageBuilder {
+    pub fn subject(mut self, subject: &str) -> Self {
+        self.subject = strip_line_breaks(subject);
+        self
+    }
+
+    pub fn html(mut self, html: &str) -> Self {
+        self.html = html.to_string();
+        self
+    }
+
+    // 空的纯文本版本等同于没有设置，发送时从HTML生成
+    pub fn text(mut self, text: &str) -> Self {
+        self.text = Some(text.to_string()).filter(|t| !t.trim().is_empty());
+        self
+    }
+
+    pub fn attachments(mut self, attachments: Vec<Attachment>) -> Self {
+        self.attachments = attachments;
+        self
+    }
+
+    pub fn build(self) -> EmailMessage {
+        let html = sanitize_html(&inline_css(&self.html));
+        let text = self.text.unwrap_or_else(|| html_to_text(&html));
+        EmailMessage {
+            from: self.from,
+            to: self.to,
+            subject: self.subject,
+            html,
+            text,
+            attachments: self.attachments,
+        }
+    }
+}
+
+// 邮件头中不能出现换行，否则可以注入其他的头
+fn strip_line_breaks(value: &str) -> String {
+    value
+        .split(['\r', '\n'])
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn encode_header(value: &str) -> String {
+    if value.is_ascii() {
+        value.to_string()
+    } else {
+        encode_word(value)
+    }
+}
+
+// RFC 2047的encoded-word，多个之间折行。不会在UTF-8字符的中间切开
+fn encode_word(value: &str) -> String {
+    // 39个字节编码后是52个字符，加上"=?UTF-8?B?"和"?="共64个字符，为头的名称留出空间，
+    // 使每行不超过76个字符
+    const MAX_BYTES: usize = 39;
+    let mut words = Vec::new();
+    let mut chunk = String::new();
+    for c in value.chars() {
+        if chunk.len() + c.len_utf8() > MAX_BYTES {
+            words.push(std::mem::take(&mut chunk));
+        }
+        chunk.push(c);
+    }
+    words.push(chunk);
+    words
+        .iter()
+        .map(|word| {
+            format!(
+                "=?UTF-8?B?{}?=",
+                base64::engine::general_purpose::STANDARD.encode(word)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\r\n ")
+}
+
+// quoted-printable编码（RFC 2045），每行不超过76个字符，超出时使用软换行
+fn quoted_printable(body: &str) -> String {
+    let mut out = String::with_capacity(body.len() * 2);
+    let normalized = body.replace("\r\n", "\n");
+    for line in normalized.split('\n') {
+        let mut length = 0;
+        let bytes = line.as_bytes();
+        for (i, &byte) in bytes.iter().enumerate() {
+            let is_last = i + 1 == bytes.len();
+            let encoded = match byte {
+                // 行尾的空白必须编码
+                b' ' | b'\t' if is_last => format!("={:02X}", byte),
+                b'=' => "=3D".to_string(),
+                b' ' | b'\t' | 33..=126 => (byte as char).to_string(),
+                _ => format!("={:02X}", byte),
+            };
+            if length + encoded.len() > 75 {
+                out.push_str("=\r\n");
+                length = 0;
+            }
+            length += encoded.len();
+            out.push_str(&encoded);
+        }
+        out.push_str("\r\n");
+    }
+    // 去掉最后多出的换行
+    out.truncate(out.len() - 2);
+    out
+}
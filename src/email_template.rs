@@ -0,0 +1,418 @@
+// 邮件模板：用Handlebars渲染管理员保存在templates表中的模板
+// HTML版本中的变量会被转义，纯文本版本和标题不转义。模板可以通过{{> 名称}}引用其他模板作为片段，
+// 也可以指定一个布局：先渲染模板本身，再把结果作为{{{body}}}渲染布局
+// 模板可以用{{t "键" 占位符=值}}插入收件人所用语言的翻译，见crate::i18n
+use std::collections::{HashMap, HashSet};
+
+use handlebars::{
+    Context, Handlebars, Helper, HelperResult, Output, RenderError, RenderErrorReason,
+};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::domain::FieldErrors;
+use crate::i18n;
+use crate::repository::templates::{self, StoredTemplate};
+use crate::routes::{escape_html, preferences_link};
+
+// 布局最多可以嵌套几层
+const MAX_LAYOUT_DEPTH: usize = 8;
+
+// 模板中可以使用的变量。不适用于某封邮件的变量为空字符串，例如确认邮件中的unsubscribe_url
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct TemplateVariables {
+    pub name: String,
+    pub email: String,
+    // 列表的名称
+    pub list: String,
+    // 新闻邮件的标题
+    pub title: String,
+    pub unsubscribe_url: String,
+    pub preferences_url: String,
+    pub confirmation_url: String,
+    // 收件人所用语言的目录，如zh-CN，t助手按它选择翻译
+    pub locale: String,
+}
+
+impl TemplateVariables {
+    // 预览和保存前校验时使用的示例订阅者
+    pub fn sample(base_url: &str, locale: &str) -> Self {
+        Self {
+            name: "Jane Doe".into(),
+            email: "jane.doe@example.com".into(),
+            list: "Newsletter".into(),
+            title: "Sample issue".into(),
+            unsubscribe_url: format!("{}/subscriptions/unsubscribe?token=sample", base_url),
+            preferences_url: preferences_link(base_url, "sample"),
+            confirmation_url: format!(
+                "{}/subscriptions/confirm?subscription_token=sample",
+                base_url
+            ),
+            locale: locale.into(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct RenderContext<'a> {
+    #[serde(flatten)]
+    variables: &'a TemplateVariables,
+    // 布局中插入模板内容的位置
+    body: &'a str,
+}
+
+// 渲染后的邮件
+#[derive(Serialize, Debug)]
+pub struct RenderedEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+}
+
+// 编译好的全部模板
+pub struct TemplateSet {
+    templates: HashMap<String, StoredTemplate>,
+    html: Handlebars<'static>,
+    text: Handlebars<'static>,
+}
+
+impl TemplateSet {
+    pub fn new(stored: Vec<StoredTemplate>) -> Result<Self, String> {
+        Self::compile(stored, false).map_err(|errors| {
+            errors
+                .into_iter()
+                .flat_map(|(field, messages)| {
+                    messages
+                        .into_iter()
+                        .map(move |message| format!("{}: {}", field, message))
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        })
+    }
+
+    pub async fn load(pool: &PgPool) -> Result<Self, String> {
+        let stored = templates::all(pool).await.map_err(|e| e.to_string())?;
+        Self::new(stored)
+    }
+
+    fn compile(stored: Vec<StoredTemplate>, strict: bool) -> Result<Self, FieldErrors> {
+        let mut html = Handlebars::new();
+        html.register_escape_fn(escape_html);
+        html.set_strict_mode(strict);
+        html.register_helper("t", Box::new(translate));
+        let mut text = Handlebars::new();
+        text.register_escape_fn(handlebars::no_escape);
+        text.set_strict_mode(strict);
+        text.register_helper("t", Box::new(translate));
+        let mut errors = FieldErrors::new();
+        for template in &stored {
+            for (field, registry, source) in [
+                ("html", &mut html, &template.html),
+                ("text", &mut text, &template.text),
+            ] {
+                if let Err(e) = registry.register_template_string(&template.name, source) {
+                    errors
+                        .entry(field)
+                        .or_default()
+                        .push(describe(&template.name, &e));
+                }
+            }
+        }
+        if !errors.is_empty() {
+            return Err(errors);
+        }
+        let templates = stored.into_iter().map(|t| (t.name.clone(), t)).collect();
+        Ok(Self {
+            templates,
+            html,
+            text,
+        })
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.templates.contains_key(name)
+    }
+
+    // 渲染一封完整的邮件，包括布局
+    pub fn render(
+        &self,
+        name: &str,
+        variables: &TemplateVariables,
+    ) -> Result<RenderedEmail, String> {
+        let template = self
+            .templates
+            .get(name)
+            .ok_or_else(|| format!("There is no template called {}.", name))?;
+        let (mut html, mut text) = self.render_body(name, variables)?;
+        let mut layout = template.layout.as_deref();
+        let mut depth = 0;
+        while let Some(name) = layout {
+            depth += 1;
+            if depth > MAX_LAYOUT_DEPTH {
+                return Err(format!(
+                    "Layouts must not be nested more than {} levels deep.",
+                    MAX_LAYOUT_DEPTH
+                ));
+            }
+            html = self.render_one(&self.html, name, variables, &html)?;
+            text = self.render_one(&self.text, name, variables, &text)?;
+            layout = self.templates.get(name).and_then(|t| t.layout.as_deref());
+        }
+        let subject = match &template.subject {
+            Some(subject) => self
+                .text
+                .render_template(
+                    subject,
+                    &RenderContext {
+                        variables,
+                        body: "",
+                    },
+                )
+                .map_err(|e| e.to_string())?,
+            None => variables.title.clone(),
+        };
+        Ok(RenderedEmail {
+            subject,
+            html,
+            text,
+        })
+    }
+
+    // 只渲染模板本身，不套用布局，用于每周摘要中的一项
+    pub fn render_body(
+        &self,
+        name: &str,
+        variables: &TemplateVariables,
+    ) -> Result<(String, String), String> {
+        Ok((
+            self.render_one(&self.html, name, variables, "")?,
+            self.render_one(&self.text, name, variables, "")?,
+        ))
+    }
+
+    fn render_one(
+        &self,
+        registry: &Handlebars<'static>,
+        name: &str,
+        variables: &TemplateVariables,
+        body: &str,
+    ) -> Result<String, String> {
+        registry
+            .render(name, &RenderContext { variables, body })
+            .map_err(|e| e.to_string())
+    }
+}
+
+// 保存模板之前的校验：语法、引用的片段和布局是否存在、是否有循环引用，
+// 以及用示例订阅者渲染时是否用到了未知的变量。错误按字段（subject、html、text、layout）返回
+pub fn validate(
+    existing: Vec<StoredTemplate>,
+    candidate: &StoredTemplate,
+    base_url: &str,
+    locale: &str,
+) -> Result<(), FieldErrors> {
+    let mut stored: Vec<StoredTemplate> = existing
+        .into_iter()
+        .filter(|t| t.name != candidate.name)
+        .collect();
+    stored.push(candidate.clone());
+    let mut errors = FieldErrors::new();
+
+    // 片段引用
+    let mut references = HashMap::new();
+    for template in &stored {
+        let mut names = Vec::new();
+        for (field, source) in [("html", &template.html), ("text", &template.text)] {
+            match partials(source) {
+                Ok(found) => names.extend(found),
+                Err(e) if template.name == candidate.name => {
+                    errors.entry(field).or_default().push(e)
+                }
+                Err(_) => {}
+            }
+        }
+        references.insert(template.name.as_str(), names);
+    }
+    for (field, source) in [("html", &candidate.html), ("text", &candidate.text)] {
+        for name in partials(source).unwrap_or_default() {
+            if !references.contains_key(name.as_str()) {
+                errors
+                    .entry(field)
+                    .or_default()
+                    .push(format!("There is no template called {}.", name));
+            }
+        }
+    }
+    if reaches(&references, &candidate.name) {
+        errors
+            .entry("html")
+            .or_default()
+            .push("The template includes itself through its partials.".into());
+    }
+
+    // 布局
+    if let Some(layout) = &candidate.layout {
+        let layouts: HashMap<&str, Option<&str>> = stored
+            .iter()
+            .map(|t| (t.name.as_str(), t.layout.as_deref()))
+            .collect();
+        if !layouts.contains_key(layout.as_str()) || layout == &candidate.name {
+            errors
+                .entry("layout")
+                .or_default()
+                .push(format!("There is no other template called {}.", layout));
+        } else {
+            let mut current = Some(layout.as_str());
+            let mut seen = HashSet::new();
+            while let Some(name) = current {
+                if name == candidate.name || !seen.insert(name) {
+                    errors
+                        .entry("layout")
+                        .or_default()
+                        .push("The layouts of the template form a cycle.".into());
+                    break;
+                }
+                current = layouts.get(name).copied().flatten();
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    // 语法错误，以及严格模式下渲染示例时的错误（未知的变量）
+    let set = TemplateSet::compile(stored, true)?;
+    // 每个目录的键都相同，用一种语言校验即可
+    let variables = TemplateVariables::sample(base_url, locale);
+    for (field, registry) in [("html", &set.html), ("text", &set.text)] {
+        if let Err(e) = set.render_one(registry, &candidate.name, &variables, "") {
+            errors.entry(field).or_default().push(e);
+        }
+    }
+    if let Some(subject) = &candidate.subject {
+        if let Err(e) = set.text.render_template(
+            subject,
+            &RenderContext {
+                variables: &variables,
+                body: "",
+            },
+        ) {
+            errors.entry("subject").or_default().push(e.to_string());
+        }
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+// {{t "unsubscribe.done" list=list}}：按上下文中的locale翻译，替换占位符后按所在的版本转义
+fn translate(
+    h: &Helper,
+    r: &Handlebars,
+    ctx: &Context,
+    _: &mut handlebars::RenderContext,
+    out: &mut dyn Output,
+) -> HelperResult {
+    let key = h
+        .param(0)
+        .and_then(|p| p.value().as_str())
+        .ok_or(RenderErrorReason::ParamNotFoundForIndex("t", 0))?;
+    let locale = ctx
+        .data()
+        .get("locale")
+        .and_then(|l| l.as_str())
+        .unwrap_or_default();
+    let catalogue = i18n::find(locale).ok_or_else(|| {
+        RenderError::from(RenderErrorReason::Other(format!(
+            "There is no catalogue for {}.",
+            locale
+        )))
+    })?;
+    if catalogue.get(key).is_none() {
+        return Err(
+            RenderErrorReason::Other(format!("There is no translation called {}.", key)).into(),
+        );
+    }
+    let arguments: Vec<(&str, String)> = h
+        .hash()
+        .iter()
+        .map(|(name, value)| {
+            let value = match value.value() {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (*name, value)
+        })
+        .collect();
+    let arguments: Vec<(&str, &str)> = arguments
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+    out.write(&r.get_escape_fn()(&catalogue.format(key, &arguments)))?;
+    Ok(())
+}
+
+// 语法错误的描述，如：line 1, column 7 of welcome: invalid handlebars syntax ...
+fn describe(name: &str, e: &handlebars::TemplateError) -> String {
+    match e.pos() {
+        Some((line, column)) => format!(
+            "Line {}, column {} of {}: {}",
+            line,
+            column,
+            name,
+            e.reason()
+        ),
+        None => format!("{}: {}", name, e.reason()),
+    }
+}
+
+// 模板通过{{> 名称}}（或{{~> 名称}}、{{#> 名称}}）引用的片段
+fn partials(source: &str) -> Result<Vec<String>, String> {
+    let mut names = Vec::new();
+    let mut rest = source;
+    while let Some(start) = rest.find("{{") {
+        rest = &rest[start + 2..];
+        let tag = rest.trim_start_matches('~');
+        let tag = tag.strip_prefix('#').unwrap_or(tag);
+        let Some(tag) = tag.strip_prefix('>') else {
+            continue;
+        };
+        let name: String = tag
+            .trim_start()
+            .chars()
+            .take_while(|c| !c.is_whitespace() && *c != '}' && *c != '~')
+            .collect();
+        let name = name.trim_matches('"').to_string();
+        if name.starts_with('(') {
+            return Err("Dynamic partials are not supported.".into());
+        }
+        // {{> @partial-block}}是片段块的内容，不是另一个模板
+        if !name.is_empty() && !name.starts_with('@') {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+// 从start出发，沿片段引用能否回到start
+fn reaches(references: &HashMap<&str, Vec<String>>, start: &str) -> bool {
+    let mut stack: Vec<&str> = references
+        .get(start)
+        .map(|names| names.iter().map(String::as_str).collect())
+        .unwrap_or_default();
+    let mut seen = HashSet::new();
+    while let Some(name) = stack.pop() {
+        if name == start {
+            return true;
+        }
+        if seen.insert(name) {
+            if let Some(names) = references.get(name) {
+                stack.extend(names.iter().map(String::as_str));
+            }
+        }
+    }
+    false
+}
@@ -0,0 +1,136 @@
+// 系统邮件和网页的翻译。每种语言一个目录，保存在locales/下的JSON文件中，编译时嵌入程序
+// 目录是扁平的键值表，值中的{name}是占位符，由Catalogue::format替换
+// 选择目录的顺序：邮件使用订阅者保存的locale属性，网页使用请求的Accept-Language头，
+// 都没有对应的目录时使用配置中的默认语言
+use std::collections::BTreeMap;
+
+use actix_web::{http::header, HttpRequest};
+use once_cell::sync::Lazy;
+
+// 一种语言的全部翻译
+#[derive(Debug)]
+pub struct Catalogue {
+    locale: &'static str,
+    messages: BTreeMap<String, String>,
+}
+
+static CATALOGUES: Lazy<Vec<Catalogue>> = Lazy::new(|| {
+    [
+        ("en", include_str!("../locales/en.json")),
+        ("zh-CN", include_str!("../locales/zh-CN.json")),
+    ]
+    .into_iter()
+    .map(|(locale, source)| Catalogue {
+        locale,
+        messages: serde_json::from_str(source)
+            .unwrap_or_else(|e| panic!("The {} catalogue is invalid: {}", locale, e)),
+    })
+    .collect()
+});
+
+// 所有的目录
+pub fn catalogues() -> &'static [Catalogue] {
+    &CATALOGUES
+}
+
+// 与语言标签对应的目录：先按完整的标签匹配，再只按语言部分匹配（如zh-TW使用zh-CN的目录）
+pub fn find(locale: &str) -> Option<&'static Catalogue> {
+    let language = |locale: &str| {
+        locale
+            .split(['-', '_'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase()
+    };
+    catalogues()
+        .iter()
+        .find(|c| c.locale.eq_ignore_ascii_case(&locale.replace('_', "-")))
+        .or_else(|| {
+            catalogues()
+                .iter()
+                .find(|c| language(c.locale) == language(locale))
+        })
+}
+
+// Accept-Language头中按权重从高到低第一个有对应目录的语言，如"de,zh-TW;q=0.9,en;q=0.8"得到zh-CN的目录
+pub fn negotiate(accept_language: &str) -> Option<&'static Catalogue> {
+    let mut ranges: Vec<(&str, f32)> = accept_language
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let weight = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .map_or(Some(1.0), |q| q.trim().parse::<f32>().ok())?;
+            (!tag.is_empty() && tag != "*" && weight > 0.0).then_some((tag, weight))
+        })
+        .collect();
+    // 排序是稳定的，权重相同时保持头中的顺序
+    ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+    ranges.into_iter().find_map(|(tag, _)| find(tag))
+}
+
+impl Catalogue {
+    // 目录的语言标签，如zh-CN，可以直接用作HTML的lang属性
+    pub fn locale(&self) -> &'static str {
+        self.locale
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.messages.keys().map(String::as_str)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.messages.get(key).map(String::as_str)
+    }
+
+    // 缺少的键原样返回。测试保证每个目录都有全部的键
+    pub fn t<'a>(&'a self, key: &'a str) -> &'a str {
+        self.get(key).unwrap_or(key)
+    }
+
+    // 翻译并替换占位符，如format("unsubscribe.done", &[("list", "Newsletter")])
+    pub fn format(&self, key: &str, arguments: &[(&str, &str)]) -> String {
+        let mut message = self.t(key).to_string();
+        for (name, value) in arguments {
+            message = message.replace(&format!("{{{}}}", name), value);
+        }
+        message
+    }
+}
+
+// 带有默认语言的目录选择器，默认语言来自配置
+#[derive(Debug, Clone)]
+pub struct I18n {
+    default: &'static Catalogue,
+}
+
+impl I18n {
+    pub fn new(default_locale: &str) -> Result<Self, String> {
+        find(default_locale)
+            .map(|default| Self { default })
+            .ok_or_else(|| format!("There is no catalogue for {}.", default_locale))
+    }
+
+    pub fn default_catalogue(&self) -> &'static Catalogue {
+        self.default
+    }
+
+    // 订阅者保存的语言（可能没有保存，或者没有对应的目录）
+    pub fn for_locale(&self, locale: Option<&str>) -> &'static Catalogue {
+        locale.and_then(find).unwrap_or(self.default)
+    }
+
+    // 网页使用的语言：按请求的Accept-Language头选择
+    pub fn for_request(&self, req: &HttpRequest) -> &'static Catalogue {
+        accept_language(req)
+            .and_then(negotiate)
+            .unwrap_or(self.default)
+    }
+}
+
+pub fn accept_language(req: &HttpRequest) -> Option<&str> {
+    req.headers()
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|value| value.to_str().ok())
+}
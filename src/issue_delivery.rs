@@ -0,0 +1,336 @@
+// 定时发送：调度循环把到期的一期放入投递队列，投递循环逐封发送队列中的邮件
+// 两者都通过FOR UPDATE SKIP LOCKED在多个实例之间分配工作：每一期只会被一个实例取出并放入队列一次，
+// 每封邮件先被认领（推迟一段租期），发送成功后才从队列中删除
+// 导入的待确认订阅者的确认邮件也放在队列中，由投递循环以同样的方式发送
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use actix_web::ResponseError;
+use chrono::Utc;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments::Attachments;
+use crate::configuration::DeliverySettings;
+use crate::domain::{DeliveryFrequency, NewSubscriber, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_template::TemplateSet;
+use crate::i18n::I18n;
+use crate::newsletter_issue::{PreparedIssue, PublishError};
+use crate::repository::confirmation_queue::{self, ConfirmationTask};
+use crate::repository::delivery_queue::{self, DeliveryTask};
+use crate::repository::{digests, issue_revisions, issues, suppressions};
+use crate::routes::send_confirmation_email;
+use crate::send_throttle::SendThrottle;
+use crate::tracking::Tracking;
+
+// 认领一封邮件后的租期，应当比等待发送速率的令牌和一次发送的超时加起来还长
+const CLAIM_LEASE: Duration = Duration::from_secs(10 * 60);
+// 指数退避的上限：重试次数较多时间隔不再翻倍，避免把邮件推迟到遥远的将来
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(6 * 60 * 60);
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExecutionOutcome {
+    TaskCompleted,
+    EmptyQueue,
+}
+
+pub struct IssueDeliveryWorker {
+    pool: PgPool,
+    email_client: EmailClient,
+    i18n: I18n,
+    attachments: Attachments,
+    base_url: String,
+    poll_interval: Duration,
+    max_retries: u16,
+    retry_base: Duration,
+    throttle: SendThrottle,
+    tracking: Tracking,
+    // 最近发送的一期，连续发送同一期的邮件时不必重新加载模板和附件
+    current: Mutex<Option<(Uuid, Arc<PreparedIssue>)>>,
+}
+
+impl IssueDeliveryWorker {
+    pub fn new(
+        pool: PgPool,
+        email_client: EmailClient,
+        i18n: I18n,
+        attachments: Attachments,
+        base_url: String,
+        tracking: Tracking,
+        settings: &DeliverySettings,
+    ) -> Self {
+        let throttle =
+            SendThrottle::new(pool.clone(), &settings.send_rate, &email_client.transport());
+        Self {
+            pool,
+            email_client,
+            i18n,
+            attachments,
+            base_url,
+            poll_interval: settings.poll_interval(),
+            max_retries: settings.max_retries,
+            retry_base: settings.retry_base(),
+            throttle,
+            tracking,
+            current: Mutex::new(None),
+        }
+    }
+
+    pub async fn run_until_stopped(self) {
+        loop {
+            match self.run_once().await {
+                Ok(ExecutionOutcome::TaskCompleted) => {}
+                Ok(ExecutionOutcome::EmptyQueue) => tokio::time::sleep(self.poll_interval).await,
+                // 数据库暂时不可用等，稍后再试
+                Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+            }
+        }
+    }
+
+    // 先处理到期的一期，再发送一封队列中的邮件，最后是导入的订阅者的确认邮件。都没有时把已经发完的一期标记为sent
+    pub async fn run_once(&self) -> Result<ExecutionOutcome, PublishError> {
+        if self.promote_due_issue().await?
+            || self.deliver_next().await?
+            || self.send_next_confirmation().await?
+        {
+            return Ok(ExecutionOutcome::TaskCompleted);
+        }
+        issues::mark_sent(&self.pool).await?;
+        Ok(ExecutionOutcome::EmptyQueue)
+    }
+
+    // 把一期到期的scheduled放入投递队列，选择每周摘要的订阅者放入摘要队列。没有到期的一期时返回false
+    #[tracing::instrument(name = "Promote due newsletter issue", skip(self))]
+    async fn promote_due_issue(&self) -> Result<bool, PublishError> {
+        let mut transaction = self.pool.begin().await?;
+        let Some(issue) = issues::lock_due(&mut transaction, Utc::now()).await? else {
+            return Ok(false);
+        };
+        // 发送的是安排时选定的版本，之后的编辑不影响这次发送
+        let revision = match issue.published_revision {
+            Some(revision) => issue_revisions::find(&mut *transaction, issue.id, revision).await?,
+            None => None,
+        };
+        let result = match revision {
+            // 注：保存的请求中只有对asset的引用，没有需要上传的文件，因此用户id不会被使用
+            Some(revision) => {
+                PreparedIssue::prepare(
+                    &self.pool,
+                    &revision.request,
+                    Vec::new(),
+                    &self.attachments,
+                    issue.created_by.unwrap_or_default(),
+                )
+                .await
+            }
+            None => Err(PublishError::Invalid(
+                "The scheduled revision does not exist.".into(),
+            )),
+        };
+        let prepared = match result {
+            Ok(prepared) => prepared,
+            // 服务器端的问题（如blob存储不可用）回滚事务，下一次循环再试
+            Err(e) if e.status_code().is_server_error() => return Err(e),
+            Err(e) => {
+                tracing::warn!("Newsletter issue {} can no longer be sent: {}", issue.id, e);
+                issues::fail(&mut transaction, issue.id, &e.to_string()).await?;
+                transaction.commit().await?;
+                return Ok(true);
+            }
+        };
+        let (recipients, digest_recipients) = tokio::try_join!(
+            prepared.recipients(&self.pool, DeliveryFrequency::Immediate),
+            prepared.recipients(&self.pool, DeliveryFrequency::WeeklyDigest),
+        )?;
+        let items = prepared.digest_items(&digest_recipients, &self.i18n, &self.base_url)?;
+        digests::queue(&mut *transaction, prepared.title(), &items).await?;
+        let subscriber_ids: Vec<Uuid> = recipients.iter().map(|r| r.subscriber_id).collect();
+        issues::start_sending(&mut transaction, issue.id, &subscriber_ids).await?;
+        transaction.commit().await?;
+        tracing::info!(
+            "Newsletter issue {} queued for {} subscribers and {} digests",
+            issue.id,
+            subscriber_ids.len(),
+            items.len()
+        );
+        *self.current.lock().unwrap() = Some((issue.id, Arc::new(prepared)));
+        Ok(true)
+    }
+
+    // 发送队列中的一封邮件。失败时按指数退避推迟，超过重试次数后放弃。队列中没有到期的邮件时返回false
+    #[tracing::instrument(name = "Deliver newsletter issue", skip(self))]
+    async fn deliver_next(&self) -> Result<bool, PublishError> {
+        let now = Utc::now();
+        let lease_until = now + chrono::Duration::from_std(CLAIM_LEASE).unwrap();
+        let Some(task) = delivery_queue::claim(&self.pool, now, lease_until).await? else {
+            return Ok(false);
+        };
+        match self.deliver(&task).await {
+            Ok(()) => delivery_queue::delete(&self.pool, &task).await?,
+            Err(e) if task.n_retries < self.max_retries as i16 => {
+                let delay = self.retry_delay(task.n_retries);
+                tracing::warn!(
+                    "Failed to deliver newsletter issue {} to subscriber {}, retrying in {:?}: {:?}",
+                    task.newsletter_issue_id,
+                    task.subscriber_id,
+                    delay,
+                    e
+                );
+                let execute_after = Utc::now() + chrono::Duration::from_std(delay).unwrap();
+                delivery_queue::retry(&self.pool, &task, execute_after).await?;
+            }
+            Err(e) => {
+                tracing::error!(
+                    "Giving up delivering newsletter issue {} to subscriber {}: {:?}",
+                    task.newsletter_issue_id,
+                    task.subscriber_id,
+                    e
+                );
+                delivery_queue::delete(&self.pool, &task).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn deliver(&self, task: &DeliveryTask) -> Result<(), PublishError> {
+        let issue = self.prepared(task.newsletter_issue_id).await?;
+        // 放入队列之后退订、暂停或者不再满足分组的订阅者不再发送
+        let Some(recipient) = issue.recipient(&self.pool, task.subscriber_id).await? else {
+            return Ok(());
+        };
+        let email = match SubscriberEmail::parse(recipient.email.clone()) {
+            Ok(email) => email,
+            Err(e) => {
+                tracing::warn!(
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid: {}",
+                    e
+                );
+                return Ok(());
+            }
+        };
+        let message = issue.message(
+            &recipient,
+            &email,
+            self.email_client.sender(),
+            &self.i18n,
+            &self.base_url,
+            Some((&self.tracking, task.newsletter_issue_id)),
+        )?;
+        // 超过发送速率时在这里等待，队列中的邮件按配置的速率平稳发出
+        let waited = self.throttle.acquire().await?;
+        if !waited.is_zero() {
+            tracing::debug!("Delivery throttled for {:?}", waited);
+        }
+        self.email_client
+            .send(&message)
+            .await
+            .map_err(|e| PublishError::Unexpected(e.to_string()))
+    }
+
+    // 发送一封队列中的确认邮件，失败时的处理与deliver_next相同。队列中没有到期的确认邮件时返回false
+    #[tracing::instrument(name = "Send queued confirmation email", skip(self))]
+    async fn send_next_confirmation(&self) -> Result<bool, PublishError> {
+        let now = Utc::now();
+        let lease_until = now + chrono::Duration::from_std(CLAIM_LEASE).unwrap();
+        let Some(task) = confirmation_queue::claim(&self.pool, now, lease_until).await? else {
+            return Ok(false);
+        };
+        match self.send_confirmation(&task).await {
+            Ok(()) => confirmation_queue::delete(&self.pool, &task).await?,
+            Err(e) if task.n_retries < self.max_retries as i16 => {
+                let delay = self.retry_delay(task.n_retries);
+                tracing::warn!(
+                    "Failed to send a queued confirmation email, retrying in {:?}: {:?}",
+                    delay,
+                    e
+                );
+                let execute_after = Utc::now() + chrono::Duration::from_std(delay).unwrap();
+                confirmation_queue::retry(&self.pool, &task, execute_after).await?;
+            }
+            Err(e) => {
+                tracing::error!("Giving up sending a queued confirmation email: {:?}", e);
+                confirmation_queue::delete(&self.pool, &task).await?;
+            }
+        }
+        Ok(true)
+    }
+
+    async fn send_confirmation(&self, task: &ConfirmationTask) -> Result<(), PublishError> {
+        // 放入队列之后已经确认、退订或被删除的订阅者不再发送
+        let Some(pending) =
+            confirmation_queue::find_pending(&self.pool, &task.subscription_token).await?
+        else {
+            return Ok(());
+        };
+        if !suppressions::find_suppressed(&self.pool, &[pending.email.as_str()])
+            .await?
+            .is_empty()
+        {
+            return Ok(());
+        }
+        let subscriber = match NewSubscriber::parse(Some(pending.email), Some(pending.name)) {
+            Ok(subscriber) => subscriber,
+            Err(errors) => {
+                tracing::warn!(
+                    "Skipping a pending subscriber. Their stored contact details are invalid: {:?}",
+                    errors
+                );
+                return Ok(());
+            }
+        };
+        let templates = TemplateSet::load(&self.pool)
+            .await
+            .map_err(PublishError::Unexpected)?;
+        // 确认邮件与newsletter共用发送速率
+        self.throttle.acquire().await?;
+        send_confirmation_email(
+            &self.email_client,
+            &templates,
+            self.i18n.for_locale(pending.locale.as_deref()),
+            &subscriber,
+            &pending.list,
+            &self.base_url,
+            &task.subscription_token,
+        )
+        .await
+        .map_err(PublishError::Unexpected)
+    }
+
+    // 第n_retries次失败后等待的时间：按指数增长，但不超过MAX_RETRY_DELAY
+    fn retry_delay(&self, n_retries: i16) -> Duration {
+        self.retry_base
+            .saturating_mul(2u32.saturating_pow(n_retries as u32))
+            .min(MAX_RETRY_DELAY)
+    }
+
+    async fn prepared(&self, issue_id: Uuid) -> Result<Arc<PreparedIssue>, PublishError> {
+        if let Some((id, prepared)) = self.current.lock().unwrap().as_ref() {
+            if *id == issue_id {
+                return Ok(prepared.clone());
+            }
+        }
+        let issue = issues::find_by_id(&self.pool, issue_id)
+            .await?
+            .ok_or_else(|| PublishError::Unexpected(format!("Issue {} is gone.", issue_id)))?;
+        let revision = match issue.published_revision {
+            Some(revision) => issue_revisions::find(&self.pool, issue_id, revision).await?,
+            None => None,
+        }
+        .ok_or_else(|| {
+            PublishError::Unexpected(format!("Issue {} has no published revision.", issue_id))
+        })?;
+        let prepared = Arc::new(
+            PreparedIssue::prepare(
+                &self.pool,
+                &revision.request,
+                Vec::new(),
+                &self.attachments,
+                issue.created_by.unwrap_or_default(),
+            )
+            .await?,
+        );
+        *self.current.lock().unwrap() = Some((issue_id, prepared.clone()));
+        Ok(prepared)
+    }
+}
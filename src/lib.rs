@@ -1,4 +1,23 @@
+pub mod attachments;
+pub mod authentication;
+pub mod bot_protection;
 pub mod configuration;
+pub mod digest;
+pub mod domain;
+pub mod email_client;
+pub mod email_events;
+pub mod email_message;
+pub mod email_template;
+pub mod i18n;
+pub mod issue_delivery;
+pub mod newsletter_issue;
+pub mod rate_limit;
+pub mod repository;
+pub mod request_id;
 pub mod routes;
+pub mod send_throttle;
 pub mod startup;
-pub mod telemetry;
\ No newline at end of file
+pub mod subscriber_import;
+pub mod telemetry;
+pub mod text_diff;
+pub mod tracking;
@@ -4,9 +4,17 @@ use secrecy::ExposeSecret;
 // use env_logger::Env;
 use sqlx::PgPool;
 use zero2prod_lib::{
+    attachments::Attachments,
+    bot_protection::BotProtection,
     configuration::get_configuration,
+    email_client::EmailClient,
+    email_events::EmailEvents,
+    i18n::I18n,
+    issue_delivery::IssueDeliveryWorker,
+    rate_limit::RateLimiter,
     startup::run,
     telemetry::{get_subscriber, init_subscriber},
+    tracking::Tracking,
 };
 
 #[actix_web::main]
@@ -32,8 +40,42 @@ async fn main() -> std::io::Result<()> {
     // 推迟在首次启动时建立连接
     let connection_pool = PgPool::connect_lazy(conf.database.connection_string().expose_secret())
         .expect("Failed to create Postgres connection pool.");
+    let email_client =
+        EmailClient::from_settings(&conf.email_client).expect("Invalid email client settings");
+    let rate_limiter = RateLimiter::new(&conf.rate_limit, connection_pool.clone());
+    let rate_limit_cleanup = rate_limiter.cleanup();
+    let bot_protection = BotProtection::new(&conf.bot_protection);
+    let i18n = I18n::new(&conf.application.default_locale).expect("Invalid default locale");
+    let attachments = Attachments::new(&conf.attachments).expect("Invalid attachment settings");
+    // 定时发送的后台任务与HTTP服务运行在同一个进程中，多个实例时各自的任务通过数据库行锁分配工作
+    let delivery_worker = IssueDeliveryWorker::new(
+        connection_pool.clone(),
+        EmailClient::from_settings(&conf.email_client).expect("Invalid email client settings"),
+        i18n.clone(),
+        Attachments::new(&conf.attachments).expect("Invalid attachment settings"),
+        conf.application.base_url.clone(),
+        Tracking::new(&conf.tracking),
+        &conf.delivery,
+    );
     let address = format!("{}:{}", conf.application.host, conf.application.port);
     dbg!(&address);
     let listener = TcpListener::bind(&address)?;
-    run(listener, connection_pool)?.await
+    let server = run(
+        listener,
+        connection_pool,
+        email_client,
+        rate_limiter,
+        bot_protection,
+        conf.application.base_url,
+        i18n,
+        attachments,
+        EmailEvents::new(&conf.email_events),
+        Tracking::new(&conf.tracking),
+    )?;
+    // 任意一个结束（HTTP服务收到停止信号）时整个进程退出
+    tokio::select! {
+        result = server => result,
+        () = delivery_worker.run_until_stopped() => Ok(()),
+        () = rate_limit_cleanup.run_until_stopped() => Ok(()),
+    }
 }
@@ -0,0 +1,570 @@
+// 一期新闻邮件：发布请求中的内容，以及解析好列表、分组、模板和附件之后按收件人生成的邮件
+// 立即发布（POST /api/v1/newsletters）和定时发送（issue_delivery）共用这里的逻辑
+use actix_web::{http::StatusCode, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::attachments::{AttachmentError, Attachments, FormPart};
+use crate::domain::{DeliveryFrequency, Segment, SubscriberEmail};
+use crate::email_message::{html_to_text, Attachment, EmailMessage, Mailbox};
+use crate::email_template::{RenderedEmail, TemplateSet, TemplateVariables};
+use crate::i18n::{Catalogue, I18n};
+use crate::repository::assets::{self, Asset};
+use crate::repository::lists::{self, DEFAULT_LIST};
+use crate::repository::memberships::{self, Recipient};
+use crate::repository::{segments, suppressions};
+use crate::routes::{escape_html, preferences_link};
+use crate::tracking::Tracking;
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct BodyData {
+    pub title: String,
+    // content和template二选一：直接提供内容，或使用一个保存的模板按订阅者渲染
+    pub content: Option<Content>,
+    pub template: Option<String>,
+    // 发送给哪些列表（列表的slug），默认为newsletter
+    // 同时在多个列表中的订阅者只会收到一封
+    pub lists: Option<Vec<String>>,
+    // 只发送给满足某个保存的分组的订阅者，分组由POST /admin/segments创建
+    pub segment_id: Option<Uuid>,
+    // 通过POST /admin/assets上传的附件
+    pub attachments: Option<Vec<AttachmentReference>>,
+    // 打开和点击跟踪，默认都不开启
+    pub tracking: Option<TrackingOptions>,
+}
+
+#[derive(Serialize, Deserialize, ToSchema, Clone, Copy, Debug, Default, PartialEq)]
+pub struct TrackingOptions {
+    // 加入跟踪像素，记录邮件何时被打开
+    #[serde(default)]
+    pub opens: bool,
+    // 把链接改写为跟踪链接，记录点击了哪个链接
+    #[serde(default)]
+    pub clicks: bool,
+}
+
+// 引用一个已经上传的文件作为附件
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct AttachmentReference {
+    pub asset_id: Uuid,
+    // 设置时作为内嵌图片，HTML中通过<img src="cid:{content_id}">引用
+    pub content_id: Option<String>,
+}
+
+// HTML和纯文本两个版本，不支持HTML的邮件客户端会显示纯文本版本
+// 省略纯文本版本时从HTML生成
+#[derive(Serialize, Deserialize, ToSchema, Clone, Debug, PartialEq)]
+pub struct Content {
+    pub html: String,
+    pub text: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum PublishError {
+    // 请求引用了不存在的列表、分组、模板或asset等
+    Invalid(String),
+    Attachment(AttachmentError),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for PublishError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => f.write_str(e),
+            Self::Attachment(e) => write!(f, "{}", e),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for PublishError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Attachment(e) => e.status_code(),
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for PublishError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+impl From<AttachmentError> for PublishError {
+    fn from(e: AttachmentError) -> Self {
+        Self::Attachment(e)
+    }
+}
+
+// 一期邮件的内容来源
+enum Source {
+    Content(Content),
+    Template(Box<TemplateSet>, String),
+}
+
+// 检查过的一期邮件，可以为任意收件人生成邮件
+pub struct PreparedIssue {
+    title: String,
+    list_ids: Vec<Uuid>,
+    // 第一个目标列表的名称，用于预览
+    list_name: String,
+    segment: Option<Segment>,
+    source: Source,
+    files: Vec<(Asset, Attachment)>,
+    tracking: TrackingOptions,
+}
+
+impl PreparedIssue {
+    // 解析发布请求引用的列表、分组、模板和附件。uploads是multipart请求体中上传的文件，会被保存为新的asset
+    #[tracing::instrument(name = "Prepare newsletter issue", skip_all, fields(title = %body.title))]
+    pub async fn prepare(
+        pool: &PgPool,
+        body: &BodyData,
+        uploads: Vec<FormPart>,
+        attachments: &Attachments,
+        user_id: Uuid,
+    ) -> Result<Self, PublishError> {
+        let slugs = body
+            .lists
+            .clone()
+            .unwrap_or_else(|| vec![DEFAULT_LIST.into()]);
+        if slugs.is_empty() {
+            return Err(PublishError::Invalid(
+                "At least one list is required.".into(),
+            ));
+        }
+        let lists = lists::find_by_slugs(pool, &slugs)
+            .await?
+            .map_err(|unknown| {
+                PublishError::Invalid(format!("There is no list called {}.", unknown))
+            })?;
+        let segment = match body.segment_id {
+            Some(segment_id) => {
+                let saved = segments::find_by_id(pool, segment_id)
+                    .await?
+                    .ok_or_else(|| {
+                        PublishError::Invalid(format!(
+                            "There is no segment with id {}.",
+                            segment_id
+                        ))
+                    })?;
+                // 保存前已经校验过，只有在表达式语法变化后才会发生
+                let segment = Segment::parse(&saved.expression).map_err(|e| {
+                    tracing::error!("Saved segment {} is no longer valid: {}", saved.id, e);
+                    PublishError::Unexpected(e)
+                })?;
+                Some(segment)
+            }
+            None => None,
+        };
+        let source = match (&body.content, &body.template) {
+            (Some(content), None) => Source::Content(content.clone()),
+            (None, Some(name)) => {
+                let templates = TemplateSet::load(pool).await.map_err(|e| {
+                    tracing::error!("Failed to load email templates: {}", e);
+                    PublishError::Unexpected(e)
+                })?;
+                if !templates.contains(name) {
+                    return Err(PublishError::Invalid(format!(
+                        "There is no template called {}.",
+                        name
+                    )));
+                }
+                Source::Template(Box::new(templates), name.clone())
+            }
+            _ => {
+                return Err(PublishError::Invalid(
+                    "Provide either content or a template.".into(),
+                ))
+            }
+        };
+        let files = resolve_attachments(body, uploads, pool, attachments, user_id).await?;
+        if let Source::Content(content) = &source {
+            if let Some(unknown) =
+                content_id_references(&content.html)
+                    .into_iter()
+                    .find(|reference| {
+                        !files
+                            .iter()
+                            .any(|(_, a)| a.content_id.as_deref() == Some(reference))
+                    })
+            {
+                return Err(PublishError::Invalid(format!(
+                    "The HTML references cid:{}, but there is no inline attachment with that content id.",
+                    unknown
+                )));
+            }
+        }
+        Ok(Self {
+            title: body.title.clone(),
+            list_ids: lists.iter().map(|list| list.id).collect(),
+            list_name: lists
+                .first()
+                .map(|list| list.name.clone())
+                .unwrap_or_default(),
+            segment,
+            source,
+            files,
+            tracking: body.tracking.unwrap_or_default(),
+        })
+    }
+
+    // 保存为一期的版本时使用的请求：上传的文件已经保存为asset，改为引用它们，
+    // 这样后台任务发送时不再需要原始的请求体
+    pub fn stored_request(&self, body: &BodyData) -> BodyData {
+        let attachments = self
+            .files
+            .iter()
+            .map(|(asset, attachment)| AttachmentReference {
+                asset_id: asset.id,
+                content_id: attachment.content_id.clone(),
+            })
+            .collect::<Vec<_>>();
+        BodyData {
+            attachments: (!attachments.is_empty()).then_some(attachments),
+            ..body.clone()
+        }
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    // 预览和测试邮件使用的收件人。令牌为sample，邮件中的退订和偏好设置链接不对应任何订阅者
+    pub fn sample_recipient(&self, name: &str, email: &str, locale: &str) -> Recipient {
+        Recipient {
+            subscriber_id: Uuid::nil(),
+            email: email.into(),
+            name: name.into(),
+            list: self.list_name.clone(),
+            unsubscribe_token: "sample".into(),
+            preferences_token: "sample".into(),
+            locale: Some(locale.into()),
+            tracking_opt_out: true,
+        }
+    }
+
+    // 选择了该接收频率的收件人，被屏蔽的地址除外
+    pub async fn recipients(
+        &self,
+        pool: &PgPool,
+        frequency: DeliveryFrequency,
+    ) -> Result<Vec<Recipient>, sqlx::Error> {
+        let recipients = memberships::confirmed_recipients(
+            pool,
+            &self.list_ids,
+            self.segment.as_ref(),
+            frequency,
+        )
+        .await?;
+        without_suppressed(pool, recipients).await
+    }
+
+    // 发送前重新检查一个收件人：仍然已确认、没有暂停、满足分组且没有被屏蔽，否则返回None
+    pub async fn recipient(
+        &self,
+        pool: &PgPool,
+        subscriber_id: Uuid,
+    ) -> Result<Option<Recipient>, sqlx::Error> {
+        let recipient = memberships::confirmed_recipient(
+            pool,
+            &self.list_ids,
+            self.segment.as_ref(),
+            DeliveryFrequency::Immediate,
+            subscriber_id,
+        )
+        .await?;
+        let recipients = without_suppressed(pool, recipient.into_iter().collect()).await?;
+        Ok(recipients.into_iter().next())
+    }
+
+    // 放入每周摘要队列的内容，每一项为(订阅者id, HTML, 纯文本)。摘要不带附件，改为链接到公开的地址
+    pub fn digest_items(
+        &self,
+        recipients: &[Recipient],
+        i18n: &I18n,
+        base_url: &str,
+    ) -> Result<Vec<(Uuid, String, String)>, PublishError> {
+        let mut items = Vec::with_capacity(recipients.len());
+        for recipient in recipients {
+            let catalogue = i18n.for_locale(recipient.locale.as_deref());
+            let email = self.render(recipient, catalogue, base_url, false)?;
+            let (html, text) = link_attachments(email.html, email.text, &self.files, base_url);
+            items.push((recipient.subscriber_id, html, text));
+        }
+        Ok(items)
+    }
+
+    // 发给一个收件人的邮件。每封邮件都带有该订阅者自己的退订链接和偏好设置链接，
+    // 模板中已经包含退订链接时不再重复添加
+    // tracking为跟踪设置和这一期的id，只有定时发送的一期才有；订阅者选择了不被跟踪时不加跟踪
+    pub fn message(
+        &self,
+        recipient: &Recipient,
+        email: &SubscriberEmail,
+        sender: &str,
+        i18n: &I18n,
+        base_url: &str,
+        tracking: Option<(&Tracking, Uuid)>,
+    ) -> Result<EmailMessage, PublishError> {
+        let catalogue = i18n.for_locale(recipient.locale.as_deref());
+        let RenderedEmail {
+            subject,
+            html,
+            text,
+        } = self.render(recipient, catalogue, base_url, true)?;
+        let unsubscribe_link = unsubscribe_link(base_url, &recipient.unsubscribe_token);
+        let preferences_link = preferences_link(base_url, &recipient.preferences_token);
+        let (unsubscribe, manage) = (
+            catalogue.t("email.unsubscribe"),
+            catalogue.t("email.manage_preferences"),
+        );
+        let html = if html.contains(&unsubscribe_link) {
+            html
+        } else {
+            format!(
+                "{}<p><a href=\"{}\">{}</a> | <a href=\"{}\">{}</a></p>",
+                html,
+                unsubscribe_link,
+                escape_html(unsubscribe),
+                preferences_link,
+                escape_html(manage)
+            )
+        };
+        let text = if text.contains(&unsubscribe_link) {
+            text
+        } else {
+            format!(
+                "{}\n\n{}: {}\n{}: {}",
+                text, unsubscribe, unsubscribe_link, manage, preferences_link
+            )
+        };
+        let html = match tracking {
+            Some((tracking, issue_id)) if !recipient.tracking_opt_out => tracking.instrument(
+                html,
+                self.tracking,
+                base_url,
+                issue_id,
+                recipient.subscriber_id,
+            ),
+            _ => html,
+        };
+        Ok(
+            EmailMessage::builder(Mailbox::new(sender), Mailbox::new(email.as_ref()))
+                .subject(&subject)
+                .html(&html)
+                .text(&text)
+                .attachments(self.files.iter().map(|(_, a)| a.clone()).collect())
+                .build(),
+        )
+    }
+
+    // 为一个收件人渲染邮件，模板中的{{t}}使用收件人的语言。模板没有标题时使用新闻邮件的标题
+    // layout为false时不套用布局，用于每周摘要中的一项
+    fn render(
+        &self,
+        recipient: &Recipient,
+        catalogue: &Catalogue,
+        base_url: &str,
+        layout: bool,
+    ) -> Result<RenderedEmail, PublishError> {
+        match &self.source {
+            Source::Content(content) => Ok(RenderedEmail {
+                subject: self.title.clone(),
+                html: content.html.clone(),
+                text: content
+                    .text
+                    .clone()
+                    .filter(|text| !text.trim().is_empty())
+                    .unwrap_or_else(|| html_to_text(&content.html)),
+            }),
+            Source::Template(templates, name) => {
+                let variables = TemplateVariables {
+                    name: recipient.name.clone(),
+                    email: recipient.email.clone(),
+                    list: recipient.list.clone(),
+                    title: self.title.clone(),
+                    unsubscribe_url: unsubscribe_link(base_url, &recipient.unsubscribe_token),
+                    preferences_url: preferences_link(base_url, &recipient.preferences_token),
+                    confirmation_url: String::new(),
+                    locale: catalogue.locale().into(),
+                };
+                let rendered = if layout {
+                    templates.render(name, &variables)
+                } else {
+                    templates
+                        .render_body(name, &variables)
+                        .map(|(html, text)| RenderedEmail {
+                            subject: self.title.clone(),
+                            html,
+                            text,
+                        })
+                };
+                rendered.map_err(|e| {
+                    tracing::error!("Failed to render newsletter issue: {}", e);
+                    PublishError::Unexpected(e)
+                })
+            }
+        }
+    }
+}
+
+// 去掉退信、投诉或者要求删除数据而被屏蔽的地址
+async fn without_suppressed(
+    pool: &PgPool,
+    recipients: Vec<Recipient>,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    let emails: Vec<&str> = recipients.iter().map(|r| r.email.as_str()).collect();
+    let suppressed = suppressions::find_suppressed(pool, &emails).await?;
+    if suppressed.is_empty() {
+        return Ok(recipients);
+    }
+    Ok(recipients
+        .into_iter()
+        .filter(|r| !suppressed.contains(&suppressions::email_hash(&r.email)))
+        .collect())
+}
+
+fn unsubscribe_link(base_url: &str, token: &str) -> String {
+    format!("{}/subscriptions/unsubscribe?token={}", base_url, token)
+}
+
+// 引用的asset和上传的文件，连同它们的内容。上传的文件先保存为新的asset，内嵌图片以文件名作为content id
+// 在保存任何文件之前检查content id和总大小
+async fn resolve_attachments(
+    body: &BodyData,
+    uploads: Vec<FormPart>,
+    pool: &PgPool,
+    attachments: &Attachments,
+    user_id: Uuid,
+) -> Result<Vec<(Asset, Attachment)>, AttachmentError> {
+    let references = body.attachments.as_deref().unwrap_or_default();
+    let content_ids: Vec<&str> = references
+        .iter()
+        .filter_map(|r| r.content_id.as_deref())
+        .chain(
+            uploads
+                .iter()
+                .filter(|part| part.name == "inline")
+                .filter_map(|part| part.filename.as_deref()),
+        )
+        .collect();
+    for (i, content_id) in content_ids.iter().enumerate() {
+        if content_id.is_empty()
+            || content_id.len() > 100
+            || !content_id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "._@-".contains(c))
+        {
+            return Err(AttachmentError::Invalid(format!(
+                "{:?} is not a valid content id. Use letters, digits, '.', '_', '@' and '-'.",
+                content_id
+            )));
+        }
+        if content_ids[..i].contains(content_id) {
+            return Err(AttachmentError::Invalid(format!(
+                "The content id {} is used more than once.",
+                content_id
+            )));
+        }
+    }
+
+    let ids: Vec<Uuid> = references.iter().map(|r| r.asset_id).collect();
+    let mut resolved = Vec::with_capacity(references.len() + uploads.len());
+    for (reference, asset) in references
+        .iter()
+        .zip(assets::find_by_ids(pool, &ids).await?)
+    {
+        let asset = asset.ok_or_else(|| {
+            AttachmentError::Invalid(format!("There is no asset with id {}.", reference.asset_id))
+        })?;
+        resolved.push((asset, reference.content_id.clone()));
+    }
+    let total = resolved
+        .iter()
+        .map(|(asset, _)| asset.size_bytes as u64)
+        .sum::<u64>()
+        + uploads
+            .iter()
+            .map(|part| part.content.len() as u64)
+            .sum::<u64>();
+    attachments.check_total_size(total)?;
+    for part in uploads {
+        let filename = part.filename.unwrap_or_default();
+        let content_type = part
+            .content_type
+            .unwrap_or_else(|| "application/octet-stream".into());
+        let asset = attachments
+            .upload(pool, &filename, &content_type, &part.content, user_id)
+            .await?;
+        let content_id = (part.name == "inline").then_some(filename);
+        resolved.push((asset, content_id));
+    }
+
+    let mut files = Vec::with_capacity(resolved.len());
+    for (asset, content_id) in resolved {
+        let attachment = attachments.load(&asset, content_id).await?;
+        files.push((asset, attachment));
+    }
+    Ok(files)
+}
+
+// HTML中引用的所有content id，如<img src="cid:logo.png">中的logo.png
+fn content_id_references(html: &str) -> Vec<&str> {
+    html.match_indices("cid:")
+        .map(|(start, _)| {
+            let rest = &html[start + 4..];
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "\"'<>()".contains(c))
+                .unwrap_or(rest.len());
+            &rest[..end]
+        })
+        .collect()
+}
+
+// 每周摘要不带附件：内嵌图片改为引用公开的地址，普通附件以链接的形式列在最后
+fn link_attachments(
+    mut html: String,
+    mut text: String,
+    files: &[(Asset, Attachment)],
+    base_url: &str,
+) -> (String, String) {
+    let mut links = Vec::new();
+    for (asset, attachment) in files {
+        let url = format!("{}/assets/{}", base_url, asset.id);
+        match &attachment.content_id {
+            Some(content_id) => {
+                for quote in ['"', '\''] {
+                    html = html.replace(
+                        &format!("{}cid:{}{}", quote, content_id, quote),
+                        &format!("{}{}{}", quote, url, quote),
+                    );
+                }
+            }
+            None => links.push((&asset.filename, url)),
+        }
+    }
+    if !links.is_empty() {
+        html.push_str("<ul>");
+        text.push('\n');
+        for (filename, url) in links {
+            html.push_str(&format!(
+                "<li><a href=\"{}\">{}</a></li>",
+                url,
+                escape_html(filename)
+            ));
+            text.push_str(&format!("\n{}: {}", filename, url));
+        }
+        html.push_str("</ul>");
+    }
+    (html, text)
+}
@@ -0,0 +1,34 @@
+use std::net::IpAddr;
+
+use actix_web::HttpRequest;
+
+// 确定发起请求的客户端IP
+// 只有当直接连接我们的对端是受信任的代理时，才会参考X-Forwarded-For头：
+// 从右往左（即从离我们最近的一跳开始）跳过所有受信任的代理，第一个不受信任的地址就是客户端。
+// 注：X-Forwarded-For最左侧的值完全由客户端控制，不能直接使用，否则任何人都可以伪造IP绕过限流
+pub fn client_ip(req: &HttpRequest, trusted_proxies: &[ipnet::IpNet]) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    let is_trusted = |ip: &IpAddr| trusted_proxies.iter().any(|net| net.contains(ip));
+    if !is_trusted(&peer) {
+        return Some(peer);
+    }
+
+    let hops: Vec<&str> = req
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(ip) if is_trusted(&ip) => client = ip,
+            Ok(ip) => return Some(ip),
+            // 无法解析的值说明该头已不可信，停止向左查找
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
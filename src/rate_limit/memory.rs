@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::take_token;
+use crate::configuration::TokenBucketSettings;
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+#[derive(Default)]
+pub struct InMemoryStore {
+    buckets: Mutex<HashMap<String, (Bucket, TokenBucketSettings)>>,
+}
+
+impl InMemoryStore {
+    pub fn take(&self, key: &str, settings: TokenBucketSettings) -> Result<(), Duration> {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let (bucket, _) = buckets.entry(key.to_string()).or_insert_with(|| {
+            (
+                Bucket {
+                    tokens: settings.capacity as f64,
+                    updated_at: now,
+                },
+                settings,
+            )
+        });
+        let tokens = refill(bucket, settings, now);
+        let remaining = take_token(tokens, settings)?;
+        bucket.tokens = remaining;
+        bucket.updated_at = now;
+        Ok(())
+    }
+
+    // 清理已经补满的桶（它们与不存在的桶等价），避免内存无限增长
+    // 由后台任务定时调用，而不是在处理请求时遍历所有的桶
+    pub fn prune(&self) {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .unwrap()
+            .retain(|_, (bucket, settings)| {
+                refill(bucket, *settings, now) < settings.capacity as f64
+            });
+    }
+}
+
+// 计算按经过的时间补充之后的令牌数（不超过桶的容量）
+fn refill(bucket: &Bucket, settings: TokenBucketSettings, now: Instant) -> f64 {
+    let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+    let refilled = elapsed / settings.refill_interval_seconds as f64;
+    (bucket.tokens + refilled).min(settings.capacity as f64)
+}
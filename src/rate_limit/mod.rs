@@ -0,0 +1,190 @@
+mod client_ip;
+mod memory;
+mod postgres;
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::{header, StatusCode},
+    middleware::Next,
+    web, HttpRequest, HttpResponse, ResponseError,
+};
+use sqlx::PgPool;
+
+use crate::configuration::{RateLimitSettings, RateLimitStoreKind, TokenBucketSettings};
+use memory::InMemoryStore;
+
+pub use client_ip::client_ip;
+
+// 清理已经补满的令牌桶的间隔
+const CLEANUP_INTERVAL: Duration = Duration::from_secs(60);
+
+// 令牌桶的存储后端：单实例部署时使用内存即可，多实例部署时需要共享的Postgres存储
+#[derive(Clone)]
+enum RateLimitStore {
+    InMemory(Arc<InMemoryStore>),
+    Postgres(PgPool),
+}
+
+// 对POST /subscriptions进行限流，分别按客户端IP和目标邮箱地址使用令牌桶
+pub struct RateLimiter {
+    store: RateLimitStore,
+    per_ip: TokenBucketSettings,
+    per_email: TokenBucketSettings,
+    trusted_proxies: Vec<ipnet::IpNet>,
+}
+
+impl RateLimiter {
+    pub fn new(settings: &RateLimitSettings, db_pool: PgPool) -> Self {
+        let store = match settings.store {
+            RateLimitStoreKind::Memory => RateLimitStore::InMemory(Arc::default()),
+            RateLimitStoreKind::Postgres => RateLimitStore::Postgres(db_pool),
+        };
+        Self {
+            store,
+            per_ip: settings.per_ip,
+            per_email: settings.per_email,
+            trusted_proxies: settings.trusted_proxies.clone(),
+        }
+    }
+
+    // 定时清理令牌桶的后台任务，与限流器共享同一个存储
+    pub fn cleanup(&self) -> RateLimitCleanup {
+        RateLimitCleanup {
+            store: self.store.clone(),
+            per_ip: self.per_ip,
+            per_email: self.per_email,
+        }
+    }
+
+    // 按照与限流相同的规则（只信任配置中的代理）确定客户端IP，供handler使用
+    pub fn client_ip(&self, req: &HttpRequest) -> Option<IpAddr> {
+        client_ip(req, &self.trusted_proxies)
+    }
+
+    pub async fn check_ip(&self, ip: IpAddr) -> Result<(), TooManyRequests> {
+        self.check(&format!("ip:{}", ip), self.per_ip).await
+    }
+
+    // 邮箱地址不区分大小写，先规范化再作为键，避免通过改变大小写绕过限制
+    pub async fn check_email(&self, email: &str) -> Result<(), TooManyRequests> {
+        let key = format!("email:{}", email.trim().to_lowercase());
+        self.check(&key, self.per_email).await
+    }
+
+    #[tracing::instrument(name = "Checking rate limit", skip(self, bucket))]
+    async fn check(&self, key: &str, bucket: TokenBucketSettings) -> Result<(), TooManyRequests> {
+        let outcome = match &self.store {
+            RateLimitStore::InMemory(store) => Ok(store.take(key, bucket)),
+            RateLimitStore::Postgres(pool) => postgres::take(pool, key, bucket).await,
+        };
+        match outcome {
+            Ok(Ok(())) => Ok(()),
+            Ok(Err(retry_after)) => Err(TooManyRequests { retry_after }),
+            // 存储不可用时放行请求（fail open），限流失效总好过整个订阅功能不可用
+            Err(e) => {
+                tracing::error!("Failed to check rate limit: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+// 删除已经补满的令牌桶，使内存或rate_limit_buckets表的大小只取决于近期活跃的客户端
+pub struct RateLimitCleanup {
+    store: RateLimitStore,
+    per_ip: TokenBucketSettings,
+    per_email: TokenBucketSettings,
+}
+
+impl RateLimitCleanup {
+    pub async fn run_until_stopped(self) {
+        loop {
+            // 失败时只记录日志，下一轮再试
+            let _ = self.run_once().await;
+            tokio::time::sleep(CLEANUP_INTERVAL).await;
+        }
+    }
+
+    #[tracing::instrument(name = "Pruning rate limit buckets", skip(self))]
+    pub async fn run_once(&self) -> Result<(), sqlx::Error> {
+        match &self.store {
+            RateLimitStore::InMemory(store) => store.prune(),
+            RateLimitStore::Postgres(pool) => {
+                postgres::prune(pool, "ip:", self.per_ip).await?;
+                postgres::prune(pool, "email:", self.per_email).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// 从桶中取出一个令牌。`tokens`是按经过的时间补充之后（但尚未扣减）的令牌数
+// 成功时返回剩余的令牌数，失败时返回需要等待多久才会有下一个令牌
+fn take_token(tokens: f64, bucket: TokenBucketSettings) -> Result<f64, Duration> {
+    if tokens >= 1.0 {
+        Ok(tokens - 1.0)
+    } else {
+        Err(Duration::from_secs_f64(
+            (1.0 - tokens) * bucket.refill_interval_seconds as f64,
+        ))
+    }
+}
+
+// 超过限流阈值时返回的错误，对应429响应
+#[derive(Debug)]
+pub struct TooManyRequests {
+    retry_after: Duration,
+}
+
+impl std::fmt::Display for TooManyRequests {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Too many requests, retry after {} seconds",
+            self.retry_after_seconds()
+        )
+    }
+}
+
+impl TooManyRequests {
+    // Retry-After只接受整数秒，向上取整以免客户端过早重试
+    fn retry_after_seconds(&self) -> u64 {
+        self.retry_after.as_secs_f64().ceil() as u64
+    }
+}
+
+impl ResponseError for TooManyRequests {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::TOO_MANY_REQUESTS
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::TooManyRequests()
+            .insert_header((header::RETRY_AFTER, self.retry_after_seconds()))
+            .finish()
+    }
+}
+
+// 中间件：按客户端IP限流
+pub async fn limit_by_client_ip(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let rate_limiter = req
+        .app_data::<web::Data<RateLimiter>>()
+        .expect("RateLimiter is not registered as app data")
+        .clone();
+    if let Some(ip) = client_ip(req.request(), &rate_limiter.trusted_proxies) {
+        if let Err(e) = rate_limiter.check_ip(ip).await {
+            return Ok(req.error_response(e).map_into_right_body());
+        }
+    }
+    next.call(req)
+        .await
+        .map(ServiceResponse::map_into_left_body)
+}
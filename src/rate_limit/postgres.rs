@@ -0,0 +1,92 @@
+use std::time::Duration;
+
+use sqlx::PgPool;
+
+use super::take_token;
+use crate::configuration::TokenBucketSettings;
+
+// 基于Postgres的令牌桶，供多个应用实例共享
+// 在同一个事务中通过SELECT ... FOR UPDATE锁住该行，保证并发请求不会重复消费同一个令牌
+pub async fn take(
+    pool: &PgPool,
+    key: &str,
+    settings: TokenBucketSettings,
+) -> Result<Result<(), Duration>, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+        VALUES ($1, $2, clock_timestamp())
+        ON CONFLICT (key) DO NOTHING
+        "#,
+        key,
+        settings.capacity as f64,
+    )
+    .execute(&mut *transaction)
+    .await?;
+
+    // 使用数据库的时间计算补充的令牌，避免多个实例之间的时钟偏差
+    // 注：这里使用clock_timestamp()而不是now()，因为now()是事务开始的时间，等待行锁之后它已经过时了
+    let tokens = sqlx::query_scalar!(
+        r#"
+        SELECT LEAST(
+            $2::float8,
+            tokens + EXTRACT(EPOCH FROM (clock_timestamp() - updated_at))::float8 / $3::float8
+        ) AS "tokens!"
+        FROM rate_limit_buckets
+        WHERE key = $1
+        FOR UPDATE
+        "#,
+        key,
+        settings.capacity as f64,
+        settings.refill_interval_seconds as f64,
+    )
+    .fetch_one(&mut *transaction)
+    .await?;
+
+    let outcome = match take_token(tokens, settings) {
+        Ok(remaining) => {
+            sqlx::query!(
+                r#"
+                UPDATE rate_limit_buckets
+                SET tokens = $2, updated_at = clock_timestamp()
+                WHERE key = $1
+                "#,
+                key,
+                remaining,
+            )
+            .execute(&mut *transaction)
+            .await?;
+            Ok(())
+        }
+        Err(retry_after) => Err(retry_after),
+    };
+    transaction.commit().await?;
+    Ok(outcome)
+}
+
+// 删除空闲时间超过补满时间的桶：它们已经补满，与不存在的桶等价
+// 只删除指定前缀的键，发送速率的令牌桶（send:）也保存在这张表中，使用各自的设置
+pub async fn prune(
+    pool: &PgPool,
+    key_prefix: &str,
+    settings: TokenBucketSettings,
+) -> Result<u64, sqlx::Error> {
+    let idle_seconds = settings.capacity as f64 * settings.refill_interval_seconds as f64;
+    let result = sqlx::query!(
+        r#"
+        DELETE FROM rate_limit_buckets
+        WHERE starts_with(key, $1)
+            AND updated_at < clock_timestamp() - make_interval(secs => $2)
+        "#,
+        key_prefix,
+        idle_seconds,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,89 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// assets表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct Asset {
+    pub id: Uuid,
+    #[serde(skip)]
+    pub blob_key: String,
+    pub filename: String,
+    pub content_type: String,
+    pub size_bytes: i64,
+    pub uploaded_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Insert asset", skip(pool))]
+pub async fn insert(
+    pool: &PgPool,
+    blob_key: &str,
+    filename: &str,
+    content_type: &str,
+    size_bytes: i64,
+    uploaded_by: Uuid,
+) -> Result<Asset, sqlx::Error> {
+    sqlx::query_as!(
+        Asset,
+        r#"
+        INSERT INTO assets (id, blob_key, filename, content_type, size_bytes, uploaded_by, uploaded_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, blob_key, filename, content_type, size_bytes, uploaded_at
+        "#,
+        Uuid::new_v4(),
+        blob_key,
+        filename,
+        content_type,
+        size_bytes,
+        uploaded_by,
+        Utc::now(),
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get asset by id", skip(pool))]
+pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<Asset>, sqlx::Error> {
+    sqlx::query_as!(
+        Asset,
+        r#"
+        SELECT id, blob_key, filename, content_type, size_bytes, uploaded_at
+        FROM assets WHERE id = $1
+        "#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 按给定的顺序返回，不存在的id对应None
+#[tracing::instrument(name = "Get assets by ids", skip(pool))]
+pub async fn find_by_ids(pool: &PgPool, ids: &[Uuid]) -> Result<Vec<Option<Asset>>, sqlx::Error> {
+    let found = sqlx::query_as!(
+        Asset,
+        r#"
+        SELECT id, blob_key, filename, content_type, size_bytes, uploaded_at
+        FROM assets WHERE id = ANY($1)
+        "#,
+        ids,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(ids
+        .iter()
+        .map(|id| found.iter().find(|asset| asset.id == *id).cloned())
+        .collect())
+}
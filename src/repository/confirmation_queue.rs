@@ -0,0 +1,129 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+
+use crate::repository::lists::MailingList;
+
+// 确认邮件队列中的一封邮件
+#[derive(Debug)]
+pub struct ConfirmationTask {
+    pub subscription_token: String,
+    pub n_retries: i16,
+}
+
+// 认领一个到期的任务，与delivery_queue::claim相同：推迟到lease_until后立即提交，发送在事务之外进行
+#[tracing::instrument(name = "Claim confirmation email task", skip(pool))]
+pub async fn claim(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<Option<ConfirmationTask>, sqlx::Error> {
+    sqlx::query_as!(
+        ConfirmationTask,
+        r#"
+        UPDATE confirmation_email_queue
+        SET execute_after = $2
+        WHERE subscription_token = (
+            SELECT subscription_token
+            FROM confirmation_email_queue
+            WHERE execute_after <= $1
+            ORDER BY execute_after
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING subscription_token, n_retries
+        "#,
+        now,
+        lease_until,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Delete confirmation email task", skip(pool))]
+pub async fn delete(pool: &PgPool, task: &ConfirmationTask) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM confirmation_email_queue WHERE subscription_token = $1"#,
+        task.subscription_token,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 发送失败后推迟到execute_after再试
+#[tracing::instrument(name = "Retry confirmation email task", skip(pool))]
+pub async fn retry(
+    pool: &PgPool,
+    task: &ConfirmationTask,
+    execute_after: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE confirmation_email_queue
+        SET n_retries = n_retries + 1, execute_after = $2
+        WHERE subscription_token = $1
+        "#,
+        task.subscription_token,
+        execute_after,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 确认邮件的收件人，以及要确认的列表
+pub struct PendingConfirmation {
+    pub email: String,
+    pub name: String,
+    pub locale: Option<String>,
+    pub list: MailingList,
+}
+
+// 令牌对应的订阅者仍在等待确认时返回收件人；已经确认、退订或被封禁时返回None，不再发送
+#[tracing::instrument(name = "Find pending confirmation", skip(pool, subscription_token))]
+pub async fn find_pending(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<PendingConfirmation>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, s.attributes->>'locale' AS locale,
+            l.id AS list_id, l.slug, l.name AS list_name, l.created_at
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        JOIN list_memberships m ON m.subscriber_id = t.subscriber_id AND m.list_id = t.list_id
+        JOIN lists l ON l.id = t.list_id
+        WHERE t.subscription_token = $1 AND m.status = 'pending_confirmation'
+        "#,
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(row.map(|row| PendingConfirmation {
+        email: row.email,
+        name: row.name,
+        locale: row.locale,
+        list: MailingList {
+            id: row.list_id,
+            slug: row.slug,
+            name: row.list_name,
+            created_at: row.created_at,
+        },
+    }))
+}
@@ -0,0 +1,157 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Executor, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::StatusTransition;
+
+// 订阅者状态的每一次变化。与状态变化写在同一个事务中，二者要么都记录下来，要么都没有发生
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsentEvent {
+    // 通过订阅表单注册
+    Signup,
+    Confirm,
+    Unsubscribe,
+    Block,
+    // 管理员修改了邮箱或名字
+    AdminEdit,
+    Import,
+    Delete,
+    // 订阅者要求删除自己的数据
+    Erasure,
+}
+
+impl ConsentEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Signup => "signup",
+            Self::Confirm => "confirm",
+            Self::Unsubscribe => "unsubscribe",
+            Self::Block => "block",
+            Self::AdminEdit => "admin_edit",
+            Self::Import => "import",
+            Self::Delete => "delete",
+            Self::Erasure => "erasure",
+        }
+    }
+}
+
+impl From<StatusTransition> for ConsentEvent {
+    fn from(transition: StatusTransition) -> Self {
+        match transition {
+            StatusTransition::Confirm => Self::Confirm,
+            StatusTransition::Unsubscribe => Self::Unsubscribe,
+            StatusTransition::Block => Self::Block,
+        }
+    }
+}
+
+// 事件是怎么发生的：订阅者本人发起的请求会带上IP和User-Agent，管理员和系统操作则没有
+#[derive(Debug, Clone)]
+pub struct ConsentSource {
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+impl ConsentSource {
+    pub fn new(source: impl Into<String>) -> Self {
+        Self {
+            source: source.into(),
+            ip_address: None,
+            user_agent: None,
+        }
+    }
+
+    pub fn admin(username: &str) -> Self {
+        Self::new(format!("admin:{}", username))
+    }
+}
+
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct ConsentEventRecord {
+    pub event: String,
+    // 事件涉及的列表，针对订阅者本身的事件（如修改资料）为None
+    pub list: Option<String>,
+    pub source: String,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Record consent event", skip(transaction, source))]
+pub async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Option<Uuid>,
+    event: ConsentEvent,
+    source: &ConsentSource,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO consent_events (subscriber_id, list_id, event, source, ip_address, user_agent, occurred_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        subscriber_id,
+        list_id,
+        event.as_str(),
+        source.source,
+        source.ip_address,
+        source.user_agent,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 按时间顺序返回该订阅者的全部事件
+#[tracing::instrument(name = "List consent events", skip(executor))]
+pub async fn list<'c>(
+    executor: impl Executor<'c, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ConsentEventRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        ConsentEventRecord,
+        r#"
+        SELECT e.event, l.slug AS "list?", e.source, e.ip_address, e.user_agent, e.occurred_at
+        FROM consent_events e
+        LEFT JOIN lists l ON l.id = e.list_id
+        WHERE e.subscriber_id = $1
+        ORDER BY e.occurred_at, e.id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 删除个人数据时清除IP和User-Agent，只保留“何时、以何种方式”同意或撤回同意
+#[tracing::instrument(name = "Anonymise consent events", skip(transaction))]
+pub async fn anonymise(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE consent_events SET ip_address = NULL, user_agent = NULL
+        WHERE subscriber_id = $1
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
@@ -0,0 +1,123 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// 投递队列中的一封邮件
+#[derive(Debug)]
+pub struct DeliveryTask {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub n_retries: i16,
+}
+
+// 认领一个到期的任务：把它的execute_after推迟到lease_until后立即提交，其他实例在此之前不会再取到它
+// 发送（包括等待发送速率的令牌）在事务之外进行，不会在等待期间占用连接和行锁。
+// 进程在发送完成前退出时，任务在lease_until之后被重新认领
+#[tracing::instrument(name = "Claim delivery task", skip(pool))]
+pub async fn claim(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+    lease_until: DateTime<Utc>,
+) -> Result<Option<DeliveryTask>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryTask,
+        r#"
+        UPDATE issue_delivery_queue
+        SET execute_after = $2
+        WHERE (newsletter_issue_id, subscriber_id) = (
+            SELECT newsletter_issue_id, subscriber_id
+            FROM issue_delivery_queue
+            WHERE execute_after <= $1
+            ORDER BY execute_after
+            LIMIT 1
+            FOR UPDATE SKIP LOCKED
+        )
+        RETURNING newsletter_issue_id, subscriber_id, n_retries
+        "#,
+        now,
+        lease_until,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Delete delivery task", skip(pool))]
+pub async fn delete(pool: &PgPool, task: &DeliveryTask) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        DELETE FROM issue_delivery_queue
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 发送失败后推迟到execute_after再试
+#[tracing::instrument(name = "Retry delivery task", skip(pool))]
+pub async fn retry(
+    pool: &PgPool,
+    task: &DeliveryTask,
+    execute_after: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET n_retries = n_retries + 1, execute_after = $3
+        WHERE newsletter_issue_id = $1 AND subscriber_id = $2
+        "#,
+        task.newsletter_issue_id,
+        task.subscriber_id,
+        execute_after,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 投递队列的积压情况
+#[derive(Serialize, Debug)]
+pub struct QueueBacklog {
+    // 队列中的邮件总数
+    pub pending: i64,
+    // 已经到期、等待发送的邮件数
+    pub due: i64,
+    // 最早到期的邮件的到期时间，它与当前时间的差距就是发送落后的时间
+    pub oldest_due_at: Option<DateTime<Utc>>,
+}
+
+#[tracing::instrument(name = "Get delivery queue backlog", skip(pool))]
+pub async fn backlog(pool: &PgPool, now: DateTime<Utc>) -> Result<QueueBacklog, sqlx::Error> {
+    sqlx::query_as!(
+        QueueBacklog,
+        r#"
+        SELECT COUNT(*) AS "pending!",
+            COUNT(*) FILTER (WHERE execute_after <= $1) AS "due!",
+            MIN(execute_after) FILTER (WHERE execute_after <= $1) AS oldest_due_at
+        FROM issue_delivery_queue
+        "#,
+        now,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// 一期放入摘要队列的新闻邮件
+#[derive(Debug)]
+pub struct DigestItem {
+    pub id: i64,
+    pub title: String,
+    pub html_content: String,
+    pub text_content: String,
+}
+
+// 有待发送摘要的订阅者
+#[derive(Debug)]
+pub struct DigestRecipient {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub preferences_token: String,
+    pub locale: Option<String>,
+}
+
+// 把一期新闻邮件放入选择每周摘要的订阅者的队列，每一项为(订阅者id, HTML, 纯文本)，返回放入的份数
+// 收件人由memberships::confirmed_recipients选出，内容可能按订阅者渲染
+#[tracing::instrument(name = "Queue newsletter issue for digests", skip(executor, items))]
+pub async fn queue<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+    title: &str,
+    items: &[(Uuid, String, String)],
+) -> Result<u64, sqlx::Error> {
+    let subscriber_ids: Vec<Uuid> = items.iter().map(|(id, _, _)| *id).collect();
+    let html: Vec<String> = items.iter().map(|(_, html, _)| html.clone()).collect();
+    let text: Vec<String> = items.iter().map(|(_, _, text)| text.clone()).collect();
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO digest_items (subscriber_id, title, html_content, text_content, queued_at)
+        SELECT subscriber_id, $4, html_content, text_content, $5
+        FROM UNNEST($1::uuid[], $2::text[], $3::text[])
+            AS t(subscriber_id, html_content, text_content)
+        "#,
+        &subscriber_ids,
+        &html,
+        &text,
+        title,
+        Utc::now(),
+    )
+    .execute(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected())
+}
+
+// 队列中有内容且没有暂停的订阅者
+#[tracing::instrument(name = "Get digest recipients", skip(pool))]
+pub async fn recipients(
+    pool: &PgPool,
+    now: DateTime<Utc>,
+) -> Result<Vec<DigestRecipient>, sqlx::Error> {
+    sqlx::query_as!(
+        DigestRecipient,
+        r#"
+        SELECT s.id AS subscriber_id, s.email, s.preferences_token,
+            s.attributes->>'locale' AS locale
+        FROM subscriptions s
+        WHERE EXISTS (SELECT 1 FROM digest_items d WHERE d.subscriber_id = s.id)
+            AND (s.paused_until IS NULL OR s.paused_until <= $1)
+        ORDER BY s.id
+        "#,
+        now,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 取出该订阅者队列中的全部内容并从队列中删除，按放入的顺序返回
+// 注：调用方在邮件发送成功后才提交事务，发送失败时这些内容会留在队列中
+#[tracing::instrument(name = "Take digest items", skip(transaction))]
+pub async fn take(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<DigestItem>, sqlx::Error> {
+    sqlx::query_as!(
+        DigestItem,
+        r#"
+        DELETE FROM digest_items
+        WHERE subscriber_id = $1
+        RETURNING id, title, html_content, text_content
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut **transaction)
+    .await
+    .map(|mut items| {
+        items.sort_by_key(|item| item.id);
+        items
+    })
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,152 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::EmailEventKind;
+use crate::repository::suppressions::email_hash;
+
+// 统一格式之后的一个事件
+#[derive(Serialize, Debug)]
+pub struct EmailEvent {
+    pub kind: EmailEventKind,
+    pub email: String,
+    pub provider_event_id: Option<String>,
+    pub message_id: Option<String>,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+// 数据导出中的一个事件
+#[derive(Serialize, Debug)]
+pub struct EmailEventRecord {
+    pub kind: String,
+    pub message_id: Option<String>,
+    pub detail: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+// 保存一个事件，返回false表示服务商重复发送了同一个事件
+#[tracing::instrument(name = "Insert email event", skip(transaction, event), fields(kind = %event.kind))]
+pub async fn insert(
+    transaction: &mut Transaction<'_, Postgres>,
+    event: &EmailEvent,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        INSERT INTO email_events
+            (id, kind, email_hash, subscriber_id, provider_event_id, message_id, detail,
+            occurred_at, received_at)
+        VALUES ($1, $2, $3, (SELECT id FROM subscriptions WHERE email = $4), $5, $6, $7, $8, $9)
+        ON CONFLICT (provider_event_id) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        event.kind.as_str(),
+        email_hash(&event.email),
+        event.email,
+        event.provider_event_id,
+        event.message_id,
+        event.detail,
+        event.occurred_at,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected() > 0)
+}
+
+// 增加一个地址连续软退信的次数，返回增加之后的次数
+#[tracing::instrument(name = "Count soft bounce", skip(transaction, email))]
+pub async fn count_soft_bounce(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<i32, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO soft_bounce_counts (email_hash, count, updated_at)
+        VALUES ($1, 1, $2)
+        ON CONFLICT (email_hash) DO UPDATE
+        SET count = soft_bounce_counts.count + 1, updated_at = EXCLUDED.updated_at
+        RETURNING count
+        "#,
+        email_hash(email),
+        Utc::now(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 成功投递之后重新计数
+#[tracing::instrument(name = "Reset soft bounces", skip(transaction, email))]
+pub async fn reset_soft_bounces(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM soft_bounce_counts WHERE email_hash = $1"#,
+        email_hash(email),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 一个订阅者的所有事件，包括订阅之前或者没能对应到订阅者的、同一个地址的事件
+#[tracing::instrument(name = "Get email events of subscriber", skip(executor, email))]
+pub async fn list<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<Vec<EmailEventRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        EmailEventRecord,
+        r#"
+        SELECT kind, message_id, detail, occurred_at
+        FROM email_events
+        WHERE subscriber_id = $1 OR email_hash = $2
+        ORDER BY occurred_at, id
+        "#,
+        subscriber_id,
+        email_hash(email),
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 删除订阅者时一并删除：detail中保存着服务商报告的原始内容（地址、退信原因等）
+// 屏蔽列表中的哈希值仍然保留，不会再给这个地址发送邮件
+#[tracing::instrument(name = "Delete email events of subscriber", skip(transaction, email))]
+pub async fn delete_all(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    email: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM email_events WHERE subscriber_id = $1 OR email_hash = $2"#,
+        subscriber_id,
+        email_hash(email),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
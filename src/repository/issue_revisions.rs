@@ -0,0 +1,104 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::newsletter_issue::BodyData;
+
+// newsletter_issue_revisions表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct IssueRevision {
+    pub revision: i32,
+    pub request: Json<BodyData>,
+    // 保存该版本的管理员的用户名，用户被删除后为空
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+// 版本列表中的一项，不包含内容
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct RevisionSummary {
+    pub revision: i32,
+    pub title: String,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Insert issue revision", skip(transaction, request))]
+pub async fn insert(
+    transaction: &mut Transaction<'_, Postgres>,
+    issue_id: Uuid,
+    revision: i32,
+    request: &BodyData,
+    created_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_revisions
+            (newsletter_issue_id, revision, request, created_by, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        issue_id,
+        revision,
+        Json(request) as _,
+        created_by,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get issue revision", skip(executor))]
+pub async fn find<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+    issue_id: Uuid,
+    revision: i32,
+) -> Result<Option<IssueRevision>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueRevision,
+        r#"
+        SELECT r.revision, r.request AS "request!: Json<BodyData>",
+            u.username AS "created_by?", r.created_at
+        FROM newsletter_issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.created_by
+        WHERE r.newsletter_issue_id = $1 AND r.revision = $2
+        "#,
+        issue_id,
+        revision,
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 最新的版本在前
+#[tracing::instrument(name = "Get issue revisions", skip(pool))]
+pub async fn all(pool: &PgPool, issue_id: Uuid) -> Result<Vec<RevisionSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        RevisionSummary,
+        r#"
+        SELECT r.revision, r.request->>'title' AS "title!", u.username AS "created_by?",
+            r.created_at
+        FROM newsletter_issue_revisions r
+        LEFT JOIN users u ON u.user_id = r.created_by
+        WHERE r.newsletter_issue_id = $1
+        ORDER BY r.revision DESC
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,362 @@
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::IssueStatus;
+
+// newsletter_issues表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct NewsletterIssue {
+    pub id: Uuid,
+    // 最新版本的标题
+    pub title: String,
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub timezone: Option<String>,
+    pub failure_reason: Option<String>,
+    pub latest_revision: i32,
+    // 被安排发送的版本
+    pub published_revision: Option<i32>,
+    #[serde(skip)]
+    pub created_by: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+// 新建的一期只有第一个版本，由调用方在同一个事务中通过issue_revisions::insert保存
+// 安排了发送时间时发送的就是第一个版本
+#[tracing::instrument(name = "Insert newsletter issue", skip(transaction))]
+pub async fn insert(
+    transaction: &mut Transaction<'_, Postgres>,
+    title: &str,
+    scheduled_at: Option<DateTime<Utc>>,
+    timezone: Option<&str>,
+    created_by: Uuid,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    let status = match scheduled_at {
+        Some(_) => IssueStatus::Scheduled,
+        None => IssueStatus::Draft,
+    };
+    let now = Utc::now();
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        INSERT INTO newsletter_issues
+            (id, title, status, scheduled_at, timezone, latest_revision, published_revision,
+            created_by, created_at, updated_at)
+        VALUES ($1, $2, $3, $4, $5, 1, $6, $7, $8, $8)
+        RETURNING id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        "#,
+        Uuid::new_v4(),
+        title,
+        status.as_str(),
+        scheduled_at,
+        timezone,
+        scheduled_at.map(|_| 1),
+        created_by,
+        now,
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get newsletter issue by id", skip(pool))]
+pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        FROM newsletter_issues WHERE id = $1
+        "#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 锁住一期，保存新版本时防止并发的编辑得到相同的版本号，也防止与调度循环同时修改
+#[tracing::instrument(name = "Lock newsletter issue", skip(transaction))]
+pub async fn lock(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        FROM newsletter_issues WHERE id = $1
+        FOR UPDATE
+        "#,
+        id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Set latest revision", skip(transaction))]
+pub async fn set_latest_revision(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    revision: i32,
+    title: &str,
+) -> Result<NewsletterIssue, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET latest_revision = $2, title = $3, updated_at = $4
+        WHERE id = $1
+        RETURNING id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        "#,
+        id,
+        revision,
+        title,
+        Utc::now(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 最新创建的在前，可以只列出某个状态的
+#[tracing::instrument(name = "Get newsletter issues", skip(pool))]
+pub async fn all(
+    pool: &PgPool,
+    status: Option<IssueStatus>,
+) -> Result<Vec<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        FROM newsletter_issues
+        WHERE $1::text IS NULL OR status = $1
+        ORDER BY created_at DESC, id
+        "#,
+        status.map(|s| s.as_str()),
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 安排或重新安排发送时间，并选定发送的版本。只有draft和scheduled可以被修改，其他状态（以及不存在的id）返回None
+// 注：调度循环在取出到期的一期时会锁住该行，与这里的UPDATE互斥，因此不会出现发送开始后又被修改的情况
+#[tracing::instrument(name = "Schedule newsletter issue", skip(pool))]
+pub async fn schedule(
+    pool: &PgPool,
+    id: Uuid,
+    scheduled_at: DateTime<Utc>,
+    timezone: Option<&str>,
+    revision: i32,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'scheduled', scheduled_at = $2, timezone = $3, published_revision = $5,
+            updated_at = $4
+        WHERE id = $1 AND status IN ('draft', 'scheduled')
+        RETURNING id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        "#,
+        id,
+        scheduled_at,
+        timezone,
+        Utc::now(),
+        revision,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 取消一期尚未开始发送的邮件，与schedule一样只对draft和scheduled生效
+#[tracing::instrument(name = "Cancel newsletter issue", skip(pool))]
+pub async fn cancel(pool: &PgPool, id: Uuid) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled', updated_at = $2
+        WHERE id = $1 AND status IN ('draft', 'scheduled')
+        RETURNING id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        "#,
+        id,
+        Utc::now(),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 把某个时区的本地时间转换为UTC，时区是IANA名称（如Europe/Berlin），不存在时返回None
+// 注：使用Postgres的时区数据库，夏令时切换时不存在的本地时间会被顺延
+#[tracing::instrument(name = "Convert local time", skip(pool))]
+pub async fn local_to_utc(
+    pool: &PgPool,
+    local: NaiveDateTime,
+    timezone: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT $1::timestamp AT TIME ZONE name AS "utc!"
+        FROM pg_timezone_names WHERE name = $2
+        "#,
+        local,
+        timezone,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 锁住一期到期的scheduled，其他实例会跳过被锁住的行，因此每一期只会被一个实例取出
+#[tracing::instrument(name = "Lock due newsletter issue", skip(transaction))]
+pub async fn lock_due(
+    transaction: &mut Transaction<'_, Postgres>,
+    now: DateTime<Utc>,
+) -> Result<Option<NewsletterIssue>, sqlx::Error> {
+    sqlx::query_as!(
+        NewsletterIssue,
+        r#"
+        SELECT id, title, status, scheduled_at, timezone, failure_reason, latest_revision,
+            published_revision, created_by, created_at, updated_at, sent_at
+        FROM newsletter_issues
+        WHERE status = 'scheduled' AND scheduled_at <= $1
+        ORDER BY scheduled_at
+        LIMIT 1
+        FOR UPDATE SKIP LOCKED
+        "#,
+        now,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 把收件人放入投递队列，并将状态改为sending
+#[tracing::instrument(
+    name = "Start sending newsletter issue",
+    skip(transaction, subscriber_ids)
+)]
+pub async fn start_sending(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    subscriber_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_id, execute_after)
+        SELECT $1, subscriber_id, $3 FROM UNNEST($2::uuid[]) AS t(subscriber_id)
+        "#,
+        id,
+        subscriber_ids,
+        now,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query!(
+        r#"UPDATE newsletter_issues SET status = 'sending', updated_at = $2 WHERE id = $1"#,
+        id,
+        now,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 到期时内容已经无法发送（如引用的模板或列表被删除），取消这一期并记录原因
+#[tracing::instrument(name = "Fail newsletter issue", skip(transaction))]
+pub async fn fail(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled', failure_reason = $2, updated_at = $3
+        WHERE id = $1
+        "#,
+        id,
+        reason,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 投递队列中已经没有邮件的sending改为sent，返回改变的数量
+// 注：不在删除最后一个任务时顺便修改，并发的两个实例各自删除最后两个任务时都看不到对方的删除
+#[tracing::instrument(name = "Mark newsletter issues as sent", skip(pool))]
+pub async fn mark_sent(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let now = Utc::now();
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues i
+        SET status = 'sent', sent_at = $1, updated_at = $1
+        WHERE status = 'sending'
+            AND NOT EXISTS (SELECT 1 FROM issue_delivery_queue q WHERE q.newsletter_issue_id = i.id)
+        "#,
+        now,
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.rows_affected())
+}
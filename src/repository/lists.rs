@@ -0,0 +1,108 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::domain::ListSlug;
+
+// 由迁移创建的默认列表：没有指定列表的订阅、导入和发布都使用它
+pub const DEFAULT_LIST: &str = "newsletter";
+
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct MailingList {
+    pub id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get all lists", skip(pool))]
+pub async fn all(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT id, slug, name, created_at FROM lists ORDER BY slug"#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get list by slug", skip(pool))]
+pub async fn find_by_slug(pool: &PgPool, slug: &str) -> Result<Option<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        r#"SELECT id, slug, name, created_at FROM lists WHERE slug = $1"#,
+        slug,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 按slug查找列表，结果与slugs的顺序一致。不存在的slug作为Err返回
+#[tracing::instrument(name = "Get lists by slug", skip(pool))]
+pub async fn find_by_slugs(
+    pool: &PgPool,
+    slugs: &[String],
+) -> Result<Result<Vec<MailingList>, String>, sqlx::Error> {
+    let found = sqlx::query_as!(
+        MailingList,
+        r#"SELECT id, slug, name, created_at FROM lists WHERE slug = ANY($1)"#,
+        slugs,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let mut lists = Vec::with_capacity(slugs.len());
+    for slug in slugs {
+        match found.iter().find(|list| &list.slug == slug) {
+            Some(list) => lists.push(list.clone()),
+            None => return Ok(Err(slug.clone())),
+        }
+    }
+    Ok(Ok(lists))
+}
+
+#[derive(Debug)]
+pub enum InsertListError {
+    SlugTaken,
+    Database(sqlx::Error),
+}
+
+#[tracing::instrument(name = "Insert list", skip(pool))]
+pub async fn insert(
+    pool: &PgPool,
+    slug: &ListSlug,
+    name: &str,
+) -> Result<MailingList, InsertListError> {
+    sqlx::query_as!(
+        MailingList,
+        r#"
+        INSERT INTO lists (id, slug, name, created_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, slug, name, created_at
+        "#,
+        Uuid::new_v4(),
+        slug.as_ref(),
+        name,
+        Utc::now(),
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => InsertListError::SlugTaken,
+        _ => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            InsertListError::Database(e)
+        }
+    })
+}
@@ -0,0 +1,232 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{generate_subscription_token, DeliveryFrequency, Segment, SubscriptionStatus};
+use crate::repository::segments;
+
+// 订阅者在某个列表中的状态，即SQL函数subscriber_lists()返回的数组中的一项
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ListMembership {
+    pub list: String,
+    pub status: String,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+// 通过退订令牌找到的成员资格
+#[derive(Debug)]
+pub struct UnsubscribeTarget {
+    pub subscriber_id: Uuid,
+    pub list_id: Uuid,
+    pub list_name: String,
+}
+
+// 订阅者在该列表中的状态，不是成员时返回None。该行会被锁住直到事务结束
+#[tracing::instrument(name = "Lock list membership", skip(transaction))]
+pub async fn lock(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<Option<SubscriptionStatus>, sqlx::Error> {
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT status FROM list_memberships
+        WHERE subscriber_id = $1 AND list_id = $2
+        FOR UPDATE
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    status
+        .map(|status| SubscriptionStatus::parse(&status).map_err(|e| sqlx::Error::Decode(e.into())))
+        .transpose()
+}
+
+#[tracing::instrument(name = "Add list membership", skip(transaction))]
+pub async fn insert(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        list_id,
+        status.as_str(),
+        Utc::now(),
+        generate_subscription_token(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Set list membership status", skip(transaction))]
+pub async fn set_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    status: SubscriptionStatus,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = $3
+        WHERE subscriber_id = $1 AND list_id = $2
+        "#,
+        subscriber_id,
+        list_id,
+        status.as_str(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get membership by unsubscribe token", skip(pool, token))]
+pub async fn find_by_unsubscribe_token(
+    pool: &PgPool,
+    token: &str,
+) -> Result<Option<UnsubscribeTarget>, sqlx::Error> {
+    sqlx::query_as!(
+        UnsubscribeTarget,
+        r#"
+        SELECT m.subscriber_id, m.list_id, l.name AS list_name
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        WHERE m.unsubscribe_token = $1
+        "#,
+        token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 删除订阅者在所有列表中的成员资格
+#[tracing::instrument(name = "Delete list memberships", skip(transaction))]
+pub async fn delete_all(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM list_memberships WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 新闻邮件的收件人，以及邮件中退订链接和偏好设置链接使用的令牌
+#[derive(sqlx::FromRow, Debug)]
+pub struct Recipient {
+    pub subscriber_id: Uuid,
+    pub email: String,
+    pub name: String,
+    // 退订链接对应的列表的名称
+    pub list: String,
+    pub unsubscribe_token: String,
+    pub preferences_token: String,
+    // 订阅者保存的语言，邮件中的系统文字按它翻译
+    pub locale: Option<String>,
+    // 订阅者选择了不被跟踪打开和点击
+    pub tracking_opt_out: bool,
+}
+
+// 在任意一个目标列表中已确认、选择了该接收频率且没有暂停的订阅者，指定了分组时还需满足分组表达式
+// 同时属于多个目标列表的订阅者只会出现一次，退订链接对应list_ids中排在最前的那个列表
+#[tracing::instrument(name = "Get confirmed recipients", skip(pool))]
+pub async fn confirmed_recipients(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+    frequency: DeliveryFrequency,
+) -> Result<Vec<Recipient>, sqlx::Error> {
+    recipients_query(list_ids, segment, frequency, None)
+        .build_query_as()
+        .fetch_all(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+// 与confirmed_recipients相同的条件，只查询一个订阅者，用于在发送前重新检查
+#[tracing::instrument(name = "Get confirmed recipient", skip(pool))]
+pub async fn confirmed_recipient(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+    frequency: DeliveryFrequency,
+    subscriber_id: Uuid,
+) -> Result<Option<Recipient>, sqlx::Error> {
+    recipients_query(list_ids, segment, frequency, Some(subscriber_id))
+        .build_query_as()
+        .fetch_optional(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+fn recipients_query<'a>(
+    list_ids: &[Uuid],
+    segment: Option<&Segment>,
+    frequency: DeliveryFrequency,
+    subscriber_id: Option<Uuid>,
+) -> QueryBuilder<'a, Postgres> {
+    let mut query = QueryBuilder::new(
+        "SELECT DISTINCT ON (s.id) s.id AS subscriber_id, s.email, s.name, l.name AS list, \
+            m.unsubscribe_token, s.preferences_token, s.attributes->>'locale' AS locale, \
+            s.tracking_opt_out \
+        FROM list_memberships m \
+        JOIN subscriptions s ON s.id = m.subscriber_id \
+        JOIN lists l ON l.id = m.list_id \
+        WHERE m.status = 'confirmed' AND (s.paused_until IS NULL OR s.paused_until <= now()) \
+            AND s.delivery_frequency = ",
+    );
+    query
+        .push_bind(frequency.as_str())
+        .push(" AND m.list_id = ANY(")
+        .push_bind(list_ids.to_vec())
+        .push(")");
+    if let Some(subscriber_id) = subscriber_id {
+        query.push(" AND s.id = ").push_bind(subscriber_id);
+    }
+    if let Some(segment) = segment {
+        segments::push_matching(&mut query, "s", segment);
+    }
+    query
+        .push(" ORDER BY s.id, array_position(")
+        .push_bind(list_ids.to_vec())
+        .push(", m.list_id)");
+    query
+}
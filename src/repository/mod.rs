@@ -0,0 +1,20 @@
+// 数据访问层：handler通过这里读写数据库，而不是在handler中直接编写SQL
+pub mod assets;
+pub mod confirmation_queue;
+pub mod consent_events;
+pub mod delivery_queue;
+pub mod digests;
+pub mod email_events;
+pub mod issue_revisions;
+pub mod issues;
+pub mod lists;
+pub mod memberships;
+pub mod preferences;
+pub mod privacy;
+pub mod segments;
+pub mod send_rate_metrics;
+pub mod subscribers;
+pub mod suppressions;
+pub mod templates;
+pub mod tracking_events;
+pub mod users;
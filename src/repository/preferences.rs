@@ -0,0 +1,121 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{DeliveryFrequency, SubscriberName};
+
+// 偏好设置页面上可修改的订阅者资料
+#[derive(Debug)]
+pub struct Preferences {
+    pub subscriber_id: Uuid,
+    pub name: String,
+    pub delivery_frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub tracking_opt_out: bool,
+}
+
+// 页面上列出的每一个列表，以及订阅者在其中的状态（不是成员时为None）
+#[derive(Debug)]
+pub struct ListChoice {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+#[tracing::instrument(name = "Get preferences by token", skip(pool, token))]
+pub async fn find_by_token(pool: &PgPool, token: &str) -> Result<Option<Preferences>, sqlx::Error> {
+    sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT id AS subscriber_id, name, delivery_frequency, paused_until, tracking_opt_out
+        FROM subscriptions
+        WHERE preferences_token = $1
+        "#,
+        token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 与find_by_token相同，但会锁住这一行直到事务结束
+#[tracing::instrument(name = "Lock preferences by token", skip(transaction, token))]
+pub async fn lock_by_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    token: &str,
+) -> Result<Option<Preferences>, sqlx::Error> {
+    sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT id AS subscriber_id, name, delivery_frequency, paused_until, tracking_opt_out
+        FROM subscriptions
+        WHERE preferences_token = $1
+        FOR UPDATE
+        "#,
+        token,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 所有列表，按slug排序
+#[tracing::instrument(name = "Get list choices", skip(executor))]
+pub async fn list_choices<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<ListChoice>, sqlx::Error> {
+    sqlx::query_as!(
+        ListChoice,
+        r#"
+        SELECT l.id AS list_id, l.slug, l.name, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.id AND m.subscriber_id = $1
+        ORDER BY l.slug
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Update preferences", skip(transaction, name))]
+pub async fn update(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    name: &SubscriberName,
+    delivery_frequency: DeliveryFrequency,
+    paused_until: Option<DateTime<Utc>>,
+    tracking_opt_out: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET name = $2, delivery_frequency = $3, paused_until = $4, tracking_opt_out = $5
+        WHERE id = $1
+        "#,
+        subscriber_id,
+        name.as_ref(),
+        delivery_frequency.as_str(),
+        paused_until,
+        tracking_opt_out,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
@@ -0,0 +1,230 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::repository::consent_events::{self, ConsentEvent, ConsentEventRecord, ConsentSource};
+use crate::repository::email_events::{self, EmailEventRecord};
+use crate::repository::memberships::ListMembership;
+use crate::repository::tracking_events::{self, TrackingEvent};
+use crate::repository::{subscribers, suppressions};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyRequestKind {
+    // 导出我们保存的关于该订阅者的全部数据
+    Access,
+    // 删除该订阅者的全部数据
+    Erasure,
+}
+
+impl PrivacyRequestKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Access => "access",
+            Self::Erasure => "erasure",
+        }
+    }
+}
+
+#[tracing::instrument(name = "Store privacy request token", skip(transaction, privacy_token))]
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    kind: PrivacyRequestKind,
+    privacy_token: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO privacy_request_tokens (privacy_token, subscriber_id, kind, requested_at, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        privacy_token,
+        subscriber_id,
+        kind.as_str(),
+        Utc::now(),
+        expires_at,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 令牌对应的订阅者。令牌不存在、已过期或用途不符时返回None
+#[tracing::instrument(
+    name = "Get subscriber_id from privacy token",
+    skip(pool, privacy_token)
+)]
+pub async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    privacy_token: &str,
+    kind: PrivacyRequestKind,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT subscriber_id
+        FROM privacy_request_tokens
+        WHERE privacy_token = $1 AND kind = $2 AND expires_at > $3
+        "#,
+        privacy_token,
+        kind.as_str(),
+        Utc::now(),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 数据导出的内容：所有表中与该订阅者相关的行
+#[derive(Serialize, Debug)]
+pub struct SubscriberDataExport {
+    pub subscriber: SubscriberDetails,
+    pub subscription_tokens: Vec<String>,
+    pub privacy_requests: Vec<PrivacyRequest>,
+    // 管理员或订阅者本人对资料所做的修改
+    pub changes: Vec<Change>,
+    pub consent_events: Vec<ConsentEventRecord>,
+    // 打开和点击跟踪的记录
+    pub tracking_events: Vec<TrackingEvent>,
+    // 邮件服务商报告的投递、退信和投诉等事件
+    pub email_events: Vec<EmailEventRecord>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct SubscriberDetails {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub consent_source: Option<String>,
+    pub delivery_frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub attributes: serde_json::Value,
+    pub lists: Json<Vec<ListMembership>>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct PrivacyRequest {
+    pub kind: String,
+    pub requested_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct Change {
+    pub action: String,
+    pub changes: serde_json::Value,
+    pub changed_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Export subscriber data", skip(pool))]
+pub async fn export(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<SubscriberDataExport>, sqlx::Error> {
+    // 在同一个事务中读取，保证各部分数据是一致的
+    let mut transaction = pool.begin().await?;
+    let subscriber = sqlx::query_as!(
+        SubscriberDetails,
+        r#"
+        SELECT id, email, name, subscribed_at, consent_source, delivery_frequency, paused_until,
+            tags, attributes, subscriber_lists(id) AS "lists!: Json<Vec<ListMembership>>"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut *transaction)
+    .await?;
+    let Some(subscriber) = subscriber else {
+        return Ok(None);
+    };
+    let subscription_tokens = sqlx::query_scalar!(
+        r#"SELECT subscription_token FROM subscription_tokens WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let privacy_requests = sqlx::query_as!(
+        PrivacyRequest,
+        r#"
+        SELECT kind, requested_at, expires_at
+        FROM privacy_request_tokens
+        WHERE subscriber_id = $1
+        ORDER BY requested_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let changes = sqlx::query_as!(
+        Change,
+        r#"
+        SELECT action, changes, changed_at
+        FROM subscriber_audit_log
+        WHERE subscriber_id = $1
+        ORDER BY changed_at
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(&mut *transaction)
+    .await?;
+    let consent_events = consent_events::list(&mut *transaction, subscriber_id).await?;
+    let tracking_events = tracking_events::list(&mut *transaction, subscriber_id).await?;
+    let email_events =
+        email_events::list(&mut *transaction, subscriber_id, &subscriber.email).await?;
+    transaction.commit().await?;
+    Ok(Some(SubscriberDataExport {
+        subscriber,
+        subscription_tokens,
+        privacy_requests,
+        changes,
+        consent_events,
+        tracking_events,
+        email_events,
+    }))
+}
+
+// 删除订阅者：删除所有与其相关的行，审计记录和同意记录中的个人数据被清空，
+// 同时记录邮箱地址的哈希值，以免之后又被导入
+// 必须在一个事务中完成，不能只删除一部分
+#[tracing::instrument(name = "Erase subscriber", skip(transaction))]
+pub async fn erase(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let Some(subscriber) = subscribers::lock_by_id(transaction, subscriber_id).await? else {
+        return Ok(());
+    };
+    suppressions::insert(transaction, &subscriber.email, "erasure", None, None).await?;
+    // 保留“何时、由谁做了修改”，但清除修改的内容（其中包含邮箱地址和名字）
+    sqlx::query!(
+        r#"UPDATE subscriber_audit_log SET changes = '{}'::jsonb WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    email_events::delete_all(transaction, subscriber_id, &subscriber.email).await?;
+    // 同意记录本身仍需保留，以证明订阅者曾经同意以及何时撤回了同意
+    consent_events::anonymise(transaction, subscriber_id).await?;
+    consent_events::record(
+        transaction,
+        subscriber_id,
+        None,
+        ConsentEvent::Erasure,
+        &ConsentSource::new("privacy_request"),
+    )
+    .await?;
+    subscribers::delete(transaction, subscriber_id).await?;
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::domain::{Comparison, Segment};
+
+// segments表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct SavedSegment {
+    pub id: Uuid,
+    pub name: String,
+    pub expression: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get all segments", skip(pool))]
+pub async fn all(pool: &PgPool) -> Result<Vec<SavedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSegment,
+        r#"SELECT id, name, expression, created_at FROM segments ORDER BY name"#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get segment by id", skip(pool))]
+pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<SavedSegment>, sqlx::Error> {
+    sqlx::query_as!(
+        SavedSegment,
+        r#"SELECT id, name, expression, created_at FROM segments WHERE id = $1"#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[derive(Debug)]
+pub enum InsertSegmentError {
+    NameTaken,
+    Database(sqlx::Error),
+}
+
+// expression应当已经通过Segment::parse校验
+#[tracing::instrument(name = "Insert segment", skip(pool))]
+pub async fn insert(
+    pool: &PgPool,
+    name: &str,
+    expression: &str,
+    created_by: Uuid,
+) -> Result<SavedSegment, InsertSegmentError> {
+    sqlx::query_as!(
+        SavedSegment,
+        r#"
+        INSERT INTO segments (id, name, expression, created_by, created_at)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id, name, expression, created_at
+        "#,
+        Uuid::new_v4(),
+        name,
+        expression,
+        created_by,
+        Utc::now(),
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => InsertSegmentError::NameTaken,
+        _ => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            InsertSegmentError::Database(e)
+        }
+    })
+}
+
+// 在任意一个列表中已确认、且满足分组表达式的订阅者数量，即发布到这些列表时的目标人数
+// 与发布不同，这里不排除选择每周摘要或暂停中的订阅者
+#[tracing::instrument(name = "Count subscribers in segment", skip(pool))]
+pub async fn count_matching(
+    pool: &PgPool,
+    list_ids: &[Uuid],
+    segment: &Segment,
+) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT count(DISTINCT s.id) FROM list_memberships m \
+        JOIN subscriptions s ON s.id = m.subscriber_id \
+        WHERE m.status = 'confirmed' AND m.list_id = ANY(",
+    );
+    query.push_bind(list_ids.to_vec()).push(")");
+    push_matching(&mut query, "s", segment);
+    query
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+// 追加条件：别名为alias的subscriptions行满足分组表达式
+// 表达式被编译为参数化的SQL，标签、属性和日期都作为绑定参数传入，不会拼接到SQL中
+pub fn push_matching(query: &mut QueryBuilder<'_, Postgres>, alias: &str, segment: &Segment) {
+    query
+        .push(" AND ")
+        .push(alias)
+        .push(".id IN (SELECT id FROM subscriptions WHERE ");
+    push_condition(query, segment);
+    query.push(")");
+}
+
+fn push_condition(query: &mut QueryBuilder<'_, Postgres>, segment: &Segment) {
+    match segment {
+        Segment::And(operands) | Segment::Or(operands) => {
+            let separator = if matches!(segment, Segment::And(_)) {
+                " AND "
+            } else {
+                " OR "
+            };
+            query.push("(");
+            for (i, operand) in operands.iter().enumerate() {
+                if i > 0 {
+                    query.push(separator);
+                }
+                push_condition(query, operand);
+            }
+            query.push(")");
+        }
+        Segment::Not(operand) => {
+            query.push("NOT (");
+            push_condition(query, operand);
+            query.push(")");
+        }
+        Segment::Tag(tag) => {
+            query
+                .push("(tags @> ARRAY[")
+                .push_bind(tag.as_ref().to_string())
+                .push("]::text[])");
+        }
+        // 没有该属性的订阅者满足!=
+        Segment::Attribute { key, op, value } => {
+            if *op == Comparison::Ne {
+                query.push("NOT ");
+            }
+            query
+                .push("(attributes @> ")
+                .push_bind(serde_json::json!({ key: value }))
+                .push(")");
+        }
+        // 按UTC日期比较，转换为时间范围，以便使用subscribed_at上的索引
+        Segment::SubscribedAt { op, date } => {
+            let start = midnight(*date);
+            let end = midnight(*date + Days::new(1));
+            match op {
+                Comparison::Eq | Comparison::Ne => {
+                    if *op == Comparison::Ne {
+                        query.push("NOT ");
+                    }
+                    query
+                        .push("(subscribed_at >= ")
+                        .push_bind(start)
+                        .push(" AND subscribed_at < ")
+                        .push_bind(end)
+                        .push(")");
+                }
+                Comparison::Lt => {
+                    query.push("subscribed_at < ").push_bind(start);
+                }
+                Comparison::Le => {
+                    query.push("subscribed_at < ").push_bind(end);
+                }
+                Comparison::Gt => {
+                    query.push("subscribed_at >= ").push_bind(end);
+                }
+                Comparison::Ge => {
+                    query.push("subscribed_at >= ").push_bind(start);
+                }
+            }
+        }
+    }
+}
+
+fn midnight(date: NaiveDate) -> DateTime<Utc> {
+    date.and_time(Default::default()).and_utc()
+}
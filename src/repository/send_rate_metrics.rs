@@ -0,0 +1,65 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+
+// send_rate_metrics表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct SendRateMetrics {
+    pub key: String,
+    pub messages_per_second: f64,
+    pub sent: i64,
+    pub throttled: i64,
+    pub waited_seconds: f64,
+    pub updated_at: DateTime<Utc>,
+}
+
+// 记录通过某个桶发出的一封邮件。waited_seconds大于0表示这封邮件因为速率限制等待过
+#[tracing::instrument(name = "Record send rate metrics", skip(transaction))]
+pub async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    key: &str,
+    messages_per_second: f64,
+    waited_seconds: f64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO send_rate_metrics
+            (key, messages_per_second, sent, throttled, waited_seconds, updated_at)
+        VALUES ($1, $2, 1, CASE WHEN $3::float8 > 0 THEN 1 ELSE 0 END, $3, clock_timestamp())
+        ON CONFLICT (key) DO UPDATE
+        SET messages_per_second = EXCLUDED.messages_per_second,
+            sent = send_rate_metrics.sent + 1,
+            throttled = send_rate_metrics.throttled + EXCLUDED.throttled,
+            waited_seconds = send_rate_metrics.waited_seconds + EXCLUDED.waited_seconds,
+            updated_at = EXCLUDED.updated_at
+        "#,
+        key,
+        messages_per_second,
+        waited_seconds,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get send rate metrics", skip(pool))]
+pub async fn all(pool: &PgPool) -> Result<Vec<SendRateMetrics>, sqlx::Error> {
+    sqlx::query_as!(
+        SendRateMetrics,
+        r#"
+        SELECT key, messages_per_second, sent, throttled, waited_seconds, updated_at
+        FROM send_rate_metrics
+        ORDER BY key
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
@@ -0,0 +1,609 @@
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use sqlx::{PgPool, Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use crate::domain::{
+    generate_subscription_token, NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag,
+    SubscriptionStatus, SUBSCRIBER_ATTRIBUTES,
+};
+use crate::repository::consent_events::ConsentEvent;
+use crate::repository::memberships::{self, ListMembership};
+
+// subscriptions表中的一行，以及订阅者所在的列表
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct SubscriberRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub subscribed_at: DateTime<Utc>,
+    pub delivery_frequency: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub tags: Vec<String>,
+    pub attributes: serde_json::Value,
+    pub lists: Json<Vec<ListMembership>>,
+}
+
+// 列表和计数共用的过滤条件
+#[derive(Deserialize, Debug, Default)]
+pub struct SubscriberFilters {
+    // 在某个列表中的状态。只指定status时匹配任意一个列表
+    pub status: Option<SubscriptionStatus>,
+    // 列表的slug
+    pub list: Option<String>,
+    // 订阅时间范围：[subscribed_after, subscribed_before)
+    pub subscribed_after: Option<DateTime<Utc>>,
+    pub subscribed_before: Option<DateTime<Utc>>,
+    // 在邮箱和名字中做不区分大小写的子串搜索
+    pub q: Option<String>,
+    // 带有该标签
+    pub tag: Option<SubscriberTag>,
+    // 某个属性等于某个值，写作key=value，如locale=de
+    pub attribute: Option<AttributeFilter>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(try_from = "String")]
+pub struct AttributeFilter {
+    key: String,
+    value: String,
+}
+
+impl TryFrom<String> for AttributeFilter {
+    type Error = String;
+
+    // 只能按订阅表单接受的属性过滤
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        match s.split_once('=') {
+            Some((key, value)) if SUBSCRIBER_ATTRIBUTES.contains(&key) => Ok(Self {
+                key: key.into(),
+                value: value.into(),
+            }),
+            _ => Err(format!(
+                "{} is not a valid attribute filter. Use key=value with one of: {}.",
+                s,
+                SUBSCRIBER_ATTRIBUTES.join(", ")
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortColumn {
+    #[default]
+    SubscribedAt,
+    Email,
+    Name,
+}
+
+impl SortColumn {
+    // 注：列名只能来自这里，绝不能把用户输入拼接到SQL中
+    fn as_sql(&self) -> &'static str {
+        match self {
+            Self::SubscribedAt => "subscribed_at",
+            Self::Email => "email",
+            Self::Name => "name",
+        }
+    }
+
+    // 某一行在该列上的排序键
+    pub fn key_of(&self, record: &SubscriberRecord) -> SortKey {
+        match self {
+            Self::SubscribedAt => SortKey::SubscribedAt(record.subscribed_at),
+            Self::Email => SortKey::Text(record.email.clone()),
+            Self::Name => SortKey::Text(record.name.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    // 默认最新的订阅者在前
+    #[default]
+    Desc,
+}
+
+// 排序列的值
+#[derive(Debug)]
+pub enum SortKey {
+    SubscribedAt(DateTime<Utc>),
+    Text(String),
+}
+
+// 按(排序列, id)的顺序，返回排在after之后的最多limit个订阅者（键集分页）
+#[tracing::instrument(name = "Get a page of subscribers", skip(pool))]
+pub async fn list(
+    pool: &PgPool,
+    filters: &SubscriberFilters,
+    sort: SortColumn,
+    order: SortOrder,
+    after: Option<(SortKey, Uuid)>,
+    limit: i64,
+) -> Result<Vec<SubscriberRecord>, sqlx::Error> {
+    let mut query = QueryBuilder::new(
+        "SELECT id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes, subscriber_lists(id) AS lists FROM subscriptions WHERE TRUE",
+    );
+    push_filters(&mut query, filters);
+
+    let column = sort.as_sql();
+    let (comparison, direction) = match order {
+        SortOrder::Asc => (">", "ASC"),
+        SortOrder::Desc => ("<", "DESC"),
+    };
+    if let Some((key, id)) = after {
+        // 行比较(a, b) > (x, y)可以直接利用(a, b)上的复合索引
+        query.push(format_args!(" AND ({column}, id) {comparison} ("));
+        match key {
+            SortKey::SubscribedAt(value) => query.push_bind(value),
+            SortKey::Text(value) => query.push_bind(value),
+        };
+        query.push(", ").push_bind(id).push(")");
+    }
+    query
+        .push(format_args!(
+            " ORDER BY {column} {direction}, id {direction} LIMIT "
+        ))
+        .push_bind(limit);
+
+    query.build_query_as().fetch_all(pool).await.map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Count subscribers", skip(pool))]
+pub async fn count(pool: &PgPool, filters: &SubscriberFilters) -> Result<i64, sqlx::Error> {
+    let mut query = QueryBuilder::new("SELECT COUNT(*) FROM subscriptions WHERE TRUE");
+    push_filters(&mut query, filters);
+    query
+        .build_query_scalar()
+        .fetch_one(pool)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+// 按订阅时间顺序逐行返回满足过滤条件的所有订阅者
+// 行通过数据库游标按需读取，不会一次性全部载入内存。返回的Stream拥有连接池的句柄，可以直接作为响应体
+pub fn stream(
+    pool: PgPool,
+    filters: SubscriberFilters,
+) -> impl Stream<Item = Result<SubscriberRecord, sqlx::Error>> + 'static {
+    async_stream::try_stream! {
+        let mut query = QueryBuilder::new(
+            "SELECT id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes, subscriber_lists(id) AS lists FROM subscriptions WHERE TRUE",
+        );
+        push_filters(&mut query, &filters);
+        query.push(" ORDER BY subscribed_at, id");
+        let mut rows = query.build_query_as::<SubscriberRecord>().fetch(&pool);
+        while let Some(row) = rows.try_next().await.map_err(|e| {
+            tracing::error!("Failed to fetch subscriber: {:?}", e);
+            e
+        })? {
+            yield row;
+        }
+    }
+}
+
+fn push_filters(query: &mut QueryBuilder<'_, Postgres>, filters: &SubscriberFilters) {
+    if filters.status.is_some() || filters.list.is_some() {
+        query.push(
+            " AND EXISTS (SELECT 1 FROM list_memberships m JOIN lists l ON l.id = m.list_id \
+            WHERE m.subscriber_id = subscriptions.id",
+        );
+        if let Some(status) = filters.status {
+            query.push(" AND m.status = ").push_bind(status.as_str());
+        }
+        if let Some(list) = filters.list.clone() {
+            query.push(" AND l.slug = ").push_bind(list);
+        }
+        query.push(")");
+    }
+    if let Some(subscribed_after) = filters.subscribed_after {
+        query
+            .push(" AND subscribed_at >= ")
+            .push_bind(subscribed_after);
+    }
+    if let Some(subscribed_before) = filters.subscribed_before {
+        query
+            .push(" AND subscribed_at < ")
+            .push_bind(subscribed_before);
+    }
+    if let Some(q) = filters.q.as_deref().filter(|q| !q.is_empty()) {
+        let pattern = format!("%{}%", escape_like(q));
+        query
+            .push(" AND (email ILIKE ")
+            .push_bind(pattern.clone())
+            .push(r" ESCAPE '\' OR name ILIKE ")
+            .push_bind(pattern)
+            .push(r" ESCAPE '\')");
+    }
+    if let Some(tag) = &filters.tag {
+        query
+            .push(" AND tags @> ARRAY[")
+            .push_bind(tag.as_ref().to_string())
+            .push("]::text[]");
+    }
+    if let Some(attribute) = &filters.attribute {
+        // @>可以使用attributes上的GIN索引
+        query
+            .push(" AND attributes @> ")
+            .push_bind(serde_json::json!({ &attribute.key: &attribute.value }));
+    }
+}
+
+// 转义LIKE模式中的特殊字符，使搜索词按字面匹配
+fn escape_like(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[tracing::instrument(name = "Get subscriber by id", skip(pool))]
+pub async fn find_by_id(pool: &PgPool, id: Uuid) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes,
+            subscriber_lists(id) AS "lists!: Json<Vec<ListMembership>>"
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get subscriber by email", skip(pool, email))]
+pub async fn find_by_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes,
+            subscriber_lists(id) AS "lists!: Json<Vec<ListMembership>>"
+        FROM subscriptions
+        WHERE email = $1
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 与find_by_id相同，但会锁住这一行直到事务结束，避免并发的修改互相覆盖
+#[tracing::instrument(name = "Lock subscriber by id", skip(transaction))]
+pub async fn lock_by_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<Option<SubscriberRecord>, sqlx::Error> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        SELECT id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes,
+            subscriber_lists(id) AS "lists!: Json<Vec<ListMembership>>"
+        FROM subscriptions
+        WHERE id = $1
+        FOR UPDATE
+        "#,
+        id,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[derive(Debug)]
+pub enum UpdateSubscriberError {
+    // 新的邮箱地址已经属于另一个订阅者
+    EmailTaken,
+    Database(sqlx::Error),
+}
+
+// 注：返回的lists包含同一事务中此前对成员资格所做的修改
+#[tracing::instrument(name = "Update subscriber", skip(transaction, email, name))]
+pub async fn update(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    email: &SubscriberEmail,
+    name: &SubscriberName,
+) -> Result<SubscriberRecord, UpdateSubscriberError> {
+    sqlx::query_as!(
+        SubscriberRecord,
+        r#"
+        UPDATE subscriptions
+        SET email = $2, name = $3
+        WHERE id = $1
+        RETURNING id, email, name, subscribed_at, delivery_frequency, paused_until, tags, attributes,
+            subscriber_lists(id) AS "lists!: Json<Vec<ListMembership>>"
+        "#,
+        id,
+        email.as_ref(),
+        name.as_ref(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| match e.as_database_error() {
+        Some(db_error) if db_error.is_unique_violation() => UpdateSubscriberError::EmailTaken,
+        _ => {
+            tracing::error!("Failed to execute query: {:?}", e);
+            UpdateSubscriberError::Database(e)
+        }
+    })
+}
+
+// 删除订阅者及其确认令牌和成员资格
+#[tracing::instrument(name = "Delete subscriber", skip(transaction))]
+pub async fn delete(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+) -> Result<(), sqlx::Error> {
+    memberships::delete_all(transaction, id).await?;
+    sqlx::query!(
+        r#"DELETE FROM subscription_tokens WHERE subscriber_id = $1"#,
+        id
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    sqlx::query!(r#"DELETE FROM subscriptions WHERE id = $1"#, id)
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+// 在同一个事务中记录一次修改，修改和审计记录要么都写入，要么都不写入
+#[tracing::instrument(name = "Record subscriber change", skip(transaction, changes))]
+pub async fn record_change(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    // 执行修改的管理员，订阅者自己修改时为None
+    changed_by: Option<Uuid>,
+    action: &str,
+    changes: serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_audit_log (subscriber_id, changed_by, action, changes, changed_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        changed_by,
+        action,
+        changes,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 为一批订阅者各写入一条审计记录，changes与subscriber_ids一一对应
+#[tracing::instrument(name = "Record changes to subscribers", skip(transaction, changes))]
+pub async fn record_changes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+    changed_by: Option<Uuid>,
+    action: &str,
+    changes: &[serde_json::Value],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriber_audit_log (subscriber_id, changed_by, action, changes, changed_at)
+        SELECT subscriber_id, $3, $4, changes, $5
+        FROM UNNEST($1::uuid[], $2::jsonb[]) AS t(subscriber_id, changes)
+        "#,
+        subscriber_ids,
+        changes,
+        changed_by,
+        action,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 批量修改标签的对象：指定的订阅者，或满足过滤条件的所有订阅者
+#[derive(Debug)]
+pub enum TagTarget<'a> {
+    Ids(&'a [Uuid]),
+    Matching(&'a SubscriberFilters),
+}
+
+// 标签确实发生了变化的订阅者
+#[derive(sqlx::FromRow, Debug)]
+pub struct TagChange {
+    pub id: Uuid,
+    pub old_tags: Vec<String>,
+    pub new_tags: Vec<String>,
+}
+
+// 给一批订阅者添加和移除标签，同一个标签同时出现在add和remove中时以remove为准
+// 标签按字母顺序保存且不重复。只返回标签发生了变化的订阅者
+#[tracing::instrument(name = "Update subscriber tags", skip(transaction))]
+pub async fn update_tags(
+    transaction: &mut Transaction<'_, Postgres>,
+    target: TagTarget<'_>,
+    add: &[SubscriberTag],
+    remove: &[SubscriberTag],
+) -> Result<Vec<TagChange>, sqlx::Error> {
+    let add: Vec<String> = add.iter().map(|t| t.as_ref().to_string()).collect();
+    let remove: Vec<String> = remove.iter().map(|t| t.as_ref().to_string()).collect();
+    let mut query = QueryBuilder::new(
+        "WITH target AS (SELECT id, tags AS old_tags, \
+        ARRAY(SELECT DISTINCT t FROM unnest(array_cat(tags, ",
+    );
+    query
+        .push_bind(add)
+        .push("::text[])) AS t WHERE t <> ALL(")
+        .push_bind(remove)
+        .push("::text[]) ORDER BY t) AS new_tags FROM subscriptions WHERE TRUE");
+    match target {
+        TagTarget::Ids(ids) => {
+            query
+                .push(" AND id = ANY(")
+                .push_bind(ids.to_vec())
+                .push(")");
+        }
+        TagTarget::Matching(filters) => push_filters(&mut query, filters),
+    }
+    query.push(
+        " FOR UPDATE) \
+        UPDATE subscriptions SET tags = target.new_tags FROM target \
+        WHERE subscriptions.id = target.id AND target.old_tags <> target.new_tags \
+        RETURNING subscriptions.id, target.old_tags, target.new_tags",
+    );
+    query
+        .build_query_as()
+        .fetch_all(&mut **transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })
+}
+
+// 批量导入的一行
+pub struct ImportedSubscriber {
+    pub subscriber: NewSubscriber,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+// 把一批订阅者加入list_id列表，返回实际加入的邮箱地址
+// 尚不存在的订阅者会被创建；已经在该列表中的订阅者被跳过，其他列表中的订阅者则只会增加一个成员资格
+// 每个加入的订阅者同时记录一条import事件；待确认的订阅者还会得到确认令牌，确认邮件放入队列，
+// 由后台任务按发送速率发送。全部写入都在同一条语句中完成，不会只写入其中一部分
+#[tracing::instrument(name = "Insert a batch of subscribers", skip(pool, subscribers), fields(batch_size = subscribers.len()))]
+pub async fn insert_many(
+    pool: &PgPool,
+    subscribers: &[ImportedSubscriber],
+    list_id: Uuid,
+    status: SubscriptionStatus,
+    consent_source: Option<&str>,
+) -> Result<Vec<String>, sqlx::Error> {
+    let ids: Vec<Uuid> = subscribers.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.email.as_ref().to_string())
+        .collect();
+    let names: Vec<String> = subscribers
+        .iter()
+        .map(|s| s.subscriber.name.as_ref().to_string())
+        .collect();
+    let subscribed_at: Vec<DateTime<Utc>> = subscribers.iter().map(|s| s.subscribed_at).collect();
+    let unsubscribe_tokens: Vec<String> = subscribers
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    let confirmation_tokens: Vec<String> = subscribers
+        .iter()
+        .map(|_| generate_subscription_token())
+        .collect();
+    // 注：每列作为一个数组参数传入再用UNNEST展开，这样参数个数不会随批量大小增长
+    let inserted = sqlx::query_scalar!(
+        r#"
+        WITH input AS (
+            SELECT *
+            FROM UNNEST($1::uuid[], $2::text[], $3::text[], $4::timestamptz[], $5::text[], $11::text[])
+                AS t(id, email, name, subscribed_at, unsubscribe_token, confirmation_token)
+        ), inserted AS (
+            INSERT INTO subscriptions (id, email, name, subscribed_at, consent_source)
+            SELECT id, email, name, subscribed_at, $7
+            FROM input
+            ON CONFLICT (email) DO NOTHING
+            RETURNING id, email
+        ), targets AS (
+            -- 新创建的订阅者，加上已经存在的订阅者（语句中的查询看不到inserted刚写入的行）
+            SELECT id, email FROM inserted
+            UNION ALL
+            SELECT s.id, s.email FROM subscriptions s JOIN input USING (email)
+        ), joined AS (
+            INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+            SELECT targets.id, $6, $8, input.subscribed_at, input.unsubscribe_token
+            FROM targets
+            JOIN input USING (email)
+            ON CONFLICT (subscriber_id, list_id) DO NOTHING
+            RETURNING subscriber_id
+        ), events AS (
+            INSERT INTO consent_events (subscriber_id, list_id, event, source, occurred_at)
+            SELECT subscriber_id, $6, $9, COALESCE($7, 'csv_import'), $10
+            FROM joined
+        ), tokens AS (
+            INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+            SELECT input.confirmation_token, joined.subscriber_id, $6
+            FROM joined
+            JOIN targets ON targets.id = joined.subscriber_id
+            JOIN input USING (email)
+            WHERE $8 = 'pending_confirmation'
+            RETURNING subscription_token
+        ), queued AS (
+            INSERT INTO confirmation_email_queue (subscription_token, execute_after)
+            SELECT subscription_token, $10
+            FROM tokens
+        )
+        SELECT targets.email AS "email!"
+        FROM targets
+        JOIN joined ON joined.subscriber_id = targets.id
+        "#,
+        &ids,
+        &emails,
+        &names,
+        &subscribed_at,
+        &unsubscribe_tokens,
+        list_id,
+        consent_source,
+        status.as_str(),
+        ConsentEvent::Import.as_str(),
+        Utc::now(),
+        &confirmation_tokens,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(inserted)
+}
@@ -0,0 +1,243 @@
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// suppressions表中的一行
+#[derive(Serialize, Debug)]
+pub struct Suppression {
+    pub email_hash: String,
+    pub reason: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// suppression_audit_log表中的一行
+#[derive(Serialize, Debug)]
+pub struct SuppressionChange {
+    pub action: String,
+    pub reason: String,
+    pub note: Option<String>,
+    // 操作的管理员的用户名，自动屏蔽或者用户被删除后为空
+    pub performed_by: Option<String>,
+    pub performed_at: DateTime<Utc>,
+}
+
+// 邮箱地址的哈希值。先做规范化，使大小写不同的同一个地址得到相同的哈希值
+pub fn email_hash(email: &str) -> String {
+    hex::encode(Sha256::digest(email.trim().to_lowercase().as_bytes()))
+}
+
+// 屏蔽一个地址并记录审计日志，返回false表示该地址已经被屏蔽
+// 退信、投诉和删除数据自动屏蔽时performed_by和note为None
+#[tracing::instrument(name = "Suppress email address", skip(transaction, email, note))]
+pub async fn insert(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: &str,
+    performed_by: Option<Uuid>,
+    note: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let email_hash = email_hash(email);
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO suppressions (email_hash, reason, created_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (email_hash) DO NOTHING
+        "#,
+        email_hash,
+        reason,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .rows_affected()
+        == 1;
+    if inserted {
+        record(
+            transaction,
+            &email_hash,
+            "added",
+            reason,
+            performed_by,
+            note,
+        )
+        .await?;
+    }
+    Ok(inserted)
+}
+
+// 解除屏蔽并记录审计日志，返回被解除的屏蔽，不存在时返回None
+// 同时清除软退信的计数，否则下一次软退信就会再次屏蔽该地址
+#[tracing::instrument(name = "Lift suppression", skip(transaction, note))]
+pub async fn delete(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hash: &str,
+    performed_by: Uuid,
+    note: &str,
+) -> Result<Option<Suppression>, sqlx::Error> {
+    let Some(suppression) = sqlx::query_as!(
+        Suppression,
+        r#"
+        DELETE FROM suppressions
+        WHERE email_hash = $1
+        RETURNING email_hash, reason, created_at
+        "#,
+        email_hash,
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    else {
+        return Ok(None);
+    };
+    sqlx::query!(
+        r#"DELETE FROM soft_bounce_counts WHERE email_hash = $1"#,
+        email_hash,
+    )
+    .execute(&mut **transaction)
+    .await?;
+    record(
+        transaction,
+        email_hash,
+        "lifted",
+        &suppression.reason,
+        Some(performed_by),
+        Some(note),
+    )
+    .await?;
+    Ok(Some(suppression))
+}
+
+async fn record(
+    transaction: &mut Transaction<'_, Postgres>,
+    email_hash: &str,
+    action: &str,
+    reason: &str,
+    performed_by: Option<Uuid>,
+    note: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO suppression_audit_log
+            (email_hash, action, reason, note, performed_by, performed_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+        email_hash,
+        action,
+        reason,
+        note,
+        performed_by,
+        Utc::now(),
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
+
+// 最近屏蔽的在前，可以按哈希值和原因筛选
+#[tracing::instrument(name = "List suppressions", skip(pool))]
+pub async fn list(
+    pool: &PgPool,
+    email_hash: Option<&str>,
+    reason: Option<&str>,
+    limit: i64,
+) -> Result<Vec<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"
+        SELECT email_hash, reason, created_at
+        FROM suppressions
+        WHERE ($1::text IS NULL OR email_hash = $1)
+            AND ($2::text IS NULL OR reason = $2)
+        ORDER BY created_at DESC, email_hash
+        LIMIT $3
+        "#,
+        email_hash,
+        reason,
+        limit,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get suppression", skip(pool))]
+pub async fn find(pool: &PgPool, email_hash: &str) -> Result<Option<Suppression>, sqlx::Error> {
+    sqlx::query_as!(
+        Suppression,
+        r#"SELECT email_hash, reason, created_at FROM suppressions WHERE email_hash = $1"#,
+        email_hash,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 一个地址被屏蔽和解除屏蔽的历史，按时间顺序
+#[tracing::instrument(name = "Get suppression history", skip(pool))]
+pub async fn history(
+    pool: &PgPool,
+    email_hash: &str,
+) -> Result<Vec<SuppressionChange>, sqlx::Error> {
+    sqlx::query_as!(
+        SuppressionChange,
+        r#"
+        SELECT a.action, a.reason, a.note, u.username AS "performed_by?", a.performed_at
+        FROM suppression_audit_log a
+        LEFT JOIN users u ON u.user_id = a.performed_by
+        WHERE a.email_hash = $1
+        ORDER BY a.performed_at, a.id
+        "#,
+        email_hash,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 返回emails中被屏蔽的地址的哈希值
+#[tracing::instrument(name = "Find suppressed email addresses", skip(pool, emails))]
+pub async fn find_suppressed(
+    pool: &PgPool,
+    emails: &[&str],
+) -> Result<HashSet<String>, sqlx::Error> {
+    let hashes: Vec<String> = emails.iter().map(|email| email_hash(email)).collect();
+    let suppressed = sqlx::query_scalar!(
+        r#"SELECT email_hash FROM suppressions WHERE email_hash = ANY($1)"#,
+        &hashes,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(suppressed.into_iter().collect())
+}
+
+pub async fn is_suppressed(pool: &PgPool, email: &str) -> Result<bool, sqlx::Error> {
+    Ok(!find_suppressed(pool, &[email]).await?.is_empty())
+}
@@ -0,0 +1,106 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// templates表中的一行
+#[derive(sqlx::FromRow, Serialize, Debug, Clone)]
+pub struct StoredTemplate {
+    pub name: String,
+    pub subject: Option<String>,
+    pub html: String,
+    pub text: String,
+    pub layout: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(name = "Get all templates", skip(executor))]
+pub async fn all<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+) -> Result<Vec<StoredTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredTemplate,
+        r#"SELECT name, subject, html, text, layout, updated_at FROM templates ORDER BY name"#,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+#[tracing::instrument(name = "Get template by name", skip(pool))]
+pub async fn find_by_name(
+    pool: &PgPool,
+    name: &str,
+) -> Result<Option<StoredTemplate>, sqlx::Error> {
+    sqlx::query_as!(
+        StoredTemplate,
+        r#"SELECT name, subject, html, text, layout, updated_at FROM templates WHERE name = $1"#,
+        name,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 保存模板之前的校验依赖其他模板（片段和布局），锁住整张表，避免并发保存时形成循环引用
+#[tracing::instrument(name = "Lock templates", skip(transaction))]
+pub async fn lock(transaction: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+    sqlx::query!("LOCK TABLE templates IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut **transaction)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to execute query: {:?}", e);
+            e
+        })?;
+    Ok(())
+}
+
+// 新建或替换模板。第二个返回值表示是否为新建
+#[tracing::instrument(name = "Save template", skip(transaction, template))]
+pub async fn save(
+    transaction: &mut Transaction<'_, Postgres>,
+    template: &StoredTemplate,
+    updated_by: Uuid,
+) -> Result<(StoredTemplate, bool), sqlx::Error> {
+    let saved = sqlx::query!(
+        r#"
+        INSERT INTO templates (name, subject, html, text, layout, updated_by, updated_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        ON CONFLICT (name) DO UPDATE
+        SET subject = EXCLUDED.subject, html = EXCLUDED.html, text = EXCLUDED.text,
+            layout = EXCLUDED.layout, updated_by = EXCLUDED.updated_by,
+            updated_at = EXCLUDED.updated_at
+        RETURNING name, subject, html, text, layout, updated_at, (xmax = 0) AS "created!"
+        "#,
+        template.name,
+        template.subject,
+        template.html,
+        template.text,
+        template.layout,
+        updated_by,
+        Utc::now(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok((
+        StoredTemplate {
+            name: saved.name,
+            subject: saved.subject,
+            html: saved.html,
+            text: saved.text,
+            layout: saved.layout,
+            updated_at: saved.updated_at,
+        },
+        saved.created,
+    ))
+}
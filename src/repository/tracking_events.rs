@@ -0,0 +1,151 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// 一期的打开和点击统计。unique_*为去重后的订阅者数
+#[derive(Serialize, Debug)]
+pub struct Engagement {
+    pub opens: i64,
+    pub unique_opens: i64,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+    pub links: Vec<LinkClicks>,
+}
+
+// 每个链接的点击次数，点击最多的在前
+#[derive(Serialize, Debug)]
+pub struct LinkClicks {
+    pub url: String,
+    pub clicks: i64,
+    pub unique_clicks: i64,
+}
+
+// 数据导出中的一条跟踪记录
+#[derive(Serialize, Debug)]
+pub struct TrackingEvent {
+    pub newsletter_issue_id: Uuid,
+    pub kind: String,
+    pub url: Option<String>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+// 记录一次打开（url为None）或点击。订阅者已被删除、选择了不被跟踪或者这一期已被删除时不记录，返回false
+#[tracing::instrument(name = "Record tracking event", skip(pool, url))]
+pub async fn record(
+    pool: &PgPool,
+    issue_id: Uuid,
+    subscriber_id: Uuid,
+    url: Option<&str>,
+) -> Result<bool, sqlx::Error> {
+    let kind = if url.is_some() { "click" } else { "open" };
+    let inserted = sqlx::query!(
+        r#"
+        INSERT INTO tracking_events (newsletter_issue_id, subscriber_id, kind, url, occurred_at)
+        SELECT i.id, s.id, $3, $4, $5
+        FROM newsletter_issues i, subscriptions s
+        WHERE i.id = $1 AND s.id = $2 AND NOT s.tracking_opt_out
+        "#,
+        issue_id,
+        subscriber_id,
+        kind,
+        url,
+        Utc::now(),
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(inserted.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Get issue engagement", skip(pool))]
+pub async fn engagement(pool: &PgPool, issue_id: Uuid) -> Result<Engagement, sqlx::Error> {
+    let totals = sqlx::query!(
+        r#"
+        SELECT
+            COUNT(*) FILTER (WHERE kind = 'open') AS "opens!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'open') AS "unique_opens!",
+            COUNT(*) FILTER (WHERE kind = 'click') AS "clicks!",
+            COUNT(DISTINCT subscriber_id) FILTER (WHERE kind = 'click') AS "unique_clicks!"
+        FROM tracking_events
+        WHERE newsletter_issue_id = $1
+        "#,
+        issue_id,
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    let links = sqlx::query_as!(
+        LinkClicks,
+        r#"
+        SELECT url AS "url!", COUNT(*) AS "clicks!",
+            COUNT(DISTINCT subscriber_id) AS "unique_clicks!"
+        FROM tracking_events
+        WHERE newsletter_issue_id = $1 AND kind = 'click'
+        GROUP BY url
+        ORDER BY COUNT(*) DESC, url
+        "#,
+        issue_id,
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(Engagement {
+        opens: totals.opens,
+        unique_opens: totals.unique_opens,
+        clicks: totals.clicks,
+        unique_clicks: totals.unique_clicks,
+        links,
+    })
+}
+
+#[tracing::instrument(name = "Get tracking events of subscriber", skip(executor))]
+pub async fn list<'c>(
+    executor: impl sqlx::Executor<'c, Database = Postgres>,
+    subscriber_id: Uuid,
+) -> Result<Vec<TrackingEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        TrackingEvent,
+        r#"
+        SELECT newsletter_issue_id, kind, url, occurred_at
+        FROM tracking_events
+        WHERE subscriber_id = $1
+        ORDER BY occurred_at, id
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(executor)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 订阅者选择不被跟踪时，已有的跟踪记录也一并删除
+#[tracing::instrument(name = "Delete tracking events of subscriber", skip(transaction))]
+pub async fn delete_all(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM tracking_events WHERE subscriber_id = $1"#,
+        subscriber_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(())
+}
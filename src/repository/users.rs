@@ -0,0 +1,51 @@
+use serde::Serialize;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// 管理员自己的账户信息
+#[derive(sqlx::FromRow, Serialize, Debug)]
+pub struct Account {
+    pub user_id: Uuid,
+    pub username: String,
+    // 测试邮件发送到这个地址
+    pub email: Option<String>,
+}
+
+#[tracing::instrument(name = "Get account", skip(pool))]
+pub async fn find_by_id(pool: &PgPool, user_id: Uuid) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query_as!(
+        Account,
+        r#"SELECT user_id, username, email FROM users WHERE user_id = $1"#,
+        user_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// email应当已经通过SubscriberEmail::parse校验，None表示清除
+#[tracing::instrument(name = "Update account email", skip(pool))]
+pub async fn set_email(
+    pool: &PgPool,
+    user_id: Uuid,
+    email: Option<&str>,
+) -> Result<Option<Account>, sqlx::Error> {
+    sqlx::query_as!(
+        Account,
+        r#"
+        UPDATE users SET email = $2 WHERE user_id = $1
+        RETURNING user_id, username, email
+        "#,
+        user_id,
+        email,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
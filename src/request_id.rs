@@ -0,0 +1,211 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+    body::MessageBody,
+    dev::{ServiceRequest, ServiceResponse},
+    http::header::{self, HeaderName, HeaderValue},
+    middleware::Next,
+    FromRequest, HttpMessage, HttpRequest,
+};
+use serde::Serialize;
+use tracing::Span;
+use tracing_actix_web::{DefaultRootSpanBuilder, RootSpanBuilder};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+// 请求id所在的HTTP头：既用于接收调用方提供的id，也用于在响应和对外请求中回传
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// 调用方提供的请求id的最大长度（足够容纳UUID、ULID等常见格式）
+const MAX_REQUEST_ID_LENGTH: usize = 64;
+
+tokio::task_local! {
+    // 当前正在处理的请求的id。在中间件中设置，使得处理请求的整个future（包括对外调用）都可以读取它
+    static CURRENT_REQUEST_ID: RequestId;
+}
+
+// 每个请求的唯一标识，可以作为extractor在handler中使用
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    // 生成一个新的随机请求id
+    pub fn generate() -> Self {
+        Self(Uuid::new_v4().to_string())
+    }
+
+    // 校验调用方提供的请求id：只允许非空的、不超过64个字符的ASCII字母、数字以及`-`、`_`、`.`
+    // 注：该值会被写入日志和转发给第三方，因此不能信任任意输入（如换行符、超长字符串）
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let is_valid_char = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+        if s.is_empty() || s.len() > MAX_REQUEST_ID_LENGTH || !s.chars().all(is_valid_char) {
+            Err(format!("{} is not a valid request id.", s))
+        } else {
+            Ok(Self(s.to_string()))
+        }
+    }
+
+    // 获取当前请求的id。如果不在请求的上下文中（如后台任务），返回None
+    pub fn current() -> Option<Self> {
+        CURRENT_REQUEST_ID.try_with(|id| id.clone()).ok()
+    }
+
+    // 在该请求id的上下文中执行future，期间RequestId::current()都会返回该id
+    pub async fn scope<F: std::future::Future>(self, future: F) -> F::Output {
+        CURRENT_REQUEST_ID.scope(self, future).await
+    }
+
+    // 优先使用请求头中合法的id，否则生成一个新的id
+    fn from_headers(request: &ServiceRequest) -> Self {
+        request
+            .headers()
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| Self::parse(value).ok())
+            .unwrap_or_else(Self::generate)
+    }
+}
+
+impl AsRef<str> for RequestId {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromRequest for RequestId {
+    type Error = actix_web::Error;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _payload: &mut actix_web::dev::Payload) -> Self::Future {
+        ready(
+            req.extensions()
+                .get::<RequestId>()
+                .cloned()
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError("Missing request id")),
+        )
+    }
+}
+
+// 错误响应的JSON响应体
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody<'a> {
+    pub error: &'a str,
+    pub request_id: &'a str,
+}
+
+// 中间件：确定请求id并将其放入请求的extensions中，在处理请求期间设置CURRENT_REQUEST_ID，
+// 然后在响应中回传X-Request-Id头，并将非JSON的错误响应替换为带有请求id的JSON响应体
+// 面向浏览器的HTML错误页面保持不变
+// 注：该中间件必须注册在TracingLogger之后（即在它的外层），这样RequestIdRootSpanBuilder才能读到请求id
+pub async fn propagate_request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let request_id = RequestId::from_headers(&req);
+    req.extensions_mut().insert(request_id.clone());
+
+    let response = request_id.clone().scope(next.call(req)).await?;
+
+    let mut response = if is_error_without_json_or_html_body(&response) {
+        // 服务端错误的详细信息只应出现在日志中，不能暴露给调用方
+        let status = response.status();
+        let message = match response.response().error() {
+            Some(e) if status.is_client_error() => e.to_string(),
+            _ => status
+                .canonical_reason()
+                .unwrap_or("Unknown error")
+                .to_string(),
+        };
+        let body = serde_json::to_string(&ErrorBody {
+            error: &message,
+            request_id: request_id.as_ref(),
+        })
+        .expect("Failed to serialize error body");
+        response.map_into_boxed_body().map_body(|head, _| {
+            head.headers_mut().insert(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            );
+            body.boxed()
+        })
+    } else {
+        response.map_into_boxed_body()
+    };
+
+    response.headers_mut().insert(
+        REQUEST_ID_HEADER,
+        HeaderValue::from_str(request_id.as_ref()).expect("Request id is a valid header value"),
+    );
+    Ok(response)
+}
+
+fn is_error_without_json_or_html_body<B>(response: &ServiceResponse<B>) -> bool {
+    let status = response.status();
+    let has_body = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| {
+            value.starts_with("application/json") || value.starts_with("text/html")
+        });
+    (status.is_client_error() || status.is_server_error()) && !has_body
+}
+
+// 与tracing_actix_web::DefaultRootSpanBuilder相同，但使用我们自己的请求id（可能由调用方提供）
+// 而不是TracingLogger内部生成的id
+pub struct RequestIdRootSpanBuilder;
+
+impl RootSpanBuilder for RequestIdRootSpanBuilder {
+    fn on_request_start(request: &ServiceRequest) -> Span {
+        let request_id = request
+            .extensions()
+            .get::<RequestId>()
+            .cloned()
+            .unwrap_or_else(RequestId::generate);
+        let user_agent = request
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or("");
+        let http_route = request.match_pattern().unwrap_or_else(|| "default".into());
+        let connection_info = request.connection_info();
+        tracing::info_span!(
+            "HTTP request",
+            http.method = %request.method(),
+            http.route = %http_route,
+            http.scheme = %connection_info.scheme(),
+            http.host = %connection_info.host(),
+            http.client_ip = %connection_info.realip_remote_addr().unwrap_or(""),
+            http.user_agent = %user_agent,
+            http.target = %request.uri().path_and_query().map(|p| p.as_str()).unwrap_or(""),
+            http.status_code = tracing::field::Empty,
+            otel.name = %format!("{} {}", request.method(), http_route),
+            otel.kind = "server",
+            otel.status_code = tracing::field::Empty,
+            request_id = %request_id,
+            exception.message = tracing::field::Empty,
+            exception.details = tracing::field::Empty,
+        )
+    }
+
+    fn on_request_end<B: MessageBody>(
+        span: Span,
+        outcome: &Result<ServiceResponse<B>, actix_web::Error>,
+    ) {
+        DefaultRootSpanBuilder::on_request_end(span, outcome);
+    }
+}
+
+// 将当前请求id附加到对外的HTTP请求上（如调用邮件服务商的API）
+pub fn with_current_request_id(builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+    match RequestId::current() {
+        Some(request_id) => builder.header(REQUEST_ID_HEADER.as_str(), request_id.as_ref()),
+        None => builder,
+    }
+}
@@ -0,0 +1,79 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::SubscriberEmail;
+use crate::repository::users;
+
+// PATCH /admin/account的请求体
+#[derive(Deserialize, Debug)]
+pub struct AccountBody {
+    // 接收测试邮件的地址，null表示清除
+    email: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AdminAccountError {
+    Invalid(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminAccountError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => f.write_str(e),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminAccountError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for AdminAccountError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 管理后台：当前管理员自己的账户
+#[tracing::instrument(name = "Get account", skip(pool, user), fields(username = %user.username))]
+pub async fn get_account(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminAccountError> {
+    let account = users::find_by_id(&pool, user.user_id)
+        .await?
+        .ok_or_else(|| AdminAccountError::Unexpected("The account is gone.".into()))?;
+    Ok(HttpResponse::Ok().json(account))
+}
+
+// 管理后台：设置或清除自己的邮箱地址，测试邮件只发送到这里
+#[tracing::instrument(name = "Update account", skip(body, pool, user), fields(username = %user.username))]
+pub async fn update_account(
+    body: web::Json<AccountBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminAccountError> {
+    let email = body
+        .into_inner()
+        .email
+        .map(SubscriberEmail::parse)
+        .transpose()
+        .map_err(AdminAccountError::Invalid)?;
+    let account = users::set_email(&pool, user.user_id, email.as_ref().map(|e| e.as_ref()))
+        .await?
+        .ok_or_else(|| AdminAccountError::Unexpected("The account is gone.".into()))?;
+    Ok(HttpResponse::Ok().json(account))
+}
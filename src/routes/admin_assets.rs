@@ -0,0 +1,42 @@
+use actix_web::{web, HttpMessage, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::attachments::{read_payload, AttachmentError, Attachments};
+use crate::authentication::AuthenticatedUser;
+
+#[derive(Deserialize, Debug)]
+pub struct UploadParameters {
+    filename: String,
+}
+
+// 管理后台：上传一个附件或内嵌图片，请求体是文件的原始内容，Content-Type头是文件的类型
+// 返回的id可以在发布新闻邮件时引用
+#[tracing::instrument(
+    name = "Upload an asset",
+    skip(req, body, pool, attachments, user),
+    fields(username = %user.username)
+)]
+pub async fn upload_asset(
+    req: HttpRequest,
+    body: web::Payload,
+    parameters: web::Query<UploadParameters>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AttachmentError> {
+    let content_type = req.content_type().to_string();
+    // 在读取请求体之前拒绝不允许的类型
+    attachments.check_content_type(&content_type)?;
+    let content = read_payload(body, attachments.max_size_bytes()).await?;
+    let asset = attachments
+        .upload(
+            &pool,
+            &parameters.filename,
+            &content_type,
+            &content,
+            user.user_id,
+        )
+        .await?;
+    Ok(HttpResponse::Created().json(asset))
+}
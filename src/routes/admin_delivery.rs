@@ -0,0 +1,56 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use chrono::Utc;
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::repository::delivery_queue::{self, QueueBacklog};
+use crate::repository::send_rate_metrics::{self, SendRateMetrics};
+
+// 投递任务的状态：队列的积压情况和发送速率限制的统计（所有实例合计）
+// 队列中到期的邮件持续增多、throttled和waited_seconds持续增长说明发送受到了速率限制
+#[derive(Serialize)]
+pub struct DeliveryMetrics {
+    queue: QueueBacklog,
+    send_rates: Vec<SendRateMetrics>,
+}
+
+#[derive(Debug)]
+pub enum AdminDeliveryError {
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminDeliveryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Something went wrong.")
+    }
+}
+
+impl ResponseError for AdminDeliveryError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::INTERNAL_SERVER_ERROR
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for AdminDeliveryError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 管理后台：投递任务的积压和发送速率
+#[tracing::instrument(name = "Get delivery metrics", skip(pool, user), fields(username = %user.username))]
+pub async fn delivery_metrics(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminDeliveryError> {
+    let (queue, send_rates) = tokio::try_join!(
+        delivery_queue::backlog(&pool, Utc::now()),
+        send_rate_metrics::all(&pool),
+    )?;
+    Ok(HttpResponse::Ok().json(DeliveryMetrics { queue, send_rates }))
+}
@@ -0,0 +1,681 @@
+use actix_web::{
+    http::{
+        header::{ContentDisposition, DispositionParam, DispositionType},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments::Attachments;
+use crate::authentication::AuthenticatedUser;
+use crate::domain::{IssueStatus, SubscriberEmail};
+use crate::email_client::EmailClient;
+use crate::email_template::RenderedEmail;
+use crate::i18n::I18n;
+use crate::newsletter_issue::{BodyData, PreparedIssue, PublishError};
+use crate::repository::issue_revisions::{self, IssueRevision};
+use crate::repository::issues::{self, NewsletterIssue};
+use crate::repository::{tracking_events, users};
+use crate::routes::PreviewFormat;
+use crate::startup::ApplicationBaseUrl;
+use crate::text_diff::unified_diff;
+
+// 差异中每个变化块前后保留的未变化行数
+const DIFF_CONTEXT_LINES: usize = 3;
+
+// POST /admin/issues的请求体：与发布接口相同的内容，加上可选的发送时间
+#[derive(Deserialize)]
+pub struct NewIssueBody {
+    #[serde(flatten)]
+    newsletter: BodyData,
+    // 省略时保存为草稿
+    scheduled_at: Option<String>,
+    timezone: Option<String>,
+}
+
+// PUT /admin/issues/{id}/schedule的请求体
+#[derive(Deserialize)]
+pub struct ScheduleBody {
+    scheduled_at: String,
+    timezone: Option<String>,
+    // 发送哪个版本，默认为最新版本
+    revision: Option<i32>,
+}
+
+// POST /admin/issues/{id}/publish和/test的请求体
+#[derive(Deserialize)]
+pub struct RevisionBody {
+    // 默认为最新版本
+    revision: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DiffParameters {
+    // 默认比较最新版本和它的上一个版本
+    from: Option<i32>,
+    to: Option<i32>,
+}
+
+#[derive(Deserialize)]
+pub struct IssuePreviewParameters {
+    revision: Option<i32>,
+    // 按哪种语言渲染页脚等系统文字，默认为配置中的默认语言
+    locale: Option<String>,
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+// 两个版本之间的差异，只列出有变化的字段
+#[derive(Serialize)]
+pub struct RevisionDiff {
+    from: i32,
+    to: i32,
+    changes: Vec<FieldDiff>,
+}
+
+#[derive(Serialize)]
+pub struct FieldDiff {
+    field: &'static str,
+    // 统一格式（diff -u）的按行差异
+    diff: String,
+}
+
+#[derive(Serialize)]
+pub struct TestSendReport {
+    sent_to: String,
+}
+
+#[derive(Deserialize)]
+pub struct IssueFilters {
+    status: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AdminIssuesError {
+    Invalid(String),
+    Publish(PublishError),
+    NotFound,
+    // 已经开始发送、发送完成或被取消的一期不能再被修改
+    Conflict(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminIssuesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) | Self::Conflict(e) => f.write_str(e),
+            Self::Publish(e) => write!(f, "{}", e),
+            Self::NotFound => f.write_str("The issue does not exist."),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminIssuesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Publish(e) => e.status_code(),
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for AdminIssuesError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+impl From<PublishError> for AdminIssuesError {
+    fn from(e: PublishError) -> Self {
+        Self::Publish(e)
+    }
+}
+
+// 发送时间：带偏移量的RFC 3339时间（如2026-10-26T09:00:00+02:00），
+// 或者不带偏移量的本地时间加上IANA时区（如2026-10-26T09:00和Europe/Berlin），
+// 后者用于"订阅者所在时区的周一9点"，夏令时由时区数据库处理。返回UTC时间和使用的时区
+async fn resolve_schedule(
+    pool: &PgPool,
+    scheduled_at: &str,
+    timezone: Option<String>,
+) -> Result<(DateTime<Utc>, Option<String>), AdminIssuesError> {
+    let scheduled_at = scheduled_at.trim();
+    let (at, timezone) = match DateTime::parse_from_rfc3339(scheduled_at) {
+        Ok(_) if timezone.is_some() => {
+            return Err(AdminIssuesError::Invalid(
+                "Give scheduled_at either with an offset or together with a timezone, not both."
+                    .into(),
+            ))
+        }
+        Ok(at) => (at.with_timezone(&Utc), None),
+        Err(_) => {
+            let local = NaiveDateTime::parse_from_str(scheduled_at, "%Y-%m-%dT%H:%M:%S")
+                .or_else(|_| NaiveDateTime::parse_from_str(scheduled_at, "%Y-%m-%dT%H:%M"))
+                .map_err(|_| {
+                    AdminIssuesError::Invalid(format!(
+                        "{:?} is not a valid date and time. Use e.g. 2026-10-26T09:00:00+02:00, or 2026-10-26T09:00 with a timezone.",
+                        scheduled_at
+                    ))
+                })?;
+            let timezone = timezone.ok_or_else(|| {
+                AdminIssuesError::Invalid(
+                    "scheduled_at has no offset, so a timezone is required.".into(),
+                )
+            })?;
+            let at = issues::local_to_utc(pool, local, &timezone)
+                .await?
+                .ok_or_else(|| {
+                    AdminIssuesError::Invalid(format!("{} is not a known timezone.", timezone))
+                })?;
+            (at, Some(timezone))
+        }
+    };
+    if at <= Utc::now() {
+        return Err(AdminIssuesError::Invalid(
+            "The scheduled time must be in the future.".into(),
+        ));
+    }
+    Ok((at, timezone))
+}
+
+// 修改失败时区分不存在和状态不允许
+async fn not_editable(pool: &PgPool, id: Uuid) -> AdminIssuesError {
+    match issues::find_by_id(pool, id).await {
+        Ok(Some(issue)) => AdminIssuesError::Conflict(format!(
+            "The issue is {} and can no longer be changed.",
+            issue.status
+        )),
+        Ok(None) => AdminIssuesError::NotFound,
+        Err(e) => e.into(),
+    }
+}
+
+// 管理后台：保存一期新闻邮件，给出scheduled_at时在该时间由后台任务发送，否则保存为草稿
+// 内容在保存时就被校验（列表、分组、模板和附件都必须存在），附件需要先通过POST /admin/assets上传
+#[tracing::instrument(
+    name = "Create newsletter issue",
+    skip(body, pool, attachments, user),
+    fields(username = %user.username)
+)]
+pub async fn create_issue(
+    body: web::Json<NewIssueBody>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let NewIssueBody {
+        newsletter,
+        scheduled_at,
+        timezone,
+    } = body.into_inner();
+    PreparedIssue::prepare(&pool, &newsletter, Vec::new(), &attachments, user.user_id).await?;
+    let (scheduled_at, timezone) = match scheduled_at {
+        Some(scheduled_at) => {
+            let (at, timezone) = resolve_schedule(&pool, &scheduled_at, timezone).await?;
+            (Some(at), timezone)
+        }
+        None if timezone.is_some() => {
+            return Err(AdminIssuesError::Invalid(
+                "A timezone is only used together with scheduled_at.".into(),
+            ))
+        }
+        None => (None, None),
+    };
+    let mut transaction = pool.begin().await?;
+    let issue = issues::insert(
+        &mut transaction,
+        &newsletter.title,
+        scheduled_at,
+        timezone.as_deref(),
+        user.user_id,
+    )
+    .await?;
+    issue_revisions::insert(&mut transaction, issue.id, 1, &newsletter, user.user_id).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Created().json(issue))
+}
+
+// 管理后台：所有保存的一期，最新的在前，可以按状态筛选
+#[tracing::instrument(name = "List newsletter issues", skip(query, pool, user), fields(username = %user.username))]
+pub async fn list_issues(
+    query: web::Query<IssueFilters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let status = query
+        .status
+        .as_deref()
+        .map(IssueStatus::parse)
+        .transpose()
+        .map_err(AdminIssuesError::Invalid)?;
+    let issues: Vec<NewsletterIssue> = issues::all(&pool, status).await?;
+    Ok(HttpResponse::Ok().json(issues))
+}
+
+#[tracing::instrument(name = "Get newsletter issue", skip(pool, user), fields(username = %user.username))]
+pub async fn get_issue(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+// 管理后台：一期的打开和点击统计。选择了不被跟踪的订阅者不在其中，打开数还受邮件客户端是否加载图片影响
+#[tracing::instrument(name = "Get newsletter issue engagement", skip(pool, user), fields(username = %user.username))]
+pub async fn issue_engagement(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let engagement = tracking_events::engagement(&pool, issue.id).await?;
+    Ok(HttpResponse::Ok().json(engagement))
+}
+
+// 管理后台：安排草稿的发送时间，或者重新安排尚未开始发送的一期。发送的版本在这里选定，之后的编辑不影响它
+#[tracing::instrument(
+    name = "Schedule newsletter issue",
+    skip(body, pool, attachments, user),
+    fields(username = %user.username)
+)]
+pub async fn schedule_issue(
+    id: web::Path<Uuid>,
+    body: web::Json<ScheduleBody>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let ScheduleBody {
+        scheduled_at,
+        timezone,
+        revision,
+    } = body.into_inner();
+    let (at, timezone) = resolve_schedule(&pool, &scheduled_at, timezone).await?;
+    let issue = freeze(
+        &pool,
+        &attachments,
+        &user,
+        id.into_inner(),
+        revision,
+        at,
+        timezone,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+// 管理后台：立即发布一期，发送的版本在这里选定。由后台任务在下一次循环中发送
+#[tracing::instrument(
+    name = "Publish newsletter issue",
+    skip(body, pool, attachments, user),
+    fields(username = %user.username)
+)]
+pub async fn publish_issue(
+    id: web::Path<Uuid>,
+    body: web::Json<RevisionBody>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = freeze(
+        &pool,
+        &attachments,
+        &user,
+        id.into_inner(),
+        body.revision,
+        Utc::now(),
+        None,
+    )
+    .await?;
+    Ok(HttpResponse::Accepted().json(issue))
+}
+
+// 选定发送的版本并安排发送时间。引用的列表、模板和附件可能在保存之后被删除，因此重新校验该版本
+async fn freeze(
+    pool: &PgPool,
+    attachments: &Attachments,
+    user: &AuthenticatedUser,
+    id: Uuid,
+    revision: Option<i32>,
+    scheduled_at: DateTime<Utc>,
+    timezone: Option<String>,
+) -> Result<NewsletterIssue, AdminIssuesError> {
+    let issue = issues::find_by_id(pool, id)
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let revision = find_revision(pool, &issue, revision).await?;
+    PreparedIssue::prepare(
+        pool,
+        &revision.request,
+        Vec::new(),
+        attachments,
+        user.user_id,
+    )
+    .await?;
+    match issues::schedule(
+        pool,
+        id,
+        scheduled_at,
+        timezone.as_deref(),
+        revision.revision,
+    )
+    .await?
+    {
+        Some(issue) => Ok(issue),
+        None => Err(not_editable(pool, id).await),
+    }
+}
+
+// 一期的某个版本，省略时为最新版本
+async fn find_revision(
+    pool: &PgPool,
+    issue: &NewsletterIssue,
+    revision: Option<i32>,
+) -> Result<IssueRevision, AdminIssuesError> {
+    let revision = revision.unwrap_or(issue.latest_revision);
+    issue_revisions::find(pool, issue.id, revision)
+        .await?
+        .ok_or_else(|| {
+            AdminIssuesError::Invalid(format!("The issue has no revision {}.", revision))
+        })
+}
+
+// 管理后台：取消尚未开始发送的一期
+#[tracing::instrument(name = "Cancel newsletter issue", skip(pool, user), fields(username = %user.username))]
+pub async fn cancel_issue(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let id = id.into_inner();
+    match issues::cancel(&pool, id).await? {
+        Some(issue) => Ok(HttpResponse::Ok().json(issue)),
+        None => Err(not_editable(&pool, id).await),
+    }
+}
+
+// 管理后台：保存草稿（或尚未开始发送的一期）的新内容。每次保存生成一个新的版本，旧版本不会被修改
+// 内容与最新版本相同时不生成新版本。已经安排的一期仍然发送安排时选定的版本
+#[tracing::instrument(
+    name = "Update newsletter issue",
+    skip(body, pool, attachments, user),
+    fields(username = %user.username)
+)]
+pub async fn update_issue(
+    id: web::Path<Uuid>,
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let id = id.into_inner();
+    let newsletter = body.into_inner();
+    PreparedIssue::prepare(&pool, &newsletter, Vec::new(), &attachments, user.user_id).await?;
+    let mut transaction = pool.begin().await?;
+    let issue = issues::lock(&mut transaction, id)
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let editable = IssueStatus::parse(&issue.status)
+        .map(|status| status.is_editable())
+        .unwrap_or(false);
+    if !editable {
+        return Err(AdminIssuesError::Conflict(format!(
+            "The issue is {} and can no longer be changed.",
+            issue.status
+        )));
+    }
+    let latest = issue_revisions::find(&mut *transaction, id, issue.latest_revision).await?;
+    if latest.is_some_and(|latest| latest.request.0 == newsletter) {
+        return Ok(HttpResponse::Ok().json(issue));
+    }
+    let revision = issue.latest_revision + 1;
+    issue_revisions::insert(&mut transaction, id, revision, &newsletter, user.user_id).await?;
+    let issue =
+        issues::set_latest_revision(&mut transaction, id, revision, &newsletter.title).await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(issue))
+}
+
+// 管理后台：一期的所有版本，最新的在前
+#[tracing::instrument(name = "List issue revisions", skip(pool, user), fields(username = %user.username))]
+pub async fn list_revisions(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let revisions = issue_revisions::all(&pool, issue.id).await?;
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+#[tracing::instrument(name = "Get issue revision", skip(pool, user), fields(username = %user.username))]
+pub async fn get_revision(
+    path: web::Path<(Uuid, i32)>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let (id, revision) = path.into_inner();
+    let revision = issue_revisions::find(pool.get_ref(), id, revision)
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+// 管理后台：比较两个版本，按字段给出按行的差异
+#[tracing::instrument(name = "Diff issue revisions", skip(pool, user), fields(username = %user.username))]
+pub async fn diff_revisions(
+    id: web::Path<Uuid>,
+    query: web::Query<DiffParameters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let to = find_revision(&pool, &issue, query.to).await?;
+    let from = find_revision(&pool, &issue, Some(query.from.unwrap_or(to.revision - 1))).await?;
+    let (old, new) = (&from.request.0, &to.request.0);
+    let changes = fields(old)
+        .into_iter()
+        .zip(fields(new))
+        .filter(|((_, old), (_, new))| old != new)
+        .map(|((field, old), (_, new))| FieldDiff {
+            field,
+            diff: unified_diff(&old, &new, DIFF_CONTEXT_LINES),
+        })
+        .collect();
+    Ok(HttpResponse::Ok().json(RevisionDiff {
+        from: from.revision,
+        to: to.revision,
+        changes,
+    }))
+}
+
+// 参与比较的字段及其文本形式，列表等结构化的字段每项一行
+fn fields(body: &BodyData) -> Vec<(&'static str, String)> {
+    let lines = |items: Vec<String>| items.join("\n");
+    vec![
+        ("title", body.title.clone()),
+        (
+            "content.html",
+            body.content
+                .as_ref()
+                .map(|c| c.html.clone())
+                .unwrap_or_default(),
+        ),
+        (
+            "content.text",
+            body.content
+                .as_ref()
+                .and_then(|c| c.text.clone())
+                .unwrap_or_default(),
+        ),
+        ("template", body.template.clone().unwrap_or_default()),
+        ("lists", lines(body.lists.clone().unwrap_or_default())),
+        (
+            "segment_id",
+            body.segment_id.map(|id| id.to_string()).unwrap_or_default(),
+        ),
+        (
+            "attachments",
+            lines(
+                body.attachments
+                    .iter()
+                    .flatten()
+                    .map(|a| match &a.content_id {
+                        Some(content_id) => format!("{} cid:{}", a.asset_id, content_id),
+                        None => a.asset_id.to_string(),
+                    })
+                    .collect(),
+            ),
+        ),
+        (
+            "tracking",
+            body.tracking
+                .map(|t| format!("opens: {}\nclicks: {}", t.opens, t.clicks))
+                .unwrap_or_default(),
+        ),
+    ]
+}
+
+// 管理后台：用示例订阅者渲染某个版本，查看邮件的最终效果（包括页脚和附件）
+// 注：参数都是actix-web的extractor，数量多一些并不影响可读性
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Preview newsletter issue",
+    skip(query, pool, attachments, base_url, i18n, email_client, user),
+    fields(username = %user.username)
+)]
+pub async fn preview_issue(
+    id: web::Path<Uuid>,
+    query: web::Query<IssuePreviewParameters>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    email_client: web::Data<EmailClient>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let revision = find_revision(&pool, &issue, query.revision).await?;
+    let prepared = PreparedIssue::prepare(
+        &pool,
+        &revision.request,
+        Vec::new(),
+        &attachments,
+        user.user_id,
+    )
+    .await?;
+    let locale = i18n.for_locale(query.locale.as_deref()).locale();
+    let recipient = prepared.sample_recipient("Jane Doe", "jane.doe@example.com", locale);
+    let email =
+        SubscriberEmail::parse(recipient.email.clone()).map_err(AdminIssuesError::Unexpected)?;
+    let message = prepared.message(
+        &recipient,
+        &email,
+        email_client.sender(),
+        &i18n,
+        &base_url.0,
+        None,
+    )?;
+    match query.format {
+        PreviewFormat::Json => Ok(HttpResponse::Ok().json(RenderedEmail {
+            subject: message.subject().to_string(),
+            html: message.html().to_string(),
+            text: message.text().to_string(),
+        })),
+        PreviewFormat::Eml => Ok(HttpResponse::Ok()
+            .content_type("message/rfc822")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!(
+                    "{}-{}.eml",
+                    issue.id, revision.revision
+                ))],
+            })
+            .body(message.to_mime())),
+    }
+}
+
+// 管理后台：把某个版本作为测试邮件发送到当前管理员自己的邮箱（通过PATCH /admin/account设置），不会发给任何订阅者
+// 注：参数都是actix-web的extractor，数量多一些并不影响可读性
+#[allow(clippy::too_many_arguments)]
+#[tracing::instrument(
+    name = "Send test newsletter issue",
+    skip(body, pool, attachments, base_url, i18n, email_client, user),
+    fields(username = %user.username)
+)]
+pub async fn send_test_issue(
+    id: web::Path<Uuid>,
+    body: web::Json<RevisionBody>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    email_client: web::Data<EmailClient>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminIssuesError> {
+    let account = users::find_by_id(&pool, user.user_id)
+        .await?
+        .ok_or_else(|| AdminIssuesError::Unexpected("The account is gone.".into()))?;
+    let email = account
+        .email
+        .ok_or_else(|| {
+            AdminIssuesError::Invalid(
+                "Your account has no email address. Set one with PATCH /admin/account first."
+                    .into(),
+            )
+        })
+        .and_then(|email| SubscriberEmail::parse(email).map_err(AdminIssuesError::Unexpected))?;
+    let issue = issues::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AdminIssuesError::NotFound)?;
+    let revision = find_revision(&pool, &issue, body.revision).await?;
+    let prepared = PreparedIssue::prepare(
+        &pool,
+        &revision.request,
+        Vec::new(),
+        &attachments,
+        user.user_id,
+    )
+    .await?;
+    let locale = i18n.for_locale(None).locale();
+    let recipient = prepared.sample_recipient(&account.username, email.as_ref(), locale);
+    let message = prepared.message(
+        &recipient,
+        &email,
+        email_client.sender(),
+        &i18n,
+        &base_url.0,
+        None,
+    )?;
+    email_client
+        .send(&message)
+        .await
+        .map_err(|e| AdminIssuesError::Unexpected(e.to_string()))?;
+    Ok(HttpResponse::Ok().json(TestSendReport {
+        sent_to: email.as_ref().to_string(),
+    }))
+}
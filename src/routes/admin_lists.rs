@@ -0,0 +1,116 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::{FieldErrors, ListSlug};
+use crate::repository::lists::{self, InsertListError};
+use crate::routes::validation_failed;
+
+// 列表名称的最大长度
+const MAX_NAME_LENGTH: usize = 256;
+
+// POST /admin/lists的请求体
+#[derive(Deserialize, Debug)]
+pub struct NewListBody {
+    slug: Option<String>,
+    name: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum AdminListsError {
+    Invalid(FieldErrors),
+    Conflict(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminListsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(_) => f.write_str("The list is invalid."),
+            Self::Conflict(reason) => f.write_str(reason),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminListsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Invalid(errors) => validation_failed("The list is invalid.", errors),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AdminListsError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 管理后台：所有邮件列表
+#[tracing::instrument(name = "List mailing lists", skip(pool, user), fields(username = %user.username))]
+pub async fn list_lists(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminListsError> {
+    let lists = lists::all(&pool).await?;
+    Ok(HttpResponse::Ok().json(lists))
+}
+
+// 管理后台：创建一个新的邮件列表，订阅者随后可以通过slug订阅它
+#[tracing::instrument(
+    name = "Create mailing list",
+    skip(body, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn create_list(
+    body: web::Json<NewListBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminListsError> {
+    let NewListBody { slug, name } = body.into_inner();
+    let mut errors = FieldErrors::new();
+    let slug = match ListSlug::parse(slug.unwrap_or_default()) {
+        Ok(slug) => Some(slug),
+        Err(e) => {
+            errors.insert("slug", vec![e]);
+            None
+        }
+    };
+    let name = name.unwrap_or_default().trim().to_string();
+    if name.is_empty() {
+        errors.insert("name", vec!["The name must not be empty.".into()]);
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        errors.insert(
+            "name",
+            vec![format!(
+                "The name must not be longer than {} characters.",
+                MAX_NAME_LENGTH
+            )],
+        );
+    }
+    let slug = match slug {
+        Some(slug) if errors.is_empty() => slug,
+        _ => return Err(AdminListsError::Invalid(errors)),
+    };
+
+    let list = lists::insert(&pool, &slug, &name)
+        .await
+        .map_err(|e| match e {
+            InsertListError::SlugTaken => {
+                AdminListsError::Conflict(format!("A list called {} already exists.", slug))
+            }
+            InsertListError::Database(e) => e.into(),
+        })?;
+    Ok(HttpResponse::Created().json(list))
+}
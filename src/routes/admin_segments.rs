@@ -0,0 +1,169 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::{FieldErrors, Segment};
+use crate::repository::lists::{self, DEFAULT_LIST};
+use crate::repository::segments::{self, InsertSegmentError};
+use crate::routes::validation_failed;
+
+// 分组名称的最大长度
+const MAX_NAME_LENGTH: usize = 256;
+
+// POST /admin/segments的请求体
+#[derive(Deserialize, Debug)]
+pub struct NewSegmentBody {
+    name: Option<String>,
+    expression: Option<String>,
+}
+
+// POST /admin/segments/preview的请求体
+#[derive(Deserialize, Debug)]
+pub struct SegmentPreviewBody {
+    expression: Option<String>,
+    // 在哪些列表中计数（列表的slug），默认为newsletter，与发布接口一致
+    lists: Option<Vec<String>>,
+}
+
+#[derive(Serialize)]
+pub struct SegmentPreview {
+    count: i64,
+}
+
+#[derive(Debug)]
+pub enum AdminSegmentsError {
+    Invalid(FieldErrors),
+    Conflict(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminSegmentsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(_) => f.write_str("The segment is invalid."),
+            Self::Conflict(reason) => f.write_str(reason),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminSegmentsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Invalid(errors) => validation_failed("The segment is invalid.", errors),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AdminSegmentsError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+fn parse_expression(expression: &str, errors: &mut FieldErrors) -> Option<Segment> {
+    match Segment::parse(expression) {
+        Ok(segment) => Some(segment),
+        Err(e) => {
+            errors.insert("expression", vec![e]);
+            None
+        }
+    }
+}
+
+// 管理后台：所有保存的分组
+#[tracing::instrument(name = "List segments", skip(pool, user), fields(username = %user.username))]
+pub async fn list_segments(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSegmentsError> {
+    let segments = segments::all(&pool).await?;
+    Ok(HttpResponse::Ok().json(segments))
+}
+
+// 管理后台：保存一个分组，发布时通过segment_id引用它
+#[tracing::instrument(
+    name = "Create segment",
+    skip(body, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn create_segment(
+    body: web::Json<NewSegmentBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSegmentsError> {
+    let NewSegmentBody { name, expression } = body.into_inner();
+    let mut errors = FieldErrors::new();
+    let name = name.unwrap_or_default().trim().to_string();
+    if name.is_empty() {
+        errors.insert("name", vec!["The name must not be empty.".into()]);
+    } else if name.chars().count() > MAX_NAME_LENGTH {
+        errors.insert(
+            "name",
+            vec![format!(
+                "The name must not be longer than {} characters.",
+                MAX_NAME_LENGTH
+            )],
+        );
+    }
+    let expression = expression.unwrap_or_default().trim().to_string();
+    parse_expression(&expression, &mut errors);
+    if !errors.is_empty() {
+        return Err(AdminSegmentsError::Invalid(errors));
+    }
+
+    let segment = segments::insert(&pool, &name, &expression, user.user_id)
+        .await
+        .map_err(|e| match e {
+            InsertSegmentError::NameTaken => {
+                AdminSegmentsError::Conflict(format!("A segment called {} already exists.", name))
+            }
+            InsertSegmentError::Database(e) => e.into(),
+        })?;
+    Ok(HttpResponse::Created().json(segment))
+}
+
+// 管理后台：在保存或发布之前，查看有多少个已确认的订阅者满足分组表达式
+#[tracing::instrument(
+    name = "Preview segment",
+    skip(body, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn preview_segment(
+    body: web::Json<SegmentPreviewBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSegmentsError> {
+    let SegmentPreviewBody { expression, lists } = body.into_inner();
+    let mut errors = FieldErrors::new();
+    let segment = parse_expression(&expression.unwrap_or_default(), &mut errors);
+    let slugs = lists.unwrap_or_else(|| vec![DEFAULT_LIST.into()]);
+    let lists = match lists::find_by_slugs(&pool, &slugs).await? {
+        Ok(lists) => lists,
+        Err(unknown) => {
+            errors.insert(
+                "lists",
+                vec![format!("There is no list called {}.", unknown)],
+            );
+            Vec::new()
+        }
+    };
+    let segment = match segment {
+        Some(segment) if errors.is_empty() => segment,
+        _ => return Err(AdminSegmentsError::Invalid(errors)),
+    };
+
+    let list_ids: Vec<_> = lists.iter().map(|list| list.id).collect();
+    let count = segments::count_matching(&pool, &list_ids, &segment).await?;
+    Ok(HttpResponse::Ok().json(SegmentPreview { count }))
+}
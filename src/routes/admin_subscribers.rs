@@ -0,0 +1,497 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::{
+    FieldErrors, StatusTransition, SubscriberEmail, SubscriberName, SubscriberTag, SubscriberUpdate,
+};
+use crate::repository::consent_events::{self, ConsentEvent, ConsentEventRecord, ConsentSource};
+use crate::repository::lists::{self, DEFAULT_LIST};
+use crate::repository::memberships;
+use crate::repository::subscribers::{
+    self, SortColumn, SortKey, SortOrder, SubscriberFilters, SubscriberRecord, TagTarget,
+    UpdateSubscriberError,
+};
+use crate::routes::validation_failed;
+
+// 每页默认和最多返回的订阅者数量
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+#[derive(Deserialize, Debug)]
+pub struct ListParameters {
+    #[serde(flatten)]
+    filters: SubscriberFilters,
+    #[serde(default)]
+    sort: SortColumn,
+    #[serde(default)]
+    order: SortOrder,
+    limit: Option<i64>,
+    // 上一页响应中的next_cursor
+    cursor: Option<String>,
+}
+
+// 游标记录上一页最后一行的排序键(排序列的值, id)，下一页从它之后开始（键集分页）
+// 与OFFSET不同，翻页的代价不会随页数增加，翻页期间有新的订阅者加入也不会导致重复或遗漏
+// 游标同时记录了排序方式，不能用于另一种排序
+#[derive(Serialize, Deserialize, Debug)]
+struct Cursor {
+    sort: SortColumn,
+    order: SortOrder,
+    value: String,
+    id: Uuid,
+}
+
+impl Cursor {
+    fn new(sort: SortColumn, order: SortOrder, last: &SubscriberRecord) -> Self {
+        let value = match sort.key_of(last) {
+            SortKey::SubscribedAt(value) => value.to_rfc3339(),
+            SortKey::Text(value) => value,
+        };
+        Self {
+            sort,
+            order,
+            value,
+            id: last.id,
+        }
+    }
+
+    fn encode(&self) -> String {
+        let json = serde_json::to_vec(self).expect("Failed to serialize cursor");
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(json)
+    }
+
+    fn decode(s: &str) -> Result<Self, AdminSubscribersError> {
+        let json = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(s)
+            .map_err(|_| AdminSubscribersError::InvalidCursor)?;
+        serde_json::from_slice(&json).map_err(|_| AdminSubscribersError::InvalidCursor)
+    }
+
+    fn sort_key(self) -> Result<(SortKey, Uuid), AdminSubscribersError> {
+        let key = match self.sort {
+            SortColumn::SubscribedAt => SortKey::SubscribedAt(
+                DateTime::parse_from_rfc3339(&self.value)
+                    .map_err(|_| AdminSubscribersError::InvalidCursor)?
+                    .with_timezone(&Utc),
+            ),
+            SortColumn::Email | SortColumn::Name => SortKey::Text(self.value),
+        };
+        Ok((key, self.id))
+    }
+}
+
+#[derive(Serialize)]
+pub struct SubscriberPage {
+    subscribers: Vec<SubscriberRecord>,
+    // 没有更多数据时为null
+    next_cursor: Option<String>,
+}
+
+// 单个订阅者的详情，附带其同意记录
+#[derive(Serialize)]
+pub struct SubscriberDetail {
+    #[serde(flatten)]
+    subscriber: SubscriberRecord,
+    consent_events: Vec<ConsentEventRecord>,
+}
+
+#[derive(Serialize)]
+pub struct SubscriberCount {
+    count: i64,
+}
+
+// PATCH /admin/subscribers/{id}的请求体，所有字段都是可选的
+#[derive(Deserialize, Debug)]
+pub struct SubscriberPatch {
+    email: Option<String>,
+    name: Option<String>,
+    transition: Option<StatusTransition>,
+    // 状态变更针对的列表，默认为newsletter
+    list: Option<String>,
+}
+
+// POST /admin/subscribers/tags的请求体
+// ids和filters二选一：修改指定的订阅者，或满足过滤条件的所有订阅者
+#[derive(Deserialize, Debug)]
+pub struct TagsUpdate {
+    ids: Option<Vec<Uuid>>,
+    filters: Option<SubscriberFilters>,
+    #[serde(default)]
+    add: Vec<String>,
+    #[serde(default)]
+    remove: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct TagsUpdated {
+    // 标签确实发生了变化的订阅者数量
+    updated: usize,
+}
+
+#[derive(Debug)]
+pub enum AdminSubscribersError {
+    InvalidCursor,
+    Invalid(FieldErrors),
+    NotFound,
+    Conflict(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminSubscribersError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidCursor => {
+                f.write_str("The cursor is malformed or was issued for a different sort order.")
+            }
+            Self::Invalid(_) => f.write_str("The subscriber update is invalid."),
+            Self::NotFound => f.write_str("The subscriber does not exist."),
+            Self::Conflict(reason) => f.write_str(reason),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminSubscribersError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::InvalidCursor | Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            // 与订阅接口一样，按字段报告校验错误
+            Self::Invalid(errors) => validation_failed("The subscriber update is invalid.", errors),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AdminSubscribersError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 管理后台：分页列出订阅者
+#[tracing::instrument(
+    name = "List subscribers",
+    skip(parameters, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let parameters = parameters.into_inner();
+    let after = match parameters
+        .cursor
+        .as_deref()
+        .map(Cursor::decode)
+        .transpose()?
+    {
+        Some(cursor) if cursor.sort != parameters.sort || cursor.order != parameters.order => {
+            return Err(AdminSubscribersError::InvalidCursor)
+        }
+        Some(cursor) => Some(cursor.sort_key()?),
+        None => None,
+    };
+    let limit = parameters
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+
+    // 多取一行，用来判断是否还有下一页
+    let mut page = subscribers::list(
+        &pool,
+        &parameters.filters,
+        parameters.sort,
+        parameters.order,
+        after,
+        limit + 1,
+    )
+    .await?;
+    let next_cursor = if page.len() as i64 > limit {
+        page.truncate(limit as usize);
+        page.last()
+            .map(|last| Cursor::new(parameters.sort, parameters.order, last).encode())
+    } else {
+        None
+    };
+    Ok(HttpResponse::Ok().json(SubscriberPage {
+        subscribers: page,
+        next_cursor,
+    }))
+}
+
+// 管理后台：满足过滤条件的订阅者总数
+#[tracing::instrument(
+    name = "Count subscribers",
+    skip(filters, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn count_subscribers(
+    filters: web::Query<SubscriberFilters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let count = subscribers::count(&pool, &filters).await?;
+    Ok(HttpResponse::Ok().json(SubscriberCount { count }))
+}
+
+#[tracing::instrument(
+    name = "Get subscriber",
+    skip(pool, user),
+    fields(username = %user.username)
+)]
+pub async fn get_subscriber(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let subscriber = subscribers::find_by_id(&pool, *id)
+        .await?
+        .ok_or(AdminSubscribersError::NotFound)?;
+    let consent_events = consent_events::list(pool.get_ref(), *id).await?;
+    Ok(HttpResponse::Ok().json(SubscriberDetail {
+        subscriber,
+        consent_events,
+    }))
+}
+
+// 修改订阅者的邮箱、名字，或手动变更其状态。每次修改都会记录操作者以及修改前后的值
+#[tracing::instrument(
+    name = "Update subscriber",
+    skip(patch, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn update_subscriber(
+    id: web::Path<Uuid>,
+    patch: web::Json<SubscriberPatch>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let patch = patch.into_inner();
+    let update =
+        SubscriberUpdate::parse(patch.email, patch.name).map_err(AdminSubscribersError::Invalid)?;
+
+    let list = match patch.transition {
+        Some(_) => {
+            let slug = patch.list.unwrap_or_else(|| DEFAULT_LIST.into());
+            let list = lists::find_by_slug(&pool, &slug).await?.ok_or_else(|| {
+                AdminSubscribersError::Invalid(FieldErrors::from([(
+                    "list",
+                    vec![format!("There is no list called {}.", slug)],
+                )]))
+            })?;
+            Some(list)
+        }
+        None => None,
+    };
+
+    let mut transaction = pool.begin().await?;
+    let current = subscribers::lock_by_id(&mut transaction, *id)
+        .await?
+        .ok_or(AdminSubscribersError::NotFound)?;
+    // 数据库中已有的值在当时已经校验过，沿用即可
+    let email = match update.email {
+        Some(email) => email,
+        None => SubscriberEmail::parse(current.email.clone())
+            .map_err(AdminSubscribersError::Unexpected)?,
+    };
+    let name = match update.name {
+        Some(name) => name,
+        None => SubscriberName::parse(current.name.clone())
+            .map_err(AdminSubscribersError::Unexpected)?,
+    };
+
+    let mut changes = serde_json::Map::new();
+    let mut record = |field: &str, from: &str, to: &str| {
+        if from != to {
+            changes.insert(field.into(), json!({ "from": from, "to": to }));
+        }
+    };
+    record("email", &current.email, email.as_ref());
+    record("name", &current.name, name.as_ref());
+    // 修改邮箱或名字与变更状态分别记录为不同的同意事件
+    let edited = !changes.is_empty();
+
+    // 状态属于订阅者在某个列表中的成员资格
+    let mut status_change = None;
+    if let (Some(transition), Some(list)) = (patch.transition, &list) {
+        let current_status = memberships::lock(&mut transaction, *id, list.id)
+            .await?
+            .ok_or_else(|| {
+                AdminSubscribersError::Conflict(format!(
+                    "{} is not on the {} list.",
+                    current.email, list.slug
+                ))
+            })?;
+        let status = current_status
+            .apply(transition)
+            .map_err(AdminSubscribersError::Conflict)?;
+        changes.insert(
+            "status".into(),
+            json!({ "list": list.slug, "from": current_status.as_str(), "to": status.as_str() }),
+        );
+        status_change = Some((transition, list.id, status));
+    }
+    if changes.is_empty() {
+        return Ok(HttpResponse::Ok().json(current));
+    }
+
+    // 先修改成员资格，update返回的lists才会包含新的状态
+    if let Some((_, list_id, status)) = status_change {
+        memberships::set_status(&mut transaction, *id, list_id, status).await?;
+    }
+    let updated = subscribers::update(&mut transaction, *id, &email, &name)
+        .await
+        .map_err(|e| match e {
+            UpdateSubscriberError::EmailTaken => AdminSubscribersError::Conflict(format!(
+                "Another subscriber already uses {}.",
+                email
+            )),
+            UpdateSubscriberError::Database(e) => e.into(),
+        })?;
+    subscribers::record_change(
+        &mut transaction,
+        *id,
+        Some(user.user_id),
+        "update",
+        changes.into(),
+    )
+    .await?;
+    let consent = ConsentSource::admin(&user.username);
+    if edited {
+        consent_events::record(
+            &mut transaction,
+            *id,
+            None,
+            ConsentEvent::AdminEdit,
+            &consent,
+        )
+        .await?;
+    }
+    if let Some((transition, list_id, _)) = status_change {
+        consent_events::record(
+            &mut transaction,
+            *id,
+            Some(list_id),
+            transition.into(),
+            &consent,
+        )
+        .await?;
+    }
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+// 批量添加和移除标签。每个标签发生了变化的订阅者各有一条审计记录
+#[tracing::instrument(
+    name = "Update subscriber tags",
+    skip(update, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn update_tags(
+    update: web::Json<TagsUpdate>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let update = update.into_inner();
+    let mut errors = FieldErrors::new();
+    let mut parse_tags = |field: &'static str, tags: Vec<String>| {
+        let mut parsed = Vec::new();
+        for tag in tags {
+            match SubscriberTag::parse(tag) {
+                Ok(tag) => parsed.push(tag),
+                Err(e) => errors.entry(field).or_default().push(e),
+            }
+        }
+        parsed
+    };
+    let add = parse_tags("add", update.add);
+    let remove = parse_tags("remove", update.remove);
+    if add.is_empty() && remove.is_empty() && errors.is_empty() {
+        errors
+            .entry("add")
+            .or_default()
+            .push("Add or remove at least one tag.".into());
+    }
+    let target = match (&update.ids, &update.filters) {
+        (Some(ids), None) => Some(TagTarget::Ids(ids)),
+        (None, Some(filters)) => Some(TagTarget::Matching(filters)),
+        _ => {
+            errors
+                .entry("ids")
+                .or_default()
+                .push("Specify either ids or filters.".into());
+            None
+        }
+    };
+    let target = match target {
+        Some(target) if errors.is_empty() => target,
+        _ => return Err(AdminSubscribersError::Invalid(errors)),
+    };
+
+    let mut transaction = pool.begin().await?;
+    let changed = subscribers::update_tags(&mut transaction, target, &add, &remove).await?;
+    let ids: Vec<Uuid> = changed.iter().map(|c| c.id).collect();
+    let changes: Vec<serde_json::Value> = changed
+        .iter()
+        .map(|c| json!({ "tags": { "from": c.old_tags, "to": c.new_tags } }))
+        .collect();
+    subscribers::record_changes(&mut transaction, &ids, Some(user.user_id), "tags", &changes)
+        .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(TagsUpdated {
+        updated: changed.len(),
+    }))
+}
+
+// 删除订阅者。审计记录中保留被删除时的数据
+#[tracing::instrument(
+    name = "Delete subscriber",
+    skip(pool, user),
+    fields(username = %user.username)
+)]
+pub async fn delete_subscriber(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSubscribersError> {
+    let mut transaction = pool.begin().await?;
+    let current = subscribers::lock_by_id(&mut transaction, *id)
+        .await?
+        .ok_or(AdminSubscribersError::NotFound)?;
+    subscribers::delete(&mut transaction, *id).await?;
+    let snapshot = serde_json::to_value(&current)
+        .map_err(|e| AdminSubscribersError::Unexpected(e.to_string()))?;
+    subscribers::record_change(
+        &mut transaction,
+        *id,
+        Some(user.user_id),
+        "delete",
+        snapshot,
+    )
+    .await?;
+    consent_events::record(
+        &mut transaction,
+        *id,
+        None,
+        ConsentEvent::Delete,
+        &ConsentSource::admin(&user.username),
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(HttpResponse::NoContent().finish())
+}
@@ -0,0 +1,127 @@
+use actix_web::{
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    web, HttpResponse,
+};
+use chrono::Utc;
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::repository::subscribers::{self, SubscriberFilters, SubscriberRecord};
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    // 每行一个JSON对象
+    Ndjson,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Csv => "text/csv; charset=utf-8",
+            Self::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Csv => "csv",
+            Self::Ndjson => "ndjson",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ExportParameters {
+    #[serde(flatten)]
+    filters: SubscriberFilters,
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+const CSV_HEADER: &str = "id,email,name,lists,subscribed_at\n";
+
+// 管理后台：以CSV或NDJSON格式导出订阅者，支持与列表接口相同的过滤条件
+// 数据边查询边发送，导出的数据量不受内存限制
+#[tracing::instrument(
+    name = "Export subscribers",
+    skip(parameters, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn export_subscribers(
+    parameters: web::Query<ExportParameters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> HttpResponse {
+    let ExportParameters { filters, format } = parameters.into_inner();
+    let header = match format {
+        ExportFormat::Csv => Some(Ok(web::Bytes::from_static(CSV_HEADER.as_bytes()))),
+        ExportFormat::Ndjson => None,
+    };
+    let rows = subscribers::stream(pool.get_ref().clone(), filters).map(move |record| {
+        record.map(|record| match format {
+            ExportFormat::Csv => csv_line(&record),
+            ExportFormat::Ndjson => ndjson_line(&record),
+        })
+    });
+    let body = futures_util::stream::iter(header).chain(rows);
+
+    let filename = format!(
+        "subscribers-{}.{}",
+        Utc::now().format("%Y%m%d"),
+        format.extension()
+    );
+    HttpResponse::Ok()
+        .insert_header((header::CONTENT_TYPE, format.content_type()))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename(filename)],
+        })
+        .streaming(body)
+}
+
+fn csv_line(record: &SubscriberRecord) -> web::Bytes {
+    let fields = [
+        record.id.to_string(),
+        csv_field(&record.email),
+        csv_field(&record.name),
+        csv_field(&lists_cell(record)),
+        record.subscribed_at.to_rfc3339(),
+    ];
+    format!("{}\n", fields.join(",")).into()
+}
+
+// 订阅者所在的列表及状态，形如newsletter:confirmed;product-updates:pending
+fn lists_cell(record: &SubscriberRecord) -> String {
+    record
+        .lists
+        .iter()
+        .map(|m| format!("{}:{}", m.list, m.status))
+        .collect::<Vec<_>>()
+        .join(";")
+}
+
+fn ndjson_line(record: &SubscriberRecord) -> web::Bytes {
+    let mut line = serde_json::to_vec(record).expect("Failed to serialize subscriber");
+    line.push(b'\n');
+    line.into()
+}
+
+// 按RFC 4180转义一个字段。导出的文件会被用电子表格打开，
+// 以=、+、-、@开头的值会被当成公式执行，因此在前面加一个单引号使其成为普通文本
+fn csv_field(value: &str) -> String {
+    let value = if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        format!("'{}", value)
+    } else {
+        value.to_string()
+    };
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value
+    }
+}
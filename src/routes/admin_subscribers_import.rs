@@ -0,0 +1,53 @@
+use actix_web::{web, HttpResponse};
+use futures_util::StreamExt;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::subscriber_import::{ImportError, ImportOptions, SubscriberImporter};
+
+#[derive(Deserialize, Debug)]
+pub struct ImportParameters {
+    #[serde(default)]
+    confirmed: bool,
+    consent_source: Option<String>,
+    // 导入到哪个列表，默认为newsletter
+    list: Option<String>,
+}
+
+// 管理后台：从CSV批量导入订阅者（表头需包含email和name列，可选subscribed_at列）
+// 请求体按块处理，不会被整个读入内存，因此可以上传很大的文件
+#[tracing::instrument(
+    name = "Import subscribers",
+    skip(body, pool, user),
+    fields(username = %user.username)
+)]
+pub async fn import_subscribers(
+    mut body: web::Payload,
+    parameters: web::Query<ImportParameters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, ImportError> {
+    let parameters = parameters.into_inner();
+    let mut importer = SubscriberImporter::new(
+        &pool,
+        ImportOptions {
+            confirmed: parameters.confirmed,
+            consent_source: parameters.consent_source,
+            list: parameters.list,
+        },
+    )
+    .await?;
+    while let Some(chunk) = body.next().await {
+        let chunk = chunk.map_err(|e| ImportError::Payload(e.to_string()))?;
+        importer.feed(&chunk).await?;
+    }
+    let report = importer.finish().await?;
+    tracing::info!(
+        imported = report.imported,
+        duplicates = report.duplicates,
+        invalid = report.invalid,
+        "Finished importing subscribers"
+    );
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,185 @@
+use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::SubscriberEmail;
+use crate::repository::suppressions::{self, Suppression, SuppressionChange};
+
+// 每次默认和最多返回的屏蔽数量
+const DEFAULT_PAGE_SIZE: i64 = 50;
+const MAX_PAGE_SIZE: i64 = 200;
+
+// 管理员手动屏蔽的原因
+const MANUAL_REASON: &str = "manual";
+
+#[derive(Deserialize, Debug)]
+pub struct SuppressionFilters {
+    // 表中只保存哈希值，按地址查询时先计算哈希值
+    email: Option<String>,
+    reason: Option<String>,
+    limit: Option<i64>,
+}
+
+// POST /admin/suppressions的请求体
+#[derive(Deserialize)]
+pub struct NewSuppressionBody {
+    email: String,
+    // 审计说明：为什么屏蔽这个地址
+    note: String,
+}
+
+// POST /admin/suppressions/{email_hash}/lift的请求体
+#[derive(Deserialize)]
+pub struct LiftSuppressionBody {
+    // 审计说明：为什么可以再给这个地址发送邮件
+    note: String,
+}
+
+// 一个地址当前的屏蔽（已解除时为null）以及屏蔽和解除的历史
+#[derive(Serialize)]
+pub struct SuppressionDetail {
+    suppression: Option<Suppression>,
+    history: Vec<SuppressionChange>,
+}
+
+#[derive(Debug)]
+pub enum AdminSuppressionsError {
+    Invalid(String),
+    NotFound,
+    Conflict,
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminSuppressionsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(e) => f.write_str(e),
+            Self::NotFound => f.write_str("The address is not suppressed."),
+            Self::Conflict => f.write_str("The address is already suppressed."),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminSuppressionsError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict => StatusCode::CONFLICT,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for AdminSuppressionsError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 审计说明不能为空
+fn parse_note(note: String) -> Result<String, AdminSuppressionsError> {
+    let note = note.trim();
+    if note.is_empty() {
+        return Err(AdminSuppressionsError::Invalid(
+            "A note explaining the change is required.".into(),
+        ));
+    }
+    Ok(note.to_string())
+}
+
+// 管理后台：被屏蔽的地址，最近屏蔽的在前
+#[tracing::instrument(name = "List suppressions", skip(pool, user), fields(username = %user.username))]
+pub async fn list_suppressions(
+    filters: web::Query<SuppressionFilters>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSuppressionsError> {
+    let filters = filters.into_inner();
+    let email_hash = filters.email.as_deref().map(suppressions::email_hash);
+    let limit = filters
+        .limit
+        .unwrap_or(DEFAULT_PAGE_SIZE)
+        .clamp(1, MAX_PAGE_SIZE);
+    let suppressions = suppressions::list(
+        &pool,
+        email_hash.as_deref(),
+        filters.reason.as_deref(),
+        limit,
+    )
+    .await?;
+    Ok(HttpResponse::Ok().json(suppressions))
+}
+
+// 管理后台：手动屏蔽一个地址，例如订阅者通过其他渠道要求不再收到邮件
+#[tracing::instrument(name = "Add suppression", skip(body, pool, user), fields(username = %user.username))]
+pub async fn add_suppression(
+    body: web::Json<NewSuppressionBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSuppressionsError> {
+    let NewSuppressionBody { email, note } = body.into_inner();
+    let email = SubscriberEmail::parse(email).map_err(AdminSuppressionsError::Invalid)?;
+    let note = parse_note(note)?;
+    let mut transaction = pool.begin().await?;
+    if !suppressions::insert(
+        &mut transaction,
+        email.as_ref(),
+        MANUAL_REASON,
+        Some(user.user_id),
+        Some(&note),
+    )
+    .await?
+    {
+        return Err(AdminSuppressionsError::Conflict);
+    }
+    transaction.commit().await?;
+    let suppression = suppressions::find(&pool, &suppressions::email_hash(email.as_ref()))
+        .await?
+        .ok_or_else(|| AdminSuppressionsError::Unexpected("The suppression is gone.".into()))?;
+    Ok(HttpResponse::Created().json(suppression))
+}
+
+// 管理后台：一个地址的屏蔽和审计历史
+#[tracing::instrument(name = "Get suppression", skip(pool, user), fields(username = %user.username))]
+pub async fn get_suppression(
+    path: web::Path<String>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSuppressionsError> {
+    let email_hash = path.into_inner();
+    let (suppression, history) = tokio::try_join!(
+        suppressions::find(&pool, &email_hash),
+        suppressions::history(&pool, &email_hash),
+    )?;
+    if suppression.is_none() && history.is_empty() {
+        return Err(AdminSuppressionsError::NotFound);
+    }
+    Ok(HttpResponse::Ok().json(SuppressionDetail {
+        suppression,
+        history,
+    }))
+}
+
+// 管理后台：解除屏蔽，之后该地址可以重新订阅或被导入
+#[tracing::instrument(name = "Lift suppression", skip(body, pool, user), fields(username = %user.username))]
+pub async fn lift_suppression(
+    path: web::Path<String>,
+    body: web::Json<LiftSuppressionBody>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminSuppressionsError> {
+    let note = parse_note(body.into_inner().note)?;
+    let mut transaction = pool.begin().await?;
+    let suppression = suppressions::delete(&mut transaction, &path, user.user_id, &note)
+        .await?
+        .ok_or(AdminSuppressionsError::NotFound)?;
+    transaction.commit().await?;
+    Ok(HttpResponse::Ok().json(suppression))
+}
@@ -0,0 +1,231 @@
+use actix_web::{
+    http::{
+        header::{ContentDisposition, DispositionParam, DispositionType},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use chrono::Utc;
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::authentication::AuthenticatedUser;
+use crate::domain::{FieldErrors, TemplateName};
+use crate::email_client::EmailClient;
+use crate::email_message::{EmailMessage, Mailbox};
+use crate::email_template::{self, RenderedEmail, TemplateSet, TemplateVariables};
+use crate::i18n::I18n;
+use crate::repository::templates::{self, StoredTemplate};
+use crate::routes::validation_failed;
+use crate::startup::ApplicationBaseUrl;
+
+// PUT /admin/templates/{name}的请求体
+#[derive(Deserialize, Debug)]
+pub struct TemplateBody {
+    // 只有直接发送的模板才需要标题，布局和片段可以省略
+    subject: Option<String>,
+    html: Option<String>,
+    text: Option<String>,
+    // 套用的布局（另一个模板的名称）
+    layout: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PreviewFormat {
+    // 标题、HTML和纯文本组成的JSON对象
+    #[default]
+    Json,
+    // 完整的MIME消息，可以直接用邮件客户端打开
+    Eml,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PreviewParameters {
+    // 按哪种语言渲染{{t}}，默认为配置中的默认语言
+    locale: Option<String>,
+    #[serde(default)]
+    format: PreviewFormat,
+}
+
+#[derive(Debug)]
+pub enum AdminTemplatesError {
+    Invalid(FieldErrors),
+    NotFound,
+    Unexpected(String),
+}
+
+impl std::fmt::Display for AdminTemplatesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Invalid(_) => f.write_str("The template is invalid."),
+            Self::NotFound => f.write_str("The template does not exist."),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for AdminTemplatesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Invalid(errors) => validation_failed("The template is invalid.", errors),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for AdminTemplatesError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 管理后台：所有模板
+#[tracing::instrument(name = "List templates", skip(pool, user), fields(username = %user.username))]
+pub async fn list_templates(
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminTemplatesError> {
+    let templates = templates::all(pool.get_ref()).await?;
+    Ok(HttpResponse::Ok().json(templates))
+}
+
+#[tracing::instrument(name = "Get template", skip(pool, user), fields(username = %user.username))]
+pub async fn get_template(
+    name: web::Path<String>,
+    pool: web::Data<PgPool>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminTemplatesError> {
+    let template = templates::find_by_name(&pool, &name)
+        .await?
+        .ok_or(AdminTemplatesError::NotFound)?;
+    Ok(HttpResponse::Ok().json(template))
+}
+
+// 管理后台：新建或替换模板。保存前编译模板并用示例订阅者渲染一遍，任何错误都不会保存
+#[tracing::instrument(
+    name = "Save template",
+    skip(body, pool, base_url, i18n, user),
+    fields(username = %user.username)
+)]
+pub async fn save_template(
+    name: web::Path<String>,
+    body: web::Json<TemplateBody>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminTemplatesError> {
+    let TemplateBody {
+        subject,
+        html,
+        text,
+        layout,
+    } = body.into_inner();
+    let mut errors = FieldErrors::new();
+    let name = match TemplateName::parse(name.into_inner()) {
+        Ok(name) => Some(name),
+        Err(e) => {
+            errors.insert("name", vec![e]);
+            None
+        }
+    };
+    let mut required = |field: &'static str, value: Option<String>| {
+        let value = value.unwrap_or_default();
+        if value.trim().is_empty() {
+            errors.insert(field, vec![format!("The {} must not be empty.", field)]);
+        }
+        value
+    };
+    let html = required("html", html);
+    let text = required("text", text);
+    let name = match name {
+        Some(name) if errors.is_empty() => name,
+        _ => return Err(AdminTemplatesError::Invalid(errors)),
+    };
+    let candidate = StoredTemplate {
+        name: name.as_ref().to_string(),
+        subject: subject.filter(|s| !s.trim().is_empty()),
+        html,
+        text,
+        layout: layout.filter(|s| !s.trim().is_empty()),
+        updated_at: Utc::now(),
+    };
+
+    let mut transaction = pool.begin().await?;
+    templates::lock(&mut transaction).await?;
+    let existing = templates::all(&mut *transaction).await?;
+    email_template::validate(
+        existing,
+        &candidate,
+        &base_url.0,
+        i18n.default_catalogue().locale(),
+    )
+    .map_err(AdminTemplatesError::Invalid)?;
+    let (saved, created) = templates::save(&mut transaction, &candidate, user.user_id).await?;
+    transaction.commit().await?;
+    if created {
+        Ok(HttpResponse::Created().json(saved))
+    } else {
+        Ok(HttpResponse::Ok().json(saved))
+    }
+}
+
+// 管理后台：用示例订阅者渲染模板（包括布局），查看邮件的最终效果
+// 与发送时一样，HTML经过净化和CSS内联，纯文本为空时从HTML生成
+#[tracing::instrument(
+    name = "Preview template",
+    skip(pool, base_url, i18n, email_client, user),
+    fields(username = %user.username)
+)]
+pub async fn preview_template(
+    name: web::Path<String>,
+    parameters: web::Query<PreviewParameters>,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    email_client: web::Data<EmailClient>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, AdminTemplatesError> {
+    let templates = TemplateSet::load(&pool)
+        .await
+        .map_err(AdminTemplatesError::Unexpected)?;
+    if !templates.contains(&name) {
+        return Err(AdminTemplatesError::NotFound);
+    }
+    let locale = i18n.for_locale(parameters.locale.as_deref()).locale();
+    let variables = TemplateVariables::sample(&base_url.0, locale);
+    let email = templates
+        .render(&name, &variables)
+        .map_err(AdminTemplatesError::Unexpected)?;
+    let message = EmailMessage::builder(
+        Mailbox::new(email_client.sender()),
+        Mailbox::with_name(variables.name.as_str(), variables.email.as_str()),
+    )
+    .subject(&email.subject)
+    .html(&email.html)
+    .text(&email.text)
+    .build();
+    match parameters.format {
+        PreviewFormat::Json => Ok(HttpResponse::Ok().json(RenderedEmail {
+            subject: message.subject().to_string(),
+            html: message.html().to_string(),
+            text: message.text().to_string(),
+        })),
+        PreviewFormat::Eml => Ok(HttpResponse::Ok()
+            .content_type("message/rfc822")
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename(format!("{}.eml", name))],
+            })
+            .body(message.to_mime())),
+    }
+}
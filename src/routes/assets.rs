@@ -0,0 +1,38 @@
+use actix_web::{
+    http::header::{
+        CacheControl, CacheDirective, ContentDisposition, DispositionParam, DispositionType,
+    },
+    web, HttpResponse,
+};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::attachments::{AttachmentError, Attachments};
+use crate::repository::assets;
+
+// 公开地提供上传的文件。每周摘要不带附件，其中的内嵌图片和附件改为链接到这里
+// asset的内容不会改变，因此可以被长期缓存
+#[tracing::instrument(name = "Serve an asset", skip(pool, attachments))]
+pub async fn asset(
+    id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    attachments: web::Data<Attachments>,
+) -> Result<HttpResponse, AttachmentError> {
+    let asset = assets::find_by_id(&pool, id.into_inner())
+        .await?
+        .ok_or(AttachmentError::NotFound)?;
+    let content = attachments.content(&asset).await?;
+    Ok(HttpResponse::Ok()
+        .content_type(asset.content_type)
+        .insert_header(CacheControl(vec![
+            CacheDirective::Public,
+            CacheDirective::MaxAge(31_536_000),
+            CacheDirective::Extension("immutable".into(), None),
+        ]))
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Inline,
+            parameters: vec![DispositionParam::Filename(asset.filename)],
+        })
+        .insert_header(("X-Content-Type-Options", "nosniff"))
+        .body(content))
+}
@@ -0,0 +1,37 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{dev::Payload, http::header, web, FromRequest, HttpMessage, HttpRequest};
+use serde::de::DeserializeOwned;
+
+// 根据Content-Type，将请求体按application/json或application/x-www-form-urlencoded解析为T
+pub struct FormOrJson<T>(pub T);
+
+impl<T: DeserializeOwned + 'static> FromRequest for FormOrJson<T> {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        if is_json(req.content_type()) {
+            let json = web::Json::<T>::from_request(req, payload);
+            Box::pin(async move { Ok(FormOrJson(json.await?.into_inner())) })
+        } else {
+            let form = web::Form::<T>::from_request(req, payload);
+            Box::pin(async move { Ok(FormOrJson(form.await?.into_inner())) })
+        }
+    }
+}
+
+// 客户端是否希望得到JSON响应：显式地在Accept中声明了application/json，或者请求体本身就是JSON
+pub fn wants_json(req: &HttpRequest) -> bool {
+    let accepts_json = req
+        .headers()
+        .get_all(header::ACCEPT)
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("application/json"));
+    accepts_json || is_json(req.content_type())
+}
+
+fn is_json(content_type: &str) -> bool {
+    content_type == "application/json"
+}
@@ -1,8 +1,52 @@
+mod admin_account;
+mod admin_assets;
+mod admin_delivery;
+mod admin_issues;
+mod admin_lists;
+mod admin_segments;
+mod admin_subscribers;
+mod admin_subscribers_export;
+mod admin_subscribers_import;
+mod admin_suppressions;
+mod admin_templates;
+mod assets;
+mod content_negotiation;
 mod health_check;
+mod newsletters;
+mod openapi;
+mod preferences;
+mod privacy;
 mod subscriptions;
+mod subscriptions_challenge;
+mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
+mod webhooks;
 
 // 注：
 // - mod health_check + pub use health_check::函数名A：别的模块可以直接以crate::routes::函数名A的方式调用该函数
 // - 如果pub mod health_check：别的模块需要以crate::routes::health_check::函数名A的方式调用该函数
+pub use admin_account::*;
+pub use admin_assets::*;
+pub use admin_delivery::*;
+pub use admin_issues::*;
+pub use admin_lists::*;
+pub use admin_segments::*;
+pub use admin_subscribers::*;
+pub use admin_subscribers_export::*;
+pub use admin_subscribers_import::*;
+pub use admin_suppressions::*;
+pub use admin_templates::*;
+pub use assets::*;
+pub use content_negotiation::*;
 pub use health_check::*;
+pub use newsletters::*;
+pub use openapi::*;
+pub use preferences::*;
+pub use privacy::*;
 pub use subscriptions::*;
+pub use subscriptions_challenge::*;
+pub use subscriptions_confirm::*;
+pub use subscriptions_unsubscribe::*;
+pub use tracking::*;
+pub use webhooks::*;
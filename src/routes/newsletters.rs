@@ -0,0 +1,173 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use actix_web::{dev::Payload, web, FromRequest, HttpMessage, HttpRequest, HttpResponse};
+use chrono::Utc;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::attachments::{parse_form_data, read_payload, Attachments, FormPart};
+use crate::authentication::AuthenticatedUser;
+use crate::domain::DeliveryFrequency;
+use crate::i18n::I18n;
+use crate::newsletter_issue::{BodyData, PreparedIssue, PublishError};
+use crate::repository::{digests, issue_revisions, issues};
+use crate::startup::ApplicationBaseUrl;
+
+// multipart/form-data形式的请求体：newsletter部分是JSON格式的BodyData，
+// attachment部分是普通附件，inline部分是内嵌图片（HTML中通过cid:{文件名}引用）
+// 注：只用于生成OpenAPI文档，请求由PublishRequest解析
+#[derive(ToSchema)]
+#[allow(dead_code)]
+pub struct NewsletterForm {
+    newsletter: BodyData,
+    #[schema(value_type = Option<Vec<String>>, format = Binary)]
+    attachment: Vec<Vec<u8>>,
+    #[schema(value_type = Option<Vec<String>>, format = Binary)]
+    inline: Vec<Vec<u8>>,
+}
+
+// 除附件之外，multipart请求体中JSON和各部分的头所允许的大小
+const FORM_OVERHEAD_BYTES: u64 = 1024 * 1024;
+
+// 发布请求：application/json的BodyData，或者multipart/form-data（见NewsletterForm）
+pub struct PublishRequest {
+    body: BodyData,
+    // multipart请求体中上传的文件
+    uploads: Vec<FormPart>,
+}
+
+impl FromRequest for PublishRequest {
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self, Self::Error>>>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut Payload) -> Self::Future {
+        let mime = req.mime_type().ok().flatten();
+        let Some(mime) = mime.filter(|m| m.essence_str() == "multipart/form-data") else {
+            let json = web::Json::<BodyData>::from_request(req, payload);
+            return Box::pin(async move {
+                Ok(PublishRequest {
+                    body: json.await?.into_inner(),
+                    uploads: Vec::new(),
+                })
+            });
+        };
+        let boundary = mime.get_param("boundary").map(|b| b.to_string());
+        let limit = req
+            .app_data::<web::Data<Attachments>>()
+            .map_or(0, |attachments| attachments.max_total_bytes())
+            + FORM_OVERHEAD_BYTES;
+        let payload = payload.take();
+        Box::pin(async move {
+            let boundary = boundary.ok_or_else(|| {
+                actix_web::error::ErrorBadRequest("The multipart body has no boundary.")
+            })?;
+            let content = read_payload(payload, limit).await?;
+            let mut body = None;
+            let mut uploads = Vec::new();
+            for part in
+                parse_form_data(&boundary, &content).map_err(actix_web::error::ErrorBadRequest)?
+            {
+                match (part.name.as_str(), &part.filename) {
+                    ("newsletter", None) => {
+                        body = Some(serde_json::from_slice(&part.content).map_err(|e| {
+                            actix_web::error::ErrorBadRequest(format!(
+                                "The newsletter part is invalid: {}",
+                                e
+                            ))
+                        })?)
+                    }
+                    ("attachment" | "inline", Some(_)) => uploads.push(part),
+                    (name, _) => {
+                        return Err(actix_web::error::ErrorBadRequest(format!(
+                            "Unexpected multipart part {}.",
+                            name
+                        )))
+                    }
+                }
+            }
+            Ok(PublishRequest {
+                body: body.ok_or_else(|| {
+                    actix_web::error::ErrorBadRequest("The newsletter part is missing.")
+                })?,
+                uploads,
+            })
+        })
+    }
+}
+
+// 将一期newsletter发送给目标列表中所有已确认的订阅者，指定了分组时只发送给满足分组的订阅者
+// 选择每周摘要的放入摘要队列，暂停中的跳过
+// 内容被保存为一期，收件人放入投递队列，由后台任务按发送速率发送。请求不必等待发送完成，
+// 中途失败的邮件由后台任务重试，不会因为重新提交请求而重复发送
+#[utoipa::path(
+    post,
+    path = "/api/v1/newsletters",
+    tag = "newsletters",
+    request_body(content(
+        (BodyData = "application/json"),
+        (NewsletterForm = "multipart/form-data"),
+    )),
+    security(("basic_auth" = [])),
+    responses(
+        (status = 202, description = "The issue has been queued for all confirmed subscribers of the lists, or for their weekly digest. Its status can be followed through /admin/issues/{id}."),
+        (status = 400, description = "The request body is invalid or names an unknown list, segment, template or asset.", body = crate::request_id::ErrorBody),
+        (status = 401, description = "Missing or invalid credentials.", body = crate::request_id::ErrorBody),
+        (status = 413, description = "The attachments exceed the configured size limits.", body = crate::request_id::ErrorBody),
+        (status = 415, description = "An attachment has a type that is not allowed.", body = crate::request_id::ErrorBody),
+    )
+)]
+#[tracing::instrument(
+    name = "Publish a newsletter issue",
+    skip(request, pool, base_url, i18n, attachments, user),
+    fields(username = %user.username, user_id = %user.user_id)
+)]
+pub async fn publish_newsletter(
+    request: PublishRequest,
+    pool: web::Data<PgPool>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    attachments: web::Data<Attachments>,
+    user: AuthenticatedUser,
+) -> Result<HttpResponse, PublishError> {
+    let PublishRequest { body, uploads } = request;
+    let issue = PreparedIssue::prepare(&pool, &body, uploads, &attachments, user.user_id).await?;
+    let (recipients, digest_recipients) = tokio::try_join!(
+        issue.recipients(&pool, DeliveryFrequency::Immediate),
+        issue.recipients(&pool, DeliveryFrequency::WeeklyDigest),
+    )?;
+    // 选择每周摘要的订阅者由send_digests汇总发送
+    let items = issue.digest_items(&digest_recipients, &i18n, &base_url.0)?;
+    let subscriber_ids: Vec<Uuid> = recipients.iter().map(|r| r.subscriber_id).collect();
+    // 保存的一期、摘要和投递队列要么都写入，要么都不写入
+    let mut transaction = pool.begin().await?;
+    let saved = issues::insert(
+        &mut transaction,
+        &body.title,
+        Some(Utc::now()),
+        None,
+        user.user_id,
+    )
+    .await?;
+    issue_revisions::insert(
+        &mut transaction,
+        saved.id,
+        1,
+        &issue.stored_request(&body),
+        user.user_id,
+    )
+    .await?;
+    digests::queue(&mut *transaction, issue.title(), &items).await?;
+    issues::start_sending(&mut transaction, saved.id, &subscriber_ids).await?;
+    transaction.commit().await?;
+    tracing::info!(
+        "Newsletter issue {} queued for {} subscribers and {} digests",
+        saved.id,
+        subscriber_ids.len(),
+        items.len()
+    );
+    // 返回状态已经是sending的一期
+    let saved = issues::find_by_id(&pool, saved.id).await?;
+    Ok(HttpResponse::Accepted().json(saved))
+}
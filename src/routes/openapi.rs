@@ -0,0 +1,41 @@
+use actix_web::HttpResponse;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// /api/v1的OpenAPI 3文档，由handler上的#[utoipa::path]和类型上的#[derive(ToSchema)]生成
+// 注：修改接口后需要更新openapi/v1.json快照，否则测试会失败
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "zero2prod", description = "Public API of the zero2prod newsletter."),
+    paths(
+        crate::routes::subscribe,
+        crate::routes::subscription_challenge,
+        crate::routes::confirm,
+        crate::routes::unsubscribe_form,
+        crate::routes::unsubscribe,
+        crate::routes::publish_newsletter,
+    ),
+    modifiers(&BasicAuth),
+    tags(
+        (name = "subscriptions", description = "Subscribing to the newsletter."),
+        (name = "newsletters", description = "Publishing newsletter issues."),
+    )
+)]
+pub struct ApiDoc;
+
+// 声明受保护接口所使用的HTTP Basic认证方案
+struct BasicAuth;
+
+impl Modify for BasicAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "basic_auth",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Basic).build()),
+        );
+    }
+}
+
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
@@ -0,0 +1,360 @@
+use actix_web::{http::header, http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+
+use crate::domain::{
+    DeliveryFrequency, FieldErrors, PausedUntil, StatusTransition, SubscriberName,
+    SubscriptionStatus,
+};
+use crate::i18n::{Catalogue, I18n};
+use crate::rate_limit::RateLimiter;
+use crate::repository::consent_events::{self, ConsentEvent};
+use crate::repository::preferences::{self, ListChoice, Preferences};
+use crate::repository::{memberships, subscribers, tracking_events};
+use crate::routes::{consent_from_request, escape_html, validation_failed};
+
+#[derive(Deserialize, Debug)]
+pub struct PreferencesParameters {
+    token: String,
+}
+
+// 新闻邮件中偏好设置页面的链接
+pub fn preferences_link(base_url: &str, preferences_token: &str) -> String {
+    format!("{}/preferences?token={}", base_url, preferences_token)
+}
+
+// 返回给订阅者的错误信息按页面的语言翻译
+#[derive(Debug)]
+pub enum PreferencesError {
+    // 令牌缺失或不存在
+    UnknownToken(&'static str),
+    Invalid(&'static str, FieldErrors),
+    Unexpected(String),
+}
+
+impl PreferencesError {
+    fn unknown_token(catalogue: &'static Catalogue) -> Self {
+        Self::UnknownToken(catalogue.t("errors.invalid_link"))
+    }
+}
+
+impl std::fmt::Display for PreferencesError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownToken(message) | Self::Invalid(message, _) => f.write_str(message),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::UnknownToken(_) => StatusCode::UNAUTHORIZED,
+            Self::Invalid(..) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            Self::Invalid(message, errors) => validation_failed(message, errors),
+            _ => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for PreferencesError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 偏好设置页面，订阅者通过新闻邮件中的链接进入，令牌即身份凭证
+#[tracing::instrument(name = "Show preferences", skip(req, parameters, pool, i18n))]
+pub async fn preferences_form(
+    req: HttpRequest,
+    parameters: web::Query<PreferencesParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+) -> Result<HttpResponse, PreferencesError> {
+    let catalogue = i18n.for_request(&req);
+    let current = preferences::find_by_token(&pool, &parameters.token)
+        .await?
+        .ok_or_else(|| PreferencesError::unknown_token(catalogue))?;
+    let choices = preferences::list_choices(&**pool, current.subscriber_id).await?;
+    Ok(page(catalogue, &parameters.token, &current, &choices, None))
+}
+
+// 保存偏好设置。所有字段都按领域类型校验，有任何错误时不做任何修改
+// 注：勾选的列表可能有多个，表单中同名字段会出现多次，因此按键值对的列表解析
+#[tracing::instrument(name = "Update preferences", skip(req, form, pool, i18n, rate_limiter))]
+pub async fn update_preferences(
+    req: HttpRequest,
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> Result<HttpResponse, PreferencesError> {
+    let catalogue = i18n.for_request(&req);
+    let field = |key: &str| form.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str());
+    let token = field("token").ok_or_else(|| PreferencesError::unknown_token(catalogue))?;
+    let selected: Vec<&str> = form
+        .iter()
+        .filter(|(k, _)| k == "list")
+        .map(|(_, v)| v.as_str())
+        .collect();
+
+    let mut transaction = pool.begin().await?;
+    let current = preferences::lock_by_token(&mut transaction, token)
+        .await?
+        .ok_or_else(|| PreferencesError::unknown_token(catalogue))?;
+    let choices = preferences::list_choices(&mut *transaction, current.subscriber_id).await?;
+
+    let mut errors = FieldErrors::new();
+    let name = SubscriberName::parse(field("name").unwrap_or_default().to_string())
+        .map_err(|e| errors.insert("name", vec![e]))
+        .ok();
+    let frequency = DeliveryFrequency::parse(field("delivery_frequency").unwrap_or_default())
+        .map_err(|e| errors.insert("delivery_frequency", vec![e]))
+        .ok();
+    let paused_until = match field("paused_until").map(str::trim) {
+        None | Some("") => Some(None),
+        Some(date) => PausedUntil::parse(date, Utc::now().date_naive())
+            .map(|date| Some(date.into_inner()))
+            .map_err(|e| errors.insert("paused_until", vec![e]))
+            .ok(),
+    };
+    // 勾选“不要跟踪”时才会提交该字段
+    let tracking_opt_out = field("no_tracking").is_some();
+    let unknown: Vec<String> = selected
+        .iter()
+        .filter(|slug| !choices.iter().any(|choice| choice.slug == **slug))
+        .map(|slug| format!("There is no list called {}.", slug))
+        .collect();
+    if !unknown.is_empty() {
+        errors.insert("lists", unknown);
+    }
+    let (name, frequency, paused_until) = match (name, frequency, paused_until) {
+        (Some(name), Some(frequency), Some(paused_until)) if errors.is_empty() => {
+            (name, frequency, paused_until)
+        }
+        _ => {
+            return Err(PreferencesError::Invalid(
+                catalogue.t("errors.invalid_preferences"),
+                errors,
+            ))
+        }
+    };
+
+    let mut changes = serde_json::Map::new();
+    let mut record = |field: &str, from: serde_json::Value, to: serde_json::Value| {
+        if from != to {
+            changes.insert(field.into(), json!({ "from": from, "to": to }));
+        }
+    };
+    record("name", json!(current.name), json!(name.as_ref()));
+    record(
+        "delivery_frequency",
+        json!(current.delivery_frequency),
+        json!(frequency.as_str()),
+    );
+    record(
+        "paused_until",
+        json!(current.paused_until),
+        json!(paused_until),
+    );
+    record(
+        "tracking_opt_out",
+        json!(current.tracking_opt_out),
+        json!(tracking_opt_out),
+    );
+
+    // 勾选的列表变为已确认（令牌只会发到订阅者的邮箱，无需再次确认），取消勾选的已确认列表变为已退订
+    // 被封禁的列表不能修改，待确认的列表不勾选时保持原状
+    let consent = consent_from_request("preferences_page", &req, &rate_limiter);
+    let mut list_changes = serde_json::Map::new();
+    for choice in &choices {
+        let status = choice
+            .status
+            .as_deref()
+            .map(SubscriptionStatus::parse)
+            .transpose()
+            .map_err(PreferencesError::Unexpected)?;
+        let wanted = selected.contains(&choice.slug.as_str());
+        let (new_status, event) = match (status, wanted) {
+            (Some(SubscriptionStatus::Blocked), _)
+            | (Some(SubscriptionStatus::Confirmed), true) => continue,
+            (None, true) => {
+                memberships::insert(
+                    &mut transaction,
+                    current.subscriber_id,
+                    choice.list_id,
+                    SubscriptionStatus::Confirmed,
+                )
+                .await?;
+                (SubscriptionStatus::Confirmed, ConsentEvent::Confirm)
+            }
+            (Some(_), true) => (SubscriptionStatus::Confirmed, ConsentEvent::Confirm),
+            (Some(SubscriptionStatus::Confirmed), false) => (
+                SubscriptionStatus::Confirmed
+                    .apply(StatusTransition::Unsubscribe)
+                    .map_err(PreferencesError::Unexpected)?,
+                ConsentEvent::Unsubscribe,
+            ),
+            (_, false) => continue,
+        };
+        if status.is_some() {
+            memberships::set_status(
+                &mut transaction,
+                current.subscriber_id,
+                choice.list_id,
+                new_status,
+            )
+            .await?;
+        }
+        consent_events::record(
+            &mut transaction,
+            current.subscriber_id,
+            Some(choice.list_id),
+            event,
+            &consent,
+        )
+        .await?;
+        list_changes.insert(
+            choice.slug.clone(),
+            json!({ "from": choice.status, "to": new_status.as_str() }),
+        );
+    }
+    if !list_changes.is_empty() {
+        changes.insert("lists".into(), list_changes.into());
+    }
+
+    if !changes.is_empty() {
+        preferences::update(
+            &mut transaction,
+            current.subscriber_id,
+            &name,
+            frequency,
+            paused_until,
+            tracking_opt_out,
+        )
+        .await?;
+        // 选择不被跟踪时，之前的打开和点击记录也一并删除
+        if tracking_opt_out && !current.tracking_opt_out {
+            tracking_events::delete_all(&mut transaction, current.subscriber_id).await?;
+        }
+        subscribers::record_change(
+            &mut transaction,
+            current.subscriber_id,
+            None,
+            "preferences",
+            changes.into(),
+        )
+        .await?;
+    }
+    let saved = preferences::lock_by_token(&mut transaction, token)
+        .await?
+        .ok_or_else(|| PreferencesError::unknown_token(catalogue))?;
+    let choices = preferences::list_choices(&mut *transaction, saved.subscriber_id).await?;
+    transaction.commit().await?;
+    Ok(page(
+        catalogue,
+        token,
+        &saved,
+        &choices,
+        Some(catalogue.t("preferences.saved")),
+    ))
+}
+
+// 渲染偏好设置表单。令牌已经在数据库中找到，只可能由字母和数字组成，可以直接放进HTML中
+fn page(
+    catalogue: &Catalogue,
+    token: &str,
+    current: &Preferences,
+    choices: &[ListChoice],
+    notice: Option<&str>,
+) -> HttpResponse {
+    let lists: String = choices
+        .iter()
+        .map(|choice| {
+            let (checked, note) = match choice.status.as_deref() {
+                Some("confirmed") => (" checked", None),
+                Some("pending_confirmation") => ("", Some("preferences.awaiting_confirmation")),
+                Some("blocked") => (" disabled", Some("preferences.blocked")),
+                _ => ("", None),
+            };
+            let note = note
+                .map(|key| format!(" ({})", escape_html(catalogue.t(key))))
+                .unwrap_or_default();
+            format!(
+                "<label><input type=\"checkbox\" name=\"list\" value=\"{}\"{}> {}{}</label><br>\n",
+                escape_html(&choice.slug),
+                checked,
+                escape_html(&choice.name),
+                note
+            )
+        })
+        .collect();
+    let frequency = |value: &str, label: &str| {
+        let checked = if current.delivery_frequency == value {
+            " checked"
+        } else {
+            ""
+        };
+        format!(
+            "<label><input type=\"radio\" name=\"delivery_frequency\" value=\"{}\"{}> {}</label><br>\n",
+            value,
+            checked,
+            escape_html(catalogue.t(label))
+        )
+    };
+    let paused_until = current
+        .paused_until
+        .filter(|until| *until > Utc::now())
+        .map(|until| until.format("%Y-%m-%d").to_string())
+        .unwrap_or_default();
+    let notice = notice
+        .map(|n| format!("<p>{}</p>\n", escape_html(n)))
+        .unwrap_or_default();
+
+    HttpResponse::Ok()
+        .content_type(header::ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+{notice}<form action="/preferences" method="post">
+<input type="hidden" name="token" value="{token}">
+<p><label>{name_label} <input type="text" name="name" value="{name}"></label></p>
+<fieldset><legend>{lists_label}</legend>
+{lists}</fieldset>
+<fieldset><legend>{frequency_label}</legend>
+{immediate}{digest}</fieldset>
+<p><label>{pause_label} <input type="date" name="paused_until" value="{paused_until}"></label></p>
+<p><label><input type="checkbox" name="no_tracking" value="on"{no_tracking}> {no_tracking_label}</label></p>
+<button type="submit">{save}</button>
+</form>
+</body>
+</html>"#,
+            lang = catalogue.locale(),
+            title = escape_html(catalogue.t("preferences.title")),
+            name_label = escape_html(catalogue.t("preferences.name")),
+            lists_label = escape_html(catalogue.t("preferences.lists")),
+            frequency_label = escape_html(catalogue.t("preferences.frequency")),
+            pause_label = escape_html(catalogue.t("preferences.pause")),
+            no_tracking_label = escape_html(catalogue.t("preferences.no_tracking")),
+            save = escape_html(catalogue.t("preferences.save")),
+            notice = notice,
+            token = token,
+            name = escape_html(&current.name),
+            lists = lists,
+            immediate = frequency("immediate", "preferences.immediate"),
+            digest = frequency("weekly_digest", "preferences.weekly_digest"),
+            paused_until = paused_until,
+            no_tracking = if current.tracking_opt_out { " checked" } else { "" },
+        ))
+}
@@ -0,0 +1,258 @@
+use actix_web::{
+    http::header::{self, ContentDisposition, DispositionParam, DispositionType},
+    web, HttpRequest, HttpResponse,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+
+use crate::domain::{generate_subscription_token, SubscriberEmail};
+use crate::email_client::{EmailClient, EmailClientError};
+use crate::i18n::{Catalogue, I18n};
+use crate::rate_limit::RateLimiter;
+use crate::repository::privacy::{self, PrivacyRequestKind};
+use crate::repository::subscribers;
+use crate::routes::{escape_html, invalid_link, FormOrJson};
+use crate::startup::ApplicationBaseUrl;
+
+// 邮件中链接的有效期
+const PRIVACY_TOKEN_TTL_HOURS: i64 = 24;
+
+#[derive(Deserialize, Debug)]
+pub struct PrivacyRequestForm {
+    email: String,
+    kind: PrivacyRequestKind,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PrivacyTokenParameters {
+    token: String,
+}
+
+// 订阅者申请导出或删除自己的数据。我们把带有令牌的链接发到该邮箱，只有邮箱的主人才能继续操作
+// 无论该地址是否订阅过都返回202，避免泄露某个地址是否在订阅列表中
+#[tracing::instrument(
+    name = "Request access to or erasure of personal data",
+    skip(form, pool, email_client, base_url, i18n, rate_limiter),
+    fields(kind = ?form.kind)
+)]
+pub async fn request_privacy_action(
+    FormOrJson(form): FormOrJson<PrivacyRequestForm>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> HttpResponse {
+    let email = match SubscriberEmail::parse(form.email) {
+        Ok(email) => email,
+        Err(e) => return HttpResponse::from_error(actix_web::error::ErrorBadRequest(e)),
+    };
+    // 与订阅接口一样按目标地址限流，避免被用来对某个邮箱进行轰炸
+    if let Err(e) = rate_limiter.check_email(email.as_ref()).await {
+        return HttpResponse::from_error(e);
+    }
+    let subscriber = match subscribers::find_by_email(&pool, &email).await {
+        Ok(Some(subscriber)) => subscriber,
+        Ok(None) => return HttpResponse::Accepted().finish(),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+
+    let privacy_token = generate_subscription_token();
+    let expires_at = Utc::now() + Duration::hours(PRIVACY_TOKEN_TTL_HOURS);
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if privacy::store_token(
+        &mut transaction,
+        subscriber.id,
+        form.kind,
+        &privacy_token,
+        expires_at,
+    )
+    .await
+    .is_err()
+        || transaction.commit().await.is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    // 邮件使用订阅者保存的语言
+    let locale = subscriber.attributes.get("locale").and_then(|l| l.as_str());
+    if send_privacy_email(
+        &email_client,
+        i18n.for_locale(locale),
+        &email,
+        &base_url.0,
+        form.kind,
+        &privacy_token,
+    )
+    .await
+    .is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Accepted().finish()
+}
+
+#[tracing::instrument(
+    name = "Send a privacy request email",
+    skip(email_client, catalogue, email, base_url, privacy_token)
+)]
+async fn send_privacy_email(
+    email_client: &EmailClient,
+    catalogue: &Catalogue,
+    email: &SubscriberEmail,
+    base_url: &str,
+    kind: PrivacyRequestKind,
+    privacy_token: &str,
+) -> Result<(), EmailClientError> {
+    let path = match kind {
+        PrivacyRequestKind::Access => "access",
+        PrivacyRequestKind::Erasure => "erasure",
+    };
+    let key = |name: &str| format!("privacy.{}.{}", path, name);
+    let link = format!("{}/privacy/{}?token={}", base_url, path, privacy_token);
+    let expiry = catalogue.format(
+        "privacy.expiry",
+        &[("hours", &PRIVACY_TOKEN_TTL_HOURS.to_string())],
+    );
+    let plain_body = format!(
+        "{}\n{}",
+        catalogue.format(&key("visit"), &[("url", &link)]),
+        expiry
+    );
+    let html_body = format!(
+        "<a href=\"{}\">{}</a><br />{}",
+        link,
+        escape_html(catalogue.t(&key("link"))),
+        escape_html(&expiry)
+    );
+    email_client
+        .send_email(
+            email.as_ref(),
+            catalogue.t(&key("subject")),
+            &html_body,
+            &plain_body,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to send a privacy request email: {:?}", e);
+            e
+        })
+}
+
+// 以JSON文件的形式返回我们保存的关于该订阅者的全部数据
+#[tracing::instrument(name = "Export personal data", skip(req, parameters, pool, i18n))]
+pub async fn privacy_access(
+    req: HttpRequest,
+    parameters: web::Query<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    let subscriber_id = match privacy::get_subscriber_id_from_token(
+        &pool,
+        &parameters.token,
+        PrivacyRequestKind::Access,
+    )
+    .await
+    {
+        Ok(Some(subscriber_id)) => subscriber_id,
+        // 令牌不存在或已过期
+        Ok(None) => return invalid_link(catalogue),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match privacy::export(&pool, subscriber_id).await {
+        Ok(Some(export)) => HttpResponse::Ok()
+            .insert_header(ContentDisposition {
+                disposition: DispositionType::Attachment,
+                parameters: vec![DispositionParam::Filename("my-data.json".into())],
+            })
+            .json(export),
+        Ok(None) => invalid_link(catalogue),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+// 删除前的确认页面
+// 注：邮件客户端和安全扫描器会预先访问邮件中的链接，因此GET请求本身不能删除数据
+#[tracing::instrument(name = "Show erasure confirmation", skip(req, parameters, pool, i18n))]
+pub async fn privacy_erasure_form(
+    req: HttpRequest,
+    parameters: web::Query<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    match privacy::get_subscriber_id_from_token(
+        &pool,
+        &parameters.token,
+        PrivacyRequestKind::Erasure,
+    )
+    .await
+    {
+        // 令牌只可能由字母和数字组成（否则查询不到），可以直接放进HTML中
+        Ok(Some(_)) => HttpResponse::Ok()
+            .content_type(header::ContentType::html())
+            .body(format!(
+                r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<p>{warning}</p>
+<form action="/privacy/erasure" method="post">
+<input type="hidden" name="token" value="{token}">
+<button type="submit">{button}</button>
+</form>
+</body>
+</html>"#,
+                lang = catalogue.locale(),
+                title = escape_html(catalogue.t("erasure.title")),
+                warning = escape_html(catalogue.t("erasure.warning")),
+                token = parameters.token,
+                button = escape_html(catalogue.t("erasure.button")),
+            )),
+        Ok(None) => invalid_link(catalogue),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[tracing::instrument(name = "Erase personal data", skip(req, form, pool, i18n))]
+pub async fn privacy_erasure(
+    req: HttpRequest,
+    form: web::Form<PrivacyTokenParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    let subscriber_id = match privacy::get_subscriber_id_from_token(
+        &pool,
+        &form.token,
+        PrivacyRequestKind::Erasure,
+    )
+    .await
+    {
+        Ok(Some(subscriber_id)) => subscriber_id,
+        Ok(None) => return invalid_link(catalogue),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    if privacy::erase(&mut transaction, subscriber_id)
+        .await
+        .is_err()
+        || transaction.commit().await.is_err()
+    {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(header::ContentType::html())
+        .body(format!(
+            "<!DOCTYPE html><html lang=\"{}\"><body><p>{}</p></body></html>",
+            catalogue.locale(),
+            escape_html(catalogue.t("erasure.done"))
+        ))
+}
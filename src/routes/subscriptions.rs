@@ -1,14 +1,60 @@
-use actix_web::{web, HttpResponse};
-use chrono::Utc;
-use serde::Deserialize;
-use sqlx::PgPool;
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
 use uuid::Uuid;
-#[derive(Deserialize, Debug)]
+
+use crate::bot_protection::{BotProtection, ProofOfWorkError};
+use crate::domain::{
+    generate_subscription_token, FieldErrors, NewSubscriber, SubscriberAttributes,
+    SubscriptionStatus,
+};
+use crate::email_client::EmailClient;
+use crate::email_template::{TemplateSet, TemplateVariables};
+use crate::i18n::{self, Catalogue, I18n};
+use crate::rate_limit::RateLimiter;
+use crate::repository::consent_events::{self, ConsentEvent, ConsentSource};
+use crate::repository::lists::{self, MailingList, DEFAULT_LIST};
+use crate::repository::{memberships, suppressions};
+use crate::request_id::RequestId;
+use crate::routes::{wants_json, FormOrJson};
+use crate::startup::ApplicationBaseUrl;
+
+// 订阅请求，可以是表单也可以是JSON
+// 注：email和name声明为Option，缺失的字段与非法的字段一样交给NewSubscriber::parse，以便按字段报告错误
+#[derive(Deserialize, Debug, ToSchema)]
 pub struct FormData {
-    email: String,
-    name: String,
+    email: Option<String>,
+    name: Option<String>,
+    // 蜜罐字段：在页面上被隐藏，真实用户不会填写它。取一个看起来很正常的名字，以便机器人上钩
+    website: Option<String>,
+    // 由GET /subscriptions/challenge签发的工作量证明挑战，以及客户端计算出的解
+    pow_challenge: Option<String>,
+    pow_solution: Option<String>,
+    // 订阅哪个列表（列表的slug），默认为newsletter
+    list: Option<String>,
+    // 可选的订阅者属性，用于之后向部分订阅者发送邮件：从哪个页面订阅的，以及偏好的语言
+    source: Option<String>,
+    locale: Option<String>,
 }
 
+// 生成OpenAPI文档中该接口的描述
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions",
+    tag = "subscriptions",
+    request_body(content(
+        (FormData = "application/x-www-form-urlencoded"),
+        (FormData = "application/json"),
+    )),
+    responses(
+        (status = 200, description = "A confirmation email has been sent (form clients)."),
+        (status = 201, description = "A confirmation email has been sent (JSON clients).", body = Subscription),
+        (status = 400, description = "The request is invalid. JSON clients get the errors of each field.", body = ValidationErrorBody),
+        (status = 429, description = "Too many requests for this client or email address.", body = crate::request_id::ErrorBody),
+    )
+)]
 // tracing::instrument过程宏，使得所有subscribe函数中的步骤都在request_span的上下文中，即将subscribe包装在一个跨度中
 // #[tracing::instrument]在函数声明处创建了一个跨度，并将所有参数都放入这个跨度的上下文中（即form和pool）
 // 注：tracing::instrument对于异步函数插桩也是有效的
@@ -17,17 +63,110 @@ pub struct FormData {
     name = "Adding a new subscriber",
     // 很多时候我们不希望日志中记录某些参数（如pool），这时就可以显式地指定如何捕获它们——可通过skip指令告诉tracing忽略它们
     // 注：tracing会自动记录显示所有传入跨度的参数，如果不希望在日志中记录某些变量，请使用skip();
-    skip(form, pool),
+    skip(req, form, pool, email_client, base_url, i18n, rate_limiter, bot_protection),
     // 通过field将某些值添加到跨度是上下文中（语法同tracing::info_span!上的语法类似）
     fields(
         // 生成一个随机的请求id，用于将日志和请求关联起来（此处定义request_id会覆盖TracingLogger提供的request_id，所以要注释掉）
         // request_id = %Uuid::new_v4(),
-        subscriber_emial = %form.email,
-        subscriber_name = %form.name,
+        subscriber_emial = ?form.email,
+        subscriber_name = ?form.name,
     )
 )]
 // 负责调用流程中所需的子程序，根据HTTP的规则和约定将它们返回的结果转换为请求响应
-pub async fn subscribe(form: web::Form<FormData>, pool: web::Data<PgPool>) -> HttpResponse {
+// 注：参数都是actix-web的extractor，数量多一些并不影响可读性
+#[allow(clippy::too_many_arguments)]
+pub async fn subscribe(
+    req: HttpRequest,
+    FormOrJson(form): FormOrJson<FormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    i18n: web::Data<I18n>,
+    rate_limiter: web::Data<RateLimiter>,
+    bot_protection: web::Data<BotProtection>,
+) -> HttpResponse {
+    let json = wants_json(&req);
+    // 对于落入蜜罐的请求，假装订阅成功，但不做任何处理，避免机器人察觉并调整策略
+    if bot_protection.is_caught_by_honeypot(form.website.as_deref()) {
+        tracing::info!("Dropping a submission caught by the honeypot");
+        let fake = Subscription {
+            id: Uuid::new_v4(),
+            email: form.email.unwrap_or_default(),
+            name: form.name.unwrap_or_default(),
+            list: form.list.unwrap_or_else(|| DEFAULT_LIST.into()),
+            status: PENDING_CONFIRMATION,
+            subscribed_at: Utc::now(),
+        };
+        return subscription_created(json, &fake);
+    }
+    if let Some(proof_of_work) = &bot_protection.proof_of_work {
+        let outcome = match (&form.pow_challenge, &form.pow_solution) {
+            (Some(challenge), Some(solution)) => {
+                proof_of_work.verify(&pool, challenge, solution).await
+            }
+            _ => Err(ProofOfWorkError::Missing),
+        };
+        if let Err(e) = outcome {
+            tracing::warn!(
+                "Rejecting a submission without a valid proof of work: {:?}",
+                e
+            );
+            return HttpResponse::from_error(e);
+        }
+    }
+    let new_subscriber = NewSubscriber::parse(form.email, form.name);
+    // 表单没有指定语言时，按浏览器的Accept-Language头选择一种有翻译的语言
+    let locale = form.locale.or_else(|| {
+        i18n::accept_language(&req)
+            .and_then(i18n::negotiate)
+            .map(|catalogue| catalogue.locale().to_string())
+    });
+    let attributes = SubscriberAttributes::parse(form.source, locale);
+    let slug = form.list.unwrap_or_else(|| DEFAULT_LIST.into());
+    let list = match lists::find_by_slug(&pool, &slug).await {
+        Ok(list) => list,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let (new_subscriber, attributes, list) = match (new_subscriber, attributes, list) {
+        (Ok(new_subscriber), Ok(attributes), Some(list)) => (new_subscriber, attributes, list),
+        (new_subscriber, attributes, list) => {
+            let mut errors = new_subscriber.err().unwrap_or_default();
+            errors.extend(attributes.err().unwrap_or_default());
+            if list.is_none() {
+                errors.insert("list", vec![format!("There is no list called {}.", slug)]);
+            }
+            if json {
+                return validation_failed("The subscription request is invalid.", &errors);
+            }
+            return HttpResponse::BadRequest().finish();
+        }
+    };
+    // 按目标邮箱地址限流（按IP的限流由中间件完成），避免同一个地址收到大量确认邮件
+    if let Err(e) = rate_limiter
+        .check_email(new_subscriber.email.as_ref())
+        .await
+    {
+        return HttpResponse::from_error(e);
+    }
+    // 退信、投诉或者要求删除数据而被屏蔽的地址不再保存，也不发送确认邮件
+    // 与蜜罐相同，假装订阅成功，不透露某个地址是否被屏蔽
+    match suppressions::is_suppressed(&pool, new_subscriber.email.as_ref()).await {
+        Ok(false) => {}
+        Ok(true) => {
+            tracing::info!("Dropping a subscription for a suppressed address");
+            let fake = Subscription {
+                id: Uuid::new_v4(),
+                email: new_subscriber.email.as_ref().to_string(),
+                name: new_subscriber.name.as_ref().to_string(),
+                list: list.slug,
+                status: PENDING_CONFIRMATION,
+                subscribed_at: Utc::now(),
+            };
+            return subscription_created(json, &fake);
+        }
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    }
+
     // 生成一个随机的请求id，用于将日志和请求关联起来
     // let request_id = Uuid::new_v4();
     // span和log一样，有一个关联级别
@@ -78,41 +217,348 @@ pub async fn subscribe(form: web::Form<FormData>, pool: web::Data<PgPool>) -> Ht
     // // 首先要绑定query_span这个插桩，然后等待这个future完成
     // .instrument(query_span)
     // .await
-    match insert_subscriber(&pool, &form).await {
-        Ok(_) => {
-            // tracing::info!("request_id {request_id} - New subscriber details have been saved");
-            HttpResponse::Ok().finish()
+    // 订阅者、成员资格和确认令牌必须同时写入（或同时不写入），因此放在同一个事务中
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // 已经订阅了其他列表的订阅者沿用原来的记录，只是多一个成员资格
+    // 同时得到订阅者保存的语言，确认邮件使用该语言
+    let (subscriber_id, locale) = match find_subscriber_id(&mut transaction, &new_subscriber).await
+    {
+        Ok(Some(subscriber_id)) => {
+            match add_attributes(&mut transaction, subscriber_id, &attributes).await {
+                Ok(locale) => (subscriber_id, locale),
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            }
         }
-        // 一旦sqlx::query!()失败
-        // Err(e) => {
-        //     // 日志的读者主要是应用程序的维护人员，应该用std::fmt::Debug格式来输出日志，获取尽可能多的信息
-        //     // std::fmt::Display则是用于展示给app的用户的
-        //     tracing::error!("Failed to execute query: {:?}", e);
-        //     HttpResponse::InternalServerError().finish()
-        // }
-        Err(_) => HttpResponse::InternalServerError().finish(),
+        Ok(None) => match insert_subscriber(&mut transaction, &new_subscriber, &attributes).await {
+            Ok(subscriber_id) => (subscriber_id, attributes.locale().map(str::to_string)),
+            // 一旦sqlx::query!()失败
+            // Err(e) => {
+            //     // 日志的读者主要是应用程序的维护人员，应该用std::fmt::Debug格式来输出日志，获取尽可能多的信息
+            //     // std::fmt::Display则是用于展示给app的用户的
+            //     tracing::error!("Failed to execute query: {:?}", e);
+            //     HttpResponse::InternalServerError().finish()
+            // }
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        },
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // 记录订阅者是在何时、从哪里同意订阅的
+    let consent = consent_from_request("subscription_form", &req, &rate_limiter);
+    let (status, subscription_token) =
+        match join_list(&mut transaction, subscriber_id, list.id, &consent).await {
+            Ok(joined) => joined,
+            Err(_) => return HttpResponse::InternalServerError().finish(),
+        };
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
     }
+    if let Some(subscription_token) = subscription_token {
+        let templates = match TemplateSet::load(&pool).await {
+            Ok(templates) => templates,
+            Err(e) => {
+                tracing::error!("Failed to load email templates: {}", e);
+                return HttpResponse::InternalServerError().finish();
+            }
+        };
+        if send_confirmation_email(
+            &email_client,
+            &templates,
+            i18n.for_locale(locale.as_deref()),
+            &new_subscriber,
+            &list,
+            &base_url.0,
+            &subscription_token,
+        )
+        .await
+        .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    let subscription = Subscription {
+        id: subscriber_id,
+        email: new_subscriber.email.as_ref().to_string(),
+        name: new_subscriber.name.as_ref().to_string(),
+        list: list.slug,
+        status: status.as_str(),
+        subscribed_at: Utc::now(),
+    };
+    // tracing::info!("request_id {request_id} - New subscriber details have been saved");
+    subscription_created(json, &subscription)
+}
+
+// 新订阅者需要点击确认邮件中的链接后才会真正收到邮件
+pub const PENDING_CONFIRMATION: &str = "pending_confirmation";
+
+// 返回给JSON客户端的订阅信息
+#[derive(Serialize, Debug, ToSchema)]
+pub struct Subscription {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub list: String,
+    // 订阅者在该列表中的状态
+    pub status: &'static str,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+// JSON客户端得到201和订阅信息；表单客户端保持原来的行为，只得到200
+fn subscription_created(json: bool, subscription: &Subscription) -> HttpResponse {
+    if json {
+        HttpResponse::Created().json(subscription)
+    } else {
+        HttpResponse::Ok().finish()
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ValidationErrorBody<'a> {
+    error: &'static str,
+    request_id: Option<String>,
+    // 键为字段名，值为该字段的所有错误信息
+    #[schema(value_type = std::collections::HashMap<String, Vec<String>>)]
+    fields: &'a FieldErrors,
+}
+
+pub fn validation_failed(error: &'static str, errors: &FieldErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(ValidationErrorBody {
+        error,
+        request_id: RequestId::current().map(|id| id.to_string()),
+        fields: errors,
+    })
+}
+
+// 订阅者通过邮件中的链接访问页面时，令牌不存在或已过期
+// 访问者是浏览器，因此返回与其他页面相同的HTML页面，而不是JSON错误
+pub fn invalid_link(catalogue: &'static Catalogue) -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .content_type(header::ContentType::html())
+        .body(format!(
+            "<!DOCTYPE html><html lang=\"{}\"><body><p>{}</p></body></html>",
+            catalogue.locale(),
+            escape_html(catalogue.t("errors.invalid_link"))
+        ))
+}
+
+// 订阅者本人发起的请求：记录客户端IP和User-Agent，作为其同意订阅的证据
+pub fn consent_from_request(
+    source: &str,
+    req: &HttpRequest,
+    rate_limiter: &RateLimiter,
+) -> ConsentSource {
+    ConsentSource {
+        source: source.into(),
+        ip_address: rate_limiter.client_ip(req).map(|ip| ip.to_string()),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|h| h.to_str().ok())
+            .map(str::to_string),
+    }
+}
+
+// 确认邮件使用的模板，由迁移创建
+pub const CONFIRMATION_TEMPLATE: &str = "confirmation";
+
+#[tracing::instrument(
+    name = "Send a confirmation email to a new subscriber",
+    skip(
+        email_client,
+        templates,
+        catalogue,
+        new_subscriber,
+        list,
+        base_url,
+        subscription_token
+    )
+)]
+pub async fn send_confirmation_email(
+    email_client: &EmailClient,
+    templates: &TemplateSet,
+    catalogue: &Catalogue,
+    new_subscriber: &NewSubscriber,
+    list: &MailingList,
+    base_url: &str,
+    subscription_token: &str,
+) -> Result<(), String> {
+    // 邮件内容来自可以由管理员编辑的confirmation模板
+    let variables = TemplateVariables {
+        name: new_subscriber.name.as_ref().to_string(),
+        email: new_subscriber.email.as_ref().to_string(),
+        list: list.name.clone(),
+        confirmation_url: format!(
+            "{}/subscriptions/confirm?subscription_token={}",
+            base_url, subscription_token
+        ),
+        locale: catalogue.locale().into(),
+        ..Default::default()
+    };
+    let email = templates
+        .render(CONFIRMATION_TEMPLATE, &variables)
+        .map_err(|e| {
+            tracing::error!("Failed to render the confirmation email: {}", e);
+            e
+        })?;
+    email_client
+        .send_email(
+            new_subscriber.email.as_ref(),
+            &email.subject,
+            &email.html,
+            &email.text,
+        )
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to send a confirmation email: {:?}", e);
+            e.to_string()
+        })
+}
+
+// 转义要放进HTML中的文本（如管理员填写的列表名）
+pub fn escape_html(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
 }
 
 // 负责数据库逻辑，并不关心web框架。我们并不会把web::Form和web::Data传给它
 // 此时insert_subscriber相当于是subscribe的子跨度
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(form, pool)
+    skip(new_subscriber, transaction)
+)]
+pub async fn insert_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+    attributes: &SubscriberAttributes,
+) -> Result<Uuid, sqlx::Error> {
+    let subscriber_id = Uuid::new_v4(); // 生成一个随机Uuid用作id
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, attributes)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        new_subscriber.email.as_ref(),
+        new_subscriber.name.as_ref(),
+        Utc::now(), // 使用当前时区的时间戳作为subscribed_at的值
+        attributes.as_json(),
+    )
+    // execute的参数需要是实现Executor trait, 将事务作为可替换组件
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(subscriber_id)
+}
+
+// 该邮箱地址已有的订阅者。该行会被锁住直到事务结束
+#[tracing::instrument(name = "Find existing subscriber", skip(new_subscriber, transaction))]
+pub async fn find_subscriber_id(
+    transaction: &mut Transaction<'_, Postgres>,
+    new_subscriber: &NewSubscriber,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT id FROM subscriptions WHERE email = $1 FOR UPDATE"#,
+        new_subscriber.email.as_ref(),
+    )
+    .fetch_optional(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 已有的订阅者再次提交表单时只补充缺少的属性，不覆盖已有的值。返回订阅者保存的语言
+// 注：表单无需认证，任何人都可以用别人的邮箱提交
+#[tracing::instrument(name = "Add subscriber attributes", skip(transaction, attributes))]
+pub async fn add_attributes(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    attributes: &SubscriberAttributes,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE subscriptions SET attributes = $2 || attributes WHERE id = $1
+        RETURNING attributes->>'locale'
+        "#,
+        subscriber_id,
+        attributes.as_json(),
+    )
+    .fetch_one(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })
+}
+
+// 把订阅者加入列表，返回其在该列表中的状态，需要发送确认邮件时同时返回确认令牌
+// 已退订的订阅者可以重新订阅；已确认或被封禁的订阅者保持原状，不会收到邮件
+#[tracing::instrument(name = "Join a list", skip(transaction, consent))]
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    consent: &ConsentSource,
+) -> Result<(SubscriptionStatus, Option<String>), sqlx::Error> {
+    let pending = SubscriptionStatus::PendingConfirmation;
+    match memberships::lock(transaction, subscriber_id, list_id).await? {
+        None => memberships::insert(transaction, subscriber_id, list_id, pending).await?,
+        Some(SubscriptionStatus::Unsubscribed) => {
+            memberships::set_status(transaction, subscriber_id, list_id, pending).await?
+        }
+        // 待确认的订阅者再次提交时重新发送确认邮件
+        Some(SubscriptionStatus::PendingConfirmation) => {}
+        Some(status) => return Ok((status, None)),
+    }
+    let subscription_token = generate_subscription_token();
+    store_token(transaction, subscriber_id, list_id, &subscription_token).await?;
+    consent_events::record(
+        transaction,
+        subscriber_id,
+        Some(list_id),
+        ConsentEvent::Signup,
+        consent,
+    )
+    .await?;
+    Ok((pending, Some(subscription_token)))
+}
+
+#[tracing::instrument(
+    name = "Store subscription token in the database",
+    skip(subscription_token, transaction)
 )]
-pub async fn insert_subscriber(pool: &PgPool, form: &FormData) -> Result<(), sqlx::Error> {
+pub async fn store_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    subscription_token: &str,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at)
-        VALUES ($1, $2, $3, $4)
+        INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id)
+        VALUES ($1, $2, $3)
         "#,
-        Uuid::new_v4(), // 生成一个随机Uuid用作id
-        form.email,
-        form.name,
-        Utc::now() // 使用当前时区的时间戳作为subscribed_at的值
+        subscription_token,
+        subscriber_id,
+        list_id,
     )
-    // execute的参数需要是实现Executor trait, 将连接池作为可替换组件
-    .execute(pool)
+    .execute(&mut **transaction)
     .await
     .map_err(|e| {
         tracing::error!("Failed to execute query: {:?}", e);
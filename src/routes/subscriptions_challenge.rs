@@ -0,0 +1,20 @@
+use actix_web::{web, HttpResponse};
+
+use crate::bot_protection::BotProtection;
+
+// 签发一个新的工作量证明挑战。未启用工作量证明时返回404
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/challenge",
+    tag = "subscriptions",
+    responses(
+        (status = 200, description = "A new proof of work challenge.", body = crate::bot_protection::Challenge),
+        (status = 404, description = "Proof of work is disabled.", body = crate::request_id::ErrorBody),
+    )
+)]
+pub async fn subscription_challenge(bot_protection: web::Data<BotProtection>) -> HttpResponse {
+    match &bot_protection.proof_of_work {
+        Some(proof_of_work) => HttpResponse::Ok().json(proof_of_work.issue()),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
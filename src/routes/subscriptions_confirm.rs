@@ -0,0 +1,135 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+use crate::i18n::I18n;
+use crate::rate_limit::RateLimiter;
+use crate::repository::consent_events::{self, ConsentEvent, ConsentSource};
+use crate::routes::{consent_from_request, escape_html, invalid_link};
+
+#[derive(Deserialize, IntoParams)]
+pub struct Parameters {
+    subscription_token: String,
+}
+
+// 用户点击确认邮件中的链接后，将订阅者在该列表中的状态从pending_confirmation改为confirmed
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscription has been confirmed.", body = String, content_type = "text/html"),
+        (status = 400, description = "The token is missing.", body = crate::request_id::ErrorBody),
+        (status = 401, description = "The token is unknown.", body = String, content_type = "text/html"),
+    )
+)]
+#[tracing::instrument(
+    name = "Confirm a pending subscriber",
+    skip(req, parameters, pool, i18n, rate_limiter)
+)]
+pub async fn confirm(
+    req: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    let id = match get_subscriber_id_from_token(&pool, &parameters.subscription_token).await {
+        Ok(id) => id,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    match id {
+        // 令牌不存在
+        None => invalid_link(catalogue),
+        Some((subscriber_id, list_id)) => {
+            let mut transaction = match pool.begin().await {
+                Ok(transaction) => transaction,
+                Err(_) => return HttpResponse::InternalServerError().finish(),
+            };
+            let consent = consent_from_request("confirmation_link", &req, &rate_limiter);
+            if confirm_subscriber(&mut transaction, subscriber_id, list_id, &consent)
+                .await
+                .is_err()
+                || transaction.commit().await.is_err()
+            {
+                return HttpResponse::InternalServerError().finish();
+            }
+            HttpResponse::Ok()
+                .content_type(header::ContentType::html())
+                .body(format!(
+                    r#"<!DOCTYPE html>
+<html lang="{}">
+<head><meta charset="utf-8"><title>{}</title></head>
+<body><p>{}</p></body>
+</html>"#,
+                    catalogue.locale(),
+                    escape_html(catalogue.t("confirmed.title")),
+                    escape_html(catalogue.t("confirmed.message")),
+                ))
+        }
+    }
+}
+
+// 只有待确认的订阅者才会被确认：重复点击链接不会产生新的事件，
+// 已退订或被封禁的订阅者也不能通过旧的确认链接恢复订阅
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction, consent)
+)]
+pub async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    consent: &ConsentSource,
+) -> Result<(), sqlx::Error> {
+    let confirmed = sqlx::query!(
+        r#"
+        UPDATE list_memberships SET status = 'confirmed'
+        WHERE subscriber_id = $1 AND list_id = $2 AND status = 'pending_confirmation'
+        "#,
+        subscriber_id,
+        list_id,
+    )
+    .execute(&mut **transaction)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?
+    .rows_affected()
+        > 0;
+    if confirmed {
+        consent_events::record(
+            transaction,
+            subscriber_id,
+            Some(list_id),
+            ConsentEvent::Confirm,
+            consent,
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+// 令牌对应的订阅者和列表
+#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
+pub async fn get_subscriber_id_from_token(
+    pool: &PgPool,
+    subscription_token: &str,
+) -> Result<Option<(Uuid, Uuid)>, sqlx::Error> {
+    let result = sqlx::query!(
+        "SELECT subscriber_id, list_id FROM subscription_tokens WHERE subscription_token = $1",
+        subscription_token,
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| {
+        tracing::error!("Failed to execute query: {:?}", e);
+        e
+    })?;
+    Ok(result.map(|r| (r.subscriber_id, r.list_id)))
+}
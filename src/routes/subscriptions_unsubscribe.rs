@@ -0,0 +1,144 @@
+use actix_web::{http::header, web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use sqlx::PgPool;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::domain::StatusTransition;
+use crate::i18n::I18n;
+use crate::rate_limit::RateLimiter;
+use crate::repository::consent_events::{self, ConsentEvent};
+use crate::repository::memberships::{self, UnsubscribeTarget};
+use crate::routes::{consent_from_request, escape_html, invalid_link};
+
+// 每封新闻邮件中的退订链接都带有订阅者在该列表中的退订令牌
+#[derive(Deserialize, IntoParams, ToSchema)]
+pub struct UnsubscribeParameters {
+    token: String,
+}
+
+// 退订前的确认页面
+// 注：邮件客户端和安全扫描器会预先访问邮件中的链接，因此GET请求本身不能退订
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    params(UnsubscribeParameters),
+    responses(
+        (status = 200, description = "A page asking the subscriber to confirm.", body = String, content_type = "text/html"),
+        (status = 400, description = "The token is missing.", body = crate::request_id::ErrorBody),
+        (status = 401, description = "The token is unknown.", body = String, content_type = "text/html"),
+    )
+)]
+#[tracing::instrument(
+    name = "Show unsubscribe confirmation",
+    skip(req, parameters, pool, i18n)
+)]
+pub async fn unsubscribe_form(
+    req: HttpRequest,
+    parameters: web::Query<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    let target = match memberships::find_by_unsubscribe_token(&pool, &parameters.token).await {
+        Ok(Some(target)) => target,
+        Ok(None) => return invalid_link(catalogue),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // 令牌已经在数据库中找到，只可能由字母和数字组成，可以直接放进HTML中
+    HttpResponse::Ok()
+        .content_type(header::ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{title}</title></head>
+<body>
+<p>{question}</p>
+<form method="post">
+<input type="hidden" name="token" value="{token}">
+<button type="submit">{button}</button>
+</form>
+</body>
+</html>"#,
+            lang = catalogue.locale(),
+            title = escape_html(catalogue.t("unsubscribe.title")),
+            question = escape_html(
+                &catalogue.format("unsubscribe.question", &[("list", &target.list_name)])
+            ),
+            token = parameters.token,
+            button = escape_html(catalogue.t("unsubscribe.button")),
+        ))
+}
+
+// 退订某一个列表，订阅者在其他列表中的订阅不受影响。重复提交不会出错
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    request_body(content = UnsubscribeParameters, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The subscriber has left the list.", body = String, content_type = "text/html"),
+        (status = 400, description = "The token is missing.", body = crate::request_id::ErrorBody),
+        (status = 401, description = "The token is unknown.", body = String, content_type = "text/html"),
+    )
+)]
+#[tracing::instrument(
+    name = "Unsubscribe from a list",
+    skip(req, form, pool, i18n, rate_limiter)
+)]
+pub async fn unsubscribe(
+    req: HttpRequest,
+    form: web::Form<UnsubscribeParameters>,
+    pool: web::Data<PgPool>,
+    i18n: web::Data<I18n>,
+    rate_limiter: web::Data<RateLimiter>,
+) -> HttpResponse {
+    let catalogue = i18n.for_request(&req);
+    let target = match memberships::find_by_unsubscribe_token(&pool, &form.token).await {
+        Ok(Some(target)) => target,
+        Ok(None) => return invalid_link(catalogue),
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let mut transaction = match pool.begin().await {
+        Ok(transaction) => transaction,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    let UnsubscribeTarget {
+        subscriber_id,
+        list_id,
+        list_name,
+    } = target;
+    let status = match memberships::lock(&mut transaction, subscriber_id, list_id).await {
+        Ok(status) => status,
+        Err(_) => return HttpResponse::InternalServerError().finish(),
+    };
+    // 已经退订或被封禁时无需任何改动
+    if let Some(Ok(unsubscribed)) = status.map(|s| s.apply(StatusTransition::Unsubscribe)) {
+        let consent = consent_from_request("unsubscribe_link", &req, &rate_limiter);
+        if memberships::set_status(&mut transaction, subscriber_id, list_id, unsubscribed)
+            .await
+            .is_err()
+            || consent_events::record(
+                &mut transaction,
+                subscriber_id,
+                Some(list_id),
+                ConsentEvent::Unsubscribe,
+                &consent,
+            )
+            .await
+            .is_err()
+        {
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+    if transaction.commit().await.is_err() {
+        return HttpResponse::InternalServerError().finish();
+    }
+    HttpResponse::Ok()
+        .content_type(header::ContentType::html())
+        .body(format!(
+            "<!DOCTYPE html><html lang=\"{}\"><body><p>{}</p></body></html>",
+            catalogue.locale(),
+            escape_html(&catalogue.format("unsubscribe.done", &[("list", &list_name)]))
+        ))
+}
@@ -0,0 +1,98 @@
+use actix_web::{
+    http::{
+        header::{self, CacheControl, CacheDirective},
+        StatusCode,
+    },
+    web, HttpResponse, ResponseError,
+};
+use sqlx::PgPool;
+
+use crate::repository::tracking_events;
+use crate::tracking::{is_redirectable, Tracking, TrackingToken};
+
+// 1x1的透明GIF
+const PIXEL: &[u8] = &[
+    0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x01, 0x00, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0xff, 0xff, 0xff, 0x21, 0xf9, 0x04, 0x01, 0x00, 0x00, 0x00, 0x00, 0x2c, 0x00, 0x00, 0x00, 0x00,
+    0x01, 0x00, 0x01, 0x00, 0x00, 0x02, 0x02, 0x44, 0x01, 0x00, 0x3b,
+];
+
+#[derive(Debug)]
+pub enum TrackingError {
+    // 签名不正确，或者不是跳转链接的令牌
+    InvalidLink,
+}
+
+impl std::fmt::Display for TrackingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("The link is invalid.")
+    }
+}
+
+impl ResponseError for TrackingError {
+    fn status_code(&self) -> StatusCode {
+        StatusCode::NOT_FOUND
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+// 总开关关闭时不记录。记录失败不影响订阅者，只写日志
+async fn record(pool: &PgPool, tracking: &Tracking, token: &TrackingToken) {
+    if !tracking.is_enabled() {
+        return;
+    }
+    if let Err(e) = tracking_events::record(
+        pool,
+        token.issue_id,
+        token.subscriber_id,
+        token.url.as_deref(),
+    )
+    .await
+    {
+        tracing::error!("Failed to record a tracking event: {:?}", e);
+    }
+}
+
+// 邮件中的跟踪像素，邮件客户端加载图片时记录一次打开
+// 禁止缓存，否则再次打开时不会重新请求
+#[tracing::instrument(name = "Track an open", skip(token, pool, tracking))]
+pub async fn track_open(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    tracking: web::Data<Tracking>,
+) -> Result<HttpResponse, TrackingError> {
+    let token = tracking
+        .decode(&token)
+        .filter(|token| token.url.is_none())
+        .ok_or(TrackingError::InvalidLink)?;
+    record(&pool, &tracking, &token).await;
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        .body(PIXEL))
+}
+
+// 邮件中的跟踪链接：记录一次点击后跳转到原来的地址
+// 只跳转到令牌中签名过的http(s)地址，伪造或篡改的令牌得到404，而不是被跳转到别处
+#[tracing::instrument(name = "Track a click", skip(token, pool, tracking))]
+pub async fn track_click(
+    token: web::Path<String>,
+    pool: web::Data<PgPool>,
+    tracking: web::Data<Tracking>,
+) -> Result<HttpResponse, TrackingError> {
+    let token = tracking
+        .decode(&token)
+        .filter(|token| token.url.as_deref().is_some_and(is_redirectable))
+        .ok_or(TrackingError::InvalidLink)?;
+    record(&pool, &tracking, &token).await;
+    let url = token.url.unwrap_or_default();
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, url))
+        .insert_header(CacheControl(vec![CacheDirective::NoStore]))
+        // 令牌中包含订阅者的id，不应通过Referer透露给目标网站
+        .insert_header((header::REFERRER_POLICY, "no-referrer"))
+        .finish())
+}
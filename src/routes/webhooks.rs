@@ -0,0 +1,64 @@
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use sqlx::PgPool;
+
+use crate::email_events::{parse_events, EmailEvents, SignatureError};
+
+#[derive(Debug)]
+pub enum WebhookError {
+    Unauthorized(SignatureError),
+    Invalid(String),
+    Unexpected(String),
+}
+
+impl std::fmt::Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unauthorized(e) => write!(f, "{}", e),
+            Self::Invalid(e) => f.write_str(e),
+            Self::Unexpected(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Invalid(_) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::new(self.status_code())
+    }
+}
+
+impl From<sqlx::Error> for WebhookError {
+    fn from(e: sqlx::Error) -> Self {
+        Self::Unexpected(e.to_string())
+    }
+}
+
+// 邮件服务商报告投递、退信、投诉、打开和点击事件。签名基于原始的请求体，因此这里不使用web::Json
+// 服务商在收到非2xx响应时会重试，已经处理过的事件会被跳过
+#[tracing::instrument(name = "Receive email events", skip(req, body, pool, email_events))]
+pub async fn email_events_webhook(
+    req: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    email_events: web::Data<EmailEvents>,
+) -> Result<HttpResponse, WebhookError> {
+    let header = |name: &str| req.headers().get(name).and_then(|h| h.to_str().ok());
+    email_events
+        .verify(
+            header("X-Webhook-Timestamp"),
+            header("X-Webhook-Signature"),
+            &body,
+        )
+        .map_err(WebhookError::Unauthorized)?;
+    let events = parse_events(&body)
+        .map_err(|e| WebhookError::Invalid(format!("The events are invalid: {}", e)))?;
+    let report = email_events.record(&pool, events).await?;
+    Ok(HttpResponse::Ok().json(report))
+}
@@ -0,0 +1,132 @@
+// 投递任务的发送速率限制：邮件服务商限制每秒发出的邮件数，超过时会封禁账号
+// 全局的令牌桶限制所有邮件，每个传输（邮件服务商API的主机名）还可以有自己的令牌桶，发送前需要从每个适用的桶中各取一个令牌
+// 令牌桶保存在rate_limit_buckets表中，多个实例共享同一个速率，发送量大的一期会按固定的速率平稳发出
+use std::time::{Duration, Instant};
+
+use sqlx::PgPool;
+
+use crate::configuration::{SendRate, SendRateSettings};
+use crate::repository::send_rate_metrics;
+
+pub struct SendThrottle {
+    pool: PgPool,
+    // 按键排序，多个实例总是以相同的顺序锁住这些桶，避免死锁
+    buckets: Vec<(String, SendRate)>,
+}
+
+impl SendThrottle {
+    pub fn new(pool: PgPool, settings: &SendRateSettings, transport: &str) -> Self {
+        let mut buckets: Vec<(String, SendRate)> = settings
+            .global
+            .map(|rate| ("send:global".to_string(), rate))
+            .into_iter()
+            .chain(
+                settings
+                    .per_transport
+                    .get(transport)
+                    .map(|rate| (format!("send:transport:{}", transport), *rate)),
+            )
+            .filter(|(key, rate)| {
+                let valid = rate.messages_per_second > 0.0 && rate.burst > 0;
+                if !valid {
+                    tracing::warn!("Ignoring send rate {} with no capacity: {:?}", key, rate);
+                }
+                valid
+            })
+            .collect();
+        buckets.sort_by(|a, b| a.0.cmp(&b.0));
+        Self { pool, buckets }
+    }
+
+    // 等待直到每个适用的桶中都有令牌，然后各取一个。返回等待的时间
+    #[tracing::instrument(name = "Acquire send rate tokens", skip(self))]
+    pub async fn acquire(&self) -> Result<Duration, sqlx::Error> {
+        if self.buckets.is_empty() {
+            return Ok(Duration::ZERO);
+        }
+        let started = Instant::now();
+        let mut waited = Duration::ZERO;
+        loop {
+            match self.try_acquire(waited).await? {
+                Ok(()) => return Ok(waited),
+                Err(wait) => {
+                    tracing::debug!("Send rate exceeded, waiting {:?}", wait);
+                    tokio::time::sleep(wait).await;
+                    waited = started.elapsed();
+                }
+            }
+        }
+    }
+
+    // 所有桶都有令牌时各取一个并记录统计，否则不取任何令牌，返回需要等待多久
+    async fn try_acquire(&self, waited: Duration) -> Result<Result<(), Duration>, sqlx::Error> {
+        let mut transaction = self.pool.begin().await?;
+        let mut available = Vec::with_capacity(self.buckets.len());
+        for (key, rate) in &self.buckets {
+            sqlx::query!(
+                r#"
+                INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+                VALUES ($1, $2, clock_timestamp())
+                ON CONFLICT (key) DO NOTHING
+                "#,
+                key,
+                rate.burst as f64,
+            )
+            .execute(&mut *transaction)
+            .await?;
+            // 与rate_limit::postgres相同，使用数据库的时间计算补充的令牌
+            let tokens = sqlx::query_scalar!(
+                r#"
+                SELECT LEAST(
+                    $2::float8,
+                    tokens + EXTRACT(EPOCH FROM (clock_timestamp() - updated_at))::float8 * $3::float8
+                ) AS "tokens!"
+                FROM rate_limit_buckets
+                WHERE key = $1
+                FOR UPDATE
+                "#,
+                key,
+                rate.burst as f64,
+                rate.messages_per_second,
+            )
+            .fetch_one(&mut *transaction)
+            .await?;
+            available.push(tokens);
+        }
+
+        // 最慢的桶决定需要等待的时间
+        let wait = self
+            .buckets
+            .iter()
+            .zip(&available)
+            .filter(|(_, tokens)| **tokens < 1.0)
+            .map(|((_, rate), tokens)| (1.0 - tokens) / rate.messages_per_second)
+            .fold(0.0, f64::max);
+        if wait > 0.0 {
+            transaction.rollback().await?;
+            return Ok(Err(Duration::from_secs_f64(wait)));
+        }
+        for ((key, rate), tokens) in self.buckets.iter().zip(available) {
+            sqlx::query!(
+                r#"
+                UPDATE rate_limit_buckets
+                SET tokens = $2, updated_at = clock_timestamp()
+                WHERE key = $1
+                "#,
+                key,
+                tokens - 1.0,
+            )
+            .execute(&mut *transaction)
+            .await?;
+            send_rate_metrics::record(
+                &mut transaction,
+                key,
+                rate.messages_per_second,
+                waited.as_secs_f64(),
+            )
+            .await?;
+        }
+        transaction.commit().await?;
+        Ok(Ok(()))
+    }
+}
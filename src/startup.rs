@@ -17,27 +17,212 @@
 
 use std::net::TcpListener;
 
-use crate::routes::{health_check, subscribe};
-use actix_web::{dev::Server, web, App, HttpServer};
+use crate::attachments::Attachments;
+use crate::bot_protection::BotProtection;
+use crate::email_client::EmailClient;
+use crate::email_events::EmailEvents;
+use crate::i18n::I18n;
+use crate::rate_limit::{limit_by_client_ip, RateLimiter};
+use crate::request_id::{propagate_request_id, RequestIdRootSpanBuilder};
+use crate::routes::{
+    add_suppression, asset, cancel_issue, confirm, count_subscribers, create_issue, create_list,
+    create_segment, delete_subscriber, delivery_metrics, diff_revisions, email_events_webhook,
+    export_subscribers, get_account, get_issue, get_revision, get_subscriber, get_suppression,
+    get_template, health_check, import_subscribers, issue_engagement, lift_suppression,
+    list_issues, list_lists, list_revisions, list_segments, list_subscribers, list_suppressions,
+    list_templates, openapi_json, preferences_form, preview_issue, preview_segment,
+    preview_template, privacy_access, privacy_erasure, privacy_erasure_form, publish_issue,
+    publish_newsletter, request_privacy_action, save_template, schedule_issue, send_test_issue,
+    subscribe, subscription_challenge, track_click, track_open, unsubscribe, unsubscribe_form,
+    update_account, update_issue, update_preferences, update_subscriber, update_tags, upload_asset,
+};
+use crate::tracking::Tracking;
+use actix_web::{dev::Server, middleware::from_fn, web, App, HttpServer};
 use sqlx::PgPool;
 use tracing_actix_web::TracingLogger;
 
+// 对外可访问的应用地址。包装成一个新类型，以便作为app_data按类型取出（避免与其他String混淆）
+pub struct ApplicationBaseUrl(pub String);
+
 // 使用TcpListener来绑定端口，这样就可以使用端口0来做集成测试
 // 注: 端口0会分配一个可用的随机端口，该端口可以从TcpListener获得
-pub fn run(listener: TcpListener, db_pool: PgPool) -> Result<Server, std::io::Error> {
+// 注：除listener之外的参数都会被注册为app_data，逐个传入比再包装一层更直观
+#[allow(clippy::too_many_arguments)]
+pub fn run(
+    listener: TcpListener,
+    db_pool: PgPool,
+    email_client: EmailClient,
+    rate_limiter: RateLimiter,
+    bot_protection: BotProtection,
+    base_url: String,
+    i18n: I18n,
+    attachments: Attachments,
+    email_events: EmailEvents,
+    tracking: Tracking,
+) -> Result<Server, std::io::Error> {
     // 将PgPool包装在一个Arc中，这样在每个应用实例中都获得的是一个指向连接的Arc指针
     // 注：无论T是什么类型，Arc<T>都是可clone的(web::Data的本质就是一个Arc智能指针)
     let dp_pool = web::Data::new(db_pool);
+    let email_client = web::Data::new(email_client);
+    let rate_limiter = web::Data::new(rate_limiter);
+    let bot_protection = web::Data::new(bot_protection);
+    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let i18n = web::Data::new(i18n);
+    let attachments = web::Data::new(attachments);
+    let email_events = web::Data::new(email_events);
+    let tracking = web::Data::new(tracking);
     let server = HttpServer::new(move || {
         App::new()
             // 将中间件通过wrap方法加入App中
             // 注：tracing_actix_web::TracingLogger是actix-web的Logger的替代品，基于tracing而非log。这可以使tracing获得进入request的时候actix-web内的一些日志（即正式开始执行我们所写的handler逻辑之前部分）
-            .wrap(TracingLogger::default())
+            .wrap(TracingLogger::<RequestIdRootSpanBuilder>::new())
+            // 注：后注册的中间件位于外层，会先于TracingLogger执行，这样根跨度中记录的才是我们确定的请求id
+            .wrap(from_fn(propagate_request_id))
             .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
+            // 未带版本号的路由：网页表单和已经发出的确认邮件中的链接仍在使用它们
+            .configure(subscription_routes)
+            // 订阅者查询或删除自己的个人数据
+            .service(
+                web::resource("/privacy/requests")
+                    .wrap(from_fn(limit_by_client_ip))
+                    .route(web::post().to(request_privacy_action)),
+            )
+            .route("/privacy/access", web::get().to(privacy_access))
+            // 上传的附件，每周摘要中的内嵌图片和附件链接到这里
+            .route("/assets/{id}", web::get().to(asset))
+            // 订阅者通过新闻邮件中的链接管理自己的订阅
+            .service(
+                web::resource("/preferences")
+                    .route(web::get().to(preferences_form))
+                    .route(web::post().to(update_preferences)),
+            )
+            .service(
+                web::resource("/privacy/erasure")
+                    .route(web::get().to(privacy_erasure_form))
+                    .route(web::post().to(privacy_erasure)),
+            )
+            // 邮件服务商报告退信和投诉等事件，通过签名而不是登录认证
+            .route(
+                "/webhooks/email-events",
+                web::post().to(email_events_webhook),
+            )
+            // 新闻邮件中的跟踪像素和跟踪链接
+            .route("/o/{token}", web::get().to(track_open))
+            .route("/r/{token}", web::get().to(track_click))
+            .service(
+                web::scope("/api/v1")
+                    .configure(subscription_routes)
+                    .route("/newsletters", web::post().to(publish_newsletter))
+                    .route("/openapi.json", web::get().to(openapi_json)),
+            )
+            // 管理后台接口，所有handler都通过AuthenticatedUser要求认证
+            .service(
+                web::scope("/admin")
+                    .service(
+                        web::resource("/lists")
+                            .route(web::get().to(list_lists))
+                            .route(web::post().to(create_list)),
+                    )
+                    .service(
+                        web::resource("/segments")
+                            .route(web::get().to(list_segments))
+                            .route(web::post().to(create_segment)),
+                    )
+                    .route("/segments/preview", web::post().to(preview_segment))
+                    .route("/assets", web::post().to(upload_asset))
+                    // 稍后发送的一期，到期后由issue_delivery发送
+                    .service(
+                        web::resource("/issues")
+                            .route(web::get().to(list_issues))
+                            .route(web::post().to(create_issue)),
+                    )
+                    .service(
+                        web::resource("/issues/{id}")
+                            .route(web::get().to(get_issue))
+                            .route(web::put().to(update_issue)),
+                    )
+                    .route("/issues/{id}/schedule", web::put().to(schedule_issue))
+                    .route("/issues/{id}/publish", web::post().to(publish_issue))
+                    .route("/issues/{id}/cancel", web::post().to(cancel_issue))
+                    .route("/issues/{id}/engagement", web::get().to(issue_engagement))
+                    // 投递任务的积压和发送速率
+                    .route("/delivery/metrics", web::get().to(delivery_metrics))
+                    // 每次保存生成的版本
+                    .route("/issues/{id}/revisions", web::get().to(list_revisions))
+                    .route(
+                        "/issues/{id}/revisions/{revision}",
+                        web::get().to(get_revision),
+                    )
+                    .route("/issues/{id}/diff", web::get().to(diff_revisions))
+                    .route("/issues/{id}/preview", web::get().to(preview_issue))
+                    .route("/issues/{id}/test", web::post().to(send_test_issue))
+                    // 当前管理员自己的账户
+                    .service(
+                        web::resource("/account")
+                            .route(web::get().to(get_account))
+                            .route(web::patch().to(update_account)),
+                    )
+                    .route("/templates", web::get().to(list_templates))
+                    .service(
+                        web::resource("/templates/{name}")
+                            .route(web::get().to(get_template))
+                            .route(web::put().to(save_template)),
+                    )
+                    .route("/templates/{name}/preview", web::get().to(preview_template))
+                    .route("/subscribers", web::get().to(list_subscribers))
+                    .route("/subscribers/count", web::get().to(count_subscribers))
+                    .route("/subscribers/import", web::post().to(import_subscribers))
+                    .route("/subscribers/export", web::get().to(export_subscribers))
+                    .route("/subscribers/tags", web::post().to(update_tags))
+                    .service(
+                        web::resource("/subscribers/{id}")
+                            .route(web::get().to(get_subscriber))
+                            .route(web::patch().to(update_subscriber))
+                            .route(web::delete().to(delete_subscriber)),
+                    )
+                    // 不再发送邮件的地址，以邮箱地址的哈希值标识
+                    .service(
+                        web::resource("/suppressions")
+                            .route(web::get().to(list_suppressions))
+                            .route(web::post().to(add_suppression)),
+                    )
+                    .route("/suppressions/{email_hash}", web::get().to(get_suppression))
+                    .route(
+                        "/suppressions/{email_hash}/lift",
+                        web::post().to(lift_suppression),
+                    ),
+            )
             .app_data(dp_pool.clone())
+            .app_data(email_client.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(base_url.clone())
+            .app_data(i18n.clone())
+            .app_data(attachments.clone())
+            .app_data(email_events.clone())
+            .app_data(tracking.clone())
     })
     .listen(listener)?
     .run();
     Ok(server)
 }
+
+// 订阅相关的路由，同时注册在根路径和/api/v1下
+fn subscription_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/subscriptions")
+            // 只对订阅接口限流，防止批量注册以及借助确认邮件对第三方进行邮件轰炸
+            .wrap(from_fn(limit_by_client_ip))
+            .route(web::post().to(subscribe)),
+    )
+    .route(
+        "/subscriptions/challenge",
+        web::get().to(subscription_challenge),
+    )
+    .route("/subscriptions/confirm", web::get().to(confirm))
+    .service(
+        web::resource("/subscriptions/unsubscribe")
+            .route(web::get().to(unsubscribe_form))
+            .route(web::post().to(unsubscribe)),
+    );
+}
@@ -0,0 +1,384 @@
+use std::collections::HashSet;
+
+use actix_web::{http::StatusCode, ResponseError};
+use chrono::{DateTime, Utc};
+use csv_core::{ReadRecordResult, Reader};
+use serde::Serialize;
+use sqlx::PgPool;
+
+use crate::domain::{FieldErrors, NewSubscriber, SubscriptionStatus};
+use crate::repository::lists::{self, MailingList, DEFAULT_LIST};
+use crate::repository::subscribers::{self, ImportedSubscriber};
+use crate::repository::suppressions;
+
+// 每批写入的行数：足够大以减少往返次数，又不至于让单条INSERT过大
+const BATCH_SIZE: usize = 1000;
+
+pub struct ImportOptions {
+    // 导入的订阅者直接视为已确认，不再发送确认邮件。此时必须提供consent_source
+    // 否则订阅者为待确认，确认邮件放入队列，由后台任务按发送速率发送
+    pub confirmed: bool,
+    // 订阅者同意接收邮件的来源，如"legacy-tool export 2026-10"
+    pub consent_source: Option<String>,
+    // 导入到哪个列表，默认为DEFAULT_LIST
+    pub list: Option<String>,
+}
+
+// 导入结果。被跳过的每一行都会出现在rows中，说明跳过的原因
+#[derive(Serialize, Debug, Default)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub duplicates: u64,
+    // 被屏蔽的地址：曾经退信、投诉或者要求删除个人数据
+    pub suppressed: u64,
+    pub invalid: u64,
+    pub rows: Vec<RowReport>,
+}
+
+#[derive(Serialize, Debug)]
+pub struct RowReport {
+    // 行号从1开始，第1行为表头
+    pub row: u64,
+    pub email: Option<String>,
+    pub errors: FieldErrors,
+}
+
+// 导致整个导入中止的错误。单行的错误不会中止导入，而是记录在ImportReport中
+#[derive(Debug)]
+pub enum ImportError {
+    MissingConsentSource,
+    UnknownList(String),
+    MissingColumn(&'static str),
+    InvalidHeader,
+    Payload(String),
+    Database(sqlx::Error),
+}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingConsentSource => {
+                f.write_str("A consent source is required to import confirmed subscribers.")
+            }
+            Self::UnknownList(list) => write!(f, "There is no list called {}.", list),
+            Self::MissingColumn(column) => {
+                write!(f, "The CSV header does not have a '{}' column.", column)
+            }
+            Self::InvalidHeader => f.write_str("The CSV header is not valid UTF-8."),
+            Self::Payload(e) => write!(f, "Failed to read the upload: {}", e),
+            Self::Database(_) => f.write_str("Something went wrong."),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Database(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl ResponseError for ImportError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+// 表头中各列的位置。列的顺序任意，多余的列会被忽略
+struct Columns {
+    email: usize,
+    name: usize,
+    // 可选：保留订阅者在原系统中的订阅时间
+    subscribed_at: Option<usize>,
+}
+
+// 流式导入CSV：调用方把数据逐块交给feed，最后调用finish
+// 每凑够BATCH_SIZE行就写入一次数据库，因此内存占用与文件大小无关（除了用于去重的邮箱集合）
+pub struct SubscriberImporter<'a> {
+    pool: &'a PgPool,
+    list: MailingList,
+    status: SubscriptionStatus,
+    consent_source: Option<String>,
+    decoder: CsvDecoder,
+    columns: Option<Columns>,
+    row: u64,
+    // 文件中已经出现过的邮箱地址，用于发现文件内部的重复
+    seen: HashSet<String>,
+    // 等待写入的行及其行号
+    batch: Vec<ImportedSubscriber>,
+    batch_rows: Vec<u64>,
+    report: ImportReport,
+}
+
+impl<'a> SubscriberImporter<'a> {
+    pub async fn new(pool: &'a PgPool, options: ImportOptions) -> Result<Self, ImportError> {
+        let consent_source = options.consent_source.filter(|s| !s.trim().is_empty());
+        if options.confirmed && consent_source.is_none() {
+            return Err(ImportError::MissingConsentSource);
+        }
+        let slug = options.list.unwrap_or_else(|| DEFAULT_LIST.into());
+        let list = lists::find_by_slug(pool, &slug)
+            .await
+            .map_err(ImportError::Database)?
+            .ok_or(ImportError::UnknownList(slug))?;
+        let status = if options.confirmed {
+            SubscriptionStatus::Confirmed
+        } else {
+            SubscriptionStatus::PendingConfirmation
+        };
+        Ok(Self {
+            pool,
+            list,
+            status,
+            consent_source,
+            decoder: CsvDecoder::new(),
+            columns: None,
+            row: 0,
+            seen: HashSet::new(),
+            batch: Vec::with_capacity(BATCH_SIZE),
+            batch_rows: Vec::with_capacity(BATCH_SIZE),
+            report: ImportReport::default(),
+        })
+    }
+
+    pub async fn feed(&mut self, chunk: &[u8]) -> Result<(), ImportError> {
+        for record in self.decoder.decode(chunk) {
+            self.process(record).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn finish(mut self) -> Result<ImportReport, ImportError> {
+        // 最后一行可能没有换行符，需要通知解析器输入已经结束
+        for record in self.decoder.decode(&[]) {
+            self.process(record).await?;
+        }
+        if self.columns.is_none() {
+            return Err(ImportError::MissingColumn("email"));
+        }
+        self.flush().await?;
+        Ok(self.report)
+    }
+
+    async fn process(&mut self, record: Vec<Vec<u8>>) -> Result<(), ImportError> {
+        self.row += 1;
+        let columns = match &self.columns {
+            Some(columns) => columns,
+            None => {
+                self.columns = Some(parse_header(record)?);
+                return Ok(());
+            }
+        };
+
+        let fields: Result<Vec<String>, _> = record.into_iter().map(String::from_utf8).collect();
+        let fields = match fields {
+            Ok(fields) => fields,
+            Err(_) => {
+                let mut errors = FieldErrors::new();
+                errors.insert("row", vec!["The row is not valid UTF-8.".into()]);
+                self.skip_invalid(None, errors);
+                return Ok(());
+            }
+        };
+        let field = |index: usize| fields.get(index).map(|value| value.trim().to_string());
+        let email = field(columns.email);
+        let name = field(columns.name);
+
+        let mut errors = FieldErrors::new();
+        let subscribed_at = match columns.subscribed_at.and_then(field) {
+            Some(value) if !value.is_empty() => match DateTime::parse_from_rfc3339(&value) {
+                Ok(subscribed_at) => subscribed_at.with_timezone(&Utc),
+                Err(_) => {
+                    errors.insert(
+                        "subscribed_at",
+                        vec![format!("{} is not an RFC 3339 timestamp.", value)],
+                    );
+                    Utc::now()
+                }
+            },
+            _ => Utc::now(),
+        };
+        let subscriber = match NewSubscriber::parse(email.clone(), name) {
+            Ok(subscriber) if errors.is_empty() => subscriber,
+            outcome => {
+                errors.extend(outcome.err().unwrap_or_default());
+                self.skip_invalid(email, errors);
+                return Ok(());
+            }
+        };
+
+        if !self.seen.insert(subscriber.email.as_ref().to_string()) {
+            let reason = format!("{} appears earlier in the file.", subscriber.email);
+            self.skip_duplicate(self.row, subscriber.email.as_ref(), reason);
+            return Ok(());
+        }
+        self.batch.push(ImportedSubscriber {
+            subscriber,
+            subscribed_at,
+        });
+        self.batch_rows.push(self.row);
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush().await?;
+        }
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), ImportError> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let mut batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        let mut rows = std::mem::replace(&mut self.batch_rows, Vec::with_capacity(BATCH_SIZE));
+
+        let emails: Vec<&str> = batch.iter().map(|s| s.subscriber.email.as_ref()).collect();
+        let suppressed = suppressions::find_suppressed(self.pool, &emails)
+            .await
+            .map_err(ImportError::Database)?;
+        if !suppressed.is_empty() {
+            let (kept_rows, kept): (Vec<_>, Vec<_>) = rows
+                .into_iter()
+                .zip(batch)
+                .filter(|(row, imported)| {
+                    let email = imported.subscriber.email.as_ref();
+                    if suppressed.contains(&suppressions::email_hash(email)) {
+                        self.report.suppressed += 1;
+                        self.report.rows.push(RowReport {
+                            row: *row,
+                            email: Some(email.to_string()),
+                            errors: FieldErrors::from([(
+                                "email",
+                                vec![format!(
+                                    "{} has been suppressed and must not be mailed.",
+                                    email
+                                )],
+                            )]),
+                        });
+                        false
+                    } else {
+                        true
+                    }
+                })
+                .unzip();
+            rows = kept_rows;
+            batch = kept;
+        }
+        let inserted: HashSet<String> = subscribers::insert_many(
+            self.pool,
+            &batch,
+            self.list.id,
+            self.status,
+            self.consent_source.as_deref(),
+        )
+        .await
+        .map_err(ImportError::Database)?
+        .into_iter()
+        .collect();
+
+        for (row, imported) in rows.into_iter().zip(&batch) {
+            let email = imported.subscriber.email.as_ref();
+            if inserted.contains(email) {
+                self.report.imported += 1;
+            } else {
+                let reason = format!("{} is already on the {} list.", email, self.list.slug);
+                self.skip_duplicate(row, email, reason);
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_invalid(&mut self, email: Option<String>, errors: FieldErrors) {
+        self.report.invalid += 1;
+        self.report.rows.push(RowReport {
+            row: self.row,
+            email,
+            errors,
+        });
+    }
+
+    fn skip_duplicate(&mut self, row: u64, email: &str, reason: String) {
+        self.report.duplicates += 1;
+        self.report.rows.push(RowReport {
+            row,
+            email: Some(email.to_string()),
+            errors: FieldErrors::from([("email", vec![reason])]),
+        });
+    }
+}
+
+fn parse_header(record: Vec<Vec<u8>>) -> Result<Columns, ImportError> {
+    let names = record
+        .into_iter()
+        .map(|name| {
+            String::from_utf8(name)
+                .map(|name| name.trim().to_lowercase())
+                .map_err(|_| ImportError::InvalidHeader)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let position = |column: &str| names.iter().position(|name| name == column);
+    Ok(Columns {
+        email: position("email").ok_or(ImportError::MissingColumn("email"))?,
+        name: position("name").ok_or(ImportError::MissingColumn("name"))?,
+        subscribed_at: position("subscribed_at"),
+    })
+}
+
+// 基于csv_core的增量解析器：可以接收任意切分的数据块，跨块的记录会被保留到下一次调用
+struct CsvDecoder {
+    reader: Reader,
+    output: Vec<u8>,
+    output_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+}
+
+impl CsvDecoder {
+    fn new() -> Self {
+        Self {
+            reader: Reader::new(),
+            output: vec![0; 1024],
+            output_len: 0,
+            ends: vec![0; 16],
+            ends_len: 0,
+        }
+    }
+
+    // 返回这块数据中所有完整的记录。传入空切片表示输入结束
+    fn decode(&mut self, mut input: &[u8]) -> Vec<Vec<Vec<u8>>> {
+        let mut records = Vec::new();
+        loop {
+            let (result, nin, nout, nend) = self.reader.read_record(
+                input,
+                &mut self.output[self.output_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[nin..];
+            self.output_len += nout;
+            self.ends_len += nend;
+            match result {
+                ReadRecordResult::InputEmpty | ReadRecordResult::End => return records,
+                // 缓冲区不够大（字段很长或列很多），扩容后继续
+                ReadRecordResult::OutputFull => self.output.resize(self.output.len() * 2, 0),
+                ReadRecordResult::OutputEndsFull => self.ends.resize(self.ends.len() * 2, 0),
+                ReadRecordResult::Record => {
+                    let mut start = 0;
+                    let fields = self.ends[..self.ends_len]
+                        .iter()
+                        .map(|&end| {
+                            let field = self.output[start..end].to_vec();
+                            start = end;
+                            field
+                        })
+                        .collect();
+                    records.push(fields);
+                    self.output_len = 0;
+                    self.ends_len = 0;
+                }
+            }
+        }
+    }
+}
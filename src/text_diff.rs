@@ -0,0 +1,133 @@
+// 按行比较两段文本，用于比较一期邮件的两个版本
+// 输出与diff -u相同的统一格式：每个变化块以@@ -起始行,行数 +起始行,行数 @@开头，
+// 前后保留context行没有变化的内容，删除的行以-开头，新增的行以+开头
+
+// 最长公共子序列表格的大小上限，超过时把中间不同的部分视为整体替换，避免占用过多内存
+const MAX_CELLS: usize = 4_000_000;
+
+enum Edit<'a> {
+    Equal(&'a str),
+    Delete(&'a str),
+    Insert(&'a str),
+}
+
+pub fn unified_diff(old: &str, new: &str, context: usize) -> String {
+    let old: Vec<&str> = old.lines().collect();
+    let new: Vec<&str> = new.lines().collect();
+    let edits = edits(&old, &new);
+    // 每个编辑之前在两边的行号（从0开始），最后一项是两边的总行数
+    let mut positions = Vec::with_capacity(edits.len() + 1);
+    let (mut o, mut n) = (0, 0);
+    for edit in &edits {
+        positions.push((o, n));
+        match edit {
+            Edit::Equal(_) => {
+                o += 1;
+                n += 1;
+            }
+            Edit::Delete(_) => o += 1,
+            Edit::Insert(_) => n += 1,
+        }
+    }
+    positions.push((o, n));
+
+    let changes: Vec<usize> = edits
+        .iter()
+        .enumerate()
+        .filter(|(_, edit)| !matches!(edit, Edit::Equal(_)))
+        .map(|(i, _)| i)
+        .collect();
+    let mut output = String::new();
+    let mut i = 0;
+    while i < changes.len() {
+        // 两处变化之间没有变化的行不超过2 * context时合并为一个块
+        let mut j = i;
+        while j + 1 < changes.len() && changes[j + 1] - changes[j] <= 2 * context + 1 {
+            j += 1;
+        }
+        let start = changes[i].saturating_sub(context);
+        let end = (changes[j] + context + 1).min(edits.len());
+        let (old_start, new_start) = positions[start];
+        let (old_end, new_end) = positions[end];
+        output.push_str(&format!(
+            "@@ -{} +{} @@\n",
+            range(old_start, old_end - old_start),
+            range(new_start, new_end - new_start)
+        ));
+        for edit in &edits[start..end] {
+            let (prefix, line) = match edit {
+                Edit::Equal(line) => (' ', line),
+                Edit::Delete(line) => ('-', line),
+                Edit::Insert(line) => ('+', line),
+            };
+            output.push(prefix);
+            output.push_str(line);
+            output.push('\n');
+        }
+        i = j + 1;
+    }
+    output
+}
+
+// 与diff -u相同：只有一行时省略行数，没有行时起始行是前一行
+fn range(start: usize, count: usize) -> String {
+    match count {
+        0 => format!("{},0", start),
+        1 => format!("{}", start + 1),
+        _ => format!("{},{}", start + 1, count),
+    }
+}
+
+// 先去掉相同的开头和结尾，再用最长公共子序列找出中间部分的最少改动
+fn edits<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Edit<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    let mut edits: Vec<Edit> = old[..prefix].iter().map(|line| Edit::Equal(line)).collect();
+    if a.len() * b.len() > MAX_CELLS {
+        edits.extend(a.iter().map(|line| Edit::Delete(line)));
+        edits.extend(b.iter().map(|line| Edit::Insert(line)));
+    } else {
+        // lengths[i * width + j]是a[i..]和b[j..]的最长公共子序列的长度
+        let width = b.len() + 1;
+        let mut lengths = vec![0u32; (a.len() + 1) * width];
+        for i in (0..a.len()).rev() {
+            for j in (0..b.len()).rev() {
+                lengths[i * width + j] = if a[i] == b[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < a.len() && j < b.len() {
+            if a[i] == b[j] {
+                edits.push(Edit::Equal(a[i]));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                edits.push(Edit::Delete(a[i]));
+                i += 1;
+            } else {
+                edits.push(Edit::Insert(b[j]));
+                j += 1;
+            }
+        }
+        edits.extend(a[i..].iter().map(|line| Edit::Delete(line)));
+        edits.extend(b[j..].iter().map(|line| Edit::Insert(line)));
+    }
+    edits.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|line| Edit::Equal(line)),
+    );
+    edits
+}
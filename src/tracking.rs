@@ -0,0 +1,126 @@
+// 打开和点击跟踪：
+// 1. 发送时在</body>之前（没有时在HTML末尾）加入一个1x1的跟踪像素（/o/{token}），把指向外部网站的链接改写为/r/{token}
+// 2. 令牌为`{base64url编码的内容}.{签名}`，内容中包含这一期、订阅者和（点击时）原来的地址
+// 3. 跳转前校验签名，只会跳转到我们签名过的http(s)地址，不能被用来把人引到任意网站（开放重定向）
+// 纯文本版本中的链接不改写，纯文本邮件也无法加载跟踪像素
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::configuration::TrackingSettings;
+use crate::email_message::rewrite_links;
+use crate::newsletter_issue::TrackingOptions;
+
+#[derive(Clone)]
+pub struct Tracking {
+    enabled: bool,
+    signing_key: Secret<String>,
+}
+
+// 令牌中签名的内容，字段名尽量短，使链接不至于太长
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TrackingToken {
+    #[serde(rename = "i")]
+    pub issue_id: Uuid,
+    #[serde(rename = "s")]
+    pub subscriber_id: Uuid,
+    // 点击后跳转的地址，跟踪像素的令牌中没有
+    #[serde(rename = "u", default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl Tracking {
+    pub fn new(settings: &TrackingSettings) -> Self {
+        Self {
+            enabled: settings.enabled,
+            signing_key: settings.signing_key.clone(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // 按这一期的选项给发给一个订阅者的HTML加上跟踪。我们自己的链接（退订、偏好设置、附件）不改写
+    pub fn instrument(
+        &self,
+        html: String,
+        options: TrackingOptions,
+        base_url: &str,
+        issue_id: Uuid,
+        subscriber_id: Uuid,
+    ) -> String {
+        if !self.enabled {
+            return html;
+        }
+        let mut html = if options.clicks {
+            rewrite_links(&html, |url| {
+                let own = url == base_url || url.starts_with(&format!("{}/", base_url));
+                if own || !is_redirectable(url) {
+                    return None;
+                }
+                let token = self.sign(&TrackingToken {
+                    issue_id,
+                    subscriber_id,
+                    url: Some(url.to_string()),
+                });
+                Some(format!("{}/r/{}", base_url, token))
+            })
+        } else {
+            html
+        };
+        if options.opens {
+            let token = self.sign(&TrackingToken {
+                issue_id,
+                subscriber_id,
+                url: None,
+            });
+            let pixel = format!(
+                "<img src=\"{}/o/{}\" width=\"1\" height=\"1\" alt=\"\" style=\"display:none\">",
+                base_url, token
+            );
+            // 放在</body>之后的内容不属于文档，有的邮件客户端不会显示它
+            match html.to_ascii_lowercase().rfind("</body>") {
+                Some(end) => html.insert_str(end, &pixel),
+                None => html.push_str(&pixel),
+            }
+        }
+        html
+    }
+
+    // 签名不正确或者内容无法解析时返回None
+    pub fn decode(&self, token: &str) -> Option<TrackingToken> {
+        let (payload, signature) = token.rsplit_once('.')?;
+        let signature = hex::decode(signature).ok()?;
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        // verify_slice以常量时间比较签名，避免时序攻击
+        mac.verify_slice(&signature).ok()?;
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .decode(payload)
+            .ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+
+    fn sign(&self, token: &TrackingToken) -> String {
+        let payload = base64::engine::general_purpose::URL_SAFE_NO_PAD
+            .encode(serde_json::to_vec(token).expect("Failed to serialize tracking token"));
+        let mut mac = self.mac();
+        mac.update(payload.as_bytes());
+        format!("{}.{}", payload, hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.signing_key.expose_secret().as_bytes())
+            .expect("HMAC can take a key of any size")
+    }
+}
+
+// 只跳转到绝对的http和https地址，javascript:、data:以及//开头的地址都不行
+pub fn is_redirectable(url: &str) -> bool {
+    reqwest::Url::parse(url)
+        .is_ok_and(|url| matches!(url.scheme(), "http" | "https") && url.host_str().is_some())
+}
@@ -0,0 +1,431 @@
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+// 直接写入数据库，以便精确控制订阅时间和状态。订阅者只属于默认列表
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    name: &str,
+    status: &str,
+    subscribed_at: DateTime<Utc>,
+) -> Uuid {
+    let id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at) VALUES ($1, $2, $3, $4)",
+        id,
+        email,
+        name,
+        subscribed_at,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+        SELECT $1, id, $2, $3, $4 FROM lists WHERE slug = 'newsletter'
+        "#,
+        id,
+        status,
+        subscribed_at,
+        Uuid::new_v4().simple().to_string(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    id
+}
+
+fn day(n: i64) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap() + Duration::days(n)
+}
+
+// 5个订阅者，第n个在第n天订阅，奇数天订阅的已确认
+async fn seed(app: &TestApp) {
+    let subscribers = [
+        ("alice@example.com", "Alice Liddell"),
+        ("bob@example.com", "Bob Dylan"),
+        ("carol@example.com", "Carol Danvers"),
+        ("dave@example.com", "Dave Grohl"),
+        ("erin@example.com", "Erin Brockovich"),
+    ];
+    for (n, (email, name)) in subscribers.into_iter().enumerate() {
+        let status = if n % 2 == 0 {
+            "confirmed"
+        } else {
+            "pending_confirmation"
+        };
+        insert_subscriber(app, email, name, status, day(n as i64)).await;
+    }
+}
+
+async fn get_page(app: &TestApp, query: &[(&str, &str)]) -> Value {
+    let response = app.get_admin("/subscribers", query).await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+fn emails(page: &Value) -> Vec<&str> {
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn listing_subscribers_requires_authentication() {
+    let app = spawn_app().await;
+
+    let client = reqwest::Client::new();
+    let subscriber = format!("{}/admin/subscribers/{}", app.address, Uuid::new_v4());
+
+    let requests = vec![
+        client.get(format!("{}/admin/subscribers", app.address)),
+        client.get(format!("{}/admin/subscribers/count", app.address)),
+        client.get(&subscriber),
+        client.patch(&subscriber).json(&json!({"name": "x"})),
+        client.delete(&subscriber),
+    ];
+    for request in requests {
+        let request = request.build().unwrap();
+        let description = format!("{} {}", request.method(), request.url());
+        let response = client.execute(request).await.unwrap();
+
+        assert_eq!(401, response.status().as_u16(), "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn subscribers_are_listed_newest_first_by_default() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let page = get_page(&app, &[]).await;
+
+    assert_eq!(
+        emails(&page),
+        vec![
+            "erin@example.com",
+            "dave@example.com",
+            "carol@example.com",
+            "bob@example.com",
+            "alice@example.com",
+        ]
+    );
+    assert!(page["next_cursor"].is_null());
+    assert_eq!(page["subscribers"][0]["name"], "Erin Brockovich");
+    assert_eq!(page["subscribers"][0]["lists"][0]["list"], "newsletter");
+    assert_eq!(page["subscribers"][0]["lists"][0]["status"], "confirmed");
+}
+
+#[actix_web::test]
+async fn following_the_cursor_visits_every_subscriber_once() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    // 与最后一个订阅者同一时刻订阅，排序时只能靠id区分
+    insert_subscriber(&app, "frank@example.com", "Frank", "confirmed", day(4)).await;
+
+    for (sort, order) in [
+        ("subscribed_at", "desc"),
+        ("email", "asc"),
+        ("name", "desc"),
+    ] {
+        let mut seen = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let mut query = vec![("limit", "2"), ("sort", sort), ("order", order)];
+            if let Some(cursor) = &cursor {
+                query.push(("cursor", cursor));
+            }
+            let page = get_page(&app, &query).await;
+            assert!(emails(&page).len() <= 2);
+            seen.extend(emails(&page).into_iter().map(String::from));
+            match page["next_cursor"].as_str() {
+                Some(next) => cursor = Some(next.to_string()),
+                None => break,
+            }
+        }
+
+        let mut deduplicated = seen.clone();
+        deduplicated.sort();
+        deduplicated.dedup();
+        assert_eq!(6, seen.len(), "sort={} order={}: {:?}", sort, order, seen);
+        assert_eq!(
+            6,
+            deduplicated.len(),
+            "sort={} order={}: {:?}",
+            sort,
+            order,
+            seen
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_sorted_by_email() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let page = get_page(&app, &[("sort", "email"), ("order", "desc")]).await;
+
+    assert_eq!(emails(&page)[0], "erin@example.com");
+    assert_eq!(emails(&page)[4], "alice@example.com");
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_filtered_by_status_and_date_range() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    let after = day(1).to_rfc3339();
+    let before = day(4).to_rfc3339();
+
+    let page = get_page(
+        &app,
+        &[
+            ("status", "confirmed"),
+            ("subscribed_after", &after),
+            ("subscribed_before", &before),
+            ("order", "asc"),
+        ],
+    )
+    .await;
+
+    assert_eq!(emails(&page), vec!["carol@example.com"]);
+}
+
+#[actix_web::test]
+async fn search_is_a_case_insensitive_substring_match_on_email_and_name() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    insert_subscriber(
+        &app,
+        "percent@example.com",
+        "100% Real",
+        "confirmed",
+        day(9),
+    )
+    .await;
+
+    let by_name = get_page(&app, &[("q", "DYL")]).await;
+    let by_email = get_page(&app, &[("q", "Carol@")]).await;
+    // %应按字面匹配，而不是作为通配符
+    let literal = get_page(&app, &[("q", "0%")]).await;
+
+    assert_eq!(emails(&by_name), vec!["bob@example.com"]);
+    assert_eq!(emails(&by_email), vec!["carol@example.com"]);
+    assert_eq!(emails(&literal), vec!["percent@example.com"]);
+}
+
+#[actix_web::test]
+async fn count_returns_the_number_of_matching_subscribers() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let count = |query: &'static [(&'static str, &'static str)]| {
+        let app = &app;
+        async move {
+            let response = app.get_admin("/subscribers/count", query).await;
+            assert_eq!(200, response.status().as_u16());
+            response.json::<Value>().await.unwrap()["count"].clone()
+        }
+    };
+
+    assert_eq!(count(&[]).await, 5);
+    assert_eq!(count(&[("status", "pending_confirmation")]).await, 2);
+    assert_eq!(
+        count(&[("q", "example.com"), ("status", "confirmed")]).await,
+        3
+    );
+}
+
+#[actix_web::test]
+async fn invalid_parameters_are_rejected_with_400() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    let page = get_page(&app, &[("limit", "2")]).await;
+    let cursor = page["next_cursor"].as_str().unwrap();
+
+    let test_cases = vec![
+        (vec![("cursor", "not-a-cursor")], "malformed cursor"),
+        (
+            vec![("cursor", cursor), ("sort", "email")],
+            "cursor from another sort order",
+        ),
+        (vec![("status", "sleeping")], "unknown status"),
+        (vec![("sort", "password_hash")], "unknown sort column"),
+        (vec![("subscribed_after", "yesterday")], "invalid date"),
+    ];
+
+    for (query, description) in test_cases {
+        let response = app.get_admin("/subscribers", &query).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not fail with 400 Bad Request for {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn a_single_subscriber_can_be_fetched() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "le guin", "confirmed", day(0)).await;
+
+    let response = app.get_admin(&format!("/subscribers/{}", id), &[]).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let subscriber: Value = response.json().await.unwrap();
+    assert_eq!(subscriber["id"], id.to_string());
+    assert_eq!(subscriber["email"], "ursula@example.com");
+    assert_eq!(subscriber["lists"][0]["status"], "confirmed");
+}
+
+#[actix_web::test]
+async fn unknown_subscribers_return_404() {
+    let app = spawn_app().await;
+    let path = format!("/subscribers/{}", Uuid::new_v4());
+
+    assert_eq!(404, app.get_admin(&path, &[]).await.status().as_u16());
+    assert_eq!(
+        404,
+        app.patch_admin(&path, &json!({"name": "x"}))
+            .await
+            .status()
+            .as_u16()
+    );
+    assert_eq!(404, app.delete_admin(&path).await.status().as_u16());
+}
+
+#[actix_web::test]
+async fn updating_a_subscriber_records_who_changed_what() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "le giun", "confirmed", day(0)).await;
+
+    let response = app
+        .patch_admin(&format!("/subscribers/{}", id), &json!({"name": "le guin"}))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let subscriber: Value = response.json().await.unwrap();
+    assert_eq!(subscriber["name"], "le guin");
+    assert_eq!(subscriber["email"], "ursula@example.com");
+
+    let audit = sqlx::query!(
+        "SELECT changed_by, action, changes FROM subscriber_audit_log WHERE subscriber_id = $1",
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(audit.changed_by, Some(app.test_user.user_id));
+    assert_eq!(audit.action, "update");
+    assert_eq!(
+        audit.changes,
+        json!({"name": {"from": "le giun", "to": "le guin"}})
+    );
+}
+
+#[actix_web::test]
+async fn updates_are_validated_like_new_subscriptions() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "le guin", "confirmed", day(0)).await;
+
+    let response = app
+        .patch_admin(
+            &format!("/subscribers/{}", id),
+            &json!({"name": "  ", "email": "definitely-not-an-email"}),
+        )
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["fields"]["name"].is_array());
+    assert!(body["fields"]["email"].is_array());
+}
+
+#[actix_web::test]
+async fn changing_the_email_to_one_in_use_returns_409() {
+    let app = spawn_app().await;
+    insert_subscriber(&app, "taken@example.com", "first", "confirmed", day(0)).await;
+    let id = insert_subscriber(&app, "second@example.com", "second", "confirmed", day(1)).await;
+
+    let response = app
+        .patch_admin(
+            &format!("/subscribers/{}", id),
+            &json!({"email": "taken@example.com"}),
+        )
+        .await;
+
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn status_transitions_follow_the_subscription_lifecycle() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "le guin",
+        "pending_confirmation",
+        day(0),
+    )
+    .await;
+    let path = format!("/subscribers/{}", id);
+    // (执行的变更, 期望的状态码, 之后的状态)
+    let steps = [
+        ("confirm", 200, "confirmed"),
+        ("confirm", 409, "confirmed"),
+        ("unsubscribe", 200, "unsubscribed"),
+        ("confirm", 409, "unsubscribed"),
+        ("block", 200, "blocked"),
+        ("unsubscribe", 409, "blocked"),
+    ];
+
+    for (transition, expected_status, expected_state) in steps {
+        let response = app
+            .patch_admin(&path, &json!({"transition": transition}))
+            .await;
+
+        assert_eq!(
+            expected_status,
+            response.status().as_u16(),
+            "transition {}",
+            transition
+        );
+        let subscriber: Value = app.get_admin(&path, &[]).await.json().await.unwrap();
+        assert_eq!(
+            subscriber["lists"][0]["status"], expected_state,
+            "after {}",
+            transition
+        );
+    }
+}
+
+#[actix_web::test]
+async fn deleting_a_subscriber_keeps_an_audit_record() {
+    let app = spawn_app().await;
+    let id = insert_subscriber(&app, "ursula@example.com", "le guin", "confirmed", day(0)).await;
+    let path = format!("/subscribers/{}", id);
+
+    let response = app.delete_admin(&path).await;
+
+    assert_eq!(204, response.status().as_u16());
+    assert_eq!(404, app.get_admin(&path, &[]).await.status().as_u16());
+    let audit = sqlx::query!(
+        "SELECT changed_by, action, changes FROM subscriber_audit_log WHERE subscriber_id = $1",
+        id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(audit.changed_by, Some(app.test_user.user_id));
+    assert_eq!(audit.action, "delete");
+    assert_eq!(audit.changes["email"], "ursula@example.com");
+}
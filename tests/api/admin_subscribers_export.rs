@@ -0,0 +1,136 @@
+use chrono::{TimeZone, Utc};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str, day: u32) {
+    let id = Uuid::new_v4();
+    let subscribed_at = Utc.with_ymd_and_hms(2026, 1, day, 0, 0, 0).unwrap();
+    sqlx::query!(
+        "INSERT INTO subscriptions (id, email, name, subscribed_at) VALUES ($1, $2, $3, $4)",
+        id,
+        email,
+        name,
+        subscribed_at,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+        SELECT $1, id, $2, $3, $4 FROM lists WHERE slug = 'newsletter'
+        "#,
+        id,
+        status,
+        subscribed_at,
+        Uuid::new_v4().simple().to_string(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn seed(app: &TestApp) {
+    insert_subscriber(app, "ursula@example.com", "le guin", "confirmed", 1).await;
+    insert_subscriber(
+        app,
+        "octavia@example.com",
+        "Butler, Octavia",
+        "confirmed",
+        2,
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "pending@example.com",
+        "=HYPERLINK(\"x\")",
+        "pending_confirmation",
+        3,
+    )
+    .await;
+}
+
+#[actix_web::test]
+async fn exporting_requires_authentication() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/admin/subscribers/export", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn subscribers_are_exported_as_csv_by_default() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let response = app.get_admin("/subscribers/export", &[]).await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let disposition = response.headers()["Content-Disposition"].to_str().unwrap();
+    assert!(disposition.starts_with("attachment; filename=\"subscribers-"));
+    assert!(disposition.ends_with(".csv\""));
+    let body = response.text().await.unwrap();
+    let lines: Vec<&str> = body.lines().collect();
+    assert_eq!(lines[0], "id,email,name,lists,subscribed_at");
+    assert_eq!(lines.len(), 4);
+    assert!(lines[1]
+        .contains(",ursula@example.com,le guin,newsletter:confirmed,2026-01-01T00:00:00+00:00"));
+    // 含逗号的字段加引号，可能被当成公式的字段前加单引号
+    assert!(lines[2].contains(",\"Butler, Octavia\","));
+    assert!(lines[3].contains(",\"'=HYPERLINK(\"\"x\"\")\","));
+}
+
+#[actix_web::test]
+async fn subscribers_can_be_exported_as_ndjson() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let response = app
+        .get_admin("/subscribers/export", &[("format", "ndjson")])
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "application/x-ndjson");
+    let body = response.text().await.unwrap();
+    let records: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 3);
+    assert_eq!(records[1]["name"], "Butler, Octavia");
+    assert_eq!(records[2]["lists"][0]["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn exports_apply_the_listing_filters() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let response = app
+        .get_admin(
+            "/subscribers/export",
+            &[
+                ("format", "ndjson"),
+                ("status", "confirmed"),
+                ("q", "octavia"),
+            ],
+        )
+        .await;
+
+    let body = response.text().await.unwrap();
+    let records: Vec<Value> = body
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0]["email"], "octavia@example.com");
+}
@@ -0,0 +1,279 @@
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn post_import(app: &TestApp, query: &[(&str, &str)], csv: String) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Content-Type", "text/csv")
+        .query(query)
+        .body(csv)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn import(app: &TestApp, query: &[(&str, &str)], csv: &str) -> Value {
+    let response = post_import(app, query, csv.to_string()).await;
+    assert_eq!(200, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+#[actix_web::test]
+async fn importing_requires_authentication() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .body("email,name\nursula@example.com,le guin\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn valid_rows_are_imported_and_invalid_rows_are_reported() {
+    let app = spawn_app().await;
+    // 列的顺序与多余的列都不应影响导入；带引号的字段可以包含逗号
+    let csv = "\
+name,Email,notes
+le guin,ursula@example.com,first
+\"Butler, Octavia\",octavia@example.com,second
+,missing-name@example.com,third
+Ted,not-an-email,fourth
+";
+
+    let report = import(&app, &[], csv).await;
+
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["invalid"], 2);
+    assert_eq!(report["rows"][0]["row"], 4);
+    assert!(report["rows"][0]["errors"]["name"].is_array());
+    assert_eq!(report["rows"][1]["row"], 5);
+    assert!(report["rows"][1]["errors"]["email"].is_array());
+
+    let saved = sqlx::query!("SELECT name FROM subscriptions WHERE email = 'octavia@example.com'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Butler, Octavia");
+
+    // 后台任务发送确认邮件，导入的订阅者点击其中的链接即可确认
+    app.dispatch_all_pending_issues().await;
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    assert_eq!(email_requests.len(), 2);
+    let email_request = email_requests
+        .iter()
+        .find(|request| {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            body["To"].as_str().unwrap().contains("octavia@example.com")
+        })
+        .unwrap();
+    let link = app.get_confirmation_links(email_request).html;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT m.status
+        FROM subscriptions s JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.email = 'octavia@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "confirmed");
+}
+
+#[actix_web::test]
+async fn importing_queues_confirmation_emails_instead_of_sending_them() {
+    let app = spawn_app().await;
+
+    import(&app, &[], "email,name\nursula@example.com,le guin\n").await;
+
+    // 导入请求本身不发送邮件，确认邮件由后台任务按发送速率发送
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(app.email_server.received_requests().await.unwrap().len(), 1);
+}
+
+#[actix_web::test]
+async fn duplicates_in_the_file_and_in_the_database_are_skipped() {
+    let app = spawn_app().await;
+    import(&app, &[], "email,name\nexisting@example.com,existing\n").await;
+    let csv = "\
+email,name
+existing@example.com,again
+new@example.com,new
+new@example.com,twice
+";
+
+    let report = import(&app, &[], csv).await;
+
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["duplicates"], 2);
+    let rows: Vec<i64> = report["rows"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|row| row["row"].as_i64().unwrap())
+        .collect();
+    assert!(rows.contains(&2) && rows.contains(&4), "{:?}", rows);
+    let existing =
+        sqlx::query!("SELECT name FROM subscriptions WHERE email = 'existing@example.com'")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    assert_eq!(existing.name, "existing");
+}
+
+#[actix_web::test]
+async fn existing_subscribers_can_be_imported_into_another_list() {
+    let app = spawn_app().await;
+    import(&app, &[], "email,name\nexisting@example.com,existing\n").await;
+    app.post_admin(
+        "/lists",
+        &serde_json::json!({"slug": "product-updates", "name": "Product updates"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let csv = "email,name\nexisting@example.com,again\nnew@example.com,new\n";
+
+    let report = import(&app, &[("list", "product-updates")], csv).await;
+
+    assert_eq!(report["imported"], 2);
+    assert_eq!(report["duplicates"], 0);
+    let members: i64 = sqlx::query_scalar!(
+        r#"
+        SELECT count(*) AS "count!"
+        FROM list_memberships m JOIN lists l ON l.id = m.list_id
+        WHERE l.slug = 'product-updates'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(members, 2);
+    let response = post_import(&app, &[("list", "nope")], csv.to_string()).await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn confirmed_imports_require_and_record_a_consent_source() {
+    let app = spawn_app().await;
+    let csv = "email,name,subscribed_at\nursula@example.com,le guin,2019-03-01T12:00:00Z\n";
+
+    let response = post_import(&app, &[("confirmed", "true")], csv.into()).await;
+    assert_eq!(400, response.status().as_u16());
+
+    let report = import(
+        &app,
+        &[("confirmed", "true"), ("consent_source", "legacy export")],
+        csv,
+    )
+    .await;
+    assert_eq!(report["imported"], 1);
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT m.status, s.consent_source, s.subscribed_at
+        FROM subscriptions s JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.email = 'ursula@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.status, "confirmed");
+    assert_eq!(saved.consent_source.as_deref(), Some("legacy export"));
+    // 已确认的订阅者不会收到确认邮件
+    app.dispatch_all_pending_issues().await;
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        saved.subscribed_at.to_rfc3339(),
+        "2019-03-01T12:00:00+00:00"
+    );
+}
+
+#[actix_web::test]
+async fn a_header_without_the_required_columns_is_rejected() {
+    let app = spawn_app().await;
+
+    for csv in ["", "address,name\nursula@example.com,le guin\n"] {
+        let response = post_import(&app, &[], csv.into()).await;
+
+        assert_eq!(400, response.status().as_u16(), "csv: {:?}", csv);
+    }
+}
+
+#[actix_web::test]
+async fn large_files_are_imported_in_batches() {
+    let app = spawn_app().await;
+    let mut csv = String::from("email,name\n");
+    for i in 0..2500 {
+        csv.push_str(&format!("subscriber{}@example.com,Subscriber {}\n", i, i));
+    }
+
+    let report = import(&app, &[], &csv).await;
+
+    assert_eq!(report["imported"], 2500);
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, Some(2500));
+}
+
+#[actix_web::test]
+async fn the_cli_imports_a_file_into_the_configured_database() {
+    let app = spawn_app().await;
+    let database_name: String = sqlx::query_scalar!("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    let path = std::env::temp_dir().join(format!("{}.csv", Uuid::new_v4()));
+    std::fs::write(&path, "email,name\nursula@example.com,le guin\nbad,row\n").unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_import_subscribers"))
+        .arg(&path)
+        .args(["--confirmed", "--consent-source", "cli test"])
+        .env("APP_DATABASE__DATABASE_NAME", &database_name)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["invalid"], 1);
+    let status = sqlx::query_scalar!(
+        r#"
+        SELECT m.status
+        FROM subscriptions s JOIN list_memberships m ON m.subscriber_id = s.id
+        WHERE s.email = 'ursula@example.com'
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(status, "confirmed");
+}
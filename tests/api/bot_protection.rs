@@ -0,0 +1,200 @@
+use sha2::{Digest, Sha256};
+use zero2prod_lib::bot_protection::leading_zero_bits;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn post_subscription(test_app: &TestApp, body: String) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn count_subscriptions(test_app: &TestApp) -> i64 {
+    sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to count subscriptions")
+}
+
+async fn spawn_app_with_proof_of_work() -> TestApp {
+    spawn_app_with(|c| {
+        c.bot_protection.proof_of_work.enabled = true;
+        // 测试中使用较低的难度，让暴力求解足够快
+        c.bot_protection.proof_of_work.difficulty = 8;
+    })
+    .await
+}
+
+async fn get_challenge(test_app: &TestApp) -> serde_json::Value {
+    reqwest::Client::new()
+        .get(format!("{}/subscriptions/challenge", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+        .json()
+        .await
+        .unwrap()
+}
+
+// 与客户端的做法相同：不断尝试，直到找到满足难度要求的解
+fn solve(challenge: &str, difficulty: u32) -> String {
+    (0u64..)
+        .map(|n| n.to_string())
+        .find(|solution| {
+            let hash = Sha256::digest(format!("{}{}", challenge, solution).as_bytes());
+            leading_zero_bits(&hash) >= difficulty
+        })
+        .unwrap()
+}
+
+fn body_with_proof_of_work(challenge: &str, solution: &str) -> String {
+    format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&pow_challenge={}&pow_solution={}",
+        challenge, solution
+    )
+}
+
+#[actix_web::test]
+async fn submissions_caught_by_the_honeypot_are_accepted_but_dropped() {
+    let test_app = spawn_app().await;
+
+    let response = post_subscription(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=http%3A%2F%2Fspam.example.com"
+            .into(),
+    )
+    .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(0, count_subscriptions(&test_app).await);
+}
+
+#[actix_web::test]
+async fn an_empty_honeypot_field_is_ignored() {
+    let test_app = spawn_app().await;
+
+    let response = post_subscription(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&website=".into(),
+    )
+    .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&test_app).await);
+}
+
+#[actix_web::test]
+async fn the_challenge_endpoint_returns_404_when_proof_of_work_is_disabled() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/subscriptions/challenge", &test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_without_a_proof_of_work_when_it_is_enabled() {
+    let test_app = spawn_app_with_proof_of_work().await;
+
+    let response = post_subscription(
+        &test_app,
+        "name=le%20guin&email=ursula_le_guin%40gmail.com".into(),
+    )
+    .await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(0, count_subscriptions(&test_app).await);
+}
+
+#[actix_web::test]
+async fn subscribe_accepts_a_valid_proof_of_work() {
+    let test_app = spawn_app_with_proof_of_work().await;
+    let challenge = get_challenge(&test_app).await;
+    let challenge_token = challenge["challenge"].as_str().unwrap();
+    let solution = solve(
+        challenge_token,
+        challenge["difficulty"].as_u64().unwrap() as u32,
+    );
+
+    let response = post_subscription(
+        &test_app,
+        body_with_proof_of_work(challenge_token, &solution),
+    )
+    .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&test_app).await);
+}
+
+#[actix_web::test]
+async fn a_proof_of_work_cannot_be_reused() {
+    let test_app = spawn_app_with_proof_of_work().await;
+    let challenge = get_challenge(&test_app).await;
+    let challenge_token = challenge["challenge"].as_str().unwrap();
+    let solution = solve(challenge_token, 8);
+
+    post_subscription(
+        &test_app,
+        body_with_proof_of_work(challenge_token, &solution),
+    )
+    .await;
+    let response = post_subscription(
+        &test_app,
+        body_with_proof_of_work(challenge_token, &solution).replace("ursula_le_guin", "someone"),
+    )
+    .await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(1, count_subscriptions(&test_app).await);
+}
+
+#[actix_web::test]
+async fn subscribe_rejects_an_invalid_proof_of_work() {
+    let test_app = spawn_app_with_proof_of_work().await;
+    let challenge = get_challenge(&test_app).await;
+    let challenge_token = challenge["challenge"].as_str().unwrap().to_string();
+    let solution = solve(&challenge_token, 8);
+    // 降低难度后重新计算的解无法通过签名校验
+    let (payload, signature) = challenge_token.rsplit_once('.').unwrap();
+    let tampered = format!("{}.{}", payload.replace(".8", ".0"), signature);
+    let wrong_solution = (0u64..)
+        .map(|n| n.to_string())
+        .find(|s| {
+            let hash = Sha256::digest(format!("{}{}", challenge_token, s).as_bytes());
+            leading_zero_bits(&hash) < 8
+        })
+        .unwrap();
+
+    let test_cases = vec![
+        (
+            body_with_proof_of_work(&challenge_token, &wrong_solution),
+            "wrong solution",
+        ),
+        (
+            body_with_proof_of_work(&tampered, "0"),
+            "tampered challenge",
+        ),
+        (
+            body_with_proof_of_work("not-a-challenge", &solution),
+            "malformed challenge",
+        ),
+    ];
+    for (body, description) in test_cases {
+        let response = post_subscription(&test_app, body).await;
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not reject a {}.",
+            description
+        );
+    }
+    assert_eq!(0, count_subscriptions(&test_app).await);
+}
@@ -0,0 +1,188 @@
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp) -> Uuid {
+    reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("User-Agent", "consent-test/1.0")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn consent_events(app: &TestApp, id: Uuid) -> Vec<Value> {
+    let response = app.get_admin(&format!("/subscribers/{}", id), &[]).await;
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    body["consent_events"].as_array().unwrap().clone()
+}
+
+fn events(consent_events: &[Value]) -> Vec<&str> {
+    consent_events
+        .iter()
+        .map(|e| e["event"].as_str().unwrap())
+        .collect()
+}
+
+#[actix_web::test]
+async fn signing_up_records_the_client_ip_and_user_agent() {
+    let app = spawn_app().await;
+
+    let id = subscribe(&app).await;
+
+    let consent_events = consent_events(&app, id).await;
+    assert_eq!(events(&consent_events), ["signup"]);
+    assert_eq!(consent_events[0]["source"], "subscription_form");
+    assert_eq!(consent_events[0]["ip_address"], "127.0.0.1");
+    assert_eq!(consent_events[0]["user_agent"], "consent-test/1.0");
+}
+
+#[actix_web::test]
+async fn confirming_records_a_single_event_even_when_the_link_is_clicked_twice() {
+    let app = spawn_app().await;
+    let id = subscribe(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+
+    for _ in 0..2 {
+        let response = reqwest::get(confirmation_link.clone()).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    let consent_events = consent_events(&app, id).await;
+    assert_eq!(events(&consent_events), ["signup", "confirm"]);
+    assert_eq!(consent_events[1]["source"], "confirmation_link");
+    assert_eq!(consent_events[1]["ip_address"], "127.0.0.1");
+}
+
+#[actix_web::test]
+async fn old_confirmation_links_do_not_resubscribe_an_unsubscribed_subscriber() {
+    let app = spawn_app().await;
+    let id = subscribe(&app).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    app.patch_admin(
+        &format!("/subscribers/{}", id),
+        &json!({"transition": "unsubscribe"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let status = sqlx::query_scalar!("SELECT status FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(status, "unsubscribed");
+    assert_eq!(
+        events(&consent_events(&app, id).await),
+        ["signup", "unsubscribe"]
+    );
+}
+
+#[actix_web::test]
+async fn admin_edits_and_status_changes_are_recorded_with_the_admin_as_source() {
+    let app = spawn_app().await;
+    let id = subscribe(&app).await;
+
+    let response = app
+        .patch_admin(
+            &format!("/subscribers/{}", id),
+            &json!({"name": "Ursula K. Le Guin", "transition": "block"}),
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let consent_events = consent_events(&app, id).await;
+    assert_eq!(events(&consent_events), ["signup", "admin_edit", "block"]);
+    let source = format!("admin:{}", app.test_user.username);
+    for event in &consent_events[1..] {
+        assert_eq!(event["source"], source.as_str());
+        assert_eq!(event["ip_address"], Value::Null);
+    }
+}
+
+#[actix_web::test]
+async fn a_failed_status_change_records_nothing() {
+    let app = spawn_app().await;
+    let id = subscribe(&app).await;
+
+    // 已退订的订阅者不能再被确认
+    app.patch_admin(
+        &format!("/subscribers/{}", id),
+        &json!({"transition": "unsubscribe"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let response = app
+        .patch_admin(
+            &format!("/subscribers/{}", id),
+            &json!({"transition": "confirm"}),
+        )
+        .await;
+
+    assert_eq!(409, response.status().as_u16());
+    assert_eq!(
+        events(&consent_events(&app, id).await),
+        ["signup", "unsubscribe"]
+    );
+}
+
+#[actix_web::test]
+async fn imported_subscribers_get_an_import_event_with_the_consent_source() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Content-Type", "text/csv")
+        .query(&[
+            ("confirmed", "true"),
+            ("consent_source", "legacy signup form"),
+        ])
+        .body("email,name\nursula@example.com,le guin\n")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let consent_events = consent_events(&app, id).await;
+    assert_eq!(events(&consent_events), ["import"]);
+    assert_eq!(consent_events[0]["source"], "legacy signup form");
+}
+
+#[actix_web::test]
+async fn consent_events_are_kept_after_the_subscriber_is_deleted() {
+    let app = spawn_app().await;
+    let id = subscribe(&app).await;
+
+    let response = app.delete_admin(&format!("/subscribers/{}", id)).await;
+
+    assert_eq!(204, response.status().as_u16());
+    let kept = sqlx::query_scalar!(
+        "SELECT event FROM consent_events WHERE subscriber_id = $1 ORDER BY id",
+        id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(kept, ["signup", "delete"]);
+}
@@ -0,0 +1,225 @@
+use std::sync::{Arc, Mutex};
+
+use secrecy::Secret;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+use wiremock::matchers::{header, header_exists, method, path};
+use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+use zero2prod_lib::{
+    configuration::{SmtpSettings, SmtpTls},
+    email_client::{EmailClient, EmailClientError},
+    email_message::{Attachment, EmailMessage, Mailbox},
+    request_id::RequestId,
+};
+
+fn email_client(base_url: String) -> EmailClient {
+    EmailClient::new(
+        base_url,
+        "sender@example.com".into(),
+        Secret::new("my-secret-token".into()),
+        std::time::Duration::from_millis(200),
+    )
+}
+
+async fn send_test_email(client: &EmailClient) -> Result<(), EmailClientError> {
+    client
+        .send_email("recipient@example.com", "subject", "<p>html</p>", "text")
+        .await
+}
+
+#[actix_web::test]
+async fn send_email_fires_a_request_to_base_url() {
+    let mock_server = MockServer::start().await;
+    let client = email_client(mock_server.uri());
+
+    Mock::given(header_exists("X-Postmark-Server-Token"))
+        .and(header("Content-Type", "application/json"))
+        .and(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    assert!(send_test_email(&client).await.is_ok());
+}
+
+#[actix_web::test]
+async fn send_email_fails_if_the_server_returns_500() {
+    let mock_server = MockServer::start().await;
+    let client = email_client(mock_server.uri());
+
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    assert!(send_test_email(&client).await.is_err());
+}
+
+#[actix_web::test]
+async fn send_email_forwards_the_current_request_id() {
+    let mock_server = MockServer::start().await;
+    let client = email_client(mock_server.uri());
+
+    Mock::given(header("X-Request-Id", "my-request-id"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let request_id = RequestId::parse("my-request-id").unwrap();
+    let outcome = request_id.scope(send_test_email(&client)).await;
+
+    assert!(outcome.is_ok());
+}
+
+#[actix_web::test]
+async fn send_email_has_no_request_id_outside_of_a_request() {
+    let mock_server = MockServer::start().await;
+    let client = email_client(mock_server.uri());
+
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    send_test_email(&client).await.unwrap();
+
+    let requests: Vec<Request> = mock_server.received_requests().await.unwrap();
+    assert!(requests[0].headers.get("X-Request-Id").is_none());
+}
+
+#[actix_web::test]
+async fn send_keeps_the_display_names_of_sender_and_recipient() {
+    let mock_server = MockServer::start().await;
+    let client = email_client(mock_server.uri());
+
+    Mock::given(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let message = EmailMessage::builder(
+        Mailbox::with_name("Zero2Prod, Inc.", "sender@example.com"),
+        Mailbox::with_name("勒古恩", "recipient@example.com"),
+    )
+    .subject("subject")
+    .html("<p>html</p>")
+    .build();
+    client.send(&message).await.unwrap();
+
+    let requests: Vec<Request> = mock_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+    assert_eq!(body["From"], "\"Zero2Prod, Inc.\" <sender@example.com>");
+    assert_eq!(
+        body["To"],
+        "=?UTF-8?B?5YuS5Y+k5oGp?= <recipient@example.com>"
+    );
+}
+
+// 一封通过SMTP收到的邮件
+#[derive(Debug, Clone)]
+struct SmtpDelivery {
+    mail_from: String,
+    rcpt_to: Vec<String>,
+    data: String,
+}
+
+// 只够测试使用的SMTP服务器：接受所有命令，记录收到的信封和邮件内容
+async fn start_smtp_server() -> (u16, Arc<Mutex<Vec<SmtpDelivery>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let deliveries = Arc::new(Mutex::new(Vec::new()));
+    let received = deliveries.clone();
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            let deliveries = deliveries.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = stream.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 localhost\r\n").await.unwrap();
+                let mut delivery = SmtpDelivery {
+                    mail_from: String::new(),
+                    rcpt_to: Vec::new(),
+                    data: String::new(),
+                };
+                while let Ok(Some(line)) = lines.next_line().await {
+                    let command = line.to_ascii_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") {
+                        b"250 localhost\r\n"
+                    } else if command.starts_with("MAIL FROM:") {
+                        delivery.mail_from = line[10..].to_string();
+                        b"250 OK\r\n"
+                    } else if command.starts_with("RCPT TO:") {
+                        delivery.rcpt_to.push(line[8..].to_string());
+                        b"250 OK\r\n"
+                    } else if command == "DATA" {
+                        writer.write_all(b"354 Go ahead\r\n").await.unwrap();
+                        while let Ok(Some(line)) = lines.next_line().await {
+                            if line == "." {
+                                break;
+                            }
+                            delivery.data.push_str(&line);
+                            delivery.data.push_str("\r\n");
+                        }
+                        deliveries.lock().unwrap().push(delivery.clone());
+                        b"250 OK\r\n"
+                    } else if command == "QUIT" {
+                        writer.write_all(b"221 Bye\r\n").await.unwrap();
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    writer.write_all(reply).await.unwrap();
+                }
+            });
+        }
+    });
+    (port, received)
+}
+
+#[actix_web::test]
+async fn smtp_sends_the_full_mime_message_with_attachments() {
+    let (port, deliveries) = start_smtp_server().await;
+    let client = EmailClient::smtp(
+        &SmtpSettings {
+            host: "127.0.0.1".into(),
+            port,
+            username: String::new(),
+            password: Secret::new(String::new()),
+            tls: SmtpTls::None,
+        },
+        "sender@example.com".into(),
+        std::time::Duration::from_secs(5),
+    );
+    let logo = Attachment {
+        filename: "logo.png".into(),
+        content_type: "image/png".into(),
+        content: Arc::from(&b"not really an image"[..]),
+        content_id: Some("logo".into()),
+    };
+    let message = EmailMessage::builder(
+        Mailbox::with_name("Zero2Prod", "sender@example.com"),
+        Mailbox::with_name("勒古恩", "recipient@example.com"),
+    )
+    .subject("Issue")
+    .html(r#"<p><img src="cid:logo" alt="Logo"></p>"#)
+    .attachments(vec![logo])
+    .build();
+
+    client.send(&message).await.unwrap();
+
+    let deliveries = deliveries.lock().unwrap();
+    assert_eq!(deliveries.len(), 1);
+    assert_eq!(deliveries[0].mail_from, "<sender@example.com>");
+    assert_eq!(deliveries[0].rcpt_to, ["<recipient@example.com>"]);
+    let data = &deliveries[0].data;
+    assert!(data.starts_with("From: Zero2Prod <sender@example.com>\r\nTo: =?UTF-8?B?"));
+    assert!(data.contains("Content-Type: multipart/related;"));
+    assert!(data.contains("Content-ID: <logo>\r\nContent-Disposition: inline;"));
+    assert!(data.contains(r#"src=3D"cid:logo""#), "{}", data);
+}
@@ -0,0 +1,180 @@
+use serde_json::{json, Value};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": email}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+// 发布一期，返回收到它的地址
+async fn publish(app: &TestApp, title: &str) -> Vec<String> {
+    app.post_newsletters(json!({
+        "title": title,
+        "content": {"html": "<p>Hello</p>", "text": "Hello"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_issues().await;
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .filter(|body| body["Subject"] == title)
+        .map(|body| body["To"].as_str().unwrap().to_string())
+        .collect()
+}
+
+fn bounce(id: i64, kind: &str, email: &str) -> Value {
+    json!({
+        "RecordType": "Bounce",
+        "ID": id,
+        "Type": kind,
+        "Email": email,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Description": "The server was unable to deliver your message.",
+        "BouncedAt": "2026-10-19T16:09:19Z",
+    })
+}
+
+#[actix_web::test]
+async fn unsigned_or_forged_events_are_rejected() {
+    let mut app = spawn_app().await;
+    let body = bounce(1, "HardBounce", "ursula@example.com");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/webhooks/email-events", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    app.webhook_secret = "not-the-shared-secret".into();
+    let response = app.post_email_events(&body).await;
+    assert_eq!(401, response.status().as_u16());
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(Some(0), count);
+}
+
+#[actix_web::test]
+async fn hard_bounces_and_complaints_suppress_the_address_immediately() {
+    let app = spawn_app().await;
+    for email in [
+        "bounced@example.com",
+        "annoyed@example.com",
+        "happy@example.com",
+    ] {
+        create_confirmed_subscriber(&app, email).await;
+    }
+
+    let response = app
+        .post_email_events(&json!([
+            bounce(1, "HardBounce", "bounced@example.com"),
+            {
+                "RecordType": "SpamComplaint",
+                "ID": 2,
+                "Email": "annoyed@example.com",
+                "BouncedAt": "2026-10-19T16:10:00Z",
+            },
+            {
+                "RecordType": "Delivery",
+                "Recipient": "happy@example.com",
+                "DeliveredAt": "2026-10-19T16:09:00Z",
+            },
+            {"RecordType": "SubscriptionChange", "Recipient": "happy@example.com"},
+        ]))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["accepted"], 3);
+    assert_eq!(report["ignored"], 1);
+    assert_eq!(report["suppressed"], 2);
+
+    let kinds: Vec<String> = sqlx::query_scalar!("SELECT kind FROM email_events ORDER BY kind")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(kinds, ["bounced-hard", "complaint", "delivered"]);
+
+    assert_eq!(publish(&app, "After bounces").await, ["happy@example.com"]);
+}
+
+#[actix_web::test]
+async fn soft_bounces_suppress_the_address_after_repeated_failures() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "full@example.com").await;
+
+    // 两次软退信之后成功投递，重新计数
+    for id in 1..=2 {
+        app.post_email_events(&bounce(id, "SoftBounce", "full@example.com"))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    app.post_email_events(&json!({
+        "RecordType": "Delivery",
+        "Recipient": "full@example.com",
+        "DeliveredAt": "2026-10-19T16:09:00Z",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    for id in 3..=4 {
+        app.post_email_events(&bounce(id, "Transient", "full@example.com"))
+            .await
+            .error_for_status()
+            .unwrap();
+    }
+    assert_eq!(publish(&app, "Still sending").await, ["full@example.com"]);
+
+    // 服务商重试时重复发送的事件不会被重复计数
+    let response = app
+        .post_email_events(&bounce(4, "Transient", "full@example.com"))
+        .await;
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["duplicates"], 1);
+    assert_eq!(publish(&app, "Still sending again").await.len(), 1);
+
+    // 连续第三次软退信之后屏蔽
+    let response = app
+        .post_email_events(&bounce(5, "SoftBounce", "full@example.com"))
+        .await;
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["suppressed"], 1);
+    let reason = sqlx::query_scalar!("SELECT reason FROM suppressions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(reason, "soft-bounce");
+    assert!(publish(&app, "Stopped").await.is_empty());
+}
+
+#[actix_web::test]
+async fn malformed_events_are_rejected() {
+    let app = spawn_app().await;
+    let response = app
+        .post_email_events(&json!({"Email": "x@example.com"}))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use base64::Engine;
+use serde_json::{json, Value};
+use zero2prod_lib::email_message::{
+    html_to_text, inline_css, sanitize_html, Attachment, EmailMessage, Mailbox,
+};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn last_email(app: &TestApp) -> Value {
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&request.body).unwrap()
+}
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": "ursula@example.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[test]
+fn scripts_event_handlers_and_dangerous_links_are_removed() {
+    let test_cases = [
+        (
+            r#"<p onclick="steal()">Hi<script>alert("<p>")</script></p>"#,
+            "<p>Hi</p>",
+        ),
+        (
+            r#"<a href="javascript:alert(1)" title="x">link</a>"#,
+            r#"<a title="x">link</a>"#,
+        ),
+        (
+            r#"<a href="java&#x09;script:alert(1)">link</a>"#,
+            "<a>link</a>",
+        ),
+        (
+            r#"<a href='https://example.com/?a=1&amp;b=2'>ok</a>"#,
+            r#"<a href="https://example.com/?a=1&amp;b=2">ok</a>"#,
+        ),
+        (
+            r#"<div><iframe src="https://evil.example"><p>x</p></iframe><form>kept</form></div>"#,
+            "<div>kept</div>",
+        ),
+        (
+            r#"<span style="width: expression(alert(1))">a</span><!-- note --><br />"#,
+            "<span>a</span><br />",
+        ),
+        ("1 < 2 &amp; 3 > 2", "1 &lt; 2 &amp; 3 > 2"),
+    ];
+
+    for (html, expected) in test_cases {
+        assert_eq!(sanitize_html(html), expected, "{}", html);
+    }
+}
+
+#[test]
+fn links_hidden_behind_character_references_are_removed() {
+    // 浏览器会解码没有分号或者很长的数字引用，以及所有命名引用
+    let payloads = [
+        r#"<a href="javascript&#58alert(1)">link</a>"#,
+        r#"<a href="javascript&#x000000003a;alert(1)">link</a>"#,
+        r#"<a href="javascript&colon;alert(1)">link</a>"#,
+        r#"<a href="&#0000106avascript:alert(1)">link</a>"#,
+        r#"<a href="java&Tab;script&colon;alert(1)">link</a>"#,
+        r#"<img src="data&colon;text/html,x">"#,
+    ];
+    for html in payloads {
+        let sanitized = sanitize_html(html);
+        assert!(!sanitized.contains("href"), "{}", sanitized);
+        assert!(!sanitized.contains("src"), "{}", sanitized);
+    }
+
+    // 属性中没有分号的命名引用后面是=时不解码，这样的查询参数保持原样
+    let html = r#"<a href="https://example.com/?a=1&copy=2&amp;lang=en">ok</a>"#;
+    assert_eq!(sanitize_html(html), html);
+    assert_eq!(
+        html_to_text(html),
+        "ok (https://example.com/?a=1&copy=2&lang=en)"
+    );
+}
+
+#[test]
+fn styles_are_inlined_by_specificity_and_inline_styles_win() {
+    let html = r#"<html><head><style>
+        /* 注释 */
+        p { color: red; margin: 0 }
+        p.note, #lead { color: blue }
+        @media (max-width: 600px) { p { color: green } }
+        a:hover { color: black }
+    </style></head><body><p>One</p><p class="note" style="color: gray">Two</p><p id="lead">Three</p><a href="/">x</a></body></html>"#;
+
+    let inlined = sanitize_html(&inline_css(html));
+
+    assert!(!inlined.contains("<style"), "{}", inlined);
+    assert!(
+        inlined.contains(r#"<p style="color: red; margin: 0">One</p>"#),
+        "{}",
+        inlined
+    );
+    assert!(
+        inlined.contains(
+            r#"<p class="note" style="color: red; margin: 0; color: blue; color: gray">Two</p>"#
+        ),
+        "{}",
+        inlined
+    );
+    assert!(
+        inlined.contains(r#"<p id="lead" style="color: red; margin: 0; color: blue">Three</p>"#),
+        "{}",
+        inlined
+    );
+    assert!(inlined.contains(r#"<a href="/">x</a>"#), "{}", inlined);
+    // 没有样式表时原样返回
+    assert_eq!(inline_css("<p class='x'>a</p>"), "<p class='x'>a</p>");
+}
+
+#[test]
+fn the_plain_text_version_is_generated_from_html() {
+    let html = r#"<html><head><title>Ignored</title></head><body>
+        <h1>Issue&nbsp;#1</h1>
+        <p>Hello   <b>world</b>,<br>read <a href="https://example.com/post">the post</a>.</p>
+        <ul><li>First</li><li>Second</li></ul>
+        <ol><li>One</li><li>Two</li></ol>
+        <p><img src="cid:logo" alt="Logo"> &copy; 2026 <a href="https://example.com">https://example.com</a></p>
+    </body></html>"#;
+
+    assert_eq!(
+        html_to_text(html),
+        "Issue #1\n\n\
+         Hello world,\n\
+         read the post (https://example.com/post).\n\n\
+         - First\n\
+         - Second\n\n\
+         1. One\n\
+         2. Two\n\n\
+         Logo © 2026 https://example.com"
+    );
+}
+
+#[actix_web::test]
+async fn newsletters_without_plain_text_get_a_generated_one_before_the_footer() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    app.post_newsletters(json!({
+        "title": "Issue",
+        "content": {
+            "html": r#"<style>p { color: red }</style><p onclick="x()">Hello <a href="https://example.com">there</a></p><script>alert(1)</script>"#,
+        },
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_issues().await;
+
+    let email = last_email(&app).await;
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(
+        html.starts_with(
+            r#"<p style="color: red">Hello <a href="https://example.com">there</a></p><p style="color: red"><a href=""#
+        ),
+        "{}",
+        html
+    );
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(
+        text.starts_with("Hello there (https://example.com)\n\nUnsubscribe: "),
+        "{}",
+        text
+    );
+}
+
+#[actix_web::test]
+async fn templates_can_be_previewed_as_a_mime_message() {
+    let app = spawn_app().await;
+
+    let response = app
+        .get_admin(
+            "/templates/confirmation/preview",
+            &[("locale", "zh-CN"), ("format", "eml")],
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "message/rfc822"
+    );
+    let mime = response.text().await.unwrap();
+    let (headers, body) = mime.split_once("\r\n\r\n").unwrap();
+    // 展开折行的头
+    let headers = headers.replace("\r\n ", " ");
+    let header = |name: &str| {
+        headers
+            .split("\r\n")
+            .find_map(|line| line.strip_prefix(&format!("{}: ", name)))
+            .unwrap()
+    };
+    assert_eq!(header("MIME-Version"), "1.0");
+    // 非ASCII的标题使用RFC 2047编码
+    let subject = header("Subject")
+        .strip_prefix("=?UTF-8?B?")
+        .and_then(|s| s.strip_suffix("?="))
+        .unwrap();
+    let subject = base64::engine::general_purpose::STANDARD
+        .decode(subject)
+        .unwrap();
+    assert_eq!(String::from_utf8(subject).unwrap(), "欢迎订阅！");
+    let boundary = header("Content-Type")
+        .strip_prefix("multipart/alternative; boundary=\"")
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap();
+    // 纯文本部分在前，HTML部分在后
+    let parts: Vec<&str> = body.split(&format!("--{}", boundary)).collect();
+    assert_eq!(parts.len(), 4, "{}", body);
+    assert!(parts[1].starts_with(
+        "\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Transfer-Encoding: quoted-printable\r\n\r\n"
+    ));
+    assert!(parts[2].starts_with("\r\nContent-Type: text/html; charset=utf-8\r\n"));
+    assert_eq!(parts[3], "--\r\n");
+    assert!(mime.split("\r\n").all(|line| line.len() <= 76), "{}", mime);
+}
+
+#[test]
+fn attachments_are_nested_in_related_and_mixed_parts() {
+    let attachment = |filename: &str, content_id: Option<&str>| Attachment {
+        filename: filename.into(),
+        content_type: "image/png".into(),
+        content: Arc::from(vec![0u8; 100]),
+        content_id: content_id.map(Into::into),
+    };
+    let message = EmailMessage::builder(
+        Mailbox::with_name("Zero2Prod", "news@example.com"),
+        Mailbox::with_name("勒古恩", "ursula@example.com"),
+    )
+    .subject("Issue")
+    .html(r#"<img src="cid:logo">"#)
+    .attachments(vec![
+        attachment("logo.png", Some("logo")),
+        attachment("图表.png", None),
+    ])
+    .build();
+
+    let mime = message.to_mime();
+
+    assert!(mime.starts_with("From: Zero2Prod <news@example.com>\r\nTo: =?UTF-8?B?"));
+    let content_types: Vec<&str> = mime
+        .split("\r\n")
+        .filter_map(|line| line.strip_prefix("Content-Type: "))
+        .collect();
+    assert_eq!(
+        content_types,
+        [
+            "multipart/mixed;",
+            "multipart/related;",
+            "multipart/alternative;",
+            "text/plain; charset=utf-8",
+            "text/html; charset=utf-8",
+            "image/png;",
+            "image/png;",
+        ]
+    );
+    assert!(mime.contains(
+        "Content-ID: <logo>\r\nContent-Disposition: inline;\r\n filename=\"logo.png\"\r\n"
+    ));
+    // 非ASCII的文件名使用RFC 2231编码，而不是放在引号中的encoded-word
+    assert!(mime.contains("Content-Type: image/png;\r\n name*=UTF-8''%E5%9B%BE%E8%A1%A8.png\r\n"));
+    assert!(mime.contains(
+        "Content-Disposition: attachment;\r\n filename*=UTF-8''%E5%9B%BE%E8%A1%A8.png\r\n"
+    ));
+    assert!(!mime.contains("\"=?UTF-8?"));
+    assert!(mime.split("\r\n").all(|line| line.len() <= 76), "{}", mime);
+}
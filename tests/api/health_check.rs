@@ -0,0 +1,21 @@
+use crate::helpers::spawn_app;
+
+#[actix_web::test] // 是actix_web::main的测试等价物，可以使用`cargo expand --test api`（<- 测试二进制名）来看宏生成了哪些代码
+async fn health_check_works() {
+    // 准备，即在后台启动应用
+    let test_app = spawn_app().await;
+    // 使用 reqwest::Client对应用程序执行HTTP请求
+    let client = reqwest::Client::new();
+
+    // 执行
+    let response = client
+        .get(format!("{}/health_check", test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    // 检查状态码为200
+    assert!(response.status().is_success());
+    // 检查无响应体
+    assert_eq!(Some(0), response.content_length());
+}
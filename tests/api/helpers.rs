@@ -0,0 +1,354 @@
+use std::net::TcpListener;
+
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use secrecy::ExposeSecret;
+use sha2::Sha256;
+use sqlx::{Connection, Executor, PgConnection, PgPool};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+// 注：集成测试要求main函数以库的形式向外暴露
+use zero2prod_lib::{
+    attachments::Attachments,
+    bot_protection::BotProtection,
+    configuration,
+    email_client::EmailClient,
+    email_events::EmailEvents,
+    i18n::I18n,
+    issue_delivery::{ExecutionOutcome, IssueDeliveryWorker},
+    rate_limit::{RateLimitCleanup, RateLimiter},
+    startup::run,
+    telemetry::{get_subscriber, init_subscriber},
+    tracking::Tracking,
+};
+
+// 使用once_cell确保tracing最多只被初始化一次
+static TRACING: Lazy<()> = Lazy::new(|| {
+    let default_filter_level = "info".to_string();
+    let subscriber_name = "test".to_string();
+
+    if std::env::var("TEST_LOG").is_ok() {
+        // 如果环境变量TEST_LOG被设置
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        init_subscriber(subscriber);
+    } else {
+        // 如果环境变量TEST_LOG被未设置
+        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        init_subscriber(subscriber);
+    }
+});
+
+pub struct TestApp {
+    // 测试应用实例的地址
+    pub address: String,
+    // 测试应用使用的db连接池
+    pub db_pool: PgPool,
+    // 模拟邮件服务商的API
+    pub email_server: MockServer,
+    // 用于调用受保护接口的测试用户
+    pub test_user: TestUser,
+    // 定时发送的后台任务，测试中通过dispatch_all_pending_issues手动驱动
+    pub delivery_worker: IssueDeliveryWorker,
+    // 清理令牌桶的后台任务，测试中通过run_once手动驱动
+    pub rate_limit_cleanup: RateLimitCleanup,
+    // 签名邮件服务商webhook请求的共享密钥
+    pub webhook_secret: String,
+}
+
+pub struct TestUser {
+    pub user_id: Uuid,
+    pub username: String,
+    pub password: String,
+}
+
+impl TestUser {
+    pub fn generate() -> Self {
+        Self {
+            user_id: Uuid::new_v4(),
+            username: Uuid::new_v4().to_string(),
+            password: Uuid::new_v4().to_string(),
+        }
+    }
+
+    async fn store(&self, pool: &PgPool) {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        // 测试中使用最低的参数，让哈希校验足够快（生产环境的参数保存在哈希值中，校验时会被读取）
+        let password_hash = Argon2::new(
+            Algorithm::Argon2id,
+            Version::V0x13,
+            Params::new(Params::MIN_M_COST, 1, 1, None).unwrap(),
+        )
+        .hash_password(self.password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+        sqlx::query!(
+            "INSERT INTO users (user_id, username, password_hash) VALUES ($1, $2, $3)",
+            self.user_id,
+            self.username,
+            password_hash,
+        )
+        .execute(pool)
+        .await
+        .expect("Failed to store test user.");
+    }
+}
+
+// 确认邮件中的链接（HTML和纯文本各一个）
+pub struct ConfirmationLinks {
+    pub html: reqwest::Url,
+    pub plain_text: reqwest::Url,
+}
+
+impl TestApp {
+    pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/newsletters", &self.address))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // 以测试用户的身份调用管理后台的GET接口
+    pub async fn get_admin(&self, path: &str, query: &[(&str, &str)]) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/admin{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .query(query)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn post_admin(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/admin{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn patch_admin(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .patch(format!("{}/admin{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn put_admin(&self, path: &str, body: &serde_json::Value) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!("{}/admin{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    pub async fn delete_admin(&self, path: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .delete(format!("{}/admin{}", &self.address, path))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // 像邮件服务商一样对请求体签名，然后发送到webhook
+    pub async fn post_email_events(&self, body: &serde_json::Value) -> reqwest::Response {
+        let body = serde_json::to_vec(body).unwrap();
+        let timestamp = chrono::Utc::now().timestamp().to_string();
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.webhook_secret.as_bytes()).unwrap();
+        mac.update(format!("{}.", timestamp).as_bytes());
+        mac.update(&body);
+        reqwest::Client::new()
+            .post(format!("{}/webhooks/email-events", &self.address))
+            .header("Content-Type", "application/json")
+            .header("X-Webhook-Timestamp", &timestamp)
+            .header(
+                "X-Webhook-Signature",
+                format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
+    // 执行定时发送的后台任务，直到没有到期的一期和待发送的邮件
+    pub async fn dispatch_all_pending_issues(&self) {
+        loop {
+            if let ExecutionOutcome::EmptyQueue = self.delivery_worker.run_once().await.unwrap() {
+                break;
+            }
+        }
+    }
+
+    // 从发给邮件服务商的请求中提取确认链接
+    pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let get_link = |s: &str| {
+            let links: Vec<_> = linkify::LinkFinder::new()
+                .links(s)
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .collect();
+            assert_eq!(links.len(), 1);
+            let confirmation_link = reqwest::Url::parse(links[0].as_str()).unwrap();
+            // 确保不会调用到网络上真实的地址
+            assert_eq!(confirmation_link.host_str().unwrap(), "127.0.0.1");
+            confirmation_link
+        };
+        ConfirmationLinks {
+            html: get_link(body["HtmlBody"].as_str().unwrap()),
+            plain_text: get_link(body["TextBody"].as_str().unwrap()),
+        }
+    }
+}
+
+// 定义在后台某处启动应用程序
+pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// 与spawn_app相同，但允许在启动应用之前修改配置
+pub async fn spawn_app_with(configure: impl FnOnce(&mut configuration::Settings)) -> TestApp {
+    // let subscriber = get_subscriber("test".into(), "debug".into());
+    // init_subscriber(subscriber);
+
+    // 只在第一次调用TRACING时运行里面的逻辑，其他时候都会直接跳过该步骤
+    Lazy::force(&TRACING);
+
+    // 启动一个模拟服务器来代替邮件服务商的API
+    // 默认接受所有邮件（优先级最低），需要对邮件做断言的测试可以挂载自己的Mock
+    let email_server = MockServer::start().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .with_priority(u8::MAX)
+        .mount(&email_server)
+        .await;
+
+    // 尝试绑定端口0将触发操作系统扫描可用端口，即选择一个随机的可用的端口
+    let listener = TcpListener::bind("127.0.0.1:0").expect("Failed to bind random port");
+    // 得到绑定的随机端口号
+    let port = listener.local_addr().unwrap().port();
+    let address = format!("http://127.0.0.1:{port}");
+
+    let mut configuration =
+        configuration::get_configuration().expect("Failed to read configuration");
+    // 用于测试的数据库名称随机化
+    configuration.database.database_name = Uuid::new_v4().to_string();
+    // 使用模拟服务器作为邮件服务商的API
+    configuration.email_client.base_url = email_server.uri();
+    // 邮件中的链接指向测试应用
+    configuration.application.base_url = address.clone();
+    // 每个测试使用自己的blob目录
+    configuration.attachments.store.directory = std::env::temp_dir()
+        .join(format!("zero2prod-blobs-{}", Uuid::new_v4()))
+        .to_string_lossy()
+        .into_owned();
+    configure(&mut configuration);
+    let connection_pool = configure_database(&configuration.database).await;
+
+    let email_client = EmailClient::from_settings(&configuration.email_client)
+        .expect("Invalid email client settings");
+
+    let delivery_worker = IssueDeliveryWorker::new(
+        connection_pool.clone(),
+        EmailClient::from_settings(&configuration.email_client)
+            .expect("Invalid email client settings"),
+        I18n::new(&configuration.application.default_locale).expect("Invalid default locale"),
+        Attachments::new(&configuration.attachments).expect("Invalid attachment settings"),
+        configuration.application.base_url.clone(),
+        Tracking::new(&configuration.tracking),
+        &configuration.delivery,
+    );
+    let rate_limiter = RateLimiter::new(&configuration.rate_limit, connection_pool.clone());
+    let rate_limit_cleanup = rate_limiter.cleanup();
+    let bot_protection = BotProtection::new(&configuration.bot_protection);
+
+    let server = run(
+        listener,
+        connection_pool.clone(),
+        email_client,
+        rate_limiter,
+        bot_protection,
+        configuration.application.base_url,
+        I18n::new(&configuration.application.default_locale).expect("Invalid default locale"),
+        Attachments::new(&configuration.attachments).expect("Invalid attachment settings"),
+        EmailEvents::new(&configuration.email_events),
+        Tracking::new(&configuration.tracking),
+    )
+    .expect("Failed to bind address");
+    // 启动服务器作为后台任务
+    actix_web::rt::spawn(server);
+    let test_app = TestApp {
+        address,
+        db_pool: connection_pool,
+        email_server,
+        test_user: TestUser::generate(),
+        delivery_worker,
+        rate_limit_cleanup,
+        webhook_secret: configuration
+            .email_events
+            .webhook_secret
+            .expose_secret()
+            .clone(),
+    };
+    test_app.test_user.store(&test_app.db_pool).await;
+    test_app
+}
+
+// 为每次测试都提供一个全信的数据库环境
+pub async fn configure_database(config: &configuration::DatabaseSettings) -> PgPool {
+    // 创建数据库
+    let mut connection =
+        PgConnection::connect(config.conncection_string_without_db().expose_secret())
+            .await
+            .expect("Failed to connect to Postgres");
+    connection
+        .execute(format!(r#"CREATE DATABASE "{}";"#, config.database_name).as_str())
+        .await
+        .expect("Failed to create database");
+
+    // 迁移数据库
+    let connection_pool = PgPool::connect(config.connection_string().expose_secret())
+        .await
+        .expect("Failed to migrate the database");
+
+    // sqlx::migrate!和sqlx-cli执行sqlx migrate run时使用的是同一个宏
+    sqlx::migrate!("./migrations")
+        .run(&connection_pool)
+        .await
+        .expect("Failed to migrate the database");
+
+    connection_pool
+}
@@ -0,0 +1,275 @@
+use std::collections::BTreeSet;
+
+use serde_json::{json, Value};
+use zero2prod_lib::i18n::{self, Catalogue};
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+async fn last_email(app: &TestApp) -> Value {
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&request.body).unwrap()
+}
+
+async fn subscribe(app: &TestApp, body: Value, accept_language: Option<&str>) {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &app.address))
+        .json(&body);
+    if let Some(accept_language) = accept_language {
+        request = request.header("Accept-Language", accept_language);
+    }
+    request.send().await.unwrap().error_for_status().unwrap();
+}
+
+// 消息中的占位符，如{list}
+fn placeholders(message: &str) -> BTreeSet<&str> {
+    message
+        .split('{')
+        .skip(1)
+        .filter_map(|rest| rest.split_once('}').map(|(name, _)| name))
+        .collect()
+}
+
+#[test]
+fn every_catalogue_has_every_key() {
+    let catalogues = i18n::catalogues();
+    assert!(catalogues.len() > 1);
+    let all_keys: BTreeSet<&str> = catalogues.iter().flat_map(Catalogue::keys).collect();
+
+    for catalogue in catalogues {
+        let keys: BTreeSet<&str> = catalogue.keys().collect();
+        let missing: Vec<_> = all_keys.difference(&keys).collect();
+        assert!(
+            missing.is_empty(),
+            "{} is missing {:?}",
+            catalogue.locale(),
+            missing
+        );
+        // 每种语言的翻译使用相同的占位符
+        for key in &all_keys {
+            let english = i18n::find("en").unwrap().get(key).unwrap();
+            let message = catalogue.get(key).unwrap();
+            assert!(
+                !message.trim().is_empty(),
+                "{}: {}",
+                catalogue.locale(),
+                key
+            );
+            assert_eq!(
+                placeholders(message),
+                placeholders(english),
+                "{}: {}",
+                catalogue.locale(),
+                key
+            );
+        }
+    }
+}
+
+#[actix_web::test]
+async fn the_confirmation_email_uses_the_locale_from_the_form_or_accept_language() {
+    let app = spawn_app().await;
+    // (请求体, Accept-Language, 保存的语言, 邮件标题)
+    let test_cases = [
+        (
+            json!({"name": "le guin", "email": "ursula@example.com", "locale": "zh_cn"}),
+            Some("en"),
+            Some("zh-CN"),
+            "欢迎订阅！",
+        ),
+        (
+            json!({"name": "le guin", "email": "ursula2@example.com"}),
+            Some("de, zh-TW;q=0.9, en;q=0.8"),
+            Some("zh-CN"),
+            "欢迎订阅！",
+        ),
+        (
+            json!({"name": "le guin", "email": "ursula3@example.com"}),
+            Some("de"),
+            None,
+            "Welcome!",
+        ),
+        (
+            json!({"name": "le guin", "email": "ursula4@example.com", "locale": "de"}),
+            None,
+            Some("de"),
+            "Welcome!",
+        ),
+    ];
+
+    for (body, accept_language, stored, subject) in test_cases {
+        subscribe(&app, body.clone(), accept_language).await;
+
+        let email = last_email(&app).await;
+        assert_eq!(email["Subject"], subject, "{}", body);
+        let saved = sqlx::query_scalar!(
+            "SELECT attributes->>'locale' FROM subscriptions WHERE email = $1",
+            body["email"].as_str().unwrap(),
+        )
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+        assert_eq!(saved.as_deref(), stored, "{}", body);
+    }
+    let email = last_email(&app).await;
+    assert!(email["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Welcome to Newsletter!"));
+}
+
+#[actix_web::test]
+async fn subscribers_without_a_locale_get_the_default_locale_from_the_settings() {
+    let app = spawn_app_with(|c| c.application.default_locale = "zh-CN".into()).await;
+
+    subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com"}),
+        None,
+    )
+    .await;
+
+    let email = last_email(&app).await;
+    assert_eq!(email["Subject"], "欢迎订阅！");
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(
+        text.starts_with("欢迎订阅Newsletter！\n请访问 "),
+        "{}",
+        text
+    );
+    // 链接仍然有效
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&request);
+    let response = reqwest::Client::new()
+        .get(links.plain_text)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("您的订阅已确认"));
+}
+
+#[actix_web::test]
+async fn pages_and_errors_follow_accept_language() {
+    let app = spawn_app().await;
+    subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com"}),
+        None,
+    )
+    .await;
+    let token = sqlx::query_scalar!("SELECT unsubscribe_token FROM list_memberships")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    let client = reqwest::Client::new();
+    let unsubscribe_page = |token: &str, accept_language: &str| {
+        client
+            .get(format!("{}/subscriptions/unsubscribe", &app.address))
+            .query(&[("token", token)])
+            .header("Accept-Language", accept_language)
+            .send()
+    };
+
+    let response = unsubscribe_page(&token, "zh-CN,zh;q=0.9").await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<html lang="zh-CN">"#), "{}", html);
+    assert!(html.contains("不再接收Newsletter？"), "{}", html);
+
+    let response = unsubscribe_page(&token, "fr").await.unwrap();
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Stop receiving Newsletter?"), "{}", html);
+
+    let response = unsubscribe_page("unknown", "zh").await.unwrap();
+    assert_eq!(401, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/html; charset=utf-8"
+    );
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<html lang="zh-CN">"#), "{}", html);
+    assert!(html.contains("链接无效或已过期。"), "{}", html);
+}
+
+#[actix_web::test]
+async fn newsletters_are_sent_with_the_footer_in_the_subscribers_locale() {
+    let app = spawn_app().await;
+    subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com", "locale": "zh-CN"}),
+        None,
+    )
+    .await;
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    app.post_newsletters(json!({
+        "title": "第一期",
+        "content": {"text": "正文", "html": "<p>正文</p>"},
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_issues().await;
+
+    let email = last_email(&app).await;
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(html.contains(">退订</a> | <a"), "{}", html);
+    assert!(html.contains(">管理订阅偏好</a>"), "{}", html);
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(text.contains("\n\n退订: "), "{}", text);
+}
+
+#[actix_web::test]
+async fn templates_can_be_previewed_in_each_locale() {
+    let app = spawn_app().await;
+
+    for (locale, subject, html) in [
+        ("zh-CN", "欢迎订阅！", "欢迎订阅Newsletter！<br />"),
+        ("en", "Welcome!", "Welcome to Newsletter!<br />"),
+    ] {
+        let response = app
+            .get_admin("/templates/confirmation/preview", &[("locale", locale)])
+            .await;
+
+        assert_eq!(200, response.status().as_u16());
+        let email: Value = response.json().await.unwrap();
+        assert_eq!(email["subject"], subject);
+        assert!(email["html"].as_str().unwrap().starts_with(html));
+    }
+
+    // 未知的翻译在保存时被拒绝
+    let response = app
+        .put_admin(
+            "/templates/welcome",
+            &json!({"html": r#"{{t "welcome.missing"}}"#, "text": "Hi"}),
+        )
+        .await;
+    assert_eq!(400, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["fields"]["html"]
+        .to_string()
+        .contains("welcome.missing"));
+}
@@ -0,0 +1,292 @@
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": "ursula@example.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn draft(title: &str, text: &str) -> Value {
+    json!({
+        "title": title,
+        "content": {
+            "html": format!("<p>{}</p>", text.replace('\n', "</p>\n<p>")),
+            "text": text,
+        },
+    })
+}
+
+async fn create_draft(app: &TestApp, body: &Value) -> String {
+    let response = app.post_admin("/issues", body).await;
+    assert_eq!(201, response.status().as_u16());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["latest_revision"], 1);
+    issue["id"].as_str().unwrap().to_string()
+}
+
+async fn save(app: &TestApp, id: &str, body: &Value) -> reqwest::Response {
+    app.put_admin(&format!("/issues/{}", id), body).await
+}
+
+// 发给邮件服务商的请求（不含确认邮件）
+async fn sent_emails(app: &TestApp, subject_prefix: &str) -> Vec<Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .filter(|body| {
+            body["Subject"]
+                .as_str()
+                .is_some_and(|s| s.starts_with(subject_prefix))
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn every_save_creates_a_new_revision_unless_nothing_changed() {
+    let app = spawn_app().await;
+    let id = create_draft(&app, &draft("Weekly", "first line\nsecond line")).await;
+
+    let response = save(&app, &id, &draft("Weekly", "first line\nsecond line")).await;
+    assert_eq!(200, response.status().as_u16());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["latest_revision"], 1);
+
+    let response = save(&app, &id, &draft("Weekly #2", "first line\nchanged line")).await;
+    assert_eq!(200, response.status().as_u16());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["latest_revision"], 2);
+    assert_eq!(issue["title"], "Weekly #2");
+
+    let revisions: Vec<Value> = app
+        .get_admin(&format!("/issues/{}/revisions", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(2, revisions.len());
+    assert_eq!(revisions[0]["revision"], 2);
+    assert_eq!(revisions[0]["title"], "Weekly #2");
+    assert_eq!(revisions[0]["created_by"], app.test_user.username.as_str());
+
+    // 旧版本保持不变
+    let first: Value = app
+        .get_admin(&format!("/issues/{}/revisions/1", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(first["request"]["title"], "Weekly");
+    let response = app
+        .get_admin(&format!("/issues/{}/revisions/3", id), &[])
+        .await;
+    assert_eq!(404, response.status().as_u16());
+
+    // 无效的内容不会生成版本
+    let response = save(&app, &id, &json!({"title": "No content"})).await;
+    assert_eq!(400, response.status().as_u16());
+    let response = save(&app, &Uuid::new_v4().to_string(), &draft("x", "y")).await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn revisions_can_be_compared_line_by_line() {
+    let app = spawn_app().await;
+    let id = create_draft(&app, &draft("Weekly", "one\ntwo\nthree\nfour")).await;
+    save(&app, &id, &draft("Weekly", "one\nTWO\nthree\nfour\nfive"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // 默认比较最新版本和它的上一个版本
+    let diff: Value = app
+        .get_admin(&format!("/issues/{}/diff", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(diff["from"], 1);
+    assert_eq!(diff["to"], 2);
+    let changes = diff["changes"].as_array().unwrap();
+    let fields: Vec<&str> = changes
+        .iter()
+        .map(|c| c["field"].as_str().unwrap())
+        .collect();
+    assert_eq!(fields, ["content.html", "content.text"]);
+    assert_eq!(
+        changes[1]["diff"],
+        "@@ -1,4 +1,5 @@\n one\n-two\n+TWO\n three\n four\n+five\n"
+    );
+
+    // 反向比较
+    let diff: Value = app
+        .get_admin(
+            &format!("/issues/{}/diff", id),
+            &[("from", "2"), ("to", "1")],
+        )
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(diff["changes"][1]["diff"]
+        .as_str()
+        .unwrap()
+        .contains("-five\n"));
+
+    let response = app
+        .get_admin(&format!("/issues/{}/diff", id), &[("from", "7")])
+        .await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn scheduling_freezes_a_revision_so_later_edits_are_not_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = create_draft(&app, &draft("Frozen v1", "the original")).await;
+    save(&app, &id, &draft("Frozen v2", "the second draft"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // 选定第一个版本
+    let scheduled_at = (Utc::now() + Duration::hours(1)).to_rfc3339();
+    let response = app
+        .put_admin(
+            &format!("/issues/{}/schedule", id),
+            &json!({"scheduled_at": scheduled_at, "revision": 1}),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["published_revision"], 1);
+
+    // 安排之后的编辑生成新的版本，但不改变发送的版本
+    save(&app, &id, &draft("Frozen v3", "edited after scheduling"))
+        .await
+        .error_for_status()
+        .unwrap();
+    let response = app
+        .put_admin(
+            &format!("/issues/{}/schedule", id),
+            &json!({"scheduled_at": scheduled_at, "revision": 9}),
+        )
+        .await;
+    assert_eq!(400, response.status().as_u16());
+
+    // 立即发布也冻结一个版本
+    let response = app
+        .post_admin(&format!("/issues/{}/publish", id), &json!({"revision": 1}))
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_issues().await;
+
+    let sent = sent_emails(&app, "Frozen").await;
+    assert_eq!(1, sent.len());
+    assert_eq!(sent[0]["Subject"], "Frozen v1");
+    assert!(sent[0]["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("the original"));
+
+    // 发送之后不能再编辑
+    let response = save(&app, &id, &draft("Frozen v4", "too late")).await;
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn test_emails_are_sent_only_to_the_admins_own_address() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let id = create_draft(&app, &draft("Test send", "check me")).await;
+
+    // 没有设置邮箱时无法发送测试邮件
+    let response = app
+        .post_admin(&format!("/issues/{}/test", id), &json!({}))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+
+    let response = app
+        .patch_admin("/account", &json!({"email": "not an email"}))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+    let response = app
+        .patch_admin("/account", &json!({"email": "editor@example.com"}))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let account: Value = app.get_admin("/account", &[]).await.json().await.unwrap();
+    assert_eq!(account["email"], "editor@example.com");
+
+    let response = app
+        .post_admin(&format!("/issues/{}/test", id), &json!({}))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["sent_to"], "editor@example.com");
+
+    let sent = sent_emails(&app, "Test send").await;
+    assert_eq!(1, sent.len());
+    assert_eq!(sent[0]["To"], "editor@example.com");
+    // 测试邮件不改变这一期的状态
+    let issue: Value = app
+        .get_admin(&format!("/issues/{}", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "draft");
+}
+
+#[actix_web::test]
+async fn revisions_can_be_previewed_with_a_sample_subscriber() {
+    let app = spawn_app().await;
+    let id = create_draft(&app, &draft("Preview v1", "first")).await;
+    save(&app, &id, &draft("Preview v2", "second"))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let preview: Value = app
+        .get_admin(&format!("/issues/{}/preview", id), &[("revision", "1")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(preview["subject"], "Preview v1");
+    assert!(preview["text"].as_str().unwrap().contains("first"));
+    assert!(preview["text"].as_str().unwrap().contains("token=sample"));
+
+    let response = app
+        .get_admin(&format!("/issues/{}/preview", id), &[("format", "eml")])
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "message/rfc822"
+    );
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Subject: Preview v2"));
+    assert!(sent_emails(&app, "Preview").await.is_empty());
+}
@@ -0,0 +1,331 @@
+use serde_json::{json, Value};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) {
+    let response = app
+        .post_admin("/lists", &json!({"slug": slug, "name": name}))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+}
+
+// 通过订阅表单加入列表并点击确认链接
+async fn subscribe_and_confirm(app: &TestApp, email: &str, list: Option<&str>) {
+    let mut body = json!({"name": "le guin", "email": email});
+    if let Some(list) = list {
+        body["list"] = list.into();
+    }
+    app.post_subscriptions_json(&body)
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn memberships(app: &TestApp, email: &str) -> Vec<(String, String)> {
+    sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.id = m.list_id
+        JOIN subscriptions s ON s.id = m.subscriber_id
+        WHERE s.email = $1
+        ORDER BY l.slug
+        "#,
+        email,
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect()
+}
+
+// 新闻邮件请求中所有链接，每封邮件的HTML部分和纯文本部分
+fn links(email_request: &wiremock::Request) -> Vec<String> {
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    ["HtmlBody", "TextBody"]
+        .iter()
+        .flat_map(|part| {
+            linkify::LinkFinder::new()
+                .links(body[part].as_str().unwrap())
+                .filter(|l| *l.kind() == linkify::LinkKind::Url)
+                .map(|l| l.as_str().to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[actix_web::test]
+async fn lists_can_be_created_and_listed() {
+    let app = spawn_app().await;
+
+    create_list(&app, "product-updates", "Product updates").await;
+
+    let response = app.get_admin("/lists", &[]).await;
+    assert_eq!(200, response.status().as_u16());
+    let lists: Value = response.json().await.unwrap();
+    let slugs: Vec<&str> = lists
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| l["slug"].as_str().unwrap())
+        .collect();
+    assert_eq!(slugs, ["newsletter", "product-updates"]);
+    assert_eq!(lists[1]["name"], "Product updates");
+}
+
+#[actix_web::test]
+async fn invalid_or_duplicate_lists_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_admin("/lists", &json!({"slug": "Not A Slug", "name": ""}))
+        .await;
+    assert_eq!(400, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["fields"]["slug"].is_array());
+    assert!(body["fields"]["name"].is_array());
+
+    let response = app
+        .post_admin("/lists", &json!({"slug": "newsletter", "name": "Again"}))
+        .await;
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn managing_lists_requires_authentication() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/lists", &app.address))
+        .json(&json!({"slug": "sneaky", "name": "Sneaky"}))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn subscribing_to_an_unknown_list_returns_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_subscriptions_json(&json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "list": "nope",
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert!(body["fields"]["list"].is_array());
+}
+
+#[actix_web::test]
+async fn an_existing_subscriber_can_join_and_confirm_a_second_list() {
+    let app = spawn_app().await;
+    create_list(&app, "product-updates", "Product updates").await;
+    subscribe_and_confirm(&app, "ursula@example.com", None).await;
+
+    app.post_subscriptions_json(&json!({
+        "name": "le guin",
+        "email": "ursula@example.com",
+        "list": "product-updates",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    // 第二个列表的确认邮件只确认该列表
+    assert_eq!(
+        memberships(&app, "ursula@example.com").await,
+        [
+            ("newsletter".to_string(), "confirmed".to_string()),
+            (
+                "product-updates".to_string(),
+                "pending_confirmation".to_string()
+            ),
+        ]
+    );
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("Product updates"));
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let count: i64 = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriptions"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, 1);
+    assert_eq!(
+        memberships(&app, "ursula@example.com").await,
+        [
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("product-updates".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[actix_web::test]
+async fn newsletters_go_to_each_confirmed_subscriber_of_the_target_lists_once() {
+    let app = spawn_app().await;
+    create_list(&app, "product-updates", "Product updates").await;
+    create_list(&app, "events", "Events").await;
+    subscribe_and_confirm(&app, "ursula@example.com", None).await;
+    subscribe_and_confirm(&app, "ursula@example.com", Some("product-updates")).await;
+    subscribe_and_confirm(&app, "octavia@example.com", Some("events")).await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Newsletter title",
+            "lists": ["newsletter", "product-updates"],
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    app.dispatch_all_pending_issues().await;
+
+    assert_eq!(202, response.status().as_u16());
+    let requests = app.email_server.received_requests().await.unwrap();
+    let newsletter = &requests[sent_before];
+    let body: Value = serde_json::from_slice(&newsletter.body).unwrap();
+    assert_eq!(body["To"], "ursula@example.com");
+    let unsubscribe_links: Vec<_> = links(newsletter)
+        .into_iter()
+        .filter(|l| l.contains("/subscriptions/unsubscribe?token="))
+        .collect();
+    assert_eq!(unsubscribe_links.len(), 2);
+}
+
+#[actix_web::test]
+async fn newsletters_to_unknown_or_no_lists_are_rejected() {
+    let app = spawn_app().await;
+
+    for lists in [json!(["nope"]), json!([])] {
+        let response = app
+            .post_newsletters(json!({
+                "title": "Newsletter title",
+                "lists": lists,
+                "content": {"text": "text", "html": "<p>html</p>"}
+            }))
+            .await;
+
+        assert_eq!(400, response.status().as_u16(), "lists: {}", lists);
+    }
+}
+
+#[actix_web::test]
+async fn the_unsubscribe_link_leaves_only_that_list() {
+    let app = spawn_app().await;
+    create_list(&app, "product-updates", "Product updates").await;
+    subscribe_and_confirm(&app, "ursula@example.com", None).await;
+    subscribe_and_confirm(&app, "ursula@example.com", Some("product-updates")).await;
+    let sent_before = app.email_server.received_requests().await.unwrap().len();
+    app.post_newsletters(json!({
+        "title": "Newsletter title",
+        "lists": ["product-updates"],
+        "content": {"text": "text", "html": "<p>html</p>"}
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_issues().await;
+    let requests = app.email_server.received_requests().await.unwrap();
+    let link = reqwest::Url::parse(&links(&requests[sent_before])[0]).unwrap();
+    let token = link
+        .query_pairs()
+        .find(|(k, _)| k == "token")
+        .unwrap()
+        .1
+        .into_owned();
+
+    // 打开链接只会显示确认页面
+    let response = reqwest::get(link.clone()).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("Product updates"));
+    assert_eq!(
+        memberships(&app, "ursula@example.com").await[1].1,
+        "confirmed"
+    );
+
+    // 重复提交也不会出错
+    for _ in 0..2 {
+        let response = reqwest::Client::new()
+            .post(link.clone())
+            .form(&[("token", &token)])
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    assert_eq!(
+        memberships(&app, "ursula@example.com").await,
+        [
+            ("newsletter".to_string(), "confirmed".to_string()),
+            ("product-updates".to_string(), "unsubscribed".to_string()),
+        ]
+    );
+    let unsubscribes: i64 = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM consent_events WHERE event = 'unsubscribe'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(unsubscribes, 1);
+}
+
+#[actix_web::test]
+async fn unknown_unsubscribe_tokens_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/unsubscribe?token=unknown",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,30 @@
+// 将所有集成测试编译为同一个测试二进制文件，共享helpers中的测试工具
+mod admin_subscribers;
+mod admin_subscribers_export;
+mod admin_subscribers_import;
+mod attachments;
+mod bot_protection;
+mod consent_events;
+mod email_client;
+mod email_events;
+mod email_message;
+mod health_check;
+mod helpers;
+mod i18n;
+mod issue_revisions;
+mod lists;
+mod newsletters;
+mod openapi;
+mod preferences;
+mod privacy;
+mod rate_limit;
+mod request_id;
+mod scheduled_issues;
+mod segments;
+mod send_throttle;
+mod subscriptions;
+mod subscriptions_confirm;
+mod suppressions;
+mod tags;
+mod templates;
+mod tracking;
@@ -0,0 +1,54 @@
+use utoipa::OpenApi;
+use zero2prod_lib::routes::ApiDoc;
+
+use crate::helpers::spawn_app;
+
+const SNAPSHOT_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/openapi/v1.json");
+
+// 生成的OpenAPI文档必须与仓库中的快照一致，这样任何接口变化都会出现在代码评审的diff中
+// 有意修改接口之后，使用`UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi`更新快照
+#[test]
+fn the_openapi_spec_matches_the_checked_in_snapshot() {
+    let generated = ApiDoc::openapi().to_pretty_json().unwrap() + "\n";
+    if std::env::var("UPDATE_OPENAPI_SNAPSHOT").is_ok() {
+        std::fs::write(SNAPSHOT_PATH, &generated).expect("Failed to update the snapshot");
+    }
+
+    let snapshot = std::fs::read_to_string(SNAPSHOT_PATH).expect("Failed to read the snapshot");
+
+    assert!(
+        snapshot == generated,
+        "The OpenAPI spec has drifted from {}. Run `UPDATE_OPENAPI_SNAPSHOT=1 cargo test openapi` and commit the result.",
+        SNAPSHOT_PATH
+    );
+}
+
+#[actix_web::test]
+async fn the_openapi_spec_is_served() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/api/v1/openapi.json", app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    let served: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(served, serde_json::to_value(ApiDoc::openapi()).unwrap());
+}
+
+#[actix_web::test]
+async fn subscriptions_are_available_under_the_versioned_scope() {
+    let app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/api/v1/subscriptions", &app.address))
+        .json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(201, response.status().as_u16());
+}
@@ -0,0 +1,337 @@
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+
+use crate::helpers::{spawn_app, TestApp};
+
+// 通过公开的API创建一个已确认的订阅者，返回其偏好设置令牌
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> String {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": email}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query_scalar!(
+        "SELECT preferences_token FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn post_preferences(app: &TestApp, form: &[(&str, &str)]) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/preferences", app.address))
+        .form(form)
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn publish(app: &TestApp, title: &str) {
+    app.post_newsletters(json!({
+        "title": title,
+        "content": {"text": format!("{} as text", title), "html": format!("<p>{} as HTML</p>", title)}
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+    app.dispatch_all_pending_issues().await;
+}
+
+// 发往某个地址的邮件数量
+async fn emails_to(app: &TestApp, email: &str) -> Vec<Value> {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| serde_json::from_slice::<Value>(&r.body).unwrap())
+        .filter(|body| body["To"] == email)
+        .collect()
+}
+
+#[actix_web::test]
+async fn newsletters_link_to_the_preferences_page() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    publish(&app, "Issue 1").await;
+
+    let newsletter = emails_to(&app, "ursula@example.com").await.pop().unwrap();
+    let link = format!("{}/preferences?token={}", app.address, token);
+    assert!(newsletter["HtmlBody"].as_str().unwrap().contains(&link));
+    assert!(newsletter["TextBody"].as_str().unwrap().contains(&link));
+    let response = reqwest::get(link).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let page = response.text().await.unwrap();
+    assert!(page.contains(r#"value="le guin""#));
+    assert!(page.contains(r#"name="list" value="newsletter" checked"#));
+    assert!(page.contains(r#"value="immediate" checked"#));
+}
+
+#[actix_web::test]
+async fn unknown_preferences_tokens_are_rejected() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/preferences?token=unknown", app.address))
+        .await
+        .unwrap();
+    assert_eq!(401, response.status().as_u16());
+
+    let response = post_preferences(
+        &app,
+        &[
+            ("token", "unknown"),
+            ("name", "x"),
+            ("delivery_frequency", "immediate"),
+        ],
+    )
+    .await;
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn saving_preferences_updates_the_subscriber_and_the_audit_trail() {
+    let app = spawn_app().await;
+    app.post_admin(
+        "/lists",
+        &json!({"slug": "product-updates", "name": "Product updates"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let until = (Utc::now() + Duration::days(14))
+        .format("%Y-%m-%d")
+        .to_string();
+
+    // 不勾选newsletter即退订，勾选product-updates即加入
+    let response = post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "Ursula K. Le Guin"),
+            ("list", "product-updates"),
+            ("delivery_frequency", "weekly_digest"),
+            ("paused_until", &until),
+        ],
+    )
+    .await;
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("Your preferences have been saved."));
+    let saved = sqlx::query!(
+        r#"
+        SELECT name, delivery_frequency, paused_until, subscriber_lists(id) AS "lists!"
+        FROM subscriptions
+        "#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+    assert_eq!(saved.delivery_frequency, "weekly_digest");
+    assert_eq!(
+        saved.paused_until.unwrap().format("%Y-%m-%d").to_string(),
+        until
+    );
+    assert_eq!(saved.lists[0]["list"], "newsletter");
+    assert_eq!(saved.lists[0]["status"], "unsubscribed");
+    assert_eq!(saved.lists[1]["list"], "product-updates");
+    assert_eq!(saved.lists[1]["status"], "confirmed");
+
+    let audit = sqlx::query!("SELECT changed_by, action, changes FROM subscriber_audit_log")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(audit.changed_by, None);
+    assert_eq!(audit.action, "preferences");
+    assert_eq!(audit.changes["name"]["from"], "le guin");
+    assert_eq!(audit.changes["delivery_frequency"]["to"], "weekly_digest");
+    assert_eq!(audit.changes["lists"]["newsletter"]["to"], "unsubscribed");
+    assert!(audit.changes["lists"]["product-updates"]["from"].is_null());
+    let events = sqlx::query_scalar!(
+        "SELECT event FROM consent_events WHERE source = 'preferences_page' ORDER BY id"
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(events, ["unsubscribe", "confirm"]);
+}
+
+#[actix_web::test]
+async fn invalid_preferences_are_rejected_without_changing_anything() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let yesterday = (Utc::now() - Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    // (表单字段, 出错的字段)
+    let test_cases = [
+        (
+            vec![("name", ""), ("delivery_frequency", "immediate")],
+            "name",
+        ),
+        (
+            vec![("name", "x"), ("delivery_frequency", "hourly")],
+            "delivery_frequency",
+        ),
+        (
+            vec![
+                ("name", "x"),
+                ("delivery_frequency", "immediate"),
+                ("paused_until", "soon"),
+            ],
+            "paused_until",
+        ),
+        (
+            vec![
+                ("name", "x"),
+                ("delivery_frequency", "immediate"),
+                ("paused_until", &yesterday),
+            ],
+            "paused_until",
+        ),
+        (
+            vec![
+                ("name", "x"),
+                ("delivery_frequency", "immediate"),
+                ("list", "nope"),
+            ],
+            "lists",
+        ),
+    ];
+
+    for (fields, invalid_field) in test_cases {
+        let mut form = vec![("token", token.as_str())];
+        form.extend(fields.iter().cloned());
+
+        let response = post_preferences(&app, &form).await;
+
+        assert_eq!(400, response.status().as_u16(), "{:?}", fields);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["fields"][invalid_field].is_array(), "{:?}", fields);
+    }
+    let saved = sqlx::query!("SELECT name, delivery_frequency FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.delivery_frequency, "immediate");
+    let changes = sqlx::query_scalar!(r#"SELECT count(*) AS "count!" FROM subscriber_audit_log"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(changes, 0);
+}
+
+#[actix_web::test]
+async fn paused_subscribers_receive_nothing_until_the_pause_ends() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let until = (Utc::now() + Duration::days(7))
+        .format("%Y-%m-%d")
+        .to_string();
+    post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "le guin"),
+            ("list", "newsletter"),
+            ("delivery_frequency", "immediate"),
+            ("paused_until", &until),
+        ],
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let before = emails_to(&app, "ursula@example.com").await.len();
+
+    publish(&app, "Issue 1").await;
+    assert_eq!(emails_to(&app, "ursula@example.com").await.len(), before);
+
+    // 暂停结束后恢复接收
+    sqlx::query!("UPDATE subscriptions SET paused_until = now() - interval '1 day'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    publish(&app, "Issue 2").await;
+    assert_eq!(
+        emails_to(&app, "ursula@example.com").await.len(),
+        before + 1
+    );
+}
+
+#[actix_web::test]
+async fn weekly_digest_subscribers_get_one_email_with_every_queued_issue() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+    create_confirmed_subscriber(&app, "octavia@example.com").await;
+    post_preferences(
+        &app,
+        &[
+            ("token", &token),
+            ("name", "le guin"),
+            ("list", "newsletter"),
+            ("delivery_frequency", "weekly_digest"),
+        ],
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    let before = emails_to(&app, "ursula@example.com").await.len();
+
+    publish(&app, "Issue 1").await;
+    publish(&app, "Issue 2").await;
+
+    // 立即接收的订阅者不受影响
+    assert_eq!(emails_to(&app, "octavia@example.com").await.len(), 3);
+    assert_eq!(emails_to(&app, "ursula@example.com").await.len(), before);
+    let database_name: String = sqlx::query_scalar!("SELECT current_database()")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .unwrap();
+    let send_digests = || {
+        std::process::Command::new(env!("CARGO_BIN_EXE_send_digests"))
+            .env("APP_DATABASE__DATABASE_NAME", &database_name)
+            .env("APP_EMAIL_CLIENT__BASE_URL", app.email_server.uri())
+            .env("APP_APPLICATION__BASE_URL", &app.address)
+            .output()
+            .unwrap()
+    };
+
+    let output = send_digests();
+
+    assert!(output.status.success(), "{:?}", output);
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["sent"], 1);
+    let emails = emails_to(&app, "ursula@example.com").await;
+    assert_eq!(emails.len(), before + 1);
+    let digest = emails.last().unwrap();
+    assert_eq!(digest["Subject"], "Your weekly digest: 2 issues");
+    let text = digest["TextBody"].as_str().unwrap();
+    assert!(text.find("Issue 1 as text").unwrap() < text.find("Issue 2 as text").unwrap());
+    assert!(text.contains(&format!("/preferences?token={}", token)));
+
+    // 队列已清空，再次执行不会重复发送
+    let output = send_digests();
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(report["sent"], 0);
+}
@@ -0,0 +1,293 @@
+use serde_json::{json, Value};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+const EMAIL: &str = "ursula_le_guin@gmail.com";
+
+async fn subscribe(app: &TestApp) {
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+}
+
+async fn post_privacy_request(app: &TestApp, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}/privacy/requests", &app.address))
+        .json(&body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// 申请导出或删除数据，返回邮件中的链接
+async fn request_link(app: &TestApp, kind: &str) -> reqwest::Url {
+    let response = post_privacy_request(app, json!({"email": EMAIL, "kind": kind})).await;
+    assert_eq!(202, response.status().as_u16());
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+// 邮件服务商报告的一次软退信，其中包含地址和退信原因
+async fn post_soft_bounce(app: &TestApp) {
+    app.post_email_events(&json!({
+        "RecordType": "Bounce",
+        "ID": 1,
+        "Type": "SoftBounce",
+        "Email": EMAIL,
+        "MessageID": "883953f4-6105-42a2-a16a-77a8eac79483",
+        "Description": "The mailbox is full.",
+        "BouncedAt": "2026-10-19T16:09:19Z",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+}
+
+async fn erase(app: &TestApp, link: &reqwest::Url) -> reqwest::Response {
+    let token = link
+        .query_pairs()
+        .find(|(key, _)| key == "token")
+        .unwrap()
+        .1
+        .to_string();
+    reqwest::Client::new()
+        .post(format!("{}/privacy/erasure", &app.address))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn requests_for_unknown_addresses_are_accepted_without_sending_an_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_privacy_request(
+        &app,
+        json!({"email": "nobody@example.com", "kind": "access"}),
+    )
+    .await;
+
+    assert_eq!(202, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn invalid_privacy_requests_are_rejected_with_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            json!({"email": "not-an-email", "kind": "access"}),
+            "invalid email",
+        ),
+        (
+            json!({"email": EMAIL, "kind": "everything"}),
+            "unknown kind",
+        ),
+        (json!({"kind": "access"}), "missing email"),
+    ];
+
+    for (body, description) in test_cases {
+        let response = post_privacy_request(&app, body).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", description);
+    }
+}
+
+#[actix_web::test]
+async fn the_access_link_returns_everything_we_hold_about_the_subscriber() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    post_soft_bounce(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let link = request_link(&app, "access").await;
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Content-Disposition"],
+        "attachment; filename=\"my-data.json\""
+    );
+    let export: Value = response.json().await.unwrap();
+    assert_eq!(export["subscriber"]["email"], EMAIL);
+    assert_eq!(export["subscriber"]["name"], "le guin");
+    assert_eq!(export["subscription_tokens"].as_array().unwrap().len(), 1);
+    assert_eq!(export["privacy_requests"][0]["kind"], "access");
+    assert_eq!(export["consent_events"][0]["event"], "signup");
+    assert_eq!(export["email_events"][0]["kind"], "bounced-soft");
+    assert!(export["email_events"][0]["detail"]
+        .as_str()
+        .unwrap()
+        .contains("The mailbox is full."));
+}
+
+#[actix_web::test]
+async fn privacy_links_only_work_for_their_own_purpose_and_expire() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    let access_link = request_link(&app, "access").await;
+    let erasure_link = request_link(&app, "erasure").await;
+
+    // 导出数据的令牌不能用来删除数据，反之亦然
+    assert_eq!(401, erase(&app, &access_link).await.status().as_u16());
+    let mut swapped = access_link.clone();
+    swapped.set_query(erasure_link.query());
+    assert_eq!(401, reqwest::get(swapped).await.unwrap().status().as_u16());
+
+    sqlx::query!("UPDATE privacy_request_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(
+        401,
+        reqwest::get(access_link).await.unwrap().status().as_u16()
+    );
+}
+
+#[actix_web::test]
+async fn opening_the_erasure_link_does_not_delete_anything() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    let link = request_link(&app, "erasure").await;
+
+    let response = reqwest::get(link).await.unwrap();
+
+    assert_eq!(200, response.status().as_u16());
+    assert!(response.text().await.unwrap().contains("<form"));
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, Some(1));
+}
+
+#[actix_web::test]
+async fn erasure_removes_the_subscriber_and_all_related_rows() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    let subscriber_id = sqlx::query_scalar!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    // 管理员的修改记录中包含个人数据
+    app.patch_admin(
+        &format!("/subscribers/{}", subscriber_id),
+        &json!({"name": "Ursula K. Le Guin"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    post_soft_bounce(&app).await;
+    let link = request_link(&app, "erasure").await;
+
+    let response = erase(&app, &link).await;
+
+    assert_eq!(200, response.status().as_u16());
+    for table in [
+        "subscriptions",
+        "subscription_tokens",
+        "privacy_request_tokens",
+        "email_events",
+    ] {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {}", table))
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0, "{} still has rows", table);
+    }
+    let changes = sqlx::query_scalar!(
+        "SELECT changes FROM subscriber_audit_log WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(changes, json!({}));
+    // 同意记录被保留，但其中的IP和User-Agent被清除
+    let consent_events = sqlx::query!(
+        r#"
+        SELECT event, ip_address, user_agent FROM consent_events
+        WHERE subscriber_id = $1 ORDER BY id
+        "#,
+        subscriber_id
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(
+        consent_events
+            .iter()
+            .map(|e| e.event.as_str())
+            .collect::<Vec<_>>(),
+        ["signup", "admin_edit", "erasure"]
+    );
+    assert!(consent_events
+        .iter()
+        .all(|e| e.ip_address.is_none() && e.user_agent.is_none()));
+    // 链接只能使用一次
+    assert_eq!(401, erase(&app, &link).await.status().as_u16());
+}
+
+#[actix_web::test]
+async fn erased_subscribers_are_not_imported_again() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    let link = request_link(&app, "erasure").await;
+    erase(&app, &link).await.error_for_status().unwrap();
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .body(format!(
+            "email,name\n{},le guin\nURSULA_LE_GUIN@gmail.com,again\nother@example.com,other\n",
+            EMAIL
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    let report: Value = response.json().await.unwrap();
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["suppressed"], 2);
+}
+
+#[actix_web::test]
+async fn admins_can_delete_a_subscriber_with_an_outstanding_privacy_link() {
+    let app = spawn_app().await;
+    subscribe(&app).await;
+    request_link(&app, "access").await;
+    let subscriber = sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .delete_admin(&format!("/subscribers/{}", subscriber.id))
+        .await;
+
+    assert_eq!(204, response.status().as_u16());
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM privacy_request_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(count, Some(0));
+}
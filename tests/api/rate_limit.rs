@@ -0,0 +1,192 @@
+use zero2prod_lib::configuration::{RateLimitStoreKind, Settings, TokenBucketSettings};
+
+use crate::helpers::{spawn_app_with, TestApp};
+
+const STRICT_BUCKET: TokenBucketSettings = TokenBucketSettings {
+    capacity: 2,
+    refill_interval_seconds: 3600,
+};
+const LOOSE_BUCKET: TokenBucketSettings = TokenBucketSettings {
+    capacity: 100,
+    refill_interval_seconds: 1,
+};
+
+async fn post_subscription(
+    test_app: &TestApp,
+    email: &str,
+    forwarded_for: Option<&str>,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(format!("name=le%20guin&email={}", email));
+    if let Some(forwarded_for) = forwarded_for {
+        request = request.header("X-Forwarded-For", forwarded_for);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+fn limit_per_ip(store: RateLimitStoreKind) -> impl FnOnce(&mut Settings) {
+    move |c| {
+        c.rate_limit.store = store;
+        c.rate_limit.per_ip = STRICT_BUCKET;
+        c.rate_limit.per_email = LOOSE_BUCKET;
+    }
+}
+
+#[actix_web::test]
+async fn requests_over_the_per_ip_limit_are_rejected_with_a_429() {
+    for store in [RateLimitStoreKind::Memory, RateLimitStoreKind::Postgres] {
+        let test_app = spawn_app_with(limit_per_ip(store)).await;
+
+        for i in 0..2 {
+            let response =
+                post_subscription(&test_app, &format!("user{i}%40example.com"), None).await;
+            assert_eq!(200, response.status().as_u16(), "store: {:?}", store);
+        }
+        let response = post_subscription(&test_app, "user2%40example.com", None).await;
+
+        assert_eq!(429, response.status().as_u16(), "store: {:?}", store);
+        let retry_after: u64 = response.headers()["Retry-After"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(retry_after > 0 && retry_after <= 3600);
+    }
+}
+
+#[actix_web::test]
+async fn x_forwarded_for_is_ignored_when_the_peer_is_not_a_trusted_proxy() {
+    let test_app = spawn_app_with(limit_per_ip(RateLimitStoreKind::Memory)).await;
+
+    for i in 0..2 {
+        let forwarded_for = format!("203.0.113.{i}");
+        post_subscription(
+            &test_app,
+            &format!("user{i}%40example.com"),
+            Some(&forwarded_for),
+        )
+        .await;
+    }
+    let response = post_subscription(&test_app, "user2%40example.com", Some("203.0.113.2")).await;
+
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn x_forwarded_for_is_honoured_when_the_peer_is_a_trusted_proxy() {
+    let test_app = spawn_app_with(|c| {
+        limit_per_ip(RateLimitStoreKind::Memory)(c);
+        c.rate_limit.trusted_proxies = vec!["127.0.0.1/32".parse().unwrap()];
+    })
+    .await;
+
+    // 每个客户端IP各自拥有一个桶
+    for i in 0..3 {
+        let forwarded_for = format!("203.0.113.{i}");
+        let response = post_subscription(
+            &test_app,
+            &format!("user{i}%40example.com"),
+            Some(&forwarded_for),
+        )
+        .await;
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    // 客户端可以伪造X-Forwarded-For最左侧的值，但最右侧的不受信任的地址才是真正的客户端
+    for i in 3..5 {
+        let forwarded_for = format!("198.51.100.{i}, 203.0.113.0");
+        post_subscription(
+            &test_app,
+            &format!("user{i}%40example.com"),
+            Some(&forwarded_for),
+        )
+        .await;
+    }
+    let response = post_subscription(
+        &test_app,
+        "user5%40example.com",
+        Some("198.51.100.5, 203.0.113.0"),
+    )
+    .await;
+    assert_eq!(429, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn requests_over_the_per_email_limit_are_rejected_with_a_429() {
+    for store in [RateLimitStoreKind::Memory, RateLimitStoreKind::Postgres] {
+        let test_app = spawn_app_with(|c| {
+            c.rate_limit.store = store;
+            c.rate_limit.per_ip = LOOSE_BUCKET;
+            c.rate_limit.per_email = TokenBucketSettings {
+                capacity: 1,
+                refill_interval_seconds: 3600,
+            };
+        })
+        .await;
+
+        let response = post_subscription(&test_app, "ursula%40example.com", None).await;
+        assert_eq!(200, response.status().as_u16(), "store: {:?}", store);
+
+        // 邮箱地址不区分大小写
+        let response = post_subscription(&test_app, "Ursula%40Example.com", None).await;
+        assert_eq!(429, response.status().as_u16(), "store: {:?}", store);
+        assert!(response.headers().contains_key("Retry-After"));
+
+        let response = post_subscription(&test_app, "someone.else%40example.com", None).await;
+        assert_eq!(200, response.status().as_u16(), "store: {:?}", store);
+    }
+}
+
+#[actix_web::test]
+async fn idle_buckets_are_pruned_without_resetting_active_limits() {
+    for store in [RateLimitStoreKind::Memory, RateLimitStoreKind::Postgres] {
+        let test_app = spawn_app_with(limit_per_ip(store)).await;
+        for i in 0..2 {
+            post_subscription(&test_app, &format!("user{i}%40example.com"), None)
+                .await
+                .error_for_status()
+                .unwrap();
+        }
+
+        test_app.rate_limit_cleanup.run_once().await.unwrap();
+
+        // 尚未补满的桶被保留，限制仍然有效
+        let response = post_subscription(&test_app, "user2%40example.com", None).await;
+        assert_eq!(429, response.status().as_u16(), "store: {:?}", store);
+    }
+}
+
+#[actix_web::test]
+async fn postgres_buckets_idle_longer_than_their_refill_window_are_deleted() {
+    let test_app = spawn_app_with(limit_per_ip(RateLimitStoreKind::Postgres)).await;
+    post_subscription(&test_app, "ursula%40example.com", None)
+        .await
+        .error_for_status()
+        .unwrap();
+    // 每个邮箱地址的桶100秒补满，每个IP的桶2小时补满；发送速率的桶不归限流器管理
+    sqlx::query!(
+        r#"
+        INSERT INTO rate_limit_buckets (key, tokens, updated_at)
+        VALUES ('send:global', 0, now() - interval '1 day')
+        "#
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        "UPDATE rate_limit_buckets SET updated_at = now() - interval '1 hour' WHERE key NOT LIKE 'send:%'"
+    )
+    .execute(&test_app.db_pool)
+    .await
+    .unwrap();
+
+    test_app.rate_limit_cleanup.run_once().await.unwrap();
+
+    let keys = sqlx::query_scalar!("SELECT key FROM rate_limit_buckets ORDER BY key")
+        .fetch_all(&test_app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(keys, ["ip:127.0.0.1", "send:global"]);
+}
@@ -0,0 +1,105 @@
+use wiremock::matchers::{header, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+#[actix_web::test]
+async fn every_response_has_a_request_id() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", test_app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    let request_id = response
+        .headers()
+        .get("X-Request-Id")
+        .expect("Missing X-Request-Id header");
+    assert!(uuid::Uuid::parse_str(request_id.to_str().unwrap()).is_ok());
+}
+
+#[actix_web::test]
+async fn a_valid_caller_supplied_request_id_is_echoed_back() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/health_check", test_app.address))
+        .header("X-Request-Id", "support-ticket-42")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(response.headers()["X-Request-Id"], "support-ticket-42");
+}
+
+#[actix_web::test]
+async fn an_invalid_caller_supplied_request_id_is_replaced() {
+    let test_app = spawn_app().await;
+    let test_cases = vec![
+        ("a".repeat(65), "too long"),
+        ("has spaces".to_string(), "invalid characters"),
+        ("".to_string(), "empty"),
+    ];
+
+    for (invalid_id, description) in test_cases {
+        let response = reqwest::Client::new()
+            .get(format!("{}/health_check", test_app.address))
+            .header("X-Request-Id", invalid_id.as_str())
+            .send()
+            .await
+            .expect("Failed to execute request");
+
+        let request_id = response.headers()["X-Request-Id"].to_str().unwrap();
+        assert!(
+            uuid::Uuid::parse_str(request_id).is_ok(),
+            "The request id was not replaced when it was {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn error_responses_have_a_json_body_with_the_request_id() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "my-request-id")
+        .body("name=michael%20wang")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(400, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "application/json");
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["request_id"], "my-request-id");
+    assert!(body["error"].is_string());
+}
+
+#[actix_web::test]
+async fn the_request_id_is_forwarded_to_the_email_provider() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(header("X-Request-Id", "my-request-id"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("X-Request-Id", "my-request-id")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+}
@@ -0,0 +1,304 @@
+use chrono::{Duration, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_confirmed_subscriber(app: &TestApp) {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": "ursula@example.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+fn issue_body(scheduled_at: Option<String>) -> Value {
+    json!({
+        "title": "Scheduled issue",
+        "content": {
+            "html": "<p>Sent later</p>",
+            "text": "Sent later",
+        },
+        "scheduled_at": scheduled_at,
+    })
+}
+
+fn in_one_hour() -> String {
+    (Utc::now() + Duration::hours(1)).to_rfc3339()
+}
+
+async fn create_issue(app: &TestApp, body: &Value) -> Value {
+    let response = app.post_admin("/issues", body).await;
+    assert_eq!(201, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+// 把发送时间改到过去，模拟到期
+async fn make_due(app: &TestApp, id: &str) {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET scheduled_at = now() - interval '1 minute' WHERE id = $1",
+        id.parse::<Uuid>().unwrap(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+// 发给邮件服务商的新闻邮件（不含确认邮件）的数量
+async fn issues_sent(app: &TestApp) -> usize {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"] == "Scheduled issue"
+        })
+        .count()
+}
+
+async fn get_issue(app: &TestApp, id: &str) -> Value {
+    app.get_admin(&format!("/issues/{}", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn scheduled_issues_are_sent_exactly_once_when_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let issue = create_issue(&app, &issue_body(Some(in_one_hour()))).await;
+    let id = issue["id"].as_str().unwrap();
+    assert_eq!(issue["status"], "scheduled");
+
+    // 未到期时不发送
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(0, issues_sent(&app).await);
+    assert_eq!(get_issue(&app, id).await["status"], "scheduled");
+
+    make_due(&app, id).await;
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(1, issues_sent(&app).await);
+    let sent = get_issue(&app, id).await;
+    assert_eq!(sent["status"], "sent");
+    assert!(sent["sent_at"].is_string());
+
+    // 再次执行不会重复发送
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(1, issues_sent(&app).await);
+}
+
+#[actix_web::test]
+async fn local_times_are_converted_with_the_given_timezone() {
+    let app = spawn_app().await;
+
+    // 冬令时UTC+1，夏令时UTC+2
+    for (local, utc) in [
+        ("2030-01-07T09:00", "2030-01-07T08:00:00Z"),
+        ("2030-07-01T09:00:00", "2030-07-01T07:00:00Z"),
+    ] {
+        let mut body = issue_body(Some(local.into()));
+        body["timezone"] = json!("Europe/Berlin");
+        let issue = create_issue(&app, &body).await;
+        assert_eq!(issue["scheduled_at"], utc);
+        assert_eq!(issue["timezone"], "Europe/Berlin");
+    }
+
+    let past = (Utc::now() - Duration::hours(1)).to_rfc3339();
+    for (scheduled_at, timezone, error) in [
+        ("2030-01-07T09:00", None, "a timezone is required"),
+        (
+            "2030-01-07T09:00",
+            Some("Mars/Olympus_Mons"),
+            "not a known timezone",
+        ),
+        (
+            "2030-01-07T09:00:00+01:00",
+            Some("Europe/Berlin"),
+            "not both",
+        ),
+        (
+            "next monday",
+            Some("Europe/Berlin"),
+            "not a valid date and time",
+        ),
+        (past.as_str(), None, "must be in the future"),
+    ] {
+        let mut body = issue_body(Some(scheduled_at.into()));
+        body["timezone"] = json!(timezone);
+        let response = app.post_admin("/issues", &body).await;
+        assert_eq!(400, response.status().as_u16(), "{}", scheduled_at);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["error"].as_str().unwrap().contains(error), "{}", body);
+    }
+}
+
+#[actix_web::test]
+async fn issues_can_be_rescheduled_or_cancelled_before_sending_starts() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    // 没有发送时间时保存为草稿
+    let draft = create_issue(&app, &issue_body(None)).await;
+    let id = draft["id"].as_str().unwrap();
+    assert_eq!(draft["status"], "draft");
+    assert!(draft["scheduled_at"].is_null());
+
+    let response = app
+        .put_admin(
+            &format!("/issues/{}/schedule", id),
+            &json!({"scheduled_at": "2030-03-04T09:30", "timezone": "America/New_York"}),
+        )
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    let scheduled: Value = response.json().await.unwrap();
+    assert_eq!(scheduled["status"], "scheduled");
+    assert_eq!(scheduled["scheduled_at"], "2030-03-04T14:30:00Z");
+
+    let listed: Vec<Value> = app
+        .get_admin("/issues", &[("status", "scheduled")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, listed.len());
+    assert_eq!(listed[0]["id"], id);
+
+    let response = app
+        .post_admin(&format!("/issues/{}/cancel", id), &json!({}))
+        .await;
+    assert_eq!(200, response.status().as_u16());
+    make_due(&app, id).await;
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(0, issues_sent(&app).await);
+    assert_eq!(get_issue(&app, id).await["status"], "cancelled");
+
+    // 取消之后不能再安排
+    let response = app
+        .put_admin(
+            &format!("/issues/{}/schedule", id),
+            &json!({"scheduled_at": in_one_hour()}),
+        )
+        .await;
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .post_admin(&format!("/issues/{}/cancel", Uuid::new_v4()), &json!({}))
+        .await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn issues_cannot_be_changed_once_sending_has_started() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue = create_issue(&app, &issue_body(Some(in_one_hour()))).await;
+    let id = issue["id"].as_str().unwrap();
+    make_due(&app, id).await;
+
+    // 第一次执行只把这一期放入投递队列
+    app.delivery_worker.run_once().await.unwrap();
+    assert_eq!(get_issue(&app, id).await["status"], "sending");
+
+    let response = app
+        .post_admin(&format!("/issues/{}/cancel", id), &json!({}))
+        .await;
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .put_admin(
+            &format!("/issues/{}/schedule", id),
+            &json!({"scheduled_at": in_one_hour()}),
+        )
+        .await;
+    assert_eq!(409, response.status().as_u16());
+
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(1, issues_sent(&app).await);
+    assert_eq!(get_issue(&app, id).await["status"], "sent");
+}
+
+#[actix_web::test]
+async fn failed_deliveries_are_retried_later() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let issue = create_issue(&app, &issue_body(Some(in_one_hour()))).await;
+    let id = issue["id"].as_str().unwrap();
+    make_due(&app, id).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .with_priority(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_issues().await;
+
+    // 失败的邮件留在队列中，推迟到稍后再试
+    let n_retries = sqlx::query_scalar!("SELECT n_retries FROM issue_delivery_queue")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(1, n_retries);
+    assert_eq!(get_issue(&app, id).await["status"], "sending");
+
+    sqlx::query!("UPDATE issue_delivery_queue SET execute_after = now()")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    app.dispatch_all_pending_issues().await;
+    assert_eq!(2, issues_sent(&app).await);
+    assert_eq!(get_issue(&app, id).await["status"], "sent");
+}
+
+#[actix_web::test]
+async fn issues_that_can_no_longer_be_sent_are_cancelled_with_a_reason() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let response = reqwest::Client::new()
+        .post(format!("{}/admin/assets", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .query(&[("filename", "report.pdf")])
+        .header("Content-Type", "application/pdf")
+        .body(b"%PDF-1.4\nnot really a document".to_vec())
+        .send()
+        .await
+        .unwrap();
+    let asset: Value = response.json().await.unwrap();
+    let mut body = issue_body(Some(in_one_hour()));
+    body["attachments"] = json!([{"asset_id": asset["id"]}]);
+    let issue = create_issue(&app, &body).await;
+    let id = issue["id"].as_str().unwrap();
+
+    // 到期之前附件被删除
+    sqlx::query!("DELETE FROM assets")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    make_due(&app, id).await;
+    app.dispatch_all_pending_issues().await;
+
+    assert_eq!(0, issues_sent(&app).await);
+    let issue = get_issue(&app, id).await;
+    assert_eq!(issue["status"], "cancelled");
+    assert!(issue["failure_reason"]
+        .as_str()
+        .unwrap()
+        .contains("There is no asset"));
+}
@@ -0,0 +1,257 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+// 直接写入数据库，以便精确控制标签、属性和订阅时间。订阅者只属于默认列表
+async fn insert_subscriber(
+    app: &TestApp,
+    email: &str,
+    status: &str,
+    tags: &[&str],
+    locale: &str,
+    subscribed_on: &str,
+) {
+    let id = Uuid::new_v4();
+    let subscribed_at: DateTime<Utc> = NaiveDate::parse_from_str(subscribed_on, "%Y-%m-%d")
+        .unwrap()
+        .and_hms_opt(12, 0, 0)
+        .unwrap()
+        .and_utc();
+    let tags: Vec<String> = tags.iter().map(|t| t.to_string()).collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, tags, attributes)
+        VALUES ($1, $2, 'name', $3, $4, $5)
+        "#,
+        id,
+        email,
+        subscribed_at,
+        &tags,
+        json!({ "locale": locale }),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (subscriber_id, list_id, status, subscribed_at, unsubscribe_token)
+        SELECT $1, id, $2, $3, $4 FROM lists WHERE slug = 'newsletter'
+        "#,
+        id,
+        status,
+        subscribed_at,
+        Uuid::new_v4().simple().to_string(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn seed(app: &TestApp) {
+    insert_subscriber(
+        app,
+        "alice@example.com",
+        "confirmed",
+        &["beta"],
+        "de",
+        "2026-02-01",
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "bob@example.com",
+        "confirmed",
+        &["beta"],
+        "en",
+        "2026-02-01",
+    )
+    .await;
+    insert_subscriber(
+        app,
+        "carol@example.com",
+        "confirmed",
+        &[],
+        "de",
+        "2025-12-01",
+    )
+    .await;
+    // 未确认的订阅者不会被计入
+    insert_subscriber(
+        app,
+        "dave@example.com",
+        "pending_confirmation",
+        &["beta"],
+        "de",
+        "2026-03-01",
+    )
+    .await;
+}
+
+async fn preview(app: &TestApp, expression: &str) -> reqwest::Response {
+    app.post_admin("/segments/preview", &json!({ "expression": expression }))
+        .await
+}
+
+async fn create_segment(app: &TestApp, name: &str, expression: &str) -> Uuid {
+    let response = app
+        .post_admin(
+            "/segments",
+            &json!({ "name": name, "expression": expression }),
+        )
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    let segment: Value = response.json().await.unwrap();
+    segment["id"].as_str().unwrap().parse().unwrap()
+}
+
+#[actix_web::test]
+async fn preview_counts_confirmed_subscribers_matching_the_expression() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    let test_cases = [
+        (
+            r#"tag:beta AND attr.locale = "de" AND subscribed_at > 2026-01-01"#,
+            1,
+        ),
+        (r#"tag:beta OR attr.locale = de"#, 3),
+        ("NOT tag:beta", 1),
+        (r#"attr.locale != "de""#, 1),
+        ("subscribed_at = 2026-02-01", 2),
+        ("subscribed_at <= 2025-12-01", 1),
+        ("subscribed_at < 2025-12-01", 0),
+        (
+            r#"(tag:BETA or tag:"early access") and not attr.locale = "en""#,
+            1,
+        ),
+        // 值作为参数传入，不会被当作SQL执行
+        (r#"attr.locale = "de' OR '1'='1""#, 0),
+    ];
+
+    for (expression, expected) in test_cases {
+        let response = preview(&app, expression).await;
+
+        assert_eq!(200, response.status().as_u16(), "{}", expression);
+        let body: Value = response.json().await.unwrap();
+        assert_eq!(body["count"], expected, "{}", expression);
+    }
+}
+
+#[actix_web::test]
+async fn invalid_expressions_are_rejected() {
+    let app = spawn_app().await;
+    let deeply_nested = format!("{}tag:beta{}", "(".repeat(40), ")".repeat(40));
+    let test_cases = [
+        "",
+        "tag:",
+        "tag:beta AND",
+        "(tag:beta",
+        "tag:beta tag:other",
+        "tag:beta OR OR tag:other",
+        r#"attr.plan = "premium""#,
+        r#"attr.locale > "de""#,
+        r#"attr.locale = "de"#,
+        "subscribed_at > yesterday",
+        "name = 'x'",
+        deeply_nested.as_str(),
+    ];
+
+    for expression in test_cases {
+        let response = preview(&app, expression).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", expression);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["fields"]["expression"].is_array(), "{}", expression);
+    }
+}
+
+#[actix_web::test]
+async fn segments_are_saved_and_listed() {
+    let app = spawn_app().await;
+    create_segment(
+        &app,
+        "German beta testers",
+        r#"tag:beta AND attr.locale = "de""#,
+    )
+    .await;
+
+    let response = app.get_admin("/segments", &[]).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let segments: Value = response.json().await.unwrap();
+    assert_eq!(segments[0]["name"], "German beta testers");
+    assert_eq!(
+        segments[0]["expression"],
+        r#"tag:beta AND attr.locale = "de""#
+    );
+
+    // 名称不能重复，表达式在保存前校验
+    let response = app
+        .post_admin(
+            "/segments",
+            &json!({"name": "German beta testers", "expression": "tag:beta"}),
+        )
+        .await;
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .post_admin(
+            "/segments",
+            &json!({"name": "Broken", "expression": "tag:beta AND"}),
+        )
+        .await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn publishing_to_a_segment_only_reaches_matching_subscribers() {
+    let app = spawn_app().await;
+    seed(&app).await;
+    let segment_id = create_segment(&app, "German", r#"attr.locale = "de""#).await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Hallo",
+            "content": {"text": "Hallo", "html": "<p>Hallo</p>"},
+            "segment_id": segment_id,
+        }))
+        .await;
+    app.dispatch_all_pending_issues().await;
+
+    assert_eq!(202, response.status().as_u16());
+    let mut recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|r| {
+            let body: Value = serde_json::from_slice(&r.body).unwrap();
+            body["To"].as_str().unwrap().to_string()
+        })
+        .collect();
+    recipients.sort();
+    assert_eq!(recipients, ["alice@example.com", "carol@example.com"]);
+}
+
+#[actix_web::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    let app = spawn_app().await;
+    seed(&app).await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Hallo",
+            "content": {"text": "Hallo", "html": "<p>Hallo</p>"},
+            "segment_id": Uuid::new_v4(),
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+}
@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use zero2prod_lib::configuration::SendRate;
+
+use crate::helpers::{spawn_app_with, TestApp};
+
+async fn create_confirmed_subscribers(app: &TestApp, n: usize) {
+    for i in 0..n {
+        app.post_subscriptions_json(
+            &json!({"name": "le guin", "email": format!("ursula{}@example.com", i)}),
+        )
+        .await
+        .error_for_status()
+        .unwrap();
+        let request = app
+            .email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        reqwest::get(app.get_confirmation_links(&request).html)
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+    }
+}
+
+// 立即发布一期，返回投递任务发完所有邮件所用的时间
+async fn publish_and_dispatch(app: &TestApp) -> Duration {
+    let response = app
+        .post_admin(
+            "/issues",
+            &json!({
+                "title": "Throttled issue",
+                "content": {"html": "<p>Hi</p>", "text": "Hi"},
+            }),
+        )
+        .await;
+    let issue: Value = response.json().await.unwrap();
+    let response = app
+        .post_admin(
+            &format!("/issues/{}/publish", issue["id"].as_str().unwrap()),
+            &json!({}),
+        )
+        .await;
+    assert_eq!(202, response.status().as_u16());
+
+    let started = Instant::now();
+    app.dispatch_all_pending_issues().await;
+    started.elapsed()
+}
+
+async fn issues_sent(app: &TestApp) -> usize {
+    app.email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .filter(|request| {
+            let body: Value = serde_json::from_slice(&request.body).unwrap();
+            body["Subject"] == "Throttled issue"
+        })
+        .count()
+}
+
+fn rate(messages_per_second: f64, burst: u32) -> SendRate {
+    SendRate {
+        messages_per_second,
+        burst,
+    }
+}
+
+#[actix_web::test]
+async fn deliveries_are_spread_out_to_stay_under_the_global_send_rate() {
+    let app = spawn_app_with(|c| c.delivery.send_rate.global = Some(rate(5.0, 1))).await;
+    create_confirmed_subscribers(&app, 3).await;
+
+    // 第一封立即发出，之后每封间隔0.2秒
+    let elapsed = publish_and_dispatch(&app).await;
+    assert_eq!(3, issues_sent(&app).await);
+    assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+
+    let metrics: Value = app
+        .get_admin("/delivery/metrics", &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(metrics["queue"]["pending"], 0);
+    let send_rates = metrics["send_rates"].as_array().unwrap();
+    assert_eq!(1, send_rates.len());
+    assert_eq!(send_rates[0]["key"], "send:global");
+    assert_eq!(send_rates[0]["sent"], 3);
+    assert_eq!(send_rates[0]["throttled"], 2);
+    assert!(send_rates[0]["waited_seconds"].as_f64().unwrap() > 0.3);
+}
+
+#[actix_web::test]
+async fn each_transport_can_have_its_own_send_rate() {
+    let app = spawn_app_with(|c| {
+        c.delivery.send_rate.global = Some(rate(1000.0, 1000));
+        // 测试中邮件服务商的模拟服务器运行在127.0.0.1上
+        c.delivery.send_rate.per_transport = HashMap::from([
+            ("127.0.0.1".to_string(), rate(5.0, 1)),
+            ("api.example.com".to_string(), rate(0.1, 1)),
+        ]);
+    })
+    .await;
+    create_confirmed_subscribers(&app, 3).await;
+
+    let elapsed = publish_and_dispatch(&app).await;
+    assert_eq!(3, issues_sent(&app).await);
+    assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+
+    let metrics: Value = app
+        .get_admin("/delivery/metrics", &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    let send_rates: HashMap<String, Value> = metrics["send_rates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| (m["key"].as_str().unwrap().to_string(), m.clone()))
+        .collect();
+    assert_eq!(2, send_rates.len());
+    assert_eq!(send_rates["send:global"]["sent"], 3);
+    assert_eq!(send_rates["send:transport:127.0.0.1"]["sent"], 3);
+    assert_eq!(send_rates["send:transport:127.0.0.1"]["throttled"], 2);
+}
+
+#[actix_web::test]
+async fn immediate_publishes_are_queued_for_the_throttled_delivery_worker() {
+    let app = spawn_app_with(|c| c.delivery.send_rate.global = Some(rate(5.0, 1))).await;
+    create_confirmed_subscribers(&app, 3).await;
+
+    let response = app
+        .post_newsletters(json!({
+            "title": "Throttled issue",
+            "content": {"html": "<p>Hi</p>", "text": "Hi"},
+        }))
+        .await;
+
+    // 请求只把收件人放入投递队列，不等待发送
+    assert_eq!(202, response.status().as_u16());
+    let issue: Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "sending");
+    assert_eq!(0, issues_sent(&app).await);
+
+    let started = Instant::now();
+    app.dispatch_all_pending_issues().await;
+    let elapsed = started.elapsed();
+    assert_eq!(3, issues_sent(&app).await);
+    assert!(elapsed >= Duration::from_millis(350), "{:?}", elapsed);
+    let metrics: Value = app
+        .get_admin("/delivery/metrics", &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(metrics["send_rates"][0]["sent"], 3);
+    assert_eq!(metrics["send_rates"][0]["throttled"], 2);
+    let issue: Value = app
+        .get_admin(&format!("/issues/{}", issue["id"].as_str().unwrap()), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(issue["status"], "sent");
+}
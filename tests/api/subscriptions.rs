@@ -0,0 +1,232 @@
+use serde_json::json;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+use crate::helpers::spawn_app;
+
+#[actix_web::test]
+async fn subscribe_return_a_200_for_valid_form_data() {
+    let test_app = spawn_app().await;
+    // let configuration =
+    //     zero2prod_lib::configuration::get_configuration().expect("Failed to read configuration");
+    // let connection_string = configuration.database.connection_string();
+    // // 连接Postgres
+    // // 注：为了调用PgConnection::connect，必须也导入trait Connection。因为它不是该结构体的内在方法
+    // let mut connection = PgConnection::connect(&connection_string)
+    //     .await
+    //     .expect("Failed to connect to Postgres.");
+
+    let client = reqwest::Client::new();
+
+    let body = "name=michael%20wang&email=revelationofturing%40gmail.com";
+    let response = client
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(200, response.status().as_u16());
+
+    // 测试postgres连接
+    // 注：sqlx::query!返回一个匿名的记录类型。在编译时，在验证查询的有效性后生成结构体定义
+    // 每个成员都对应结果中的一个列（如：saved.email对应email列）
+    // sqlx在编译时依赖DATABASE_URL环境变量来确定postgres的位置，建议在根目录下增加一个.env
+    // sqlxm每次都将从.env文件中读取DATABASE_URL，省去了每次都要导出环境变量的麻烦。
+    // 在.env和configuration.yaml同时存数据库连接参数可能让人不爽。但没关系，.env仅与开发过程、构建和测试步骤相关。
+    let saved = sqlx::query!("SELECT email, name FROM subscriptions",) // 从subscriptions表中查询email和name列
+        // .fetch_one(&mut connection)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+
+    println!("{:?}", saved);
+    assert_eq!(saved.email, "revelationofturing@gmail.com");
+    assert_eq!(saved.name, "michael wang");
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_when_data_is_missing() {
+    let test_app = spawn_app().await;
+    let client = reqwest::Client::new();
+    let test_cases = vec![
+        ("name=michael%20wang", "email missed"),
+        ("email=revelationofturing%40gmail.com", "name missed"),
+        ("", "name and email missed"),
+    ];
+
+    for (invalid_body, msg) in test_cases {
+        let response = client
+            .post(format!("{}/subscriptions", &test_app.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(invalid_body)
+            .send()
+            .await
+            .expect(msg);
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            // 关于测试失败的附加自定义错误消息
+            "The API did not fail with 400 Bad Request when the payload was {}.",
+            invalid_body
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_when_fields_are_present_but_invalid() {
+    let test_app = spawn_app().await;
+    let test_cases = vec![
+        ("name=&email=ursula_le_guin%40gmail.com", "empty name"),
+        ("name=Ursula&email=", "empty email"),
+        ("name=Ursula&email=definitely-not-an-email", "invalid email"),
+        (
+            "name=%7Bursula%7D&email=ursula_le_guin%40gmail.com",
+            "forbidden characters",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        let response = test_app.post_subscriptions(body.into()).await;
+
+        assert_eq!(
+            400,
+            response.status().as_u16(),
+            "The API did not return a 400 Bad Request when the payload was {}.",
+            description
+        );
+    }
+}
+
+#[actix_web::test]
+async fn subscribe_persists_the_new_subscriber_as_pending_confirmation() {
+    let test_app = spawn_app().await;
+
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.email, s.name, m.status, l.slug
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN lists l ON l.id = m.list_id
+        "#,
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.name, "le guin");
+    assert_eq!(saved.slug, "newsletter");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_sends_a_confirmation_email_with_a_link() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&test_app.email_server)
+        .await;
+
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+    // 两个链接应该是相同的
+    assert_eq!(confirmation_links.html, confirmation_links.plain_text);
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_500_if_the_confirmation_email_cannot_be_sent() {
+    let test_app = spawn_app().await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&test_app.email_server)
+        .await;
+
+    let response = test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+
+    assert_eq!(500, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn subscribe_accepts_json_and_returns_the_pending_subscription() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscriptions_json(&json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["email"], "ursula_le_guin@gmail.com");
+    assert_eq!(body["name"], "le guin");
+    assert_eq!(body["status"], "pending_confirmation");
+    assert!(body["subscribed_at"].is_string());
+
+    let saved = sqlx::query!("SELECT id FROM subscriptions",)
+        .fetch_one(&test_app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(body["id"], saved.id.to_string());
+}
+
+#[actix_web::test]
+async fn subscribe_returns_json_for_a_form_when_the_client_accepts_json() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/subscriptions", &test_app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", "application/json")
+        .body("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(201, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+}
+
+#[actix_web::test]
+async fn subscribe_returns_validation_errors_per_field_in_json_mode() {
+    let test_app = spawn_app().await;
+
+    let response = test_app
+        .post_subscriptions_json(&json!({
+            "name": "",
+            "email": "definitely-not-an-email",
+        }))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["fields"]["email"].as_array().unwrap().len(), 1);
+    assert_eq!(body["fields"]["name"].as_array().unwrap().len(), 1);
+    assert!(body["request_id"].is_string());
+
+    let response = test_app.post_subscriptions_json(&json!({})).await;
+
+    assert_eq!(400, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert!(body["fields"]["email"].is_array());
+    assert!(body["fields"]["name"].is_array());
+}
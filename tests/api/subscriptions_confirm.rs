@@ -0,0 +1,51 @@
+use crate::helpers::spawn_app;
+
+#[actix_web::test]
+async fn confirmations_without_token_are_rejected_with_a_400() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/subscriptions/confirm", test_app.address))
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
+
+#[actix_web::test]
+async fn confirmations_with_an_unknown_token_are_rejected_with_a_401() {
+    let test_app = spawn_app().await;
+
+    let response = reqwest::get(format!(
+        "{}/subscriptions/confirm?subscription_token=unknown",
+        test_app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[actix_web::test]
+async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
+    let test_app = spawn_app().await;
+    test_app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &test_app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = test_app.get_confirmation_links(email_request);
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!(
+        r#"
+        SELECT s.email, m.status
+        FROM subscriptions s JOIN list_memberships m ON m.subscriber_id = s.id
+        "#,
+    )
+    .fetch_one(&test_app.db_pool)
+    .await
+    .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "confirmed");
+}
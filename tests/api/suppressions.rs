@@ -0,0 +1,192 @@
+use serde_json::{json, Value};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": email}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn suppress(app: &TestApp, email: &str) -> Value {
+    let response = app
+        .post_admin(
+            "/suppressions",
+            &json!({"email": email, "note": "Asked us by phone to stop"}),
+        )
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    response.json().await.unwrap()
+}
+
+async fn import(app: &TestApp, csv: &str) -> Value {
+    reqwest::Client::new()
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .basic_auth(&app.test_user.username, Some(&app.test_user.password))
+        .header("Content-Type", "text/csv")
+        .body(csv.to_string())
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+async fn emails_sent(app: &TestApp) -> usize {
+    app.email_server.received_requests().await.unwrap().len()
+}
+
+#[actix_web::test]
+async fn suppressed_addresses_cannot_subscribe_again() {
+    let app = spawn_app().await;
+    let suppression = suppress(&app, "ursula@example.com").await;
+    assert_eq!(suppression["reason"], "manual");
+    assert_eq!(64, suppression["email_hash"].as_str().unwrap().len());
+
+    // 大小写不同的同一个地址也被屏蔽
+    let response = app
+        .post_admin(
+            "/suppressions",
+            &json!({"email": "Ursula@Example.com", "note": "again"}),
+        )
+        .await;
+    assert_eq!(409, response.status().as_u16());
+    let response = app
+        .post_admin(
+            "/suppressions",
+            &json!({"email": "octavia@example.com", "note": "  "}),
+        )
+        .await;
+    assert_eq!(400, response.status().as_u16());
+
+    // 看起来订阅成功了，但什么都没有保存，也没有发送确认邮件
+    let response = app
+        .post_subscriptions_json(&json!({"name": "le guin", "email": "URSULA@example.com"}))
+        .await;
+    assert_eq!(201, response.status().as_u16());
+    let count = sqlx::query_scalar!("SELECT COUNT(*) FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(Some(0), count);
+    assert_eq!(0, emails_sent(&app).await);
+}
+
+#[actix_web::test]
+async fn the_delivery_worker_skips_addresses_suppressed_after_they_subscribed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+    create_confirmed_subscriber(&app, "octavia@example.com").await;
+    let response = app
+        .post_admin(
+            "/issues",
+            &json!({
+                "title": "Suppressed issue",
+                "content": {"html": "<p>Hi</p>", "text": "Hi"},
+            }),
+        )
+        .await;
+    let issue: Value = response.json().await.unwrap();
+    let response = app
+        .post_admin(
+            &format!("/issues/{}/publish", issue["id"].as_str().unwrap()),
+            &json!({}),
+        )
+        .await;
+    assert_eq!(202, response.status().as_u16());
+
+    suppress(&app, "ursula@example.com").await;
+    app.dispatch_all_pending_issues().await;
+
+    let recipients: Vec<String> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .filter(|body| body["Subject"] == "Suppressed issue")
+        .map(|body| body["To"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(recipients, ["octavia@example.com"]);
+}
+
+#[actix_web::test]
+async fn lifting_a_suppression_is_audited_and_allows_the_address_back() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+    app.post_email_events(&json!({
+        "RecordType": "Bounce",
+        "ID": 1,
+        "Type": "HardBounce",
+        "Email": "ursula@example.com",
+        "BouncedAt": "2026-10-19T16:09:19Z",
+    }))
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let suppressions: Vec<Value> = app
+        .get_admin("/suppressions", &[("email", "ursula@example.com")])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(1, suppressions.len());
+    assert_eq!(suppressions[0]["reason"], "hard-bounce");
+    let hash = suppressions[0]["email_hash"].as_str().unwrap().to_string();
+
+    // 被屏蔽的地址不会被重新导入
+    let report = import(&app, "email,name\nursula@example.com,le guin\n").await;
+    assert_eq!(report["suppressed"], 1);
+    assert_eq!(report["imported"], 0);
+
+    let lift = |note: &'static str| {
+        let path = format!("/suppressions/{}/lift", hash);
+        let app = &app;
+        async move { app.post_admin(&path, &json!({ "note": note })).await }
+    };
+    assert_eq!(400, lift("").await.status().as_u16());
+    assert_eq!(200, lift("The mailbox was fixed").await.status().as_u16());
+    assert_eq!(404, lift("Twice").await.status().as_u16());
+
+    let detail: Value = app
+        .get_admin(&format!("/suppressions/{}", hash), &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(detail["suppression"].is_null());
+    let history = detail["history"].as_array().unwrap();
+    assert_eq!(2, history.len());
+    assert_eq!(history[0]["action"], "added");
+    assert!(history[0]["performed_by"].is_null());
+    assert_eq!(history[1]["action"], "lifted");
+    assert_eq!(history[1]["reason"], "hard-bounce");
+    assert_eq!(history[1]["note"], "The mailbox was fixed");
+    assert_eq!(history[1]["performed_by"], app.test_user.username.as_str());
+
+    let response = app.get_admin("/suppressions/not-a-hash", &[]).await;
+    assert_eq!(404, response.status().as_u16());
+    let suppressions: Vec<Value> = app
+        .get_admin("/suppressions", &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(suppressions.is_empty());
+}
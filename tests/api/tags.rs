@@ -0,0 +1,293 @@
+use serde_json::{json, Value};
+use uuid::Uuid;
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn subscribe(app: &TestApp, body: Value) -> Uuid {
+    let response = app.post_subscriptions_json(&body).await;
+    assert_eq!(201, response.status().as_u16());
+    let subscription: Value = response.json().await.unwrap();
+    subscription["id"].as_str().unwrap().parse().unwrap()
+}
+
+async fn attributes_of(app: &TestApp, email: &str) -> Value {
+    sqlx::query_scalar!(
+        "SELECT attributes FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+async fn tags_of(app: &TestApp, id: Uuid) -> Vec<String> {
+    sqlx::query_scalar!("SELECT tags FROM subscriptions WHERE id = $1", id)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+}
+
+async fn listed_emails(app: &TestApp, query: &[(&str, &str)]) -> Vec<String> {
+    let response = app.get_admin("/subscribers", query).await;
+    assert_eq!(200, response.status().as_u16());
+    let page: Value = response.json().await.unwrap();
+    page["subscribers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| s["email"].as_str().unwrap().to_string())
+        .collect()
+}
+
+#[actix_web::test]
+async fn subscribe_stores_whitelisted_attributes() {
+    let app = spawn_app().await;
+
+    subscribe(
+        &app,
+        json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "source": " conference-page ",
+            "locale": "zh_cn",
+            // 不在白名单中的字段被忽略
+            "plan": "premium",
+        }),
+    )
+    .await;
+
+    assert_eq!(
+        attributes_of(&app, "ursula@example.com").await,
+        json!({"source": "conference-page", "locale": "zh-CN"})
+    );
+}
+
+#[actix_web::test]
+async fn subscribe_returns_a_400_for_invalid_attributes() {
+    let app = spawn_app().await;
+    let test_cases = [
+        (json!({"locale": "english"}), "locale"),
+        (json!({"locale": "e1"}), "locale"),
+        (json!({"source": "x".repeat(101)}), "source"),
+    ];
+
+    for (attributes, invalid_field) in test_cases {
+        let mut body = json!({"name": "le guin", "email": "ursula@example.com"});
+        body.as_object_mut()
+            .unwrap()
+            .extend(attributes.as_object().unwrap().clone());
+
+        let response = app.post_subscriptions_json(&body).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", attributes);
+        let body: Value = response.json().await.unwrap();
+        assert!(body["fields"][invalid_field].is_array(), "{}", attributes);
+    }
+}
+
+#[actix_web::test]
+async fn subscribing_again_only_adds_missing_attributes() {
+    let app = spawn_app().await;
+    app.post_admin(
+        "/lists",
+        &json!({"slug": "product-updates", "name": "Product updates"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com", "locale": "de"}),
+    )
+    .await;
+
+    subscribe(
+        &app,
+        json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "list": "product-updates",
+            "locale": "fr",
+            "source": "pricing-page",
+        }),
+    )
+    .await;
+
+    assert_eq!(
+        attributes_of(&app, "ursula@example.com").await,
+        json!({"locale": "de", "source": "pricing-page"})
+    );
+}
+
+#[actix_web::test]
+async fn admins_can_filter_subscribers_by_tag_and_attribute() {
+    let app = spawn_app().await;
+    let ursula = subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com", "locale": "de"}),
+    )
+    .await;
+    subscribe(
+        &app,
+        json!({"name": "butler", "email": "octavia@example.com", "locale": "en"}),
+    )
+    .await;
+    app.post_admin(
+        "/subscribers/tags",
+        &json!({"ids": [ursula], "add": ["VIP"]}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    assert_eq!(
+        listed_emails(&app, &[("tag", "vip")]).await,
+        ["ursula@example.com"]
+    );
+    assert_eq!(
+        listed_emails(&app, &[("attribute", "locale=en")]).await,
+        ["octavia@example.com"]
+    );
+    assert!(
+        listed_emails(&app, &[("tag", "vip"), ("attribute", "locale=en")])
+            .await
+            .is_empty()
+    );
+    let response = app
+        .get_admin("/subscribers", &[("attribute", "plan=premium")])
+        .await;
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn bulk_tagging_by_ids_updates_tags_and_the_audit_trail() {
+    let app = spawn_app().await;
+    let ursula = subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com"}),
+    )
+    .await;
+    let octavia = subscribe(
+        &app,
+        json!({"name": "butler", "email": "octavia@example.com"}),
+    )
+    .await;
+    app.post_admin(
+        "/subscribers/tags",
+        &json!({"ids": [ursula], "add": ["beta"]}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+
+    let response = app
+        .post_admin(
+            "/subscribers/tags",
+            &json!({"ids": [ursula, octavia], "add": ["VIP", "conference-2026"], "remove": ["beta"]}),
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["updated"], 2);
+    assert_eq!(tags_of(&app, ursula).await, ["conference-2026", "vip"]);
+    assert_eq!(tags_of(&app, octavia).await, ["conference-2026", "vip"]);
+    let audit = sqlx::query!(
+        "SELECT changed_by, changes FROM subscriber_audit_log WHERE subscriber_id = $1 AND action = 'tags' ORDER BY id",
+        ursula
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(audit.len(), 2);
+    assert_eq!(audit[1].changed_by, Some(app.test_user.user_id));
+    assert_eq!(
+        audit[1].changes,
+        json!({"tags": {"from": ["beta"], "to": ["conference-2026", "vip"]}})
+    );
+
+    // 没有变化的订阅者不计入updated，也不写审计记录
+    let response = app
+        .post_admin(
+            "/subscribers/tags",
+            &json!({"ids": [ursula, octavia], "add": ["vip"]}),
+        )
+        .await;
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["updated"], 0);
+    let count = sqlx::query_scalar!(
+        r#"SELECT count(*) AS "count!" FROM subscriber_audit_log WHERE action = 'tags'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert_eq!(count, 3);
+}
+
+#[actix_web::test]
+async fn bulk_tagging_by_filters_only_touches_matching_subscribers() {
+    let app = spawn_app().await;
+    let ursula = subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com", "locale": "de"}),
+    )
+    .await;
+    let octavia = subscribe(
+        &app,
+        json!({"name": "butler", "email": "octavia@example.com", "locale": "en"}),
+    )
+    .await;
+
+    let response = app
+        .post_admin(
+            "/subscribers/tags",
+            &json!({"filters": {"attribute": "locale=de"}, "add": ["german"]}),
+        )
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["updated"], 1);
+    assert_eq!(tags_of(&app, ursula).await, ["german"]);
+    assert!(tags_of(&app, octavia).await.is_empty());
+
+    let response = app
+        .post_admin(
+            "/subscribers/tags",
+            &json!({"filters": {"tag": "german"}, "remove": ["german"]}),
+        )
+        .await;
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["updated"], 1);
+    assert!(tags_of(&app, ursula).await.is_empty());
+}
+
+#[actix_web::test]
+async fn invalid_bulk_tag_requests_are_rejected() {
+    let app = spawn_app().await;
+    let ursula = subscribe(
+        &app,
+        json!({"name": "le guin", "email": "ursula@example.com"}),
+    )
+    .await;
+    // (请求体, 出错的字段)
+    let test_cases = [
+        (json!({"add": ["vip"]}), "ids"),
+        (
+            json!({"ids": [ursula], "filters": {}, "add": ["vip"]}),
+            "ids",
+        ),
+        (json!({"ids": [ursula]}), "add"),
+        (json!({"ids": [ursula], "add": [" "]}), "add"),
+        (json!({"ids": [ursula], "remove": ["a,b"]}), "remove"),
+    ];
+
+    for (body, invalid_field) in test_cases {
+        let response = app.post_admin("/subscribers/tags", &body).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", body);
+        let error: Value = response.json().await.unwrap();
+        assert!(error["fields"][invalid_field].is_array(), "{}", body);
+    }
+    assert!(tags_of(&app, ursula).await.is_empty());
+}
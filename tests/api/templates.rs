@@ -0,0 +1,308 @@
+use serde_json::{json, Value};
+
+use crate::helpers::{spawn_app, TestApp};
+
+async fn save(app: &TestApp, name: &str, template: Value) -> reqwest::Response {
+    app.put_admin(&format!("/templates/{}", name), &template)
+        .await
+}
+
+// 一个片段、一个布局和一个使用它们的模板
+async fn save_issue_templates(app: &TestApp) {
+    for (name, template) in [
+        (
+            "footer",
+            json!({"html": "<p>Sent to {{email}}</p>", "text": "Sent to {{email}}"}),
+        ),
+        (
+            "base",
+            json!({
+                "html": "<html><body>{{{body}}}{{> footer}}</body></html>",
+                "text": "{{{body}}}\n--\n{{> footer}}",
+            }),
+        ),
+        (
+            "issue",
+            json!({
+                "subject": "{{title}} for {{name}}",
+                "html": r#"<h1>Hi {{name}}</h1><a href="{{unsubscribe_url}}">Leave</a>"#,
+                "text": "Hi {{name}}",
+                "layout": "base",
+            }),
+        ),
+    ] {
+        let response = save(app, name, template).await;
+        assert_eq!(201, response.status().as_u16(), "{}", name);
+    }
+}
+
+async fn create_confirmed_subscriber(app: &TestApp, name: &str, email: &str) {
+    app.post_subscriptions_json(&json!({"name": name, "email": email}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&email_request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+async fn last_email(app: &TestApp) -> Value {
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    serde_json::from_slice(&request.body).unwrap()
+}
+
+#[actix_web::test]
+async fn the_confirmation_email_uses_the_confirmation_template() {
+    let app = spawn_app().await;
+    let response = save(
+        &app,
+        "confirmation",
+        json!({
+            "subject": "Please confirm, {{name}}",
+            "html": r#"<p>Hello {{name}}, <a href="{{confirmation_url}}">confirm</a> your {{list}} subscription.</p>"#,
+            "text": "Hello {{name}}, visit {{confirmation_url}} to confirm.",
+        }),
+    )
+    .await;
+    assert_eq!(200, response.status().as_u16());
+
+    app.post_subscriptions_json(&json!({"name": "Tom & Jerry's", "email": "tom@example.com"}))
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email = last_email(&app).await;
+    assert_eq!(email["Subject"], "Please confirm, Tom & Jerry's");
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(html.contains("Hello Tom &amp; Jerry&#39;s"), "{}", html);
+    assert!(html.contains("your Newsletter subscription"));
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(text.starts_with("Hello Tom & Jerry's"), "{}", text);
+    // 确认链接仍然有效
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let links = app.get_confirmation_links(&request);
+    assert_eq!(links.html, links.plain_text);
+    let response = reqwest::get(links.html).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn the_preview_renders_layouts_and_partials_against_a_sample_subscriber() {
+    let app = spawn_app().await;
+    save_issue_templates(&app).await;
+
+    let response = app.get_admin("/templates/issue/preview", &[]).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let email: Value = response.json().await.unwrap();
+    assert_eq!(email["subject"], "Sample issue for Jane Doe");
+    assert_eq!(
+        email["html"],
+        format!(
+            r#"<html><body><h1>Hi Jane Doe</h1><a href="{}/subscriptions/unsubscribe?token=sample">Leave</a><p>Sent to jane.doe@example.com</p></body></html>"#,
+            app.address
+        )
+    );
+    assert_eq!(
+        email["text"],
+        "Hi Jane Doe\n--\nSent to jane.doe@example.com"
+    );
+
+    let response = app.get_admin("/templates/missing/preview", &[]).await;
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[actix_web::test]
+async fn templates_are_listed_and_can_be_fetched() {
+    let app = spawn_app().await;
+    save_issue_templates(&app).await;
+
+    let response = app.get_admin("/templates", &[]).await;
+    assert_eq!(200, response.status().as_u16());
+    let templates: Value = response.json().await.unwrap();
+    let names: Vec<&str> = templates
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| t["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["base", "confirmation", "footer", "issue"]);
+
+    let response = app.get_admin("/templates/issue", &[]).await;
+    assert_eq!(200, response.status().as_u16());
+    let template: Value = response.json().await.unwrap();
+    assert_eq!(template["layout"], "base");
+    assert_eq!(template["subject"], "{{title}} for {{name}}");
+}
+
+#[actix_web::test]
+async fn invalid_templates_are_rejected_with_clear_errors() {
+    let app = spawn_app().await;
+    save(&app, "a", json!({"html": "a", "text": "a"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    save(&app, "b", json!({"html": "{{> a}}", "text": "b"}))
+        .await
+        .error_for_status()
+        .unwrap();
+    save(
+        &app,
+        "framed",
+        json!({"html": "{{{body}}}", "text": "{{{body}}}", "layout": "a"}),
+    )
+    .await
+    .error_for_status()
+    .unwrap();
+    // (模板名称, 模板, 出错的字段, 错误信息中的片段)
+    let test_cases = [
+        (
+            "welcome",
+            json!({"html": "{{#if name}}Hi", "text": "Hi"}),
+            "html",
+            "Line 1",
+        ),
+        (
+            "welcome",
+            json!({"html": "Hi", "text": "Hi {{nme}}"}),
+            "text",
+            "nme",
+        ),
+        (
+            "welcome",
+            json!({"subject": "{{titel}}", "html": "Hi", "text": "Hi"}),
+            "subject",
+            "titel",
+        ),
+        (
+            "welcome",
+            json!({"html": "{{> footer}}", "text": "Hi"}),
+            "html",
+            "footer",
+        ),
+        (
+            "welcome",
+            json!({"html": "Hi", "text": "Hi", "layout": "missing"}),
+            "layout",
+            "missing",
+        ),
+        (
+            "a",
+            json!({"html": "{{> b}}", "text": "a"}),
+            "html",
+            "itself",
+        ),
+        (
+            "a",
+            json!({"html": "a", "text": "a", "layout": "framed"}),
+            "layout",
+            "cycle",
+        ),
+        (
+            "Welcome Email",
+            json!({"html": "Hi", "text": "Hi"}),
+            "name",
+            "lowercase",
+        ),
+        (
+            "welcome",
+            json!({"html": " ", "text": "Hi"}),
+            "html",
+            "empty",
+        ),
+    ];
+
+    for (name, template, invalid_field, message) in test_cases {
+        let response = save(&app, name, template.clone()).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", template);
+        let body: Value = response.json().await.unwrap();
+        let errors = body["fields"][invalid_field].to_string();
+        assert!(errors.contains(message), "{}: {}", template, errors);
+    }
+    let response = app.get_admin("/templates/welcome", &[]).await;
+    assert_eq!(404, response.status().as_u16());
+    let a: Value = app
+        .get_admin("/templates/a", &[])
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(a["html"], "a");
+    assert!(a["layout"].is_null());
+}
+
+#[actix_web::test]
+async fn publishing_a_template_renders_it_for_each_subscriber() {
+    let app = spawn_app().await;
+    save_issue_templates(&app).await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+
+    let response = app
+        .post_newsletters(json!({"title": "Issue 1", "template": "issue"}))
+        .await;
+    app.dispatch_all_pending_issues().await;
+
+    assert_eq!(202, response.status().as_u16());
+    let email = last_email(&app).await;
+    assert_eq!(email["To"], "ursula@example.com");
+    assert_eq!(email["Subject"], "Issue 1 for le guin");
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(
+        html.starts_with("<html><body><h1>Hi le guin</h1>"),
+        "{}",
+        html
+    );
+    // 模板中已有退订链接时不再追加
+    assert_eq!(html.matches("/subscriptions/unsubscribe?token=").count(), 1);
+    let text = email["TextBody"].as_str().unwrap();
+    assert!(text.starts_with("Hi le guin\n--\nSent to ursula@example.com"));
+    assert!(text.contains("Unsubscribe: "));
+}
+
+#[actix_web::test]
+async fn publishing_requires_either_content_or_a_known_template() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "le guin", "ursula@example.com").await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    let test_cases = [
+        json!({"title": "Issue 1", "template": "missing"}),
+        json!({"title": "Issue 1"}),
+        json!({
+            "title": "Issue 1",
+            "template": "confirmation",
+            "content": {"text": "text", "html": "<p>html</p>"},
+        }),
+    ];
+
+    for body in test_cases {
+        let response = app.post_newsletters(body.clone()).await;
+
+        assert_eq!(400, response.status().as_u16(), "{}", body);
+    }
+    assert_eq!(
+        app.email_server.received_requests().await.unwrap().len(),
+        sent
+    );
+}
@@ -0,0 +1,274 @@
+use base64::Engine;
+use serde_json::{json, Value};
+
+use secrecy::Secret;
+use uuid::Uuid;
+use zero2prod_lib::configuration::TrackingSettings;
+use zero2prod_lib::newsletter_issue::TrackingOptions;
+use zero2prod_lib::tracking::Tracking;
+
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+
+// 创建一个已确认的订阅者，返回其偏好设置令牌
+async fn create_confirmed_subscriber(app: &TestApp, email: &str) -> String {
+    app.post_subscriptions_json(&json!({"name": "le guin", "email": email}))
+        .await
+        .error_for_status()
+        .unwrap();
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    reqwest::get(app.get_confirmation_links(&request).html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    sqlx::query_scalar!(
+        "SELECT preferences_token FROM subscriptions WHERE email = $1",
+        email
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+}
+
+// 立即发布一期并发送，返回这一期的id和发给各个订阅者的HTML
+async fn send_issue(app: &TestApp, title: &str, tracking: Value) -> (String, Vec<String>) {
+    let response = app
+        .post_admin(
+            "/issues",
+            &json!({
+                "title": title,
+                "content": {
+                    "html": "<p><a href=\"https://example.com/article?a=1&amp;b=2\">Read</a> \
+                        or <a href='mailto:editor@example.com'>reply</a></p>",
+                    "text": "Read https://example.com/article?a=1&b=2",
+                },
+                "tracking": tracking,
+            }),
+        )
+        .await;
+    let issue: Value = response.json().await.unwrap();
+    let id = issue["id"].as_str().unwrap().to_string();
+    app.post_admin(&format!("/issues/{}/publish", id), &json!({}))
+        .await
+        .error_for_status()
+        .unwrap();
+    app.dispatch_all_pending_issues().await;
+    let html = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| serde_json::from_slice::<Value>(&request.body).unwrap())
+        .filter(|body| body["Subject"] == title)
+        .map(|body| body["HtmlBody"].as_str().unwrap().to_string())
+        .collect();
+    (id, html)
+}
+
+// HTML中第一个以prefix开头的地址
+fn find_url(html: &str, prefix: &str) -> Option<String> {
+    let start = html.find(prefix)?;
+    let end = start + html[start..].find('"')?;
+    Some(html[start..end].to_string())
+}
+
+fn no_redirects() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+async fn engagement(app: &TestApp, id: &str) -> Value {
+    app.get_admin(&format!("/issues/{}/engagement", id), &[])
+        .await
+        .json()
+        .await
+        .unwrap()
+}
+
+#[actix_web::test]
+async fn opens_and_clicks_are_recorded_for_issues_with_tracking() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let (id, html) = send_issue(&app, "Tracked", json!({"opens": true, "clicks": true})).await;
+
+    // 外部链接被改写，mailto和我们自己的链接保持不变
+    let html = &html[0];
+    assert!(!html.contains("https://example.com/article"));
+    assert!(html.contains("mailto:editor@example.com"));
+    assert!(html.contains("/subscriptions/unsubscribe?token="));
+    let pixel = find_url(html, &format!("{}/o/", app.address)).unwrap();
+    let link = find_url(html, &format!("{}/r/", app.address)).unwrap();
+
+    let response = reqwest::get(&pixel).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    assert_eq!(response.headers()["Content-Type"], "image/gif");
+    reqwest::get(&pixel).await.unwrap();
+    let response = no_redirects().get(&link).send().await.unwrap();
+    assert_eq!(302, response.status().as_u16());
+    assert_eq!(
+        response.headers()["Location"],
+        "https://example.com/article?a=1&b=2"
+    );
+
+    let stats = engagement(&app, &id).await;
+    assert_eq!(stats["opens"], 2);
+    assert_eq!(stats["unique_opens"], 1);
+    assert_eq!(stats["clicks"], 1);
+    assert_eq!(
+        stats["links"][0]["url"],
+        "https://example.com/article?a=1&b=2"
+    );
+    assert_eq!(stats["links"][0]["unique_clicks"], 1);
+}
+
+#[actix_web::test]
+async fn forged_or_tampered_redirects_are_not_followed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+    let (id, html) = send_issue(&app, "Tracked", json!({"opens": true, "clicks": true})).await;
+    let pixel = find_url(&html[0], &format!("{}/o/", app.address)).unwrap();
+    let link = find_url(&html[0], &format!("{}/r/", app.address)).unwrap();
+
+    // 保留签名，把地址换成别的网站
+    let (payload, signature) = link.rsplit_once("/r/").unwrap().1.rsplit_once('.').unwrap();
+    let engine = base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    let mut token: Value = serde_json::from_slice(&engine.decode(payload).unwrap()).unwrap();
+    token["u"] = "https://evil.example.com/".into();
+    let forged = format!(
+        "{}/r/{}.{}",
+        app.address,
+        engine.encode(serde_json::to_vec(&token).unwrap()),
+        signature
+    );
+
+    for url in [
+        forged,
+        format!("{}/r/not-a-token", app.address),
+        // 跟踪像素的令牌不能用来跳转
+        pixel.replace("/o/", "/r/"),
+    ] {
+        let response = no_redirects().get(&url).send().await.unwrap();
+        assert_eq!(404, response.status().as_u16(), "{}", url);
+        assert!(response.headers().get("Location").is_none());
+    }
+    assert_eq!(engagement(&app, &id).await["clicks"], 0);
+}
+
+#[actix_web::test]
+async fn subscribers_who_opt_out_are_no_longer_tracked() {
+    let app = spawn_app().await;
+    let token = create_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    // 默认不跟踪
+    let (_, html) = send_issue(&app, "Untracked", json!(null)).await;
+    assert!(html[0].contains("https://example.com/article?a=1&amp;b=2"));
+    assert!(!html[0].contains("/o/"));
+
+    let (id, html) = send_issue(&app, "Tracked", json!({"opens": true})).await;
+    let pixel = find_url(&html[0], &format!("{}/o/", app.address)).unwrap();
+    reqwest::get(&pixel).await.unwrap();
+    assert_eq!(engagement(&app, &id).await["opens"], 1);
+
+    reqwest::Client::new()
+        .post(format!("{}/preferences", app.address))
+        .form(&[
+            ("token", token.as_str()),
+            ("name", "le guin"),
+            ("list", "newsletter"),
+            ("delivery_frequency", "immediate"),
+            ("no_tracking", "on"),
+        ])
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    // 之前的记录被删除，已经发出的跟踪像素也不再记录
+    assert_eq!(engagement(&app, &id).await["opens"], 0);
+    reqwest::get(&pixel).await.unwrap();
+    assert_eq!(engagement(&app, &id).await["opens"], 0);
+
+    let (_, html) = send_issue(
+        &app,
+        "After opting out",
+        json!({"opens": true, "clicks": true}),
+    )
+    .await;
+    assert!(!html[0].contains("/o/"));
+    assert!(!html[0].contains("/r/"));
+}
+
+#[actix_web::test]
+async fn the_kill_switch_turns_tracking_off_everywhere() {
+    let app = spawn_app_with(|c| c.tracking.enabled = false).await;
+    create_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    let (id, html) = send_issue(&app, "Tracked", json!({"opens": true, "clicks": true})).await;
+    assert!(!html[0].contains("/o/"));
+    assert!(!html[0].contains("/r/"));
+    assert_eq!(engagement(&app, &id).await["opens"], 0);
+
+    // 立即发布的邮件同样保存为一期，也受总开关控制
+    let response = app
+        .post_newsletters(json!({
+            "title": "Immediate",
+            "content": {"html": "<p>Hi</p>", "text": "Hi"},
+            "tracking": {"opens": true},
+        }))
+        .await;
+    assert_eq!(202, response.status().as_u16());
+    app.dispatch_all_pending_issues().await;
+    let email: Value = serde_json::from_slice(
+        &app.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .pop()
+            .unwrap()
+            .body,
+    )
+    .unwrap();
+    assert_eq!(email["Subject"], "Immediate");
+    assert!(!email["HtmlBody"].as_str().unwrap().contains("/o/"));
+}
+
+#[test]
+fn only_link_hrefs_are_rewritten_and_the_pixel_goes_inside_the_body() {
+    let tracking = Tracking::new(&TrackingSettings {
+        enabled: true,
+        signing_key: Secret::new("key".into()),
+    });
+    let html = "<html><body><p data-href=\"https://example.com/data\">x</p>\
+        <a class=\"button\" href=\"https://example.com/a\">a</a>\
+        <link href=\"https://example.com/style.css\">\
+        <p>Write href=\"https://example.com/text\"</p></BODY></html>";
+
+    let tracked = tracking.instrument(
+        html.into(),
+        TrackingOptions {
+            opens: true,
+            clicks: true,
+        },
+        "https://news.example.com",
+        Uuid::new_v4(),
+        Uuid::new_v4(),
+    );
+
+    assert!(tracked.contains("data-href=\"https://example.com/data\""));
+    assert!(tracked.contains("<link href=\"https://example.com/style.css\">"));
+    assert!(tracked.contains("href=\"https://example.com/text\""));
+    assert!(!tracked.contains("https://example.com/a\""));
+    assert!(tracked.contains("<a class=\"button\" href=\"https://news.example.com/r/"));
+    let pixel = tracked.find("https://news.example.com/o/").unwrap();
+    assert!(pixel < tracked.find("</body>").unwrap(), "{}", tracked);
+    assert!(tracked.ends_with("</body></html>"));
+}